@@ -0,0 +1,124 @@
+//! API key authentication module
+//!
+//! This module provides the middleware that rejects requests without a valid
+//! `X-Api-Key` header. The health check stays public so that load balancers
+//! and orchestrators can probe the service without credentials, and so does the
+//! API documentation, which a browser cannot load with a custom header.
+//! Requests authenticated with an admin key are marked so that handlers can
+//! gate admin-only features with [`is_admin`], and with the tenant their key
+//! belongs to, read with [`tenant`].
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpMessage, HttpRequest, web};
+use log::warn;
+
+use crate::settings::{AuthConfig, DEFAULT_TENANT, json_unauthorized};
+
+/// Header carrying the API key
+pub const API_KEY_HEADER: &str = "x-api-key";
+
+/// Compares two byte strings in constant time
+///
+/// Avoids leaking through response timing how many leading bytes of a guessed
+/// key are correct.
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+/// Request extension marking requests authenticated with an admin key
+#[derive(Clone, Copy)]
+struct AdminKey;
+
+/// Returns `true` when the request was authenticated with an admin key (`ADMIN_API_KEYS`)
+pub fn is_admin(req: &HttpRequest) -> bool {
+    req.extensions().get::<AdminKey>().is_some()
+}
+
+/// Request extension naming the tenant of the API key (`API_KEY_TENANTS`)
+#[derive(Clone)]
+struct Tenant(String);
+
+/// Returns the tenant charged for the sends of a request
+///
+/// Requests whose key is not mapped in `API_KEY_TENANTS`, and all requests when
+/// authentication is disabled, belong to the default tenant.
+pub fn tenant(req: &HttpRequest) -> String {
+    req.extensions()
+        .get::<Tenant>()
+        .map_or_else(|| DEFAULT_TENANT.to_owned(), |t| t.0.clone())
+}
+
+/// Request extension holding the API key the request was authenticated with
+#[derive(Clone)]
+struct VerifiedKey(String);
+
+/// Returns the API key a request was authenticated with
+///
+/// `None` when authentication is disabled or the path is public: the key
+/// header of such requests was never checked and must not be trusted.
+pub fn verified_key(req: &HttpRequest) -> Option<String> {
+    req.extensions().get::<VerifiedKey>().map(|k| k.0.clone())
+}
+
+/// Returns `true` for paths served without authentication (health check, API documentation)
+fn is_public(req: &ServiceRequest) -> bool {
+    matches!(req.path(), "" | "/" | "/openapi.json" | "/docs")
+}
+
+/// Middleware validating the `X-Api-Key` header
+///
+/// Registered with `actix_web::middleware::from_fn`. Requests pass through
+/// unchanged when no API key is configured. Admin keys are accepted too and
+/// mark the request as admin.
+///
+/// # Returns
+/// * `Ok(ServiceResponse)` - The response of the wrapped service
+/// * `Err(actix_web::Error)` - JSON fail response with HTTP 401 when the key is missing or unknown
+pub async fn require_api_key(
+    config: web::Data<AuthConfig>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let no_keys = config.api_keys.is_empty() && config.admin_keys.is_empty();
+    if no_keys || is_public(&req) {
+        return next.call(req).await;
+    }
+
+    let provided = req
+        .headers()
+        .get(API_KEY_HEADER)
+        .map(|v| v.as_bytes())
+        .unwrap_or_default();
+    if provided.is_empty() {
+        return Err(json_unauthorized("Missing API key"));
+    }
+
+    let admin = config
+        .admin_keys
+        .iter()
+        .any(|k| constant_time_eq(k.as_bytes(), provided));
+    let valid = admin
+        || config
+            .api_keys
+            .iter()
+            .any(|k| constant_time_eq(k.as_bytes(), provided));
+    if !valid {
+        warn!("Rejected request to {} with an invalid API key", req.path());
+        return Err(json_unauthorized("Invalid API key"));
+    }
+    if admin {
+        req.extensions_mut().insert(AdminKey);
+    }
+    let key = String::from_utf8_lossy(provided).into_owned();
+    if let Some(tenant) = config.tenants.get(&key) {
+        req.extensions_mut().insert(Tenant(tenant.clone()));
+    }
+    req.extensions_mut().insert(VerifiedKey(key));
+
+    next.call(req).await
+}
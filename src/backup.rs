@@ -0,0 +1,231 @@
+//! Backup and restore of the service state
+//!
+//! The archive is a single SQLite file holding the template files and the
+//! messages still waiting in the persistent queue (pending, scheduled and
+//! converting). Sent and failed messages are not part of the backup.
+//!
+//! Restoring writes the templates into `TEMPLATES_DIR` (overwriting files with
+//! the same name) and adds the queued messages that are not in the queue yet.
+//! It can run while the service is up: restored messages are picked up by the
+//! running worker.
+
+use std::fs;
+use std::path::{Component, Path, PathBuf};
+
+use rusqlite::{Connection, OptionalExtension, params};
+
+use crate::queue::migrations;
+use crate::queue::store::{QueueStatus, QueueStore};
+use crate::settings::{QueueConfig, TemplateConfig};
+
+/// Queue columns copied between the queue database and the archive
+const QUEUE_COLUMNS: &str = "id, envelope_from, recipients, message, status, attempts, \
+    next_attempt_at, last_error, created_at, updated_at, profile, send_at, tenant, callback_url, \
+    payload";
+
+/// What a backup or restore copied
+pub struct BackupSummary {
+    /// Number of queued messages
+    pub messages: usize,
+
+    /// Number of template files
+    pub templates: usize,
+}
+
+/// Collects the files of a directory recursively, as paths relative to `root`
+fn collect_files(root: &Path, dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), String> {
+    let entries = fs::read_dir(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    for entry in entries {
+        let path = entry.map_err(|e| e.to_string())?.path();
+        if path.is_dir() {
+            collect_files(root, &path, files)?;
+        } else if let Ok(relative) = path.strip_prefix(root) {
+            files.push(relative.to_path_buf());
+        }
+    }
+    Ok(())
+}
+
+/// Converts a relative path to its portable form, with `/` separators
+fn portable(path: &Path) -> String {
+    path.components()
+        .map(|c| c.as_os_str().to_string_lossy())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Resolves a template path read from an archive inside the templates directory
+///
+/// Paths that are absolute or climb out of the directory are rejected, so a
+/// crafted archive cannot write anywhere else.
+fn template_target(dir: &Path, path: &str) -> Result<PathBuf, String> {
+    let relative = Path::new(path);
+    let safe = relative
+        .components()
+        .all(|c| matches!(c, Component::Normal(_)));
+    if !safe || path.is_empty() {
+        return Err(format!("Invalid template path in archive: {}", path));
+    }
+    Ok(dir.join(relative))
+}
+
+/// Writes the service state to an archive
+///
+/// # Arguments
+/// * `archive` - Path of the archive to create; it must not exist
+/// * `queue_config` - Queue configuration (database path, migrations)
+/// * `template_config` - Template configuration (directory)
+///
+/// # Returns
+/// * `Ok(BackupSummary)` - What was written to the archive
+/// * `Err(String)` - The archive exists or the state cannot be read
+pub fn backup(
+    archive: &str,
+    queue_config: &QueueConfig,
+    template_config: &TemplateConfig,
+) -> Result<BackupSummary, String> {
+    if Path::new(archive).exists() {
+        return Err(format!("Backup archive {} already exists", archive));
+    }
+    // Bring the queue database to the schema this binary writes in archives
+    drop(QueueStore::open(
+        &queue_config.db_path,
+        queue_config.auto_migrate,
+    )?);
+
+    let mut conn = Connection::open(archive).map_err(|e| e.to_string())?;
+    migrations::migrate(&mut conn)?;
+    conn.execute(
+        "ATTACH DATABASE ?1 AS source",
+        params![queue_config.db_path],
+    )
+    .map_err(|e| e.to_string())?;
+    let tx = conn.transaction().map_err(|e| e.to_string())?;
+    tx.execute_batch("CREATE TABLE templates (path TEXT PRIMARY KEY, content BLOB NOT NULL);")
+        .map_err(|e| e.to_string())?;
+
+    let mut templates = 0;
+    if let Some(dir) = &template_config.dir {
+        let root = Path::new(dir);
+        let mut files = Vec::new();
+        collect_files(root, root, &mut files)?;
+        for file in files {
+            let content =
+                fs::read(root.join(&file)).map_err(|e| format!("{}: {}", file.display(), e))?;
+            tx.execute(
+                "INSERT INTO templates (path, content) VALUES (?1, ?2)",
+                params![portable(&file), content],
+            )
+            .map_err(|e| e.to_string())?;
+            templates += 1;
+        }
+    }
+
+    let messages = tx
+        .execute(
+            &format!(
+                "INSERT INTO queue ({0}) SELECT {0} FROM source.queue WHERE status IN (?1, ?2)",
+                QUEUE_COLUMNS
+            ),
+            params![
+                QueueStatus::Pending.as_str(),
+                QueueStatus::Converting.as_str()
+            ],
+        )
+        .map_err(|e| e.to_string())?;
+    tx.commit().map_err(|e| e.to_string())?;
+    conn.execute("DETACH DATABASE source", [])
+        .map_err(|e| e.to_string())?;
+
+    Ok(BackupSummary {
+        messages,
+        templates,
+    })
+}
+
+/// Restores the service state from an archive
+///
+/// # Arguments
+/// * `archive` - Path of an archive written by [`backup`]
+/// * `queue_config` - Queue configuration (database path, migrations)
+/// * `template_config` - Template configuration (directory)
+///
+/// # Returns
+/// * `Ok(BackupSummary)` - What was restored; messages already queued are not counted
+/// * `Err(String)` - The archive is invalid, holds templates while `TEMPLATES_DIR`
+///   is unset, or the state cannot be written
+pub fn restore(
+    archive: &str,
+    queue_config: &QueueConfig,
+    template_config: &TemplateConfig,
+) -> Result<BackupSummary, String> {
+    if !Path::new(archive).is_file() {
+        return Err(format!("Backup archive {} not found", archive));
+    }
+    let mut conn = Connection::open(archive).map_err(|e| e.to_string())?;
+    let is_archive = conn
+        .query_row(
+            "SELECT name FROM sqlite_master WHERE type = 'table' AND name = 'templates'",
+            [],
+            |row| row.get::<_, String>(0),
+        )
+        .optional()
+        .map_err(|e| e.to_string())?
+        .is_some();
+    if !is_archive {
+        return Err(format!("{} is not a rustmail backup archive", archive));
+    }
+    // Archives written by an older version get the current queue schema
+    migrations::migrate(&mut conn)?;
+
+    let files: Vec<(String, Vec<u8>)> = {
+        let mut stmt = conn
+            .prepare("SELECT path, content FROM templates ORDER BY path")
+            .map_err(|e| e.to_string())?;
+        stmt.query_map([], |row| Ok((row.get(0)?, row.get(1)?)))
+            .and_then(|rows| rows.collect())
+            .map_err(|e| e.to_string())?
+    };
+    if !files.is_empty() {
+        let dir = template_config.dir.as_deref().ok_or_else(|| {
+            "Archive holds templates but TEMPLATES_DIR is not configured".to_owned()
+        })?;
+        // Validate every path before writing anything
+        let targets = files
+            .iter()
+            .map(|(path, _)| template_target(Path::new(dir), path))
+            .collect::<Result<Vec<_>, _>>()?;
+        for (target, (_, content)) in targets.iter().zip(&files) {
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent).map_err(|e| format!("{}: {}", parent.display(), e))?;
+            }
+            fs::write(target, content).map_err(|e| format!("{}: {}", target.display(), e))?;
+        }
+    }
+
+    drop(QueueStore::open(
+        &queue_config.db_path,
+        queue_config.auto_migrate,
+    )?);
+    let queue = Connection::open(&queue_config.db_path).map_err(|e| e.to_string())?;
+    queue
+        .busy_timeout(std::time::Duration::from_secs(5))
+        .map_err(|e| e.to_string())?;
+    queue
+        .execute("ATTACH DATABASE ?1 AS archive", params![archive])
+        .map_err(|e| e.to_string())?;
+    let messages = queue
+        .execute(
+            &format!(
+                "INSERT OR IGNORE INTO queue ({0}) SELECT {0} FROM archive.queue",
+                QUEUE_COLUMNS
+            ),
+            [],
+        )
+        .map_err(|e| e.to_string())?;
+
+    Ok(BackupSummary {
+        messages,
+        templates: files.len(),
+    })
+}
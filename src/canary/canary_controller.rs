@@ -0,0 +1,47 @@
+//! HTTP controllers for canary endpoints
+//!
+//! This module exposes the latest deliverability canary results.
+
+use crate::canary::dto::CanaryRes;
+use crate::canary::monitor::CanaryMonitor;
+use crate::settings::{RustMailRes, Status};
+use actix_web::{HttpResponse, Result, get, web};
+use utoipa::OpenApi;
+
+/// GET endpoint listing the latest canary results
+///
+/// Returns one entry per seed mailbox with the placement (inbox, spam, missing,
+/// error) and delivery latency of its last probe. The list is empty when no seed
+/// is configured or no probe has completed yet.
+#[utoipa::path(
+    get,
+    path = "/canary",
+    tag = "canary",
+    responses(
+        (status = 200, description = "Latest result of each seed mailbox", body = CanaryRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes)
+    )
+)]
+#[get("canary")]
+async fn canary_results(monitor: web::Data<CanaryMonitor>) -> Result<HttpResponse> {
+    let x = CanaryRes {
+        status: Status::Ok,
+        results: monitor.results(),
+    };
+    Ok(HttpResponse::Ok().json(x))
+}
+
+/// OpenAPI description of the endpoints of this module
+#[derive(OpenApi)]
+#[openapi(paths(canary_results))]
+pub struct ApiDoc;
+
+/// Configures the Actix-web service routes
+///
+/// Registers all HTTP endpoints for this module with the application.
+///
+/// # Arguments
+/// * `cfg` - Actix-web service configuration to register routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(canary_results);
+}
@@ -0,0 +1,53 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::settings::Status;
+
+/// Outcome of a single canary probe
+#[derive(Serialize, Clone, Copy, PartialEq, Eq, Debug, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CanaryOutcome {
+    /// The probe was found in the inbox
+    Inbox,
+
+    /// The probe was found in the spam folder
+    Spam,
+
+    /// The probe did not arrive before the timeout
+    Missing,
+
+    /// The probe could not be sent or the mailbox could not be checked
+    Error,
+}
+
+/// Result of the last probe sent to a seed mailbox
+#[derive(Serialize, Clone, ToSchema)]
+pub struct CanaryResult {
+    /// Seed name from the configuration
+    pub seed: String,
+
+    /// Seed mailbox address
+    pub address: String,
+
+    /// Where the probe landed
+    pub outcome: CanaryOutcome,
+
+    /// Time between the SMTP send and the probe being found, in milliseconds
+    pub latency_ms: Option<u64>,
+
+    /// Unix timestamp (seconds) of the end of the probe
+    pub checked_at: u64,
+
+    /// Error description when the outcome is `error`
+    pub error: Option<String>,
+}
+
+/// Response listing the latest canary results
+#[derive(Serialize, ToSchema)]
+pub struct CanaryRes {
+    /// Response status (ok, fail, or error)
+    pub status: Status,
+
+    /// Latest result for each seed mailbox
+    pub results: Vec<CanaryResult>,
+}
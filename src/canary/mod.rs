@@ -0,0 +1,14 @@
+//! Deliverability canary module
+//!
+//! This module periodically sends probe messages to seed mailboxes, checks over
+//! IMAP whether they landed in the inbox or in the spam folder, and reports the
+//! end-to-end delivery latency.
+
+/// Data transfer objects for canary results
+pub mod dto;
+
+/// Probe scheduling and IMAP placement checks
+pub mod monitor;
+
+/// HTTP controllers for canary endpoints
+pub mod canary_controller;
@@ -0,0 +1,231 @@
+//! Probe scheduling and IMAP placement checks
+//!
+//! Each seed mailbox is probed from its own background task: a message with a
+//! unique token in the subject is sent through the SMTP transport, then the
+//! inbox and spam folders are polled over IMAP (on the blocking thread pool)
+//! until it shows up or the timeout expires. Found probes are deleted from the
+//! seed mailbox.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::rt::{self, time::sleep};
+use actix_web::web;
+use lettre::Message;
+use log::{info, warn};
+use native_tls::TlsConnector;
+
+use crate::canary::dto::{CanaryOutcome, CanaryResult};
+use crate::dkim::DkimSigner;
+use crate::settings::{CanaryConfig, CanarySeed};
+use crate::transport::{Mailer, Mailers};
+
+/// Latest canary result of each seed mailbox
+///
+/// Shared between the probe threads and the HTTP endpoint.
+#[derive(Default)]
+pub struct CanaryMonitor {
+    /// Seed name to latest result
+    results: Mutex<HashMap<String, CanaryResult>>,
+}
+
+impl CanaryMonitor {
+    /// Stores the latest result of a seed, replacing the previous one
+    fn record(&self, result: CanaryResult) {
+        let mut results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        results.insert(result.seed.clone(), result);
+    }
+
+    /// Returns the latest results sorted by seed name
+    pub fn results(&self) -> Vec<CanaryResult> {
+        let results = self.results.lock().unwrap_or_else(|e| e.into_inner());
+        let mut list: Vec<CanaryResult> = results.values().cloned().collect();
+        list.sort_by(|a, b| a.seed.cmp(&b.seed));
+        list
+    }
+}
+
+/// Returns the current Unix timestamp in seconds
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Looks for the probe in the seed inbox and spam folders
+///
+/// # Returns
+/// * `Ok(Some(outcome))` - The probe was found (and deleted) in the inbox or spam folder
+/// * `Ok(None)` - The probe has not arrived yet
+/// * `Err(String)` - The mailbox could not be checked
+fn find_probe(seed: &CanarySeed, subject: &str) -> Result<Option<CanaryOutcome>, String> {
+    let tls = TlsConnector::new().map_err(|e| e.to_string())?;
+    let client = imap::connect(
+        (seed.imap_host.as_str(), seed.imap_port),
+        &seed.imap_host,
+        &tls,
+    )
+    .map_err(|e| e.to_string())?;
+    let mut session = client
+        .login(&seed.imap_username, &seed.imap_password)
+        .map_err(|(e, _)| e.to_string())?;
+
+    let folders = [
+        ("INBOX", CanaryOutcome::Inbox),
+        (seed.spam_folder.as_str(), CanaryOutcome::Spam),
+    ];
+    let mut found_in = None;
+    for (folder, outcome) in folders {
+        session.select(folder).map_err(|e| e.to_string())?;
+        let found = session
+            .search(format!("SUBJECT \"{}\"", subject))
+            .map_err(|e| e.to_string())?;
+        if !found.is_empty() {
+            // Remove the probe so seed mailboxes do not grow forever
+            let set = found
+                .iter()
+                .map(|s| s.to_string())
+                .collect::<Vec<_>>()
+                .join(",");
+            session
+                .store(set, "+FLAGS (\\Deleted)")
+                .map_err(|e| e.to_string())?;
+            session.expunge().map_err(|e| e.to_string())?;
+            found_in = Some(outcome);
+            break;
+        }
+    }
+
+    session.logout().map_err(|e| e.to_string())?;
+    Ok(found_in)
+}
+
+/// Sends one probe to a seed mailbox and waits for it to arrive
+async fn probe(
+    seed: &CanarySeed,
+    config: &CanaryConfig,
+    mailer: &Mailer,
+    dkim: Option<&DkimSigner>,
+) -> CanaryResult {
+    let mut result = CanaryResult {
+        seed: seed.name.clone(),
+        address: seed.address.clone(),
+        outcome: CanaryOutcome::Error,
+        latency_ms: None,
+        checked_at: 0,
+        error: None,
+    };
+
+    let token = format!("{:016x}", rand::random::<u64>());
+    let subject = format!("rustmail canary {}", token);
+
+    let email = config
+        .from
+        .parse()
+        .map_err(|e: lettre::address::AddressError| e.to_string())
+        .and_then(|from| {
+            let to = seed
+                .address
+                .parse()
+                .map_err(|e: lettre::address::AddressError| e.to_string())?;
+            Message::builder()
+                .from(from)
+                .to(to)
+                .subject(subject.clone())
+                .body(format!("Deliverability probe {}", token))
+                .map_err(|e| e.to_string())
+        })
+        .map(|mut email| {
+            // Probes are signed like real mail so that placement reflects production
+            if let Some(dkim) = dkim {
+                dkim.sign(&mut email);
+            }
+            email
+        });
+
+    let sent_at = Instant::now();
+    let sent = match email {
+        Ok(email) => mailer.send(email).await.map_err(|e| e.to_string()),
+        Err(err) => Err(err),
+    };
+    if let Err(err) = sent {
+        result.error = Some(err);
+        result.checked_at = unix_now();
+        return result;
+    }
+
+    loop {
+        sleep(config.poll_interval).await;
+
+        // IMAP client is blocking, run it off the async workers
+        let imap_seed = seed.clone();
+        let imap_subject = subject.clone();
+        let found = web::block(move || find_probe(&imap_seed, &imap_subject))
+            .await
+            .unwrap_or_else(|e| Err(e.to_string()));
+        match found {
+            Ok(Some(outcome)) => {
+                result.outcome = outcome;
+                result.latency_ms = Some(sent_at.elapsed().as_millis() as u64);
+                break;
+            }
+            Ok(None) if sent_at.elapsed() >= config.timeout => {
+                result.outcome = CanaryOutcome::Missing;
+                break;
+            }
+            Ok(None) => continue,
+            Err(err) => {
+                result.error = Some(err);
+                break;
+            }
+        }
+    }
+
+    result.checked_at = unix_now();
+    result
+}
+
+/// Starts one background probe task per configured seed mailbox
+///
+/// # Arguments
+/// * `config` - Canary configuration (seeds, sender, intervals)
+/// * `mailers` - SMTP transports; probes go through the default profile
+/// * `dkim` - DKIM signer applied to the probes, when signing is configured
+/// * `monitor` - Shared store of the latest results
+pub fn start_canary(
+    config: CanaryConfig,
+    mailers: Mailers,
+    dkim: Option<Arc<DkimSigner>>,
+    monitor: Arc<CanaryMonitor>,
+) {
+    let config = Arc::new(config);
+    for seed in config.seeds.clone() {
+        let config = Arc::clone(&config);
+        let mailers = mailers.clone();
+        let dkim = dkim.clone();
+        let monitor = Arc::clone(&monitor);
+        rt::spawn(async move {
+            loop {
+                let mailer = mailers.default_mailer();
+                let result = probe(&seed, &config, &mailer, dkim.as_deref()).await;
+                match result.outcome {
+                    CanaryOutcome::Inbox => info!(
+                        "Canary {}: inbox in {}ms",
+                        seed.name,
+                        result.latency_ms.unwrap_or_default()
+                    ),
+                    outcome => warn!(
+                        "Canary {}: {:?} {}",
+                        seed.name,
+                        outcome,
+                        result.error.as_deref().unwrap_or_default()
+                    ),
+                }
+                monitor.record(result);
+                sleep(config.interval).await;
+            }
+        });
+    }
+}
@@ -0,0 +1,329 @@
+//! SMTP circuit breaker module
+//!
+//! This module guards the transport of each profile with a circuit breaker.
+//! After a configured number of failed attempts in a row the circuit opens:
+//! sends fail fast for a cooldown instead of every request hanging on a dead
+//! relay. Once the cooldown is over a single trial send is let through; its
+//! success closes the circuit, its failure opens it again. Permanent
+//! rejections (5xx replies) are answers of the relay and do not count as
+//! failures.
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use futures_util::future::BoxFuture;
+use lettre::address::Envelope;
+use log::{info, warn};
+
+use crate::settings::CircuitBreakerConfig;
+use crate::transport::{MailTransport, Mailer, TransportError};
+
+/// State of a circuit
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum CircuitState {
+    /// Sends go through
+    Closed,
+
+    /// Sends fail fast until the cooldown is over
+    Open,
+
+    /// The cooldown is over, a trial send decides whether the circuit closes
+    HalfOpen,
+}
+
+/// State of a circuit as reported by the health endpoint
+#[derive(Clone, Copy, Debug)]
+pub struct CircuitSnapshot {
+    /// State of the circuit
+    pub state: CircuitState,
+
+    /// Failed attempts in a row
+    pub failures: u32,
+
+    /// Time left before a trial send, while the circuit is open
+    pub retry_in: Option<Duration>,
+}
+
+/// Mutable state of a breaker
+#[derive(Default)]
+struct Circuit {
+    /// Failed attempts in a row
+    failures: u32,
+
+    /// Time the circuit opened; `None` while closed
+    opened_at: Option<Instant>,
+
+    /// Time the trial send of a half-open circuit started
+    trial_at: Option<Instant>,
+}
+
+/// Circuit breaker of one profile
+pub struct CircuitBreaker {
+    /// Profile name, for the logs
+    profile: String,
+
+    /// Failed attempts in a row that open the circuit
+    threshold: u32,
+
+    /// Time an open circuit fails fast
+    cooldown: Duration,
+
+    /// Failures and opening time, shared by the concurrent sends
+    circuit: Mutex<Circuit>,
+}
+
+impl CircuitBreaker {
+    /// Creates a closed breaker
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name, for the logs
+    /// * `config` - Threshold and cooldown
+    pub fn new(profile: &str, config: &CircuitBreakerConfig) -> Self {
+        CircuitBreaker {
+            profile: profile.to_owned(),
+            threshold: config.threshold,
+            cooldown: config.cooldown,
+            circuit: Mutex::new(Circuit::default()),
+        }
+    }
+
+    /// Returns the state of the circuit
+    pub fn state(&self) -> CircuitSnapshot {
+        let circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        let (state, retry_in) = match circuit.opened_at {
+            None => (CircuitState::Closed, None),
+            Some(at) => match self.cooldown.checked_sub(at.elapsed()) {
+                Some(left) if !left.is_zero() => (CircuitState::Open, Some(left)),
+                _ => (CircuitState::HalfOpen, None),
+            },
+        };
+        CircuitSnapshot {
+            state,
+            failures: circuit.failures,
+            retry_in,
+        }
+    }
+
+    /// Returns `true` while sends fail fast
+    pub fn is_open(&self) -> bool {
+        self.state().state == CircuitState::Open
+    }
+
+    /// Asks whether a send may go through
+    ///
+    /// Only one trial send goes through a half-open circuit; a trial that did
+    /// not complete within the cooldown (e.g. a dropped request) is replaced.
+    ///
+    /// # Returns
+    /// * `Ok(())` - The send may go through
+    /// * `Err(Duration)` - The send must fail fast; time before the next trial
+    fn acquire(&self) -> Result<(), Duration> {
+        let mut circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        let Some(opened_at) = circuit.opened_at else {
+            return Ok(());
+        };
+        if let Some(left) = self.cooldown.checked_sub(opened_at.elapsed())
+            && !left.is_zero()
+        {
+            return Err(left);
+        }
+        if let Some(left) = circuit
+            .trial_at
+            .and_then(|at| self.cooldown.checked_sub(at.elapsed()))
+            && !left.is_zero()
+        {
+            return Err(left);
+        }
+        circuit.trial_at = Some(Instant::now());
+        Ok(())
+    }
+
+    /// Records the result of a send that went through
+    fn record(&self, result: &Result<(), TransportError>) {
+        let mut circuit = self.circuit.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Err(err) if !err.is_permanent() => {
+                circuit.failures += 1;
+                if circuit.trial_at.take().is_some() {
+                    warn!(
+                        "SMTP profile {}: trial send failed, circuit open again for {:?}",
+                        self.profile, self.cooldown
+                    );
+                    circuit.opened_at = Some(Instant::now());
+                } else if circuit.opened_at.is_none() && circuit.failures >= self.threshold {
+                    warn!(
+                        "SMTP profile {}: {} failed attempts in a row, circuit open for {:?}",
+                        self.profile, circuit.failures, self.cooldown
+                    );
+                    circuit.opened_at = Some(Instant::now());
+                }
+            }
+            _ => {
+                if circuit.opened_at.is_some() {
+                    info!(
+                        "SMTP profile {}: relay answered, circuit closed",
+                        self.profile
+                    );
+                }
+                *circuit = Circuit::default();
+            }
+        }
+    }
+}
+
+/// Transport guarded by the circuit breaker of its profile
+pub struct BreakerTransport {
+    /// Current transport of the profile
+    pub inner: Mailer,
+
+    /// Breaker shared by every send through the profile
+    pub breaker: Arc<CircuitBreaker>,
+}
+
+impl MailTransport for BreakerTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            if let Err(retry_in) = self.breaker.acquire() {
+                return Err(TransportError::CircuitOpen {
+                    profile: self.breaker.profile.clone(),
+                    retry_in,
+                });
+            }
+            let result = self.inner.send_raw(envelope, email).await;
+            self.breaker.record(&result);
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn breaker(threshold: u32) -> CircuitBreaker {
+        CircuitBreaker::new(
+            "default",
+            &CircuitBreakerConfig {
+                threshold,
+                cooldown: Duration::from_secs(30),
+                queue: false,
+            },
+        )
+    }
+
+    fn transient() -> Result<(), TransportError> {
+        Err(TransportError::Http {
+            status: Some(503),
+            message: "unavailable".into(),
+        })
+    }
+
+    fn permanent() -> Result<(), TransportError> {
+        Err(TransportError::Http {
+            status: Some(400),
+            message: "rejected".into(),
+        })
+    }
+
+    /// Moves the opening of the circuit back past the cooldown
+    fn end_cooldown(breaker: &CircuitBreaker) {
+        let mut circuit = breaker.circuit.lock().unwrap();
+        circuit.opened_at = circuit.opened_at.map(|at| at - breaker.cooldown);
+    }
+
+    #[test]
+    fn opens_after_threshold_failures_in_a_row() {
+        let breaker = breaker(3);
+        breaker.record(&transient());
+        breaker.record(&transient());
+        assert_eq!(breaker.state().state, CircuitState::Closed);
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record(&transient());
+        let snapshot = breaker.state();
+        assert_eq!(snapshot.state, CircuitState::Open);
+        assert_eq!(snapshot.failures, 3);
+        assert!(snapshot.retry_in.is_some());
+        assert!(breaker.is_open());
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn answers_reset_the_failures() {
+        let breaker = breaker(3);
+        breaker.record(&transient());
+        breaker.record(&transient());
+        breaker.record(&Ok(()));
+        breaker.record(&transient());
+        breaker.record(&transient());
+        assert_eq!(breaker.state().state, CircuitState::Closed);
+        assert_eq!(breaker.state().failures, 2);
+    }
+
+    #[test]
+    fn permanent_rejections_are_not_failures() {
+        let breaker = breaker(2);
+        breaker.record(&transient());
+        breaker.record(&permanent());
+        breaker.record(&transient());
+        assert_eq!(breaker.state().state, CircuitState::Closed);
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn half_open_lets_a_single_trial_through() {
+        let breaker = breaker(1);
+        breaker.record(&transient());
+        end_cooldown(&breaker);
+
+        assert_eq!(breaker.state().state, CircuitState::HalfOpen);
+        assert!(breaker.state().retry_in.is_none());
+        assert!(!breaker.is_open());
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn successful_trial_closes_the_circuit() {
+        let breaker = breaker(1);
+        breaker.record(&transient());
+        end_cooldown(&breaker);
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record(&Ok(()));
+        let snapshot = breaker.state();
+        assert_eq!(snapshot.state, CircuitState::Closed);
+        assert_eq!(snapshot.failures, 0);
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn failed_trial_opens_the_circuit_again() {
+        let breaker = breaker(1);
+        breaker.record(&transient());
+        end_cooldown(&breaker);
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record(&transient());
+        assert_eq!(breaker.state().state, CircuitState::Open);
+        assert!(breaker.acquire().is_err());
+    }
+
+    #[test]
+    fn stale_trial_is_replaced() {
+        let breaker = breaker(1);
+        breaker.record(&transient());
+        end_cooldown(&breaker);
+        assert!(breaker.acquire().is_ok());
+
+        {
+            let mut circuit = breaker.circuit.lock().unwrap();
+            circuit.trial_at = circuit.trial_at.map(|at| at - breaker.cooldown);
+        }
+        assert!(breaker.acquire().is_ok());
+    }
+}
@@ -0,0 +1,245 @@
+//! Request and response body codecs
+//!
+//! This module lets endpoints accept and produce either JSON or MessagePack
+//! bodies. MessagePack is selected with `Content-Type: application/msgpack` on
+//! requests and `Accept: application/msgpack` on responses; JSON stays the default.
+//! Requests may also be HTML form submissions (`application/x-www-form-urlencoded`),
+//! with nested fields in bracket notation (e.g. `mail[to][]`); responses to
+//! them are JSON. The [`Xml`] extractor decodes XML bodies for the XML
+//! compatibility endpoint.
+//! Fields listed in `RESPONSE_OMIT_FIELDS` are removed from the encoded responses.
+//! Successful `GET` responses carry an `ETag` and honour `If-None-Match`, so
+//! polling clients get `304 Not Modified` while the resource is unchanged.
+
+use actix_web::http::header::{ACCEPT, CONTENT_TYPE, ETAG, HeaderMap, IF_NONE_MATCH};
+use actix_web::http::{Method, StatusCode};
+use actix_web::{FromRequest, HttpRequest, HttpResponse, Result, dev, web};
+use futures_util::StreamExt;
+use futures_util::future::LocalBoxFuture;
+use serde::Serialize;
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::crypto::{hex, sha256};
+use crate::settings::{ResponsePolicy, json_error, json_fail};
+
+/// MIME type used for MessagePack bodies
+const MSGPACK_MIME: &str = "application/msgpack";
+
+/// MIME type of HTML form submissions
+const FORM_MIME: &str = "application/x-www-form-urlencoded";
+
+/// Maximum nesting of form field names (e.g. `mail[attachments][0][filename]`)
+const MAX_FORM_DEPTH: usize = 5;
+
+/// Maximum accepted request body size (same as the Actix JSON extractor default)
+const MAX_PAYLOAD_SIZE: usize = 2_097_152;
+
+/// Checks whether a header lists a MessagePack MIME type
+///
+/// Both `application/msgpack` and the legacy `application/x-msgpack` are accepted.
+fn header_is_msgpack(
+    headers: &HeaderMap,
+    name: impl actix_web::http::header::AsHeaderName,
+) -> bool {
+    headers
+        .get(name)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains(MSGPACK_MIME) || v.contains("application/x-msgpack"))
+        .unwrap_or(false)
+}
+
+/// Checks whether the request body is an HTML form submission
+fn is_form(headers: &HeaderMap) -> bool {
+    headers
+        .get(CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.starts_with(FORM_MIME))
+        .unwrap_or(false)
+}
+
+/// Request body extractor supporting JSON, MessagePack and form submissions
+///
+/// Works like `web::Json<T>` but decodes MessagePack or form fields when the
+/// request `Content-Type` says so. Form field names mirror the JSON structure:
+/// `mail[subject]=Hi`, `mail[to][]=a@example.com` (or repeated `mail[to]`),
+/// `mail[attachments][0][filename]=a.pdf`; booleans are `true` or `false`.
+pub struct Payload<T>(pub T);
+
+impl<T> Payload<T> {
+    /// Unwraps the decoded body
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// Reads the whole request body, enforcing the size limit while streaming
+async fn read_body(mut payload: dev::Payload) -> Result<web::BytesMut> {
+    let mut body = web::BytesMut::new();
+    while let Some(chunk) = payload.next().await {
+        let chunk = chunk?;
+        if body.len() + chunk.len() > MAX_PAYLOAD_SIZE {
+            return Err(json_fail("Payload too large"));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(body)
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Payload<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let is_msgpack = header_is_msgpack(req.headers(), CONTENT_TYPE);
+        let is_form = is_form(req.headers());
+        let payload = payload.take();
+
+        Box::pin(async move {
+            let body = read_body(payload).await?;
+            let value = if is_msgpack {
+                rmp_serde::from_slice(&body).map_err(json_fail)?
+            } else if is_form {
+                serde_qs::Config::new()
+                    .max_depth(MAX_FORM_DEPTH)
+                    .use_form_encoding(true)
+                    .deserialize_bytes(&body)
+                    .map_err(json_fail)?
+            } else {
+                serde_json::from_slice(&body).map_err(json_fail)?
+            };
+            Ok(Payload(value))
+        })
+    }
+}
+
+/// XML request body extractor
+///
+/// Decodes the body as XML whatever the request `Content-Type`, with the same
+/// size limit as [`Payload`]. Attributes map to fields renamed with an `@`
+/// prefix, the element text to a `$text` field.
+pub struct Xml<T>(pub T);
+
+impl<T> Xml<T> {
+    /// Unwraps the decoded body
+    pub fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+impl<T: DeserializeOwned + 'static> FromRequest for Xml<T> {
+    type Error = actix_web::Error;
+    type Future = LocalBoxFuture<'static, Result<Self, Self::Error>>;
+
+    fn from_request(_req: &HttpRequest, payload: &mut dev::Payload) -> Self::Future {
+        let payload = payload.take();
+
+        Box::pin(async move {
+            let body = read_body(payload).await?;
+            let value = quick_xml::de::from_reader(&body[..]).map_err(json_fail)?;
+            Ok(Xml(value))
+        })
+    }
+}
+
+/// Removes the given fields from every object of a JSON value
+fn omit_fields(value: &mut Value, fields: &[String]) {
+    match value {
+        Value::Object(map) => {
+            map.retain(|k, _| !fields.contains(k));
+            map.values_mut().for_each(|v| omit_fields(v, fields));
+        }
+        Value::Array(items) => items.iter_mut().for_each(|v| omit_fields(v, fields)),
+        _ => {}
+    }
+}
+
+/// Encodes a response body in the negotiated format
+fn encode<T: Serialize>(req: &HttpRequest, status: StatusCode, body: &T) -> Result<HttpResponse> {
+    let headers = req.headers();
+    let (content_type, bytes) =
+        if header_is_msgpack(headers, ACCEPT) || header_is_msgpack(headers, CONTENT_TYPE) {
+            (
+                MSGPACK_MIME,
+                rmp_serde::to_vec_named(body).map_err(json_error)?,
+            )
+        } else {
+            (
+                "application/json",
+                serde_json::to_vec(body).map_err(json_error)?,
+            )
+        };
+    if req.method() != Method::GET || status != StatusCode::OK {
+        return Ok(HttpResponse::build(status)
+            .content_type(content_type)
+            .body(bytes));
+    }
+
+    let etag = entity_tag(&bytes);
+    if none_match(headers, &etag) {
+        return Ok(HttpResponse::NotModified()
+            .insert_header((ETAG, etag))
+            .finish());
+    }
+    Ok(HttpResponse::build(status)
+        .content_type(content_type)
+        .insert_header((ETAG, etag))
+        .body(bytes))
+}
+
+/// Returns the strong entity tag of an encoded body (quoted SHA-256 prefix)
+///
+/// The tag depends on the encoded bytes, so the JSON and MessagePack
+/// representations of a resource get different tags.
+fn entity_tag(bytes: &[u8]) -> String {
+    format!("\"{}\"", hex(&sha256(bytes)[..16]))
+}
+
+/// Checks whether `If-None-Match` lists the entity tag (or is `*`)
+///
+/// Tags are compared with the weak comparison of RFC 9110 section 13.1.2, so a
+/// `W/` prefix added by a proxy still matches.
+fn none_match(headers: &HeaderMap, etag: &str) -> bool {
+    headers
+        .get_all(IF_NONE_MATCH)
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .map(|t| t.trim())
+        .any(|t| t == "*" || t.trim_start_matches("W/") == etag)
+}
+
+/// Builds a successful response encoded as requested by the client
+///
+/// MessagePack is used when the client accepts it or sent a MessagePack body,
+/// JSON otherwise. Fields of the response policy (`RESPONSE_OMIT_FIELDS`) are
+/// removed from the body.
+///
+/// # Arguments
+/// * `req` - The HTTP request, used for content negotiation
+/// * `body` - The response body to serialize
+///
+/// # Returns
+/// An HTTP 200 response with the serialized body and, for `GET` requests, its
+/// `ETag`; `304 Not Modified` when the tag matches `If-None-Match`
+pub fn respond<T: Serialize>(req: &HttpRequest, body: &T) -> Result<HttpResponse> {
+    respond_with(req, StatusCode::OK, body)
+}
+
+/// Builds a response with the given status code, encoded as requested by the client
+///
+/// Same content negotiation and field redaction as [`respond`].
+pub fn respond_with<T: Serialize>(
+    req: &HttpRequest,
+    status: StatusCode,
+    body: &T,
+) -> Result<HttpResponse> {
+    let policy = req.app_data::<web::Data<ResponsePolicy>>();
+    match policy.filter(|p| !p.omit_fields.is_empty()) {
+        Some(policy) => {
+            let mut value = serde_json::to_value(body).map_err(json_error)?;
+            omit_fields(&mut value, &policy.omit_fields);
+            encode(req, status, &value)
+        }
+        None => encode(req, status, body),
+    }
+}
@@ -0,0 +1,72 @@
+//! Daemon and service manager integration module
+//!
+//! This module lets a service manager run rustmail without wrapper scripts.
+//!
+//! On Unix, with `DAEMON=true` the process forks into the background before
+//! starting its runtime, and the launching process only exits once the service
+//! is listening (status 0) or has failed to start (status 1), as init scripts
+//! and `forking` service units expect. The listening socket is bound before
+//! switching to `DAEMON_USER`, so privileged ports can be used without running
+//! as root.
+//!
+//! On Windows, `rustmail service` runs under the service control manager: the
+//! service reports itself running once listening, and a stop request drains
+//! the server and the queue like `SIGTERM` does on Unix.
+//!
+//! On both, the PID is recorded in `DAEMON_PID_FILE` for the service manager.
+
+use std::fs;
+use std::io;
+use std::process;
+
+use log::warn;
+
+use crate::settings::DaemonConfig;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub use unix::{ServiceLink, detach, drop_privileges};
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub use windows::{ServiceLink, detach, drop_privileges, prepare_service, run_service};
+
+/// Writes the PID of the process to `DAEMON_PID_FILE`
+///
+/// Called once running as `DAEMON_USER`, so the account that creates the file
+/// is also able to remove it on shutdown. An existing file is overwritten, so
+/// during a zero-downtime upgrade the file names the newest process.
+///
+/// # Returns
+/// * `Ok(())` - The file is written, or none is configured
+/// * `Err(io::Error)` - The file cannot be written
+pub fn write_pid_file(config: &DaemonConfig) -> io::Result<()> {
+    let Some(path) = &config.pid_file else {
+        return Ok(());
+    };
+    fs::write(path, format!("{}\n", process::id())).map_err(|err| {
+        io::Error::new(
+            err.kind(),
+            format!(
+                "Cannot write DAEMON_PID_FILE {} (its directory must be writable by the service account): {}",
+                path, err
+            ),
+        )
+    })
+}
+
+/// Removes `DAEMON_PID_FILE` on shutdown
+///
+/// The file is left alone when it names another process, e.g. the one that
+/// replaced this process during an upgrade.
+pub fn remove_pid_file(config: &DaemonConfig) {
+    let Some(path) = &config.pid_file else {
+        return;
+    };
+    let owned = fs::read_to_string(path).is_ok_and(|pid| pid.trim() == process::id().to_string());
+    if owned && let Err(err) = fs::remove_file(path) {
+        warn!("Cannot remove DAEMON_PID_FILE {}: {}", path, err);
+    }
+}
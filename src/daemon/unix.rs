@@ -0,0 +1,205 @@
+//! Unix daemon support
+//!
+//! Forks into the background for init scripts and `forking` service units, and
+//! switches to an unprivileged account once the listening socket is bound.
+
+use std::ffi::CString;
+use std::fs;
+use std::io::{self, Read, Write};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+use std::process;
+
+use actix_web::dev::ServerHandle;
+use log::{info, warn};
+
+use crate::settings::DaemonConfig;
+
+/// Background process that still has to report its startup
+///
+/// Dropping it without calling [`ServiceLink::ready`], e.g. when startup fails,
+/// makes the launching process exit with status 1.
+pub struct ServiceLink {
+    /// Write end of the pipe the launching process is waiting on
+    notify: OwnedFd,
+}
+
+impl ServiceLink {
+    /// Reports a successful startup to the launching process
+    ///
+    /// The standard input is then read from `/dev/null`, and the standard
+    /// outputs are sent there when they are still the terminal of the launching
+    /// shell; redirected logs keep going to their file. The server handle is
+    /// not needed: init systems stop the service with `SIGTERM`, which the
+    /// server handles itself.
+    pub fn ready(self, _server: &ServerHandle) {
+        let mut notify = fs::File::from(self.notify);
+        if let Err(err) = notify.write_all(&[1]) {
+            warn!("Cannot report startup to the launching process: {}", err);
+        }
+        match fs::OpenOptions::new()
+            .read(true)
+            .write(true)
+            .open("/dev/null")
+        {
+            Ok(null) => {
+                for fd in 0..=2 {
+                    // SAFETY: duplicates an open descriptor over a standard one
+                    unsafe {
+                        if fd == 0 || libc::isatty(fd) == 1 {
+                            libc::dup2(null.as_raw_fd(), fd);
+                        }
+                    }
+                }
+            }
+            Err(err) => warn!("Cannot open /dev/null: {}", err),
+        }
+    }
+}
+
+/// Forks the process into the background
+///
+/// Must run before any thread is started. The launching process waits until
+/// the background process reports its startup, then exits; the background
+/// process runs in a new session without a controlling terminal and keeps the
+/// working directory, so relative paths in the configuration stay valid.
+///
+/// # Returns
+/// * `Ok(ServiceLink)` - In the background process, to report the startup with
+/// * `Err(io::Error)` - The process cannot be forked
+pub fn detach() -> io::Result<ServiceLink> {
+    let mut fds = [0; 2];
+    // SAFETY: pipe fills the array with two new descriptors owned from here on
+    let (wait, notify) = unsafe {
+        if libc::pipe(fds.as_mut_ptr()) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        (OwnedFd::from_raw_fd(fds[0]), OwnedFd::from_raw_fd(fds[1]))
+    };
+
+    // SAFETY: the process is still single-threaded
+    match unsafe { libc::fork() } {
+        -1 => return Err(io::Error::last_os_error()),
+        0 => drop(wait),
+        _ => {
+            drop(notify);
+            // Nothing to read means the background process exited before being ready
+            let mut status = [0];
+            let started = fs::File::from(wait).read(&mut status).is_ok_and(|n| n == 1);
+            process::exit(if started { 0 } else { 1 });
+        }
+    }
+
+    // SAFETY: plain system calls in the single-threaded child
+    unsafe {
+        if libc::setsid() == -1 {
+            return Err(io::Error::last_os_error());
+        }
+        // The session leader exits so the daemon can never acquire a terminal again
+        match libc::fork() {
+            -1 => return Err(io::Error::last_os_error()),
+            0 => {}
+            _ => libc::_exit(0),
+        }
+    }
+    Ok(ServiceLink { notify })
+}
+
+/// Switches the process to `DAEMON_USER` and `DAEMON_GROUP`
+///
+/// Called once the listening socket is bound. The supplementary groups are set
+/// to those of the user. Starting as the configured account already is accepted.
+///
+/// # Returns
+/// * `Ok(())` - The process runs as the configured account, or none is configured
+/// * `Err(io::Error)` - Unknown account or missing privileges
+pub fn drop_privileges(config: &DaemonConfig) -> io::Result<()> {
+    let Some(user) = &config.user else {
+        if config.group.is_some() {
+            return Err(io::Error::other("DAEMON_GROUP requires DAEMON_USER"));
+        }
+        return Ok(());
+    };
+    let (uid, user_gid) = lookup_user(user)?;
+    let gid = match &config.group {
+        Some(group) => lookup_group(group)?,
+        None => user_gid,
+    };
+
+    // SAFETY: plain system calls, the name outlives the initgroups call
+    unsafe {
+        if libc::geteuid() == uid && libc::getegid() == gid {
+            return Ok(());
+        }
+        if libc::geteuid() != 0 {
+            return Err(io::Error::other(format!(
+                "DAEMON_USER {} requires starting as root",
+                user
+            )));
+        }
+        let name = CString::new(user.as_str())?;
+        if libc::initgroups(name.as_ptr(), gid as _) != 0
+            || libc::setgid(gid) != 0
+            || libc::setuid(uid) != 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    info!("Running as user {} (uid {}, gid {})", user, uid, gid);
+    Ok(())
+}
+
+/// Resolves a user name to its user ID and primary group ID
+fn lookup_user(name: &str) -> io::Result<(libc::uid_t, libc::gid_t)> {
+    let cname = CString::new(name)?;
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        // SAFETY: zeroed passwd is a valid output value, buf outlives the call
+        let mut pwd: libc::passwd = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let rc = unsafe {
+            libc::getpwnam_r(
+                cname.as_ptr(),
+                &mut pwd,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if found.is_null() => {
+                return Err(io::Error::other(format!("Unknown DAEMON_USER {}", name)));
+            }
+            0 => return Ok((pwd.pw_uid, pwd.pw_gid)),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
+
+/// Resolves a group name to its group ID
+fn lookup_group(name: &str) -> io::Result<libc::gid_t> {
+    let cname = CString::new(name)?;
+    let mut buf: Vec<libc::c_char> = vec![0; 1024];
+    loop {
+        // SAFETY: zeroed group is a valid output value, buf outlives the call
+        let mut grp: libc::group = unsafe { std::mem::zeroed() };
+        let mut found = std::ptr::null_mut();
+        let rc = unsafe {
+            libc::getgrnam_r(
+                cname.as_ptr(),
+                &mut grp,
+                buf.as_mut_ptr(),
+                buf.len(),
+                &mut found,
+            )
+        };
+        match rc {
+            libc::ERANGE => buf.resize(buf.len() * 2, 0),
+            0 if found.is_null() => {
+                return Err(io::Error::other(format!("Unknown DAEMON_GROUP {}", name)));
+            }
+            0 => return Ok(grp.gr_gid),
+            err => return Err(io::Error::from_raw_os_error(err)),
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Windows service support
+//!
+//! `rustmail service` connects to the service control manager, which then
+//! starts the service body on a thread of its own. Stop and shutdown requests
+//! are turned into a graceful server stop, reported as pending until the
+//! server and the queue have drained.
+
+use std::ffi::OsString;
+use std::io;
+use std::sync::{Arc, Mutex, OnceLock};
+use std::time::Duration;
+
+use actix_web::dev::ServerHandle;
+use actix_web::rt::System;
+use log::{error, warn};
+use windows_service::service::{
+    ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus, ServiceType,
+};
+use windows_service::service_control_handler::{
+    self, ServiceControlHandlerResult, ServiceStatusHandle,
+};
+use windows_service::{define_windows_service, service_dispatcher};
+
+use crate::settings::DaemonConfig;
+
+/// Name the service registers its control handler with; the control manager
+/// ignores it for services running in their own process
+const SERVICE_NAME: &str = "rustmail";
+
+/// Time the control manager waits for the service to report it is running
+const START_WAIT_HINT: Duration = Duration::from_secs(60);
+
+/// Time the control manager waits for the service to stop, on top of draining
+const STOP_MARGIN: Duration = Duration::from_secs(10);
+
+/// Service body, run once the control manager starts the service
+type Serve = Box<dyn FnOnce(ServiceLink) -> io::Result<()> + Send>;
+
+/// Service body waiting for the control manager, with its stop wait hint
+static PENDING: Mutex<Option<(Serve, Duration)>> = Mutex::new(None);
+
+/// Outcome of the service body, returned once the dispatcher is done
+static OUTCOME: Mutex<Option<io::Result<()>>> = Mutex::new(None);
+
+define_windows_service!(ffi_service_main, service_main);
+
+/// State shared by the service body and the control handler
+struct Control {
+    /// Handle the service status is reported with
+    status: OnceLock<ServiceStatusHandle>,
+
+    /// Server to stop, and the system running it, once the service is running
+    server: Mutex<Option<(ServerHandle, System)>>,
+
+    /// Time the control manager waits for the service to stop
+    stop_wait_hint: Duration,
+}
+
+impl Control {
+    /// Reports the service state to the control manager
+    fn report(&self, state: ServiceState, exit_code: ServiceExitCode, wait_hint: Duration) {
+        let Some(status) = self.status.get() else {
+            return;
+        };
+        let controls_accepted = match state {
+            ServiceState::Running => ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            _ => ServiceControlAccept::empty(),
+        };
+        let result = status.set_service_status(ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted,
+            exit_code,
+            checkpoint: 0,
+            wait_hint,
+            process_id: None,
+        });
+        if let Err(err) = result {
+            warn!("Cannot report the service status: {}", err);
+        }
+    }
+
+    /// Stops the server gracefully when it is running
+    fn stop(&self) {
+        let server = self.server.lock().unwrap_or_else(|e| e.into_inner()).take();
+        if let Some((server, system)) = server {
+            self.report(
+                ServiceState::StopPending,
+                ServiceExitCode::NO_ERROR,
+                self.stop_wait_hint,
+            );
+            // The service body reports the stop once the server and the queue have drained
+            system
+                .arbiter()
+                .spawn(async move { server.stop(true).await });
+        }
+    }
+}
+
+/// Service started by the control manager that still has to report its startup
+///
+/// Dropping it without calling [`ServiceLink::ready`], e.g. when startup fails,
+/// leaves the service starting until the service body returns.
+pub struct ServiceLink {
+    control: Arc<Control>,
+}
+
+impl ServiceLink {
+    /// Reports the service as running and accepting stop requests
+    ///
+    /// Must be called from the system running the server.
+    pub fn ready(self, server: &ServerHandle) {
+        *self
+            .control
+            .server
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some((server.clone(), System::current()));
+        self.control.report(
+            ServiceState::Running,
+            ServiceExitCode::NO_ERROR,
+            Duration::ZERO,
+        );
+    }
+}
+
+/// Tells whether the process was started as a service
+///
+/// Services start in the system directory: in that case the working directory
+/// is changed to the directory of the executable before the configuration is
+/// loaded, so relative paths resolve next to it.
+///
+/// # Returns
+/// * `Ok(true)` - Started as `rustmail service`, the working directory is changed
+/// * `Ok(false)` - Started with other arguments
+/// * `Err(io::Error)` - The working directory cannot be changed
+pub fn prepare_service() -> io::Result<bool> {
+    let mut args = std::env::args_os().skip(1);
+    if args.next().is_none_or(|arg| arg != "service") || args.next().is_some() {
+        return Ok(false);
+    }
+    let exe = std::env::current_exe()?;
+    if let Some(dir) = exe.parent() {
+        std::env::set_current_dir(dir)?;
+    }
+    Ok(true)
+}
+
+/// Runs the service body under the service control manager
+///
+/// Blocks until the service has stopped.
+///
+/// # Arguments
+/// * `shutdown_timeout` - Time the server and the queue each take at most to drain
+/// * `serve` - Service body, reporting the startup with the given link
+///
+/// # Returns
+/// * `Ok(())` - The service stopped cleanly
+/// * `Err(io::Error)` - Not started by the control manager, or the service body failed
+pub fn run_service<F>(shutdown_timeout: Duration, serve: F) -> io::Result<()>
+where
+    F: FnOnce(ServiceLink) -> io::Result<()> + Send + 'static,
+{
+    // The server, the outbox relayer, the conversion worker and the queue worker
+    // drain one after the other
+    let stop_wait_hint = shutdown_timeout
+        .saturating_mul(4)
+        .saturating_add(STOP_MARGIN)
+        .min(Duration::from_millis(u32::MAX.into()));
+    *PENDING.lock().unwrap_or_else(|e| e.into_inner()) = Some((Box::new(serve), stop_wait_hint));
+
+    service_dispatcher::start(SERVICE_NAME, ffi_service_main).map_err(|err| {
+        io::Error::other(format!(
+            "Cannot connect to the service control manager (install rustmail as a service first): {}",
+            err
+        ))
+    })?;
+    OUTCOME
+        .lock()
+        .unwrap_or_else(|e| e.into_inner())
+        .take()
+        .unwrap_or_else(|| Err(io::Error::other("The service was never started")))
+}
+
+/// Entry point called by the control manager on a thread of its own
+fn service_main(_arguments: Vec<OsString>) {
+    let Some((serve, stop_wait_hint)) = PENDING.lock().unwrap_or_else(|e| e.into_inner()).take()
+    else {
+        return;
+    };
+    let control = Arc::new(Control {
+        status: OnceLock::new(),
+        server: Mutex::new(None),
+        stop_wait_hint,
+    });
+
+    let handler = Arc::clone(&control);
+    let status = service_control_handler::register(SERVICE_NAME, move |request| match request {
+        ServiceControl::Stop | ServiceControl::Shutdown => {
+            handler.stop();
+            ServiceControlHandlerResult::NoError
+        }
+        ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+        _ => ServiceControlHandlerResult::NotImplemented,
+    });
+    let status = match status {
+        Ok(status) => status,
+        Err(err) => {
+            *OUTCOME.lock().unwrap_or_else(|e| e.into_inner()) = Some(Err(io::Error::other(
+                format!("Cannot register the service control handler: {}", err),
+            )));
+            return;
+        }
+    };
+    let _ = control.status.set(status);
+    control.report(
+        ServiceState::StartPending,
+        ServiceExitCode::NO_ERROR,
+        START_WAIT_HINT,
+    );
+
+    let result = serve(ServiceLink {
+        control: Arc::clone(&control),
+    });
+    let exit_code = match &result {
+        Ok(()) => ServiceExitCode::NO_ERROR,
+        Err(err) => {
+            error!("Service stopped: {}", err);
+            ServiceExitCode::ServiceSpecific(1)
+        }
+    };
+    control.report(ServiceState::Stopped, exit_code, Duration::ZERO);
+    *OUTCOME.lock().unwrap_or_else(|e| e.into_inner()) = Some(result);
+}
+
+/// Forks the process into the background
+///
+/// # Returns
+/// Always an error: on Windows rustmail is run as a service instead
+pub fn detach() -> io::Result<ServiceLink> {
+    Err(io::Error::other(
+        "DAEMON is only supported on Unix, install rustmail as a Windows service instead",
+    ))
+}
+
+/// Switches the process to `DAEMON_USER` and `DAEMON_GROUP`
+///
+/// # Returns
+/// * `Ok(())` - No account is configured
+/// * `Err(io::Error)` - An account is configured: set the service account instead
+pub fn drop_privileges(config: &DaemonConfig) -> io::Result<()> {
+    if config.user.is_some() || config.group.is_some() {
+        return Err(io::Error::other(
+            "DAEMON_USER and DAEMON_GROUP are only supported on Unix, set the account of the Windows service instead",
+        ));
+    }
+    Ok(())
+}
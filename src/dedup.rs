@@ -0,0 +1,239 @@
+//! Duplicate-content send guard
+//!
+//! This module remembers which recipient received which content (subject and
+//! body) and detects identical messages sent again within a configurable window,
+//! protecting recipients from upstream retry storms.
+
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
+use std::hash::{Hash, Hasher};
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::settings::DuplicateGuardConfig;
+
+/// In-memory registry of recently sent messages
+///
+/// Shared across all workers; entries older than the configured window are
+/// pruned on every claim.
+pub struct DuplicateGuard {
+    /// Guard configuration (mode and window)
+    pub config: DuplicateGuardConfig,
+
+    /// Content fingerprint to time of the last successful send
+    sent: Mutex<HashMap<u64, Instant>>,
+}
+
+impl DuplicateGuard {
+    /// Creates an empty guard with the given configuration
+    pub fn new(config: DuplicateGuardConfig) -> Self {
+        DuplicateGuard {
+            config,
+            sent: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Computes the fingerprint of a message for one recipient
+    fn fingerprint(recipient: &str, subject: &str, body: &str) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        recipient.trim().to_lowercase().hash(&mut hasher);
+        subject.hash(&mut hasher);
+        body.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    /// Claims the recipients of a message, returning those that already received its content
+    ///
+    /// Checking and recording happen under one lock: the content is recorded
+    /// right away for the recipients that did not receive it yet, so an
+    /// identical request arriving while this one is sent sees them as
+    /// duplicates instead of sending again.
+    ///
+    /// # Arguments
+    /// * `recipients` - Recipient addresses of the message
+    /// * `subject` - Message subject
+    /// * `body` - Message body
+    ///
+    /// # Returns
+    /// The recipients that already received this content within the window, and
+    /// the claim to complete with the recipients the message was sent to
+    pub fn claim(
+        &self,
+        recipients: &[String],
+        subject: &str,
+        body: &str,
+    ) -> (Vec<String>, DuplicateClaim<'_>) {
+        let fingerprints: Vec<(String, u64)> = recipients
+            .iter()
+            .map(|r| (r.clone(), Self::fingerprint(r, subject, body)))
+            .collect();
+
+        let mut sent = self.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let window = self.config.window;
+        sent.retain(|_, at| at.elapsed() < window);
+
+        let now = Instant::now();
+        let mut duplicates = Vec::new();
+        let mut pending = HashSet::new();
+        for (recipient, fingerprint) in &fingerprints {
+            if sent.contains_key(fingerprint) {
+                duplicates.push(recipient.clone());
+            } else {
+                sent.insert(*fingerprint, now);
+                pending.insert(*fingerprint);
+            }
+        }
+
+        let claim = DuplicateClaim {
+            guard: self,
+            fingerprints,
+            pending,
+        };
+        (duplicates, claim)
+    }
+}
+
+/// Recipients of a message being sent, recorded by the guard
+///
+/// Dropping the claim without completing it, e.g. when sending fails, forgets
+/// the recipients again, so a retry of the request is not taken for a duplicate.
+pub struct DuplicateClaim<'a> {
+    /// Guard holding the claim
+    guard: &'a DuplicateGuard,
+
+    /// Fingerprint of the content for each recipient of the message
+    fingerprints: Vec<(String, u64)>,
+
+    /// Fingerprints recorded by the claim and not sent yet
+    pending: HashSet<u64>,
+}
+
+impl DuplicateClaim<'_> {
+    /// Records the send of the content to the given recipients
+    ///
+    /// The other claimed recipients (failed, skipped or discarded) are forgotten.
+    ///
+    /// # Arguments
+    /// * `recipients` - Recipient addresses the message was sent to
+    pub fn complete(mut self, recipients: &[String]) {
+        let mut sent = self.guard.sent.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        for (recipient, fingerprint) in &self.fingerprints {
+            if recipients.contains(recipient) {
+                sent.insert(*fingerprint, now);
+                self.pending.remove(fingerprint);
+            }
+        }
+    }
+}
+
+impl Drop for DuplicateClaim<'_> {
+    fn drop(&mut self) {
+        if self.pending.is_empty() {
+            return;
+        }
+        let mut sent = self.guard.sent.lock().unwrap_or_else(|e| e.into_inner());
+        for fingerprint in self.pending.drain() {
+            sent.remove(&fingerprint);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    use crate::settings::DuplicateGuardMode;
+
+    fn guard(window: Duration) -> DuplicateGuard {
+        DuplicateGuard::new(DuplicateGuardConfig {
+            mode: DuplicateGuardMode::Skip,
+            window,
+        })
+    }
+
+    fn recipients(addresses: &[&str]) -> Vec<String> {
+        addresses.iter().map(|a| a.to_string()).collect()
+    }
+
+    #[test]
+    fn completed_sends_are_duplicates() {
+        let guard = guard(Duration::from_secs(60));
+        let to = recipients(&["a@example.com", "b@example.com"]);
+        let (duplicates, claim) = guard.claim(&to, "Invoice", "body");
+        assert!(duplicates.is_empty());
+        claim.complete(&to);
+
+        let (duplicates, _claim) = guard.claim(&to, "Invoice", "body");
+        assert_eq!(duplicates, to);
+    }
+
+    #[test]
+    fn pending_claims_are_duplicates() {
+        let guard = guard(Duration::from_secs(60));
+        let to = recipients(&["a@example.com"]);
+        let (_, _first) = guard.claim(&to, "Invoice", "body");
+
+        let (duplicates, _second) = guard.claim(&to, "Invoice", "body");
+        assert_eq!(duplicates, to);
+    }
+
+    #[test]
+    fn recipients_are_compared_case_insensitively() {
+        let guard = guard(Duration::from_secs(60));
+        let (_, claim) = guard.claim(&recipients(&["a@example.com"]), "Invoice", "body");
+        claim.complete(&recipients(&["a@example.com"]));
+
+        let (duplicates, _claim) =
+            guard.claim(&recipients(&[" A@Example.com "]), "Invoice", "body");
+        assert_eq!(duplicates.len(), 1);
+    }
+
+    #[test]
+    fn other_content_is_not_a_duplicate() {
+        let guard = guard(Duration::from_secs(60));
+        let to = recipients(&["a@example.com"]);
+        let (_, claim) = guard.claim(&to, "Invoice", "body A");
+        claim.complete(&to);
+
+        assert!(guard.claim(&to, "Invoice", "body B").0.is_empty());
+        assert!(guard.claim(&to, "Receipt", "body A").0.is_empty());
+        assert!(
+            guard
+                .claim(&recipients(&["c@example.com"]), "Invoice", "body A")
+                .0
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn dropped_claims_are_forgotten() {
+        let guard = guard(Duration::from_secs(60));
+        let to = recipients(&["a@example.com"]);
+        drop(guard.claim(&to, "Invoice", "body"));
+
+        assert!(guard.claim(&to, "Invoice", "body").0.is_empty());
+    }
+
+    #[test]
+    fn completion_keeps_only_the_sent_recipients() {
+        let guard = guard(Duration::from_secs(60));
+        let to = recipients(&["a@example.com", "b@example.com"]);
+        let (_, claim) = guard.claim(&to, "Invoice", "body");
+        claim.complete(&recipients(&["a@example.com"]));
+
+        let (duplicates, _claim) = guard.claim(&to, "Invoice", "body");
+        assert_eq!(duplicates, recipients(&["a@example.com"]));
+    }
+
+    #[test]
+    fn sends_expire_after_the_window() {
+        let guard = guard(Duration::ZERO);
+        let to = recipients(&["a@example.com"]);
+        let (_, claim) = guard.claim(&to, "Invoice", "body");
+        claim.complete(&to);
+
+        assert!(guard.claim(&to, "Invoice", "body").0.is_empty());
+    }
+}
@@ -0,0 +1,111 @@
+//! DKIM signing module
+//!
+//! This module adds a `DKIM-Signature` header to outgoing messages with the
+//! configured domain, selector and private key, so that receivers can verify
+//! the mail even when it is sent through relays that do not sign it.
+
+use std::fs;
+
+use lettre::Message;
+use lettre::message::dkim::{
+    DkimCanonicalization, DkimCanonicalizationType, DkimConfig as SigningConfig,
+    DkimSigningAlgorithm, DkimSigningKey,
+};
+use lettre::message::header::HeaderName;
+
+use crate::settings::DkimConfig;
+
+/// Headers covered by the signature
+///
+/// `Message-ID` is left out: relays add it when missing, which would break the
+/// signature of messages built without one. The unsubscribe headers are covered
+/// as one-click unsubscribe (RFC 8058) requires.
+const SIGNED_HEADERS: [&str; 9] = [
+    "From",
+    "To",
+    "Subject",
+    "Date",
+    "Reply-To",
+    "MIME-Version",
+    "Content-Type",
+    "List-Unsubscribe",
+    "List-Unsubscribe-Post",
+];
+
+/// Signs outgoing messages with the configured DKIM key
+pub struct DkimSigner {
+    /// Signing domain (`d=` tag)
+    domain: String,
+
+    /// Signing parameters and parsed private key
+    config: SigningConfig,
+}
+
+impl DkimSigner {
+    /// Loads the signer from the DKIM configuration
+    ///
+    /// # Arguments
+    /// * `config` - DKIM configuration (domain, selector, key, algorithm)
+    ///
+    /// # Returns
+    /// * `Ok(Some(DkimSigner))` - Signing is configured and the key is valid
+    /// * `Ok(None)` - Signing is disabled (no domain configured)
+    /// * `Err(String)` - Incomplete configuration, unreadable or invalid key
+    pub fn from_config(config: &DkimConfig) -> Result<Option<Self>, String> {
+        let Some(domain) = &config.domain else {
+            return Ok(None);
+        };
+        let selector = config
+            .selector
+            .clone()
+            .ok_or_else(|| "DKIM_SELECTOR is required with DKIM_DOMAIN".to_owned())?;
+
+        let private_key = match (&config.private_key, &config.private_key_file) {
+            (Some(key), _) => key.clone(),
+            (None, Some(path)) => fs::read_to_string(path)
+                .map_err(|e| format!("Cannot read DKIM key {}: {}", path, e))?,
+            (None, None) => {
+                return Err(
+                    "DKIM_PRIVATE_KEY or DKIM_PRIVATE_KEY_FILE is required with DKIM_DOMAIN"
+                        .to_owned(),
+                );
+            }
+        };
+
+        let algorithm = match config.algorithm.as_str() {
+            "rsa" => DkimSigningAlgorithm::Rsa,
+            "ed25519" => DkimSigningAlgorithm::Ed25519,
+            other => return Err(format!("Unsupported DKIM algorithm {}", other)),
+        };
+        let key = DkimSigningKey::new(private_key.trim(), algorithm)
+            .map_err(|e| format!("Invalid DKIM private key: {}", e))?;
+
+        // Relaxed canonicalization survives the whitespace changes made by relays
+        let canonicalization = DkimCanonicalization {
+            header: DkimCanonicalizationType::Relaxed,
+            body: DkimCanonicalizationType::Relaxed,
+        };
+        let headers = SIGNED_HEADERS
+            .iter()
+            .map(|h| HeaderName::new_from_ascii_str(h))
+            .collect();
+
+        Ok(Some(DkimSigner {
+            domain: domain.clone(),
+            config: SigningConfig::new(selector, domain.clone(), key, headers, canonicalization),
+        }))
+    }
+
+    /// Returns the signing domain (`d=` tag)
+    pub fn domain(&self) -> &str {
+        &self.domain
+    }
+
+    /// Adds the `DKIM-Signature` header to a message
+    ///
+    /// Must be called once the message is complete: any later change to the
+    /// signed headers or to the body invalidates the signature.
+    pub fn sign(&self, message: &mut Message) {
+        message.sign(&self.config);
+    }
+}
@@ -0,0 +1,165 @@
+//! Fault injection module
+//!
+//! This module simulates SMTP failures so that retry, alerting and client error
+//! handling can be validated end to end in staging environments. It is disabled
+//! unless explicitly enabled by the operator through the environment, and even
+//! then only applies to the sends of admin keys that request it with a `fault`
+//! field, so that regular clients of a shared instance are never affected.
+
+use std::time::Duration;
+
+use actix_web::rt::time::sleep;
+use actix_web::{HttpRequest, Result};
+use log::warn;
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::auth::is_admin;
+use crate::send::dto::SendMailReq;
+use crate::settings::{FaultConfig, json_fail, json_forbidden};
+
+/// Simulated SMTP failures requested for one send
+///
+/// Reserved to admin keys when `FAULT_INJECTION_ENABLED` is set. Absent values
+/// take the `FAULT_*` defaults.
+#[derive(Clone, Default, Serialize, Deserialize, ToSchema)]
+pub struct FaultRequest {
+    /// Percentage (0-100) of sends dropped as if the connection failed
+    pub drop_percent: Option<u32>,
+
+    /// Percentage (0-100) of sends rejected with a transient 451 error
+    pub transient_percent: Option<u32>,
+
+    /// Latency added to the send in milliseconds
+    pub latency_ms: Option<u64>,
+}
+
+/// Checks that the caller may request faults
+///
+/// # Returns
+/// * `Ok(())` - The request asks for no fault, or is allowed to
+/// * `Err(actix_web::Error)` - JSON fail response when fault injection is disabled;
+///   HTTP 403 for non-admin callers
+pub fn check_fault(req: &HttpRequest, payload: &SendMailReq, config: &FaultConfig) -> Result<()> {
+    if payload.fault.is_none() {
+        return Ok(());
+    }
+    if !config.enabled {
+        return Err(json_fail(
+            "Fault injection is disabled (FAULT_INJECTION_ENABLED)",
+        ));
+    }
+    if !is_admin(req) {
+        return Err(json_forbidden("Fault injection requires an admin API key"));
+    }
+    Ok(())
+}
+
+/// Applies the faults requested by a send to its SMTP attempt
+///
+/// Latency is added first, then the send may be dropped or rejected with a
+/// transient (4xx) SMTP error according to the percentages of the request,
+/// which default to the configured ones.
+///
+/// # Arguments
+/// * `config` - Fault injection configuration
+/// * `fault` - Faults requested by the send, checked with [`check_fault`]
+///
+/// # Returns
+/// * `Ok(())` - The send may proceed
+/// * `Err(String)` - The simulated failure to report instead of sending
+pub async fn inject_fault(
+    config: &FaultConfig,
+    fault: Option<&FaultRequest>,
+) -> Result<(), String> {
+    let Some(fault) = fault.filter(|_| config.enabled) else {
+        return Ok(());
+    };
+
+    let latency_ms = fault.latency_ms.unwrap_or(config.latency_ms);
+    if latency_ms > 0 {
+        sleep(Duration::from_millis(latency_ms)).await;
+    }
+
+    // Percentages above 100 are clamped
+    let drop_percent = fault.drop_percent.unwrap_or(config.drop_percent).min(100);
+    if rand::random_ratio(drop_percent, 100) {
+        warn!("Fault injection: dropping SMTP send");
+        return Err("Fault injection: connection to SMTP server dropped".to_owned());
+    }
+
+    let transient_percent = fault
+        .transient_percent
+        .unwrap_or(config.transient_percent)
+        .min(100);
+    if rand::random_ratio(transient_percent, 100) {
+        warn!("Fault injection: forcing transient SMTP failure");
+        return Err("Fault injection: 451 4.3.0 Temporary failure, try again later".to_owned());
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn config(enabled: bool) -> FaultConfig {
+        FaultConfig {
+            enabled,
+            drop_percent: 100,
+            transient_percent: 0,
+            latency_ms: 0,
+        }
+    }
+
+    fn payload(fault: Option<FaultRequest>) -> SendMailReq {
+        let mut payload: SendMailReq = serde_json::from_value(serde_json::json!({
+            "mail": {
+                "from": "sender@example.com",
+                "to": ["rcpt@example.com"],
+                "subject": "Hello",
+                "text": "Hello",
+                "encoding": "plain"
+            }
+        }))
+        .unwrap();
+        payload.fault = fault;
+        payload
+    }
+
+    #[test]
+    fn only_admin_keys_request_faults() {
+        let req = TestRequest::default().to_http_request();
+        assert!(check_fault(&req, &payload(None), &config(true)).is_ok());
+
+        let faulty = payload(Some(FaultRequest::default()));
+        let err = check_fault(&req, &faulty, &config(true)).unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::FORBIDDEN
+        );
+        let err = check_fault(&req, &faulty, &config(false)).unwrap_err();
+        assert_eq!(
+            err.as_response_error().status_code(),
+            actix_web::http::StatusCode::BAD_REQUEST
+        );
+    }
+
+    #[actix_web::test]
+    async fn faults_apply_to_requesting_sends_only() {
+        assert!(inject_fault(&config(true), None).await.is_ok());
+
+        let fault = FaultRequest::default();
+        assert!(inject_fault(&config(true), Some(&fault)).await.is_err());
+        assert!(inject_fault(&config(false), Some(&fault)).await.is_ok());
+
+        let fault = FaultRequest {
+            drop_percent: Some(0),
+            ..Default::default()
+        };
+        assert!(inject_fault(&config(true), Some(&fault)).await.is_ok());
+    }
+}
@@ -3,8 +3,83 @@
 //! This library provides the core functionality for the Rustmail email service.
 //! It includes modules for sending emails and managing application settings.
 
+/// API key authentication module
+pub mod auth;
+
+/// Backup and restore module
+pub mod backup;
+
+/// Deliverability canary module
+pub mod canary;
+
+/// SMTP circuit breaker module
+pub mod circuit_breaker;
+
+/// Request and response body codecs (JSON, MessagePack, forms and XML)
+pub mod codec;
+
+/// Hashing and signing helpers (SHA-256, HMAC, hexadecimal)
+pub mod crypto;
+
+/// Unix daemon, Windows service and service manager integration module
+pub mod daemon;
+
+/// Duplicate-content send guard
+pub mod dedup;
+
+/// DKIM signing module
+pub mod dkim;
+
+/// Fault injection module for resilience testing
+pub mod fault;
+
+/// Pre-send link validation module
+pub mod link_check;
+
+/// HTTP listening socket module
+pub mod listener;
+
+/// Prometheus metrics module
+pub mod metrics;
+
+/// OAuth2 access tokens module
+pub mod oauth2;
+
+/// OpenAPI specification module
+pub mod openapi;
+
+/// Application outbox relayer module
+pub mod outbox;
+
+/// HTTP API mail providers module
+pub mod provider;
+
+/// Persistent outbound queue module
+pub mod queue;
+
+/// Request rate limiting module
+pub mod rate_limit;
+
+/// Request ID propagation module
+pub mod request_id;
+
 /// Email sending functionality module
 pub mod send;
 
 /// Application settings and configuration module
 pub mod settings;
+
+/// Email template rendering module
+pub mod template;
+
+/// Mail transport module
+pub mod transport;
+
+/// Outbound URL guard module
+pub mod url_guard;
+
+/// Delivery outcome webhooks
+pub mod webhook;
+
+/// XML compatibility endpoint module
+pub mod xml;
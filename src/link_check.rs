@@ -0,0 +1,154 @@
+//! Pre-send link validation module
+//!
+//! This module extracts the links from an HTML body and verifies them before
+//! the email is sent: unsafe schemes are reported immediately and HTTP(S) links
+//! are probed with a HEAD request, with timeout and concurrency limits.
+
+use std::sync::LazyLock;
+
+use futures_util::{StreamExt, stream};
+use log::debug;
+use regex::Regex;
+use reqwest::{Client, StatusCode, Url, redirect};
+
+use crate::settings::{FetchPolicy, LinkCheckConfig};
+use crate::url_guard::resolve_allowed;
+
+/// Matches the value of every `href` attribute in an HTML document
+static HREF_REGEX: LazyLock<Regex> =
+    LazyLock::new(|| Regex::new(r#"(?i)\bhref\s*=\s*["']([^"']*)["']"#).expect("valid href regex"));
+
+/// Schemes that are accepted without being probed
+const PASSIVE_SCHEMES: [&str; 3] = ["mailto:", "tel:", "cid:"];
+
+/// A link that failed validation
+pub struct LinkIssue {
+    /// The link as found in the HTML body
+    pub url: String,
+
+    /// Human-readable description of the problem
+    pub reason: String,
+}
+
+/// Extracts all unique `href` values from an HTML body
+///
+/// Fragment-only links (e.g. `#top`) and empty values are skipped.
+fn extract_links(html: &str) -> Vec<String> {
+    let mut links: Vec<String> = Vec::new();
+    for capture in HREF_REGEX.captures_iter(html) {
+        let link = capture[1].trim();
+        if link.is_empty() || link.starts_with('#') {
+            continue;
+        }
+        if !links.iter().any(|l| l == link) {
+            links.push(link.to_owned());
+        }
+    }
+    links
+}
+
+/// Probes a single HTTP(S) link with a HEAD request
+///
+/// The link is first checked against the fetch policy and the request is pinned
+/// to the addresses validated there. Redirects are not followed, so a redirect
+/// cannot lead the probe to a forbidden host.
+///
+/// Returns `None` when the link is reachable, otherwise the issue found.
+/// Servers that do not implement HEAD (405/501) are considered reachable.
+async fn probe_link(
+    url: String,
+    config: &LinkCheckConfig,
+    policy: &FetchPolicy,
+) -> Option<LinkIssue> {
+    let issue = |reason: String| {
+        Some(LinkIssue {
+            url: url.clone(),
+            reason,
+        })
+    };
+
+    let parsed = match Url::parse(&url) {
+        Ok(u) => u,
+        Err(err) => return issue(format!("invalid URL: {}", err)),
+    };
+    let addrs = match resolve_allowed(&parsed, policy).await {
+        Ok(a) => a,
+        Err(reason) => return issue(reason),
+    };
+    let host = parsed.host_str().unwrap_or_default();
+    let client = match Client::builder()
+        .timeout(config.timeout)
+        .redirect(redirect::Policy::none())
+        .resolve_to_addrs(host, &addrs)
+        .build()
+    {
+        Ok(c) => c,
+        Err(err) => return issue(format!("cannot build HTTP client: {}", err)),
+    };
+
+    match client.head(parsed).send().await {
+        Ok(res) => {
+            let status = res.status();
+            debug!("link check {} -> {}", url, status);
+            let head_unsupported =
+                status == StatusCode::METHOD_NOT_ALLOWED || status == StatusCode::NOT_IMPLEMENTED;
+            if status.is_client_error() || status.is_server_error() {
+                if head_unsupported {
+                    return None;
+                }
+                return Some(LinkIssue {
+                    url,
+                    reason: format!("HTTP {}", status.as_u16()),
+                });
+            }
+            None
+        }
+        Err(err) => Some(LinkIssue {
+            url,
+            reason: format!("unreachable: {}", err),
+        }),
+    }
+}
+
+/// Validates all links found in an HTML body
+///
+/// # Arguments
+/// * `html` - The HTML body to scan
+/// * `config` - Link validation configuration (timeout and concurrency)
+/// * `policy` - Outbound fetch policy restricting which hosts may be probed
+///
+/// # Returns
+/// The list of broken, unsafe or forbidden links; empty when every link is valid
+pub async fn check_links(
+    html: &str,
+    config: &LinkCheckConfig,
+    policy: &FetchPolicy,
+) -> Vec<LinkIssue> {
+    let mut issues = Vec::new();
+    let mut to_probe = Vec::new();
+
+    for link in extract_links(html) {
+        let lower = link.to_lowercase();
+        if lower.starts_with("http://") || lower.starts_with("https://") {
+            to_probe.push(link);
+        } else if !PASSIVE_SCHEMES.iter().any(|s| lower.starts_with(s)) {
+            issues.push(LinkIssue {
+                url: link,
+                reason: "unsafe or unsupported scheme".to_owned(),
+            });
+        }
+    }
+
+    if to_probe.is_empty() {
+        return issues;
+    }
+
+    let probed: Vec<Option<LinkIssue>> = stream::iter(to_probe)
+        .map(|url| probe_link(url, config, policy))
+        .buffer_unordered(config.concurrency)
+        .collect()
+        .await;
+
+    issues.extend(probed.into_iter().flatten());
+    issues
+}
@@ -0,0 +1,45 @@
+//! HTTP listening socket
+//!
+//! With `BIND_REUSE_PORT` enabled the socket is bound with `SO_REUSEPORT`, so a
+//! newly started binary can listen on the same port while the old process is
+//! still running. The upgrade is then: start the new process, wait until it is
+//! healthy, send `SIGTERM` to the old one. The old process stops accepting
+//! connections, drains in-flight requests and queue deliveries for up to
+//! `SHUTDOWN_TIMEOUT_SECS`, then exits.
+
+use std::io;
+use std::net::{TcpListener, ToSocketAddrs};
+
+use socket2::{Domain, Socket, Type};
+
+use crate::settings::ServerBind;
+
+/// Pending connection backlog, same as the Actix-web default
+const BACKLOG: i32 = 2048;
+
+/// Binds the HTTP listening socket
+///
+/// # Arguments
+/// * `bind` - Server bind configuration
+///
+/// # Returns
+/// * `Ok(TcpListener)` - The listening socket, to be passed to `HttpServer::listen`
+/// * `Err(io::Error)` - The address cannot be resolved or bound
+pub fn bind_listener(bind: &ServerBind) -> io::Result<TcpListener> {
+    let addr = (bind.addr.as_str(), bind.port)
+        .to_socket_addrs()?
+        .next()
+        .ok_or_else(|| io::Error::other(format!("Cannot resolve {}", bind.addr)))?;
+
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, None)?;
+    socket.set_reuse_address(true)?;
+    if bind.reuse_port {
+        #[cfg(unix)]
+        socket.set_reuse_port(true)?;
+        #[cfg(not(unix))]
+        log::warn!("BIND_REUSE_PORT is not supported on this platform, ignored");
+    }
+    socket.bind(&addr.into())?;
+    socket.listen(BACKLOG)?;
+    Ok(socket.into())
+}
@@ -6,51 +6,543 @@
 //! # License
 //! MIT
 
+use std::sync::Arc;
+
 use actix_web::{
     App, HttpServer,
-    middleware::{Logger, NormalizePath, TrailingSlash},
+    middleware::{Logger, NormalizePath, TrailingSlash, from_fn},
     web,
 };
 use actix_web_lab::middleware::CatchPanic;
-use log::{debug, info};
+use log::{debug, info, warn};
 use rustmail::{
-    send,
-    settings::{build_server_bind, build_smtp_config, init_logger},
+    auth::require_api_key,
+    backup,
+    canary::{
+        self,
+        monitor::{CanaryMonitor, start_canary},
+    },
+    daemon::{self, ServiceLink},
+    dedup::DuplicateGuard,
+    dkim::DkimSigner,
+    listener::bind_listener,
+    metrics::{self, registry::Metrics},
+    outbox::{relayer::start_outbox_relayer, store::OutboxStore},
+    queue::{store::QueueStore, worker::start_queue_worker},
+    rate_limit::{RateLimiter, rate_limit},
+    request_id::request_id,
+    send::{
+        self, context::SendContext, convert::start_conversion_worker, idempotency::IdempotencyStore,
+    },
+    settings::{
+        DEFAULT_SMTP_PROFILE, DaemonConfig, TransportKind, build_alignment_config,
+        build_auth_config, build_blackhole_config, build_bulk_limits, build_canary_config,
+        build_circuit_breaker_config, build_content_detection_config, build_convert_config,
+        build_cost_config, build_daemon_config, build_dark_mode_config, build_dkim_config,
+        build_duplicate_guard_config, build_fault_config, build_fetch_policy,
+        build_idempotency_config, build_link_check_config, build_load_shedding_config,
+        build_mime_limits, build_outbox_config, build_privacy_config, build_queue_config,
+        build_rate_limit_config, build_recipient_policy, build_response_policy,
+        build_sandbox_config, build_server_bind, build_smtp_config, build_smtp_override_config,
+        build_smtp_profiles, build_subject_policy, build_template_config, build_transport_config,
+        build_transport_profiles, build_webhook_config, build_xml_endpoint_config,
+        check_environment, init_logger, load_config_file,
+    },
+    template::{self, registry::TemplateRegistry},
+    transport::build_mailers,
+    webhook::Webhooks,
+    xml,
 };
 
+/// Access log format: the Actix-web default followed by the request ID
+const ACCESS_LOG_FORMAT: &str =
+    r#"%a "%r" %s %b "%{Referer}i" "%{User-Agent}i" %T %{x-request-id}o"#;
+
 /// Application entry point.
 /// Initializes the Actix-web server and starts listening for HTTP requests on 0.0.0.0:3333.
-#[actix_web::main]
-async fn main() -> std::io::Result<()> {
+fn main() -> std::io::Result<()> {
+    #[cfg(windows)]
+    let service = daemon::prepare_service()?;
+    let config_file = load_config_file().map_err(std::io::Error::other)?;
+    check_environment().map_err(|e| std::io::Error::other(e.to_string()))?;
     init_logger();
+    if let Some(path) = config_file {
+        info!("Settings loaded from config file {}", path);
+    }
+    let daemon_config = build_daemon_config();
+
+    #[cfg(windows)]
+    if service {
+        let shutdown_timeout = build_server_bind().shutdown_timeout;
+        return daemon::run_service(shutdown_timeout, move |link| {
+            serve(&daemon_config, Some(link))
+        });
+    }
+
+    // Fork before the runtime starts any thread; maintenance commands stay in the foreground
+    let link = if daemon_config.detach && std::env::args().len() == 1 {
+        Some(daemon::detach()?)
+    } else {
+        None
+    };
+    serve(&daemon_config, link)
+}
+
+/// Runs the application on a new system, then removes the PID file
+///
+/// # Arguments
+/// * `daemon_config` - Service manager integration configuration
+/// * `link` - Service manager to report the startup to, if any
+fn serve(daemon_config: &DaemonConfig, link: Option<ServiceLink>) -> std::io::Result<()> {
+    let result = actix_web::rt::System::new().block_on(run(daemon_config, link));
+    daemon::remove_pid_file(daemon_config);
+    result
+}
+
+/// Runs the maintenance command given on the command line, or serves HTTP until shutdown
+///
+/// # Arguments
+/// * `daemon_config` - Service manager integration configuration
+/// * `link` - Service manager to report the startup to, when `DAEMON` is set or running as a Windows service
+async fn run(daemon_config: &DaemonConfig, link: Option<ServiceLink>) -> std::io::Result<()> {
     let server_bind = build_server_bind();
-    let smtp_config = build_smtp_config();
+    let transport_config = build_transport_config();
+    let transport_profiles = build_transport_profiles();
+    let smtp_config = build_smtp_config().map_err(|e| std::io::Error::other(e.to_string()))?;
+    let smtp_profiles = build_smtp_profiles().map_err(|e| std::io::Error::other(e.to_string()))?;
+    let link_check_config = build_link_check_config();
+    let fetch_policy = build_fetch_policy();
+    let fault_config = build_fault_config();
+    let duplicate_guard_config = build_duplicate_guard_config();
+    let idempotency_config = build_idempotency_config();
+    let blackhole_config = build_blackhole_config();
+    let canary_config = build_canary_config();
+    let mime_limits = build_mime_limits();
+    let convert_config = build_convert_config();
+    let bulk_limits = build_bulk_limits();
+    let privacy_config = build_privacy_config();
+    let dkim_config = build_dkim_config();
+    let queue_config = build_queue_config();
+    let subject_policy = build_subject_policy();
+    let recipient_policy = build_recipient_policy();
+    let template_config = build_template_config();
+    let response_policy = build_response_policy();
+    let sandbox_config = build_sandbox_config();
+    let xml_endpoint_config = build_xml_endpoint_config();
+    let smtp_override_config = build_smtp_override_config();
+    let alignment_config = build_alignment_config();
+    let content_detection_config = build_content_detection_config();
+    let dark_mode_config = build_dark_mode_config();
+    let cost_config = build_cost_config();
+    let load_shedding_config = build_load_shedding_config();
+    let circuit_breaker_config = build_circuit_breaker_config();
+    let webhook_config = build_webhook_config();
+    let outbox_config = build_outbox_config();
+    let auth_config = web::Data::new(build_auth_config());
+    let rate_limiter = web::Data::new(RateLimiter::new(build_rate_limit_config()));
+
+    // Maintenance commands run against the configured state and exit
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    match args
+        .iter()
+        .map(String::as_str)
+        .collect::<Vec<_>>()
+        .as_slice()
+    {
+        [] => {}
+        ["service"] if cfg!(windows) => {}
+        ["migrate"] => {
+            QueueStore::open(&queue_config.db_path, true).map_err(std::io::Error::other)?;
+            info!("Queue database {} is up to date", queue_config.db_path);
+            return Ok(());
+        }
+        ["backup", archive] => {
+            let summary = backup::backup(archive, &queue_config, &template_config)
+                .map_err(std::io::Error::other)?;
+            info!(
+                "Backup {} written: {} queued message(s), {} template file(s)",
+                archive, summary.messages, summary.templates
+            );
+            return Ok(());
+        }
+        ["restore", archive] => {
+            let summary = backup::restore(archive, &queue_config, &template_config)
+                .map_err(std::io::Error::other)?;
+            info!(
+                "Backup {} restored: {} queued message(s), {} template file(s)",
+                archive, summary.messages, summary.templates
+            );
+            return Ok(());
+        }
+        _ => {
+            return Err(std::io::Error::other(if cfg!(windows) {
+                "Usage: rustmail [service | migrate | backup <archive> | restore <archive>]"
+            } else {
+                "Usage: rustmail [migrate | backup <archive> | restore <archive>]"
+            }));
+        }
+    }
+
+    // Bind while still privileged, then run as the service account, which owns the PID file
+    let listener = bind_listener(&server_bind)?;
+    daemon::drop_privileges(daemon_config)?;
+    daemon::write_pid_file(daemon_config)?;
 
     debug!(
-        "Server bind: address {} port {} workers {}",
-        server_bind.addr, server_bind.port, server_bind.workers
+        "Server bind: address {} port {} workers {} reuse_port {} shutdown_timeout {:?}",
+        server_bind.addr,
+        server_bind.port,
+        server_bind.workers,
+        server_bind.reuse_port,
+        server_bind.shutdown_timeout
+    );
+    debug!(
+        "SMTP config: host {} port {} tls_mode {:?} max_connections {} auth {} auth_mechanisms {:?}",
+        smtp_config.host,
+        smtp_config.port,
+        smtp_config.tls_mode,
+        smtp_config.max_connections,
+        smtp_config.auth.name(),
+        smtp_config.auth_mechanisms
+    );
+    for (name, profile) in &smtp_profiles {
+        debug!(
+            "SMTP profile {}: host {} port {} tls_mode {:?} max_connections {} auth {} auth_mechanisms {:?}",
+            name,
+            profile.host,
+            profile.port,
+            profile.tls_mode,
+            profile.max_connections,
+            profile.auth.name(),
+            profile.auth_mechanisms
+        );
+    }
+    let smtp_configs = std::iter::once((DEFAULT_SMTP_PROFILE, &smtp_config))
+        .chain(smtp_profiles.iter().map(|(name, c)| (name.as_str(), c)));
+    for (name, config) in smtp_configs {
+        if let Some(ca_file) = &config.tls_ca_file {
+            info!("SMTP profile {}: trusting CA file {}", name, ca_file);
+        }
+        if config.tls_accept_invalid_certs {
+            warn!(
+                "SMTP profile {}: invalid server certificates accepted (TLS_ACCEPT_INVALID_CERTS)",
+                name
+            );
+        }
+    }
+    debug!(
+        "Link check: mode {:?} timeout {:?} concurrency {}",
+        link_check_config.mode, link_check_config.timeout, link_check_config.concurrency
+    );
+    debug!(
+        "Fetch policy: allow {:?} deny {:?} allow_private_ips {}",
+        fetch_policy.allow_hosts, fetch_policy.deny_hosts, fetch_policy.allow_private_ips
+    );
+    debug!(
+        "Duplicate guard: mode {:?} window {:?}",
+        duplicate_guard_config.mode, duplicate_guard_config.window
+    );
+    debug!("Blackhole domains: {:?}", blackhole_config.domains);
+    debug!(
+        "Privacy mode: enabled {} domain {:?}",
+        privacy_config.enabled, privacy_config.domain
+    );
+    debug!(
+        "Subject policy: prefix {:?} suffix {:?}",
+        subject_policy.prefix, subject_policy.suffix
+    );
+    debug!(
+        "Recipient policy: allow {:?} deny {:?}",
+        recipient_policy.allow_domains, recipient_policy.deny_domains
+    );
+    debug!(
+        "Response policy: recipients {:?} omit_fields {:?}",
+        response_policy.recipients, response_policy.omit_fields
     );
     debug!(
-        "SMTP config: host {} port {} use_tls {}",
-        smtp_config.host, smtp_config.port, smtp_config.use_tls
+        "MIME limits: attachments {} parts {} depth {}",
+        mime_limits.max_attachments, mime_limits.max_parts, mime_limits.max_depth
     );
 
+    debug!(
+        "Bulk limits: max_messages {} concurrency {}",
+        bulk_limits.max_messages, bulk_limits.concurrency
+    );
+
+    if auth_config.api_keys.is_empty() && auth_config.admin_keys.is_empty() {
+        warn!("No API key configured (API_KEYS): every endpoint is unauthenticated");
+    } else {
+        info!(
+            "API key authentication enabled with {} key(s) and {} admin key(s)",
+            auth_config.api_keys.len(),
+            auth_config.admin_keys.len()
+        );
+    }
+
+    if smtp_override_config.enabled {
+        warn!("Per-request SMTP override enabled for admin keys (SMTP_OVERRIDE_ENABLED)");
+    }
+
+    if rate_limiter.config.per_minute > 0 {
+        info!(
+            "Rate limit: {} request(s) per minute, burst {}",
+            rate_limiter.config.per_minute, rate_limiter.config.burst
+        );
+    }
+
+    if !convert_config.converters.is_empty() {
+        let names: Vec<&str> = convert_config
+            .converters
+            .iter()
+            .map(|c| c.name.as_str())
+            .collect();
+        info!("Attachment converters: {}", names.join(", "));
+    }
+
+    if xml_endpoint_config.enabled {
+        info!("XML endpoint enabled (/send/xml)");
+    }
+
+    if sandbox_config.enabled {
+        warn!("Sandbox mode enabled (SANDBOX): messages are built but never sent");
+    }
+
+    if fault_config.enabled {
+        warn!(
+            "Fault injection enabled for admin requests: default drop {}% transient {}% latency {}ms",
+            fault_config.drop_percent, fault_config.transient_percent, fault_config.latency_ms
+        );
+    }
+
+    // Build the async pooled SMTP transports once so all workers share their connections
+    let transport_configs = std::iter::once((DEFAULT_SMTP_PROFILE, &transport_config)).chain(
+        transport_profiles
+            .iter()
+            .map(|(name, c)| (name.as_str(), c)),
+    );
+    for (name, config) in transport_configs {
+        match config.kind {
+            TransportKind::Smtp => {}
+            TransportKind::Sendmail => info!(
+                "SMTP profile {}: delivering through sendmail ({})",
+                name, config.sendmail_command
+            ),
+            kind => info!("SMTP profile {}: delivering through {:?} API", name, kind),
+        }
+    }
+    let mailers = build_mailers(
+        &transport_config,
+        &transport_profiles,
+        &smtp_config,
+        &smtp_profiles,
+        &privacy_config,
+        &circuit_breaker_config,
+    )
+    .await
+    .map_err(std::io::Error::other)?;
+    if circuit_breaker_config.threshold > 0 {
+        info!(
+            "SMTP circuit breaker enabled: open after {} failures, cooldown {:?}",
+            circuit_breaker_config.threshold, circuit_breaker_config.cooldown
+        );
+    }
+
+    // Load the DKIM key once; an invalid configuration stops the service
+    let dkim = DkimSigner::from_config(&dkim_config)
+        .map_err(std::io::Error::other)?
+        .map(Arc::new);
+    if dkim.is_some() {
+        info!(
+            "DKIM signing enabled for {:?} with selector {:?}",
+            dkim_config.domain, dkim_config.selector
+        );
+    }
+
+    if alignment_config.enabled {
+        info!("Sender domain alignment check enabled (ALIGNMENT_CHECK)");
+        for (name, domains) in &alignment_config.spf_domains {
+            if dkim.is_none() && domains.is_empty() {
+                warn!(
+                    "SMTP profile {}: no DKIM domain nor SPF domains, every send will be rejected",
+                    name
+                );
+            }
+        }
+    }
+
+    if cost_config.rates.values().any(|rate| *rate > 0.0) {
+        info!(
+            "Sending costs charged in {} per recipient, see /stats/costs",
+            cost_config.currency
+        );
+    }
+
+    if load_shedding_config.enabled() {
+        info!(
+            "Load shedding of bulk and low priority mail enabled: queue depth {:?}, relay failures {:?}, action {:?}",
+            load_shedding_config.max_queue_depth,
+            load_shedding_config.max_relay_failures,
+            load_shedding_config.action
+        );
+    }
+
+    // Start the deliverability canary when seed mailboxes are configured
+    let canary_monitor = web::Data::new(CanaryMonitor::default());
+    if !canary_config.seeds.is_empty() {
+        info!(
+            "Canary enabled for {} seed(s), every {:?}",
+            canary_config.seeds.len(),
+            canary_config.interval
+        );
+        start_canary(
+            canary_config,
+            mailers.clone(),
+            dkim.clone(),
+            canary_monitor.clone().into_inner(),
+        );
+    }
+
+    // Shared by the send handlers, the queue worker and the metrics endpoint
+    let metrics = Arc::new(Metrics::new());
+
+    // Callbacks go through the outbound fetch policy like any other request
+    let webhooks = Arc::new(Webhooks::new(webhook_config, fetch_policy.clone()));
+    if webhooks.enabled() {
+        info!("Delivery outcome callbacks enabled (callback_url)");
+    }
+
+    // Open the persistent queue and start its delivery worker
+    let queue = QueueStore::open(&queue_config.db_path, queue_config.auto_migrate)
+        .map_err(std::io::Error::other)?;
+    let queue = Arc::new(queue);
+    info!("Queue database: {}", queue_config.db_path);
+    let queue_worker = start_queue_worker(
+        Arc::clone(&queue),
+        mailers.clone(),
+        queue_config.clone(),
+        fault_config.clone(),
+        cost_config.clone(),
+        Arc::clone(&metrics),
+        Arc::clone(&webhooks),
+    );
+
+    // Load the email templates when a templates directory is configured
+    let templates = match &template_config.dir {
+        Some(dir) => {
+            info!("Templates directory: {}", dir);
+            let registry = TemplateRegistry::open(dir, template_config.inline_css)
+                .map_err(std::io::Error::other)?;
+            Some(web::Data::new(registry))
+        }
+        None => None,
+    };
+
+    // Shared across workers so registries see every request whichever worker handles it
+    let send_context = web::Data::new(SendContext {
+        mailers,
+        link_check_config,
+        fetch_policy,
+        fault_config,
+        duplicate_guard: DuplicateGuard::new(duplicate_guard_config),
+        idempotency: IdempotencyStore::new(idempotency_config),
+        blackhole_config,
+        mime_limits,
+        convert_config,
+        bulk_limits,
+        dkim,
+        privacy_config,
+        subject_policy,
+        recipient_policy,
+        response_policy: response_policy.clone(),
+        sandbox_config,
+        smtp_override_config,
+        alignment_config,
+        content_detection_config,
+        dark_mode_config,
+        cost_config,
+        load_shedding_config,
+        circuit_breaker_config,
+        queue,
+        metrics: Arc::clone(&metrics),
+        webhooks,
+    });
+    let metrics = web::Data::from(metrics);
+    let response_policy = web::Data::new(response_policy);
+
+    // Relay the application outbox when its database is configured
+    let outbox_relayer = match &outbox_config.db_path {
+        Some(path) => {
+            let store = OutboxStore::open(path).map_err(std::io::Error::other)?;
+            info!("Outbox relayer enabled for {}", path);
+            Some(start_outbox_relayer(
+                Arc::new(store),
+                send_context.clone(),
+                outbox_config,
+            ))
+        }
+        None => None,
+    };
+
+    // Convert the queued attachments when converters are configured
+    let conversion_worker = if send_context.convert_config.converters.is_empty() {
+        None
+    } else {
+        Some(start_conversion_worker(
+            Arc::clone(&send_context.queue),
+            send_context.clone(),
+            queue_config,
+        ))
+    };
+
     // Create HTTP server with middleware and routes
     let server = HttpServer::new(move || {
-        App::new()
-            .app_data(web::Data::new(smtp_config.clone()))
+        let mut app = App::new()
+            .app_data(send_context.clone())
+            .app_data(canary_monitor.clone())
+            .app_data(auth_config.clone())
+            .app_data(rate_limiter.clone())
+            .app_data(metrics.clone())
+            .app_data(response_policy.clone());
+        if let Some(templates) = &templates {
+            app = app.app_data(templates.clone());
+        }
+        if xml_endpoint_config.enabled {
+            app = app.configure(xml::xml_controller::config);
+        }
+        app.wrap(from_fn(rate_limit)) // Throttle send endpoints per client (after authentication)
+            .wrap(from_fn(require_api_key)) // Validate X-Api-Key (runs after path normalization)
             .wrap(NormalizePath::new(TrailingSlash::Trim)) // Normalize URL paths
             .wrap(CatchPanic::default()) // Catch panics (must be before Logger)
-            .wrap(Logger::default()) // Request logging middleware
+            .wrap(from_fn(request_id)) // Assign the X-Request-Id (must be before Logger)
+            .wrap(Logger::new(ACCESS_LOG_FORMAT)) // Request logging middleware
             .configure(send::send_controller::config)
+            .configure(canary::canary_controller::config)
+            .configure(template::template_controller::config)
+            .configure(metrics::metrics_controller::config)
     })
-    .workers(server_bind.workers);
+    .workers(server_bind.workers)
+    .shutdown_timeout(server_bind.shutdown_timeout.as_secs());
 
     info!("HTTP mode enabled");
 
-    // Start HTTP server
-    server
-        .bind((server_bind.addr.as_str(), server_bind.port))?
-        .run()
-        .await
+    // Start HTTP server; on SIGTERM/SIGINT it stops accepting and drains in-flight requests
+    let server = server.listen(listener)?.run();
+    let handle = server.handle();
+    let server = actix_web::rt::spawn(server);
+    if let Some(link) = link {
+        // Let the server install its signal handlers before the service manager is told
+        actix_web::rt::task::yield_now().await;
+        link.ready(&handle);
+    }
+    let result = server.await.map_err(std::io::Error::other)?;
+
+    // Stop feeding the queue, then let the queue worker finish the deliveries in flight
+    if let Some(relayer) = outbox_relayer {
+        relayer.shutdown(server_bind.shutdown_timeout).await;
+    }
+    if let Some(worker) = conversion_worker {
+        worker.shutdown(server_bind.shutdown_timeout).await;
+    }
+    queue_worker.shutdown(server_bind.shutdown_timeout).await;
+    result
 }
@@ -0,0 +1,55 @@
+//! Per tenant sending costs
+//!
+//! Every delivered message is charged to the tenant that sent it, at the rate
+//! of the profile it went through, so chargeback reports can be read from the
+//! service instead of scraped from the logs. Costs cover the deliveries made
+//! since the service started.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+/// Counters of one tenant
+#[derive(Clone, Default)]
+pub struct TenantCost {
+    /// Messages accepted by the relay
+    pub messages: u64,
+
+    /// Recipients of the accepted messages
+    pub recipients: u64,
+
+    /// Sum of the message costs
+    pub cost: f64,
+}
+
+/// Sending costs of all tenants
+#[derive(Default)]
+pub struct CostStats {
+    tenants: Mutex<HashMap<String, TenantCost>>,
+}
+
+impl CostStats {
+    /// Charges one delivered message to a tenant
+    ///
+    /// # Arguments
+    /// * `tenant` - Tenant that sent the message
+    /// * `recipients` - Recipients the relay accepted the message for
+    /// * `rate` - Price of one recipient through the profile used
+    pub fn record(&self, tenant: &str, recipients: usize, rate: f64) {
+        let mut tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        let counters = tenants.entry(tenant.to_owned()).or_default();
+        counters.messages += 1;
+        counters.recipients += recipients as u64;
+        counters.cost += rate * recipients as f64;
+    }
+
+    /// Returns the counters of every tenant, most expensive first
+    pub fn snapshot(&self) -> Vec<(String, TenantCost)> {
+        let tenants = self.tenants.lock().unwrap_or_else(|e| e.into_inner());
+        let mut snapshot: Vec<_> = tenants
+            .iter()
+            .map(|(tenant, counters)| (tenant.clone(), counters.clone()))
+            .collect();
+        snapshot.sort_by(|a, b| b.1.cost.total_cmp(&a.1.cost).then(a.0.cmp(&b.0)));
+        snapshot
+    }
+}
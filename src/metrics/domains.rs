@@ -0,0 +1,101 @@
+//! Per recipient domain delivery statistics
+//!
+//! Every SMTP attempt is counted for each recipient domain of its envelope, so
+//! that a provider starting to defer or reject our mail stands out. Statistics
+//! cover the attempts made since the service started.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lettre::address::Envelope;
+
+use crate::transport::TransportError;
+
+/// Outcome of one SMTP send attempt
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum AttemptOutcome {
+    /// Accepted by the SMTP server
+    Accepted,
+
+    /// Temporarily refused (4xx or connection problem), may be retried
+    Deferred,
+
+    /// Permanently rejected (5xx), counted as a bounce
+    Rejected,
+}
+
+impl AttemptOutcome {
+    /// Classifies the result of an SMTP send
+    pub fn of<T>(result: &Result<T, TransportError>) -> Self {
+        match result {
+            Ok(_) => AttemptOutcome::Accepted,
+            Err(err) if err.is_permanent() => AttemptOutcome::Rejected,
+            Err(_) => AttemptOutcome::Deferred,
+        }
+    }
+}
+
+/// Counters of one recipient domain
+#[derive(Clone, Default)]
+pub struct DomainCounters {
+    /// SMTP attempts
+    pub attempts: u64,
+
+    /// Attempts accepted by the SMTP server
+    pub accepted: u64,
+
+    /// Attempts temporarily refused
+    pub deferred: u64,
+
+    /// Attempts permanently rejected
+    pub rejected: u64,
+
+    /// Sum of the attempt durations
+    pub total_latency: Duration,
+}
+
+/// Delivery statistics of all recipient domains
+#[derive(Default)]
+pub struct DomainStats {
+    domains: Mutex<HashMap<String, DomainCounters>>,
+}
+
+impl DomainStats {
+    /// Counts one SMTP attempt for every recipient domain of the envelope
+    ///
+    /// # Arguments
+    /// * `envelope` - SMTP envelope of the message (recipient domains)
+    /// * `elapsed` - Duration of the attempt
+    /// * `outcome` - Outcome of the attempt
+    pub fn record(&self, envelope: &Envelope, elapsed: Duration, outcome: AttemptOutcome) {
+        let mut domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        let mut seen = Vec::new();
+        for address in envelope.to() {
+            let domain = address.domain().to_lowercase();
+            if seen.contains(&domain) {
+                continue;
+            }
+            let counters = domains.entry(domain.clone()).or_default();
+            counters.attempts += 1;
+            counters.total_latency += elapsed;
+            match outcome {
+                AttemptOutcome::Accepted => counters.accepted += 1,
+                AttemptOutcome::Deferred => counters.deferred += 1,
+                AttemptOutcome::Rejected => counters.rejected += 1,
+            }
+            seen.push(domain);
+        }
+    }
+
+    /// Returns the counters of every domain, most attempted first
+    pub fn snapshot(&self) -> Vec<(String, DomainCounters)> {
+        let domains = self.domains.lock().unwrap_or_else(|e| e.into_inner());
+        let mut snapshot: Vec<_> = domains
+            .iter()
+            .map(|(domain, counters)| (domain.clone(), counters.clone()))
+            .collect();
+        snapshot.sort_by(|a, b| b.1.attempts.cmp(&a.1.attempts).then(a.0.cmp(&b.0)));
+        snapshot
+    }
+}
@@ -0,0 +1,74 @@
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::settings::Status;
+
+/// Delivery statistics of one recipient domain
+#[derive(Serialize, ToSchema)]
+pub struct DomainStatsItem {
+    /// Recipient domain (e.g., "gmail.com")
+    pub domain: String,
+
+    /// SMTP attempts
+    pub attempts: u64,
+
+    /// Attempts accepted by the SMTP server
+    pub accepted: u64,
+
+    /// Attempts temporarily refused (4xx or connection problem)
+    pub deferred: u64,
+
+    /// Attempts permanently rejected (5xx)
+    pub rejected: u64,
+
+    /// Accepted attempts over all attempts, between 0 and 1
+    pub acceptance_rate: f64,
+
+    /// Deferred attempts over all attempts, between 0 and 1
+    pub deferral_rate: f64,
+
+    /// Rejected attempts over all attempts, between 0 and 1
+    pub bounce_rate: f64,
+
+    /// Average duration of an attempt, in milliseconds
+    pub avg_latency_ms: f64,
+}
+
+/// Response listing the delivery statistics per recipient domain
+#[derive(Serialize, ToSchema)]
+pub struct DomainStatsRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// Statistics per domain, most attempted first
+    pub domains: Vec<DomainStatsItem>,
+}
+
+/// Sending costs of one tenant
+#[derive(Serialize, ToSchema)]
+pub struct TenantCostItem {
+    /// Tenant (e.g., "billing"), `default` for keys not mapped in `API_KEY_TENANTS`
+    pub tenant: String,
+
+    /// Messages accepted by the relay
+    pub messages: u64,
+
+    /// Recipients of the accepted messages
+    pub recipients: u64,
+
+    /// Total cost of the messages, in the configured currency
+    pub cost: f64,
+}
+
+/// Response listing the sending costs per tenant
+#[derive(Serialize, ToSchema)]
+pub struct CostStatsRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// Currency of the costs (e.g., "USD")
+    pub currency: String,
+
+    /// Costs per tenant, most expensive first
+    pub tenants: Vec<TenantCostItem>,
+}
@@ -0,0 +1,140 @@
+//! HTTP controllers for the metrics endpoint
+//!
+//! This module exposes the application metrics to Prometheus, and the delivery
+//! statistics per recipient domain and the sending costs per tenant as JSON.
+
+use crate::codec::respond;
+use crate::metrics::dto::{CostStatsRes, DomainStatsItem, DomainStatsRes, TenantCostItem};
+use crate::metrics::registry::Metrics;
+use crate::send::context::SendContext;
+use crate::settings::{RustMailRes, Status, json_error};
+use actix_web::{HttpRequest, HttpResponse, Result, get, web};
+use log::warn;
+use utoipa::OpenApi;
+
+/// GET endpoint returning the metrics in the Prometheus text format
+///
+/// The queue depth is read from the queue database on every scrape.
+#[utoipa::path(
+    get,
+    path = "/metrics",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Metrics in the Prometheus text format", body = String, content_type = "text/plain"),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes)
+    )
+)]
+#[get("metrics")]
+async fn metrics(metrics: web::Data<Metrics>, ctx: web::Data<SendContext>) -> Result<HttpResponse> {
+    let queue = ctx.queue.clone();
+    match web::block(move || queue.pending_count()).await {
+        Ok(Ok(depth)) => metrics.set_queue_depth(depth),
+        Ok(Err(err)) => warn!("Cannot read queue depth: {}", err),
+        Err(err) => warn!("Cannot read queue depth: {}", err),
+    }
+
+    let body = metrics.encode().map_err(json_error)?;
+    Ok(HttpResponse::Ok()
+        .content_type(prometheus::TEXT_FORMAT)
+        .body(body))
+}
+
+/// GET endpoint returning the delivery statistics per recipient domain
+///
+/// Every SMTP attempt since the service started is counted, for direct sends and
+/// queued deliveries alike. Permanent rejections are reported as bounces.
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) statistics, most attempted domain first
+#[utoipa::path(
+    get,
+    path = "/stats/domains",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Delivery statistics per recipient domain", body = DomainStatsRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes)
+    )
+)]
+#[get("stats/domains")]
+async fn domain_stats(req: HttpRequest, registry: web::Data<Metrics>) -> Result<HttpResponse> {
+    let x = DomainStatsRes {
+        status: Status::Ok,
+        domains: registry
+            .domain_stats()
+            .into_iter()
+            .map(|(domain, c)| {
+                let rate = |count: u64| count as f64 / c.attempts.max(1) as f64;
+                DomainStatsItem {
+                    acceptance_rate: rate(c.accepted),
+                    deferral_rate: rate(c.deferred),
+                    bounce_rate: rate(c.rejected),
+                    avg_latency_ms: c.total_latency.as_secs_f64() * 1000.0
+                        / c.attempts.max(1) as f64,
+                    domain,
+                    attempts: c.attempts,
+                    accepted: c.accepted,
+                    deferred: c.deferred,
+                    rejected: c.rejected,
+                }
+            })
+            .collect(),
+    };
+    respond(&req, &x)
+}
+
+/// GET endpoint returning the sending costs per tenant
+///
+/// Every message accepted by the relay since the service started is charged to
+/// the tenant of the API key that sent it, at the rate of its profile, for
+/// direct sends and queued deliveries alike.
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) costs, most expensive tenant first
+#[utoipa::path(
+    get,
+    path = "/stats/costs",
+    tag = "metrics",
+    responses(
+        (status = 200, description = "Sending costs per tenant", body = CostStatsRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes)
+    )
+)]
+#[get("stats/costs")]
+async fn cost_stats(
+    req: HttpRequest,
+    registry: web::Data<Metrics>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    let x = CostStatsRes {
+        status: Status::Ok,
+        currency: ctx.cost_config.currency.clone(),
+        tenants: registry
+            .cost_stats()
+            .into_iter()
+            .map(|(tenant, c)| TenantCostItem {
+                tenant,
+                messages: c.messages,
+                recipients: c.recipients,
+                cost: c.cost,
+            })
+            .collect(),
+    };
+    respond(&req, &x)
+}
+
+/// OpenAPI description of the endpoints of this module
+#[derive(OpenApi)]
+#[openapi(paths(metrics, domain_stats, cost_stats))]
+pub struct ApiDoc;
+
+/// Configures the Actix-web service routes
+///
+/// Registers all HTTP endpoints for this module with the application.
+///
+/// # Arguments
+/// * `cfg` - Actix-web service configuration to register routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(metrics);
+    cfg.service(domain_stats);
+    cfg.service(cost_stats);
+}
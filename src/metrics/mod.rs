@@ -0,0 +1,21 @@
+//! Prometheus metrics module
+//!
+//! This module counts sent and failed emails, per recipient domain, measures
+//! SMTP latency and exposes everything, together with the queue depth, in the
+//! Prometheus text format. Delivery statistics per recipient domain and sending
+//! costs per tenant are also served as JSON.
+
+/// Metric definitions and recording
+pub mod registry;
+
+/// Delivery statistics per recipient domain
+pub mod domains;
+
+/// Sending costs per tenant
+pub mod costs;
+
+/// Data transfer objects for the statistics endpoint
+pub mod dto;
+
+/// HTTP controllers for the metrics endpoint
+pub mod metrics_controller;
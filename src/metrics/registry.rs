@@ -0,0 +1,196 @@
+//! Metric definitions and recording
+//!
+//! All metrics live in a dedicated registry owned by [`Metrics`], shared by the
+//! send handlers and the queue worker.
+
+use std::time::Duration;
+
+use lettre::address::Envelope;
+
+use crate::metrics::costs::{CostStats, TenantCost};
+use crate::metrics::domains::{AttemptOutcome, DomainCounters, DomainStats};
+use prometheus::{
+    Encoder, Histogram, HistogramOpts, IntCounter, IntCounterVec, IntGauge, Opts, Registry,
+    TextEncoder,
+};
+
+/// SMTP latency histogram buckets, in seconds
+const SMTP_LATENCY_BUCKETS: [f64; 10] = [0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// Application metrics
+pub struct Metrics {
+    registry: Registry,
+
+    /// Emails accepted by the SMTP server
+    emails_sent: IntCounter,
+
+    /// Emails whose delivery failed for good
+    emails_failed: IntCounter,
+
+    /// Recipients per domain and delivery result
+    recipients: IntCounterVec,
+
+    /// Duration of SMTP send attempts
+    smtp_latency: Histogram,
+
+    /// Messages waiting in the persistent queue
+    queue_depth: IntGauge,
+
+    /// SMTP attempts deferred in a row since the relay last answered
+    relay_failures: IntGauge,
+
+    /// Delivery statistics per recipient domain
+    domains: DomainStats,
+
+    /// Sending costs per tenant
+    costs: CostStats,
+}
+
+impl Metrics {
+    /// Creates and registers all metrics
+    ///
+    /// # Panics
+    /// Panics if a metric definition is invalid, which is a programming error
+    pub fn new() -> Self {
+        let registry =
+            Registry::new_custom(Some("rustmail".to_owned()), None).expect("valid metrics prefix");
+
+        let emails_sent =
+            IntCounter::new("emails_sent_total", "Emails accepted by the SMTP server")
+                .expect("valid metric");
+        let emails_failed = IntCounter::new("emails_failed_total", "Emails whose delivery failed")
+            .expect("valid metric");
+        let recipients = IntCounterVec::new(
+            Opts::new(
+                "recipients_total",
+                "Recipients per domain and delivery result",
+            ),
+            &["domain", "result"],
+        )
+        .expect("valid metric");
+        let smtp_latency = Histogram::with_opts(
+            HistogramOpts::new(
+                "smtp_send_duration_seconds",
+                "Duration of SMTP send attempts",
+            )
+            .buckets(SMTP_LATENCY_BUCKETS.to_vec()),
+        )
+        .expect("valid metric");
+        let queue_depth = IntGauge::new("queue_depth", "Messages waiting in the persistent queue")
+            .expect("valid metric");
+        let relay_failures = IntGauge::new(
+            "relay_consecutive_failures",
+            "SMTP attempts deferred in a row since the relay last answered",
+        )
+        .expect("valid metric");
+
+        registry
+            .register(Box::new(emails_sent.clone()))
+            .expect("unique metric");
+        registry
+            .register(Box::new(emails_failed.clone()))
+            .expect("unique metric");
+        registry
+            .register(Box::new(recipients.clone()))
+            .expect("unique metric");
+        registry
+            .register(Box::new(smtp_latency.clone()))
+            .expect("unique metric");
+        registry
+            .register(Box::new(queue_depth.clone()))
+            .expect("unique metric");
+        registry
+            .register(Box::new(relay_failures.clone()))
+            .expect("unique metric");
+
+        Metrics {
+            registry,
+            emails_sent,
+            emails_failed,
+            recipients,
+            smtp_latency,
+            queue_depth,
+            relay_failures,
+            domains: DomainStats::default(),
+            costs: CostStats::default(),
+        }
+    }
+
+    /// Records one SMTP send attempt
+    ///
+    /// # Arguments
+    /// * `envelope` - SMTP envelope of the message (recipient domains)
+    /// * `elapsed` - Duration of the attempt
+    /// * `outcome` - Outcome of the attempt
+    pub fn observe_smtp(&self, envelope: &Envelope, elapsed: Duration, outcome: AttemptOutcome) {
+        self.smtp_latency.observe(elapsed.as_secs_f64());
+        self.domains.record(envelope, elapsed, outcome);
+
+        // A rejection is an answer of the relay, only deferrals count as failures
+        match outcome {
+            AttemptOutcome::Deferred => self.relay_failures.inc(),
+            AttemptOutcome::Accepted | AttemptOutcome::Rejected => self.relay_failures.set(0),
+        }
+    }
+
+    /// Records the final outcome of a delivery
+    ///
+    /// # Arguments
+    /// * `envelope` - SMTP envelope of the message (recipient domains)
+    /// * `sent` - Whether the SMTP server accepted the message
+    pub fn record_delivery(&self, envelope: &Envelope, sent: bool) {
+        let (counter, result) = if sent {
+            (&self.emails_sent, "sent")
+        } else {
+            (&self.emails_failed, "failed")
+        };
+        counter.inc();
+        for address in envelope.to() {
+            let domain = address.domain().to_lowercase();
+            self.recipients.with_label_values(&[&domain, result]).inc();
+        }
+    }
+
+    /// Charges a delivered message to the tenant that sent it
+    ///
+    /// # Arguments
+    /// * `tenant` - Tenant that sent the message
+    /// * `envelope` - SMTP envelope of the accepted message (recipients)
+    /// * `rate` - Price of one recipient through the profile used
+    pub fn record_cost(&self, tenant: &str, envelope: &Envelope, rate: f64) {
+        self.costs.record(tenant, envelope.to().len(), rate);
+    }
+
+    /// Updates the number of messages waiting in the queue
+    pub fn set_queue_depth(&self, depth: usize) {
+        self.queue_depth.set(depth as i64);
+    }
+
+    /// Returns the number of SMTP attempts deferred in a row
+    pub fn relay_failures(&self) -> u32 {
+        self.relay_failures.get().max(0) as u32
+    }
+
+    /// Returns the delivery statistics of every recipient domain
+    pub fn domain_stats(&self) -> Vec<(String, DomainCounters)> {
+        self.domains.snapshot()
+    }
+
+    /// Returns the sending costs of every tenant
+    pub fn cost_stats(&self) -> Vec<(String, TenantCost)> {
+        self.costs.snapshot()
+    }
+
+    /// Encodes all metrics in the Prometheus text format
+    pub fn encode(&self) -> Result<String, prometheus::Error> {
+        let mut buffer = Vec::new();
+        TextEncoder::new().encode(&self.registry.gather(), &mut buffer)?;
+        Ok(String::from_utf8_lossy(&buffer).into_owned())
+    }
+}
+
+impl Default for Metrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,104 @@
+//! OAuth2 access tokens for XOAUTH2 SMTP authentication
+//!
+//! Providers such as Gmail and Microsoft 365 no longer accept passwords over
+//! SMTP: the client authenticates with a short-lived access token, obtained from
+//! the provider token endpoint with a long-lived refresh token.
+
+use std::time::Duration;
+
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::settings::OAuth2Config;
+
+/// Timeout of a token request
+const TOKEN_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Lifetime assumed when the provider does not give one
+const DEFAULT_TOKEN_LIFETIME_SECS: u64 = 3600;
+
+/// Access token issued by the token endpoint
+pub struct AccessToken {
+    /// Token presented to the SMTP server
+    pub token: String,
+
+    /// Time after which the token is no longer accepted
+    pub expires_in: Duration,
+
+    /// New refresh token, when the provider rotates them
+    pub refresh_token: Option<String>,
+}
+
+/// Successful token endpoint response (RFC 6749 section 5.1)
+#[derive(Deserialize)]
+struct TokenRes {
+    access_token: String,
+    expires_in: Option<u64>,
+    refresh_token: Option<String>,
+}
+
+/// Error token endpoint response (RFC 6749 section 5.2)
+#[derive(Deserialize)]
+struct TokenErrorRes {
+    error: String,
+    error_description: Option<String>,
+}
+
+/// Requests a new access token with the refresh token grant
+///
+/// # Arguments
+/// * `config` - OAuth2 client configuration
+/// * `refresh_token` - Current refresh token (the configured one, or the last rotated one)
+///
+/// # Returns
+/// * `Ok(AccessToken)` - The new access token
+/// * `Err(String)` - The endpoint is unreachable or refused the refresh token
+pub async fn refresh_access_token(
+    config: &OAuth2Config,
+    refresh_token: &str,
+) -> Result<AccessToken, String> {
+    let client = Client::builder()
+        .timeout(TOKEN_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())?;
+
+    let mut form = vec![
+        ("grant_type", "refresh_token"),
+        ("client_id", config.client_id.as_str()),
+        ("refresh_token", refresh_token),
+    ];
+    if let Some(secret) = &config.client_secret {
+        form.push(("client_secret", secret));
+    }
+    if let Some(scope) = &config.scope {
+        form.push(("scope", scope));
+    }
+
+    let res = client
+        .post(&config.token_url)
+        .form(&form)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = res.status();
+    let body = res.bytes().await.map_err(|e| e.to_string())?;
+
+    if !status.is_success() {
+        return Err(match serde_json::from_slice::<TokenErrorRes>(&body) {
+            Ok(err) => format!(
+                "{} {}: {}",
+                status,
+                err.error,
+                err.error_description.unwrap_or_default()
+            ),
+            Err(_) => status.to_string(),
+        });
+    }
+
+    let token: TokenRes = serde_json::from_slice(&body).map_err(|e| e.to_string())?;
+    Ok(AccessToken {
+        token: token.access_token,
+        expires_in: Duration::from_secs(token.expires_in.unwrap_or(DEFAULT_TOKEN_LIFETIME_SECS)),
+        refresh_token: token.refresh_token,
+    })
+}
@@ -0,0 +1,81 @@
+//! OpenAPI specification of the HTTP API
+//!
+//! Each controller module describes its own endpoints with an `ApiDoc`; this
+//! module merges them into a single OpenAPI document. The document and a
+//! Swagger UI page rendering it are served by the send controller.
+
+use std::sync::OnceLock;
+
+use utoipa::OpenApi;
+use utoipa::openapi::security::{ApiKey, ApiKeyValue, SecurityScheme};
+use utoipa::openapi::{InfoBuilder, OpenApi as OpenApiDoc};
+
+use crate::auth::API_KEY_HEADER;
+use crate::canary::canary_controller;
+use crate::metrics::metrics_controller;
+use crate::send::send_controller;
+use crate::template::template_controller;
+use crate::xml::xml_controller;
+
+/// Swagger UI page, loading its assets from a CDN and rendering `/openapi.json`
+pub const SWAGGER_UI_HTML: &str = r##"<!DOCTYPE html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>RustMail API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js"></script>
+  <script>
+    window.ui = SwaggerUIBundle({ url: "openapi.json", dom_id: "#swagger-ui" });
+  </script>
+</body>
+</html>
+"##;
+
+/// Common part of the document: security requirement and tags
+#[derive(OpenApi)]
+#[openapi(
+    security(("api_key" = [])),
+    tags(
+        (name = "health", description = "Service status"),
+        (name = "send", description = "Email sending and scheduling"),
+        (name = "template", description = "Templated emails"),
+        (name = "canary", description = "Deliverability canary"),
+        (name = "metrics", description = "Metrics and delivery statistics")
+    )
+)]
+struct RootDoc;
+
+/// Returns the OpenAPI document of the whole API
+///
+/// The document is built once and cached.
+pub fn api_doc() -> &'static OpenApiDoc {
+    static DOC: OnceLock<OpenApiDoc> = OnceLock::new();
+    DOC.get_or_init(|| {
+        let mut doc = RootDoc::openapi();
+        doc.info = InfoBuilder::new()
+            .title("RustMail")
+            .description(Some(
+                "SMTP email sending service. Request and response bodies are JSON, or \
+                 MessagePack with `Content-Type` / `Accept: application/msgpack`.",
+            ))
+            .version(env!("CARGO_PKG_VERSION"))
+            .build();
+        doc.merge(send_controller::ApiDoc::openapi());
+        doc.merge(template_controller::ApiDoc::openapi());
+        doc.merge(xml_controller::ApiDoc::openapi());
+        doc.merge(canary_controller::ApiDoc::openapi());
+        doc.merge(metrics_controller::ApiDoc::openapi());
+        // Endpoints require the `X-Api-Key` header unless they opt out
+        doc.components
+            .get_or_insert_with(Default::default)
+            .add_security_scheme(
+                "api_key",
+                SecurityScheme::ApiKey(ApiKey::Header(ApiKeyValue::new(API_KEY_HEADER))),
+            );
+        doc
+    })
+}
@@ -0,0 +1,12 @@
+//! Application outbox module
+//!
+//! This module implements the transactional outbox pattern: applications write
+//! emails into an outbox table of their own database, within their business
+//! transactions, and a relayer task submits the committed rows to the send
+//! pipeline.
+
+/// Outbox relayer task
+pub mod relayer;
+
+/// Outbox table schema and access
+pub mod store;
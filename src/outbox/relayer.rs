@@ -0,0 +1,229 @@
+//! Outbox relayer
+//!
+//! The relayer polls the outbox table of the application database and submits
+//! each new row to the send pipeline as a queued `/send` request, so delivery
+//! and its retries are owned by the persistent queue. Rows are processed in
+//! insertion order and marked once submitted. A row is submitted twice only if
+//! the process stops between queueing the email and marking the row; the
+//! duplicate guard can absorb that case.
+
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::rt::{self, task::JoinHandle, time::timeout};
+use actix_web::web;
+use log::{debug, error, info, warn};
+use tokio::sync::watch;
+
+use crate::outbox::store::{OutboxRow, OutboxStatus, OutboxStore};
+use crate::queue::worker::unix_now;
+use crate::send::context::SendContext;
+use crate::send::dto::{SendMailReq, SendOutcome};
+use crate::send::send_controller::process_mail;
+use crate::settings::OutboxConfig;
+
+/// Time a picked row stays hidden from other relayers while being submitted
+const OUTBOX_LEASE: Duration = Duration::from_secs(300);
+
+/// Pause before submitting again a row that failed with a server error
+const OUTBOX_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Server-side failures after which a row is marked failed
+const OUTBOX_MAX_ATTEMPTS: u32 = 5;
+
+/// Result of the submission of one row
+enum Submission {
+    /// Final outcome, recorded on the row
+    Finished {
+        status: OutboxStatus,
+        queue_id: Option<String>,
+        error: Option<String>,
+    },
+
+    /// Server-side failure (e.g. queue database unavailable), submitted again later
+    Retry(String),
+}
+
+/// Submits one outbox row to the send pipeline
+async fn submit(row: &OutboxRow, ctx: &SendContext) -> Submission {
+    let failed = |error: String| Submission::Finished {
+        status: OutboxStatus::Failed,
+        queue_id: None,
+        error: Some(error),
+    };
+
+    let mut payload: SendMailReq = match serde_json::from_str(&row.payload) {
+        Ok(payload) => payload,
+        Err(err) => return failed(format!("Invalid payload: {}", err)),
+    };
+    if payload.smtp.is_some() {
+        return failed("SMTP override is not accepted from the outbox".to_owned());
+    }
+    // The queue delivers and retries, the row only needs to reach it once
+    payload.queue = true;
+
+    match process_mail(payload, ctx).await {
+        Ok(SendOutcome::Queued(x)) => Submission::Finished {
+            status: OutboxStatus::Done,
+            queue_id: Some(x.id),
+            error: None,
+        },
+        // Skipped by the duplicate guard, blackholed or dry run
+        Ok(_) => Submission::Finished {
+            status: OutboxStatus::Done,
+            queue_id: None,
+            error: None,
+        },
+        Err(err) if err.as_response_error().status_code().is_server_error() => {
+            Submission::Retry(err.to_string())
+        }
+        Err(err) => failed(err.to_string()),
+    }
+}
+
+/// Handle of the running outbox relayer
+pub struct OutboxRelayer {
+    /// Signals the relayer to stop
+    stop: watch::Sender<bool>,
+
+    /// Relayer task
+    handle: JoinHandle<()>,
+}
+
+impl OutboxRelayer {
+    /// Stops the relayer once the row being submitted is done
+    ///
+    /// # Arguments
+    /// * `grace` - Maximum time to wait for the submission in flight
+    pub async fn shutdown(self, grace: Duration) {
+        let _ = self.stop.send(true);
+        match timeout(grace, self.handle).await {
+            Ok(_) => info!("Outbox relayer stopped"),
+            Err(_) => warn!(
+                "Outbox relayer still submitting after {:?}, unfinished rows retry after their lease",
+                grace
+            ),
+        }
+    }
+}
+
+/// Runs a blocking outbox operation on the thread pool, logging failures
+async fn with_store<T: Send + 'static>(
+    store: &Arc<OutboxStore>,
+    what: &str,
+    f: impl FnOnce(&OutboxStore) -> rusqlite::Result<T> + Send + 'static,
+) -> Option<T> {
+    let store = Arc::clone(store);
+    match web::block(move || f(&store)).await {
+        Ok(Ok(value)) => Some(value),
+        Ok(Err(err)) => {
+            error!("Cannot {} outbox: {}", what, err);
+            None
+        }
+        Err(err) => {
+            error!("Cannot {} outbox: {}", what, err);
+            None
+        }
+    }
+}
+
+/// Submits all pending rows
+///
+/// When the relayer is stopping, the rest of the batch is released for the
+/// next start.
+async fn process_pending(
+    store: &Arc<OutboxStore>,
+    ctx: &SendContext,
+    config: &OutboxConfig,
+    stop: &watch::Receiver<bool>,
+) {
+    let batch_size = config.batch_size;
+    let Some(rows) = with_store(store, "read", move |s| {
+        s.claim(unix_now(), batch_size, OUTBOX_LEASE)
+    })
+    .await
+    else {
+        return;
+    };
+
+    let mut rows = rows.into_iter();
+    for row in rows.by_ref() {
+        let submission = match submit(&row, ctx).await {
+            Submission::Retry(err) if row.attempts + 1 >= OUTBOX_MAX_ATTEMPTS => {
+                Submission::Finished {
+                    status: OutboxStatus::Failed,
+                    queue_id: None,
+                    error: Some(err),
+                }
+            }
+            submission => submission,
+        };
+        match submission {
+            Submission::Finished {
+                status,
+                queue_id,
+                error,
+            } => {
+                match &error {
+                    Some(err) => warn!("Outbox row {} failed: {}", row.id, err),
+                    None => debug!("Outbox row {} submitted", row.id),
+                }
+                let id = row.id;
+                with_store(store, "update", move |s| {
+                    s.finish(
+                        id,
+                        status,
+                        queue_id.as_deref(),
+                        error.as_deref(),
+                        unix_now(),
+                    )
+                })
+                .await;
+            }
+            Submission::Retry(err) => {
+                warn!(
+                    "Outbox row {} not submitted, retrying in {:?}: {}",
+                    row.id, OUTBOX_RETRY_DELAY, err
+                );
+                let (id, at) = (row.id, unix_now() + OUTBOX_RETRY_DELAY.as_secs());
+                with_store(store, "update", move |s| s.retry(id, at)).await;
+            }
+        }
+        if *stop.borrow() {
+            break;
+        }
+    }
+
+    // Rows left in the batch were picked but not submitted
+    let left: Vec<i64> = rows.map(|row| row.id).collect();
+    if !left.is_empty() {
+        with_store(store, "release", move |s| s.release(&left, unix_now())).await;
+    }
+}
+
+/// Starts the background outbox relayer
+///
+/// # Arguments
+/// * `store` - Outbox table of the application database
+/// * `ctx` - Shared send state, the rows go through the same pipeline as `/send`
+/// * `config` - Outbox relayer configuration (polling, batch size)
+///
+/// # Returns
+/// The handle used to stop the relayer on shutdown
+pub fn start_outbox_relayer(
+    store: Arc<OutboxStore>,
+    ctx: web::Data<SendContext>,
+    config: OutboxConfig,
+) -> OutboxRelayer {
+    let (stop, mut stopped) = watch::channel(false);
+    let handle = rt::spawn(async move {
+        while !*stopped.borrow() {
+            process_pending(&store, &ctx, &config, &stopped).await;
+            // Wake up early when asked to stop; a dropped handle stops the relayer too
+            if let Ok(Err(_)) = timeout(config.poll_interval, stopped.changed()).await {
+                break;
+            }
+        }
+    });
+    OutboxRelayer { stop, handle }
+}
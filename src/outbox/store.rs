@@ -0,0 +1,237 @@
+//! Outbox table of the application database
+//!
+//! Applications insert the emails to send into the `rustmail_outbox` table in
+//! the same transaction as their own changes: an email exists if and only if
+//! the transaction committed. Rust applications can use [`create_outbox_table`]
+//! and [`write_outbox`]; others insert rows with plain SQL following
+//! [`OUTBOX_SCHEMA`]. The relayer leases rows while submitting them, so several
+//! rustmail processes can share one outbox.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use rusqlite::{Connection, params};
+use serde::Serialize;
+
+/// Schema of the outbox table
+///
+/// `payload` holds the JSON body of a `/send` request. The application only
+/// sets `payload`; the other columns are maintained by the relayer:
+/// `status` goes from `pending` to `done` (queued, or skipped by the send
+/// policies) or `failed` (rejected request, reason in `error`), `attempts`
+/// counts the submissions that failed on the server side, and `queue_id` is the
+/// identifier of the email in the rustmail queue.
+pub const OUTBOX_SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS rustmail_outbox (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    payload TEXT NOT NULL,
+    status TEXT NOT NULL DEFAULT 'pending',
+    created_at INTEGER NOT NULL DEFAULT (CAST(strftime('%s', 'now') AS INTEGER)),
+    locked_until INTEGER NOT NULL DEFAULT 0,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    processed_at INTEGER,
+    queue_id TEXT,
+    error TEXT
+);
+CREATE INDEX IF NOT EXISTS rustmail_outbox_pending ON rustmail_outbox (status, id);
+";
+
+/// Processing status of an outbox row
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum OutboxStatus {
+    /// Waiting to be submitted
+    Pending,
+
+    /// Submitted to the send pipeline
+    Done,
+
+    /// Rejected by the send pipeline
+    Failed,
+}
+
+impl OutboxStatus {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            OutboxStatus::Pending => "pending",
+            OutboxStatus::Done => "done",
+            OutboxStatus::Failed => "failed",
+        }
+    }
+}
+
+/// Creates the outbox table and its index when missing
+///
+/// # Arguments
+/// * `conn` - Connection to the application database
+pub fn create_outbox_table(conn: &Connection) -> rusqlite::Result<()> {
+    conn.execute_batch(OUTBOX_SCHEMA)
+}
+
+/// Adds an email to the outbox
+///
+/// Call it with the connection (or transaction, which dereferences to one) of
+/// the application changes the email belongs to.
+///
+/// # Arguments
+/// * `conn` - Connection or transaction on the application database
+/// * `request` - Body of a `/send` request, e.g. a `serde_json::json!` value
+///
+/// # Returns
+/// The identifier of the outbox row
+pub fn write_outbox<T: Serialize>(conn: &Connection, request: &T) -> rusqlite::Result<i64> {
+    let payload = serde_json::to_string(request)
+        .map_err(|e| rusqlite::Error::ToSqlConversionFailure(Box::new(e)))?;
+    conn.execute(
+        "INSERT INTO rustmail_outbox (payload) VALUES (?1)",
+        params![payload],
+    )?;
+    Ok(conn.last_insert_rowid())
+}
+
+/// Outbox row picked for submission
+pub struct OutboxRow {
+    /// Row identifier
+    pub id: i64,
+
+    /// JSON body of a `/send` request
+    pub payload: String,
+
+    /// Failed submissions so far
+    pub attempts: u32,
+}
+
+/// Outbox table read by the relayer
+///
+/// The connection is guarded by a mutex; callers in async context should run
+/// these methods on the blocking thread pool.
+pub struct OutboxStore {
+    /// Connection to the application database
+    conn: Mutex<Connection>,
+}
+
+impl OutboxStore {
+    /// Opens the application database and creates the outbox table when missing
+    ///
+    /// The journal mode of the database is left to the application.
+    ///
+    /// # Arguments
+    /// * `path` - SQLite database file path
+    ///
+    /// # Returns
+    /// * `Ok(OutboxStore)` - The store
+    /// * `Err(String)` - The database cannot be opened or the table created
+    pub fn open(path: &str) -> Result<Self, String> {
+        let conn = Connection::open(path).map_err(|e| e.to_string())?;
+        // Wait for the application transactions instead of failing at once
+        conn.busy_timeout(Duration::from_secs(5))
+            .and_then(|_| create_outbox_table(&conn))
+            .map_err(|e| e.to_string())?;
+
+        Ok(OutboxStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Locks the connection, recovering from a poisoned mutex
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Picks pending rows in insertion order and leases them
+    ///
+    /// A row whose relayer stopped mid-submission is picked again once the
+    /// lease expires.
+    ///
+    /// # Arguments
+    /// * `now` - Current Unix timestamp in seconds
+    /// * `limit` - Maximum number of rows returned
+    /// * `lease` - Time the picked rows stay hidden from other relayers
+    pub fn claim(
+        &self,
+        now: u64,
+        limit: usize,
+        lease: Duration,
+    ) -> rusqlite::Result<Vec<OutboxRow>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "UPDATE rustmail_outbox SET locked_until = ?4
+             WHERE id IN (
+                SELECT id FROM rustmail_outbox WHERE status = ?1 AND locked_until <= ?2
+                ORDER BY id LIMIT ?3
+             )
+             RETURNING id, payload, attempts",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                OutboxStatus::Pending.as_str(),
+                now as i64,
+                limit as i64,
+                (now + lease.as_secs()) as i64
+            ],
+            |row| {
+                Ok(OutboxRow {
+                    id: row.get(0)?,
+                    payload: row.get(1)?,
+                    attempts: row.get(2)?,
+                })
+            },
+        )?;
+        let mut picked = rows.collect::<rusqlite::Result<Vec<_>>>()?;
+        // RETURNING gives no order guarantee
+        picked.sort_by_key(|r| r.id);
+        Ok(picked)
+    }
+
+    /// Records the outcome of a submitted row
+    ///
+    /// # Arguments
+    /// * `id` - Row identifier
+    /// * `status` - `Done` or `Failed`
+    /// * `queue_id` - Queue identifier of the email, when it was queued
+    /// * `error` - Reason of the failure
+    /// * `now` - Current Unix timestamp in seconds
+    pub fn finish(
+        &self,
+        id: i64,
+        status: OutboxStatus,
+        queue_id: Option<&str>,
+        error: Option<&str>,
+        now: u64,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE rustmail_outbox SET status = ?2, queue_id = ?3, error = ?4, processed_at = ?5
+             WHERE id = ?1",
+            params![id, status.as_str(), queue_id, error, now as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Counts a failed submission and leaves the row pending until the given time
+    ///
+    /// # Arguments
+    /// * `id` - Row identifier
+    /// * `at` - Unix timestamp from which the row can be picked again
+    pub fn retry(&self, id: i64, at: u64) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE rustmail_outbox SET locked_until = ?2, attempts = attempts + 1 WHERE id = ?1",
+            params![id, at as i64],
+        )?;
+        Ok(())
+    }
+
+    /// Leaves rows pending, to be picked again from the given time
+    ///
+    /// # Arguments
+    /// * `ids` - Row identifiers
+    /// * `at` - Unix timestamp from which the rows can be picked again
+    pub fn release(&self, ids: &[i64], at: u64) -> rusqlite::Result<()> {
+        let conn = self.conn();
+        let mut stmt =
+            conn.prepare("UPDATE rustmail_outbox SET locked_until = ?2 WHERE id = ?1")?;
+        for id in ids {
+            stmt.execute(params![id, at as i64])?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,95 @@
+//! Mailgun messages API transport
+//!
+//! The formatted message is uploaded as is to the `messages.mime` endpoint, so
+//! the DKIM signature of the send pipeline is preserved; the recipients are
+//! those of the envelope.
+
+use futures_util::future::BoxFuture;
+use lettre::address::Envelope;
+use reqwest::Client;
+
+use crate::provider::{http_client, submit};
+use crate::settings::ApiTransportConfig;
+use crate::transport::{MailTransport, TransportError};
+
+/// Default API base URL (US region)
+const DEFAULT_MAILGUN_URL: &str = "https://api.mailgun.net";
+
+/// Transport delivering through the Mailgun messages API
+pub struct MailgunTransport {
+    /// Shared HTTP client
+    client: Client,
+
+    /// API key, sent with basic authentication as user `api`
+    api_key: String,
+
+    /// MIME messages endpoint of the sending domain
+    endpoint: String,
+}
+
+impl MailgunTransport {
+    /// Builds the transport from the API settings of a profile
+    ///
+    /// # Returns
+    /// * `Ok(MailgunTransport)` - The transport
+    /// * `Err(String)` - The API key or the domain is missing, or the HTTP client cannot be built
+    pub fn new(config: &ApiTransportConfig) -> Result<Self, String> {
+        let api_key = config
+            .key
+            .clone()
+            .ok_or("Mailgun transport requires MAILGUN_API_KEY")?;
+        let domain = config
+            .domain
+            .as_deref()
+            .ok_or("Mailgun transport requires MAILGUN_DOMAIN")?;
+        let base = config.url.as_deref().unwrap_or(DEFAULT_MAILGUN_URL);
+
+        Ok(MailgunTransport {
+            client: http_client()?,
+            api_key,
+            endpoint: format!("{}/v3/{}/messages.mime", base.trim_end_matches('/'), domain),
+        })
+    }
+}
+
+/// Builds the `multipart/form-data` body with the recipients and the message file
+///
+/// # Returns
+/// The content type, with its boundary, and the body
+fn form_body(recipients: &str, email: &[u8]) -> (String, Vec<u8>) {
+    let boundary = format!("rustmail-{:016x}", rand::random::<u64>());
+    let mut body = Vec::with_capacity(email.len() + 512);
+    body.extend_from_slice(
+        format!(
+            "--{b}\r\nContent-Disposition: form-data; name=\"to\"\r\n\r\n{to}\r\n\
+             --{b}\r\nContent-Disposition: form-data; name=\"message\"; filename=\"message.mime\"\r\n\
+             Content-Type: message/rfc822\r\n\r\n",
+            b = boundary,
+            to = recipients
+        )
+        .as_bytes(),
+    );
+    body.extend_from_slice(email);
+    body.extend_from_slice(format!("\r\n--{}--\r\n", boundary).as_bytes());
+    (format!("multipart/form-data; boundary={}", boundary), body)
+}
+
+impl MailTransport for MailgunTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            let recipients: Vec<String> = envelope.to().iter().map(|a| a.to_string()).collect();
+            let (content_type, body) = form_body(&recipients.join(","), email);
+            let request = self
+                .client
+                .post(&self.endpoint)
+                .basic_auth("api", Some(&self.api_key))
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(body);
+            submit(request).await
+        })
+    }
+}
@@ -0,0 +1,339 @@
+//! MIME parsing of built messages
+//!
+//! Providers whose API takes the message as structured fields (SendGrid) need
+//! the parts of the formatted message back: the text and HTML bodies, the
+//! attachments and the headers. The parser handles the messages built by the
+//! send pipeline; it is not meant for arbitrary mail.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+
+/// Decoded content of a message
+#[derive(Default)]
+pub struct ParsedMessage {
+    /// Top-level headers in order, unfolded, values still encoded
+    pub headers: Vec<(String, String)>,
+
+    /// Plain text body
+    pub text: Option<String>,
+
+    /// HTML body
+    pub html: Option<String>,
+
+    /// Attachments and inline parts
+    pub attachments: Vec<ParsedAttachment>,
+}
+
+impl ParsedMessage {
+    /// Returns the first value of a header (case-insensitive name)
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(n, _)| n.eq_ignore_ascii_case(name))
+            .map(|(_, v)| v.as_str())
+    }
+}
+
+/// Attachment of a parsed message
+pub struct ParsedAttachment {
+    /// File name, when given
+    pub filename: Option<String>,
+
+    /// MIME type, e.g. `application/pdf`
+    pub content_type: String,
+
+    /// Content-ID of an inline part, without angle brackets
+    pub content_id: Option<String>,
+
+    /// Whether the part is displayed inline
+    pub inline: bool,
+
+    /// Decoded content
+    pub data: Vec<u8>,
+}
+
+/// Parses a formatted message
+///
+/// # Arguments
+/// * `raw` - Formatted message bytes
+///
+/// # Returns
+/// * `Ok(ParsedMessage)` - Headers, bodies and attachments
+/// * `Err(String)` - A multipart body has no boundary
+pub fn parse_message(raw: &[u8]) -> Result<ParsedMessage, String> {
+    let raw = String::from_utf8_lossy(raw);
+    let (head, body) = split_head(&raw);
+    let headers = parse_headers(head);
+
+    let mut message = ParsedMessage::default();
+    parse_part(&headers, body, &mut message)?;
+    message.headers = headers;
+    Ok(message)
+}
+
+/// Splits a message or part into its header block and body
+fn split_head(raw: &str) -> (&str, &str) {
+    match raw.find("\r\n\r\n") {
+        Some(i) => (&raw[..i], &raw[i + 4..]),
+        None => match raw.find("\n\n") {
+            Some(i) => (&raw[..i], &raw[i + 2..]),
+            None => (raw, ""),
+        },
+    }
+}
+
+/// Parses a header block, unfolding continuation lines
+fn parse_headers(head: &str) -> Vec<(String, String)> {
+    let mut headers: Vec<(String, String)> = Vec::new();
+    for line in head.lines() {
+        if line.starts_with([' ', '\t']) {
+            if let Some((_, value)) = headers.last_mut() {
+                value.push(' ');
+                value.push_str(line.trim());
+            }
+        } else if let Some((name, value)) = line.split_once(':') {
+            headers.push((name.trim().to_owned(), value.trim().to_owned()));
+        }
+    }
+    headers
+}
+
+/// Returns the first value of a header in a header list
+fn find<'a>(headers: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    headers
+        .iter()
+        .find(|(n, _)| n.eq_ignore_ascii_case(name))
+        .map(|(_, v)| v.as_str())
+}
+
+/// Splits a structured header value (`type; param=value`) into its main value
+/// and its parameters with lowercase names
+fn parse_params(value: &str) -> (String, Vec<(String, String)>) {
+    let mut fields = Vec::new();
+    let (mut current, mut quoted) = (String::new(), false);
+    for c in value.chars() {
+        match c {
+            '"' => quoted = !quoted,
+            ';' if !quoted => fields.push(std::mem::take(&mut current)),
+            _ => current.push(c),
+        }
+    }
+    fields.push(current);
+
+    let mut fields = fields.into_iter();
+    let main = fields.next().unwrap_or_default().trim().to_lowercase();
+    let params = fields
+        .filter_map(|f| {
+            let (name, value) = f.split_once('=')?;
+            Some((name.trim().to_lowercase(), value.trim().to_owned()))
+        })
+        .collect();
+    (main, params)
+}
+
+/// Returns a parameter value, decoding RFC 2231 (`name*=utf-8''...`, split in
+/// `name*0*`, `name*1*`... continuations) and RFC 2047 forms
+fn param(params: &[(String, String)], name: &str) -> Option<String> {
+    // Extended segments: `name*` alone, or numbered, `*` suffixed when percent-encoded
+    let mut segments: Vec<(usize, bool, &str)> = params
+        .iter()
+        .filter_map(|(n, v)| {
+            let section = n.strip_prefix(name)?.strip_prefix('*')?;
+            let (index, encoded) = match section.strip_suffix('*') {
+                Some(index) => (index, true),
+                None => (section, section.is_empty()),
+            };
+            let index = if index.is_empty() {
+                0
+            } else {
+                index.parse().ok()?
+            };
+            Some((index, encoded, v.as_str()))
+        })
+        .collect();
+    if !segments.is_empty() {
+        segments.sort_by_key(|(index, _, _)| *index);
+        let mut bytes = String::new();
+        for (index, encoded, value) in segments {
+            // The first encoded segment starts with `charset'language'`
+            let value = match (index, encoded) {
+                (0, true) => value.splitn(3, '\'').nth(2).unwrap_or(value),
+                _ => value,
+            };
+            if encoded {
+                bytes.push_str(value);
+            } else {
+                bytes.push_str(&value.replace('%', "%25"));
+            }
+        }
+        return Some(percent_decode(&bytes));
+    }
+    params
+        .iter()
+        .find(|(n, _)| n == name)
+        .map(|(_, v)| decode_header(v))
+}
+
+/// Decodes `%XX` escapes
+fn percent_decode(value: &str) -> String {
+    let bytes = value.as_bytes();
+    let mut out = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match (bytes[i], value.get(i + 1..i + 3)) {
+            (b'%', Some(hex)) if u8::from_str_radix(hex, 16).is_ok() => {
+                out.push(u8::from_str_radix(hex, 16).unwrap_or_default());
+                i += 3;
+            }
+            (b, _) => {
+                out.push(b);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&out).into_owned()
+}
+
+/// Decodes the RFC 2047 encoded words of a header value (`=?utf-8?b?...?=`)
+///
+/// Whitespace between two encoded words is dropped, as the RFC requires.
+pub fn decode_header(value: &str) -> String {
+    let mut out = String::new();
+    let mut rest = value;
+    let mut after_word = false;
+    while let Some(start) = rest.find("=?") {
+        let decoded = rest[start + 2..].split_once("?=").and_then(|(word, tail)| {
+            let mut fields = word.splitn(3, '?');
+            let (_charset, encoding, text) = (fields.next()?, fields.next()?, fields.next()?);
+            let bytes = match encoding.to_ascii_lowercase().as_str() {
+                "b" => STANDARD.decode(text).ok()?,
+                "q" => quoted_printable::decode(
+                    text.replace('_', " "),
+                    quoted_printable::ParseMode::Robust,
+                )
+                .ok()?,
+                _ => return None,
+            };
+            Some((String::from_utf8_lossy(&bytes).into_owned(), tail))
+        });
+        let Some((text, tail)) = decoded else {
+            out.push_str(&rest[..start + 2]);
+            rest = &rest[start + 2..];
+            after_word = false;
+            continue;
+        };
+        let gap = &rest[..start];
+        if !(after_word && gap.trim().is_empty()) {
+            out.push_str(gap);
+        }
+        out.push_str(&text);
+        rest = tail;
+        after_word = true;
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Decodes a part body according to its transfer encoding
+fn decode_body(headers: &[(String, String)], body: &str) -> Vec<u8> {
+    let encoding = find(headers, "Content-Transfer-Encoding")
+        .unwrap_or_default()
+        .to_lowercase();
+    match encoding.as_str() {
+        "base64" => {
+            let compact: String = body.split_whitespace().collect();
+            STANDARD.decode(compact).unwrap_or_default()
+        }
+        "quoted-printable" => quoted_printable::decode(body, quoted_printable::ParseMode::Robust)
+            .unwrap_or_else(|_| body.as_bytes().to_vec()),
+        _ => body.as_bytes().to_vec(),
+    }
+}
+
+/// Returns the body parts of a multipart body
+fn split_multipart<'a>(body: &'a str, boundary: &str) -> Vec<&'a str> {
+    let delimiter = format!("--{}", boundary);
+    let mut parts = Vec::new();
+    let mut current: Option<usize> = None;
+    let mut offset = 0;
+    for line in body.split_inclusive('\n') {
+        let trimmed = line.trim_end();
+        if trimmed.starts_with(&delimiter) {
+            if let Some(start) = current {
+                // The line break before a delimiter belongs to the delimiter
+                let part = &body[start..offset];
+                parts.push(
+                    part.strip_suffix("\r\n")
+                        .or_else(|| part.strip_suffix('\n'))
+                        .unwrap_or(part),
+                );
+            }
+            if trimmed[delimiter.len()..].starts_with("--") {
+                return parts;
+            }
+            current = Some(offset + line.len());
+        }
+        offset += line.len();
+    }
+    parts
+}
+
+/// Collects the bodies and attachments of one part, recursing into multiparts
+fn parse_part(
+    headers: &[(String, String)],
+    body: &str,
+    message: &mut ParsedMessage,
+) -> Result<(), String> {
+    let (content_type, type_params) =
+        parse_params(find(headers, "Content-Type").unwrap_or("text/plain"));
+
+    if content_type.starts_with("multipart/") {
+        let boundary = param(&type_params, "boundary")
+            .ok_or_else(|| format!("{} body without boundary", content_type))?;
+        for part in split_multipart(body, &boundary) {
+            let (head, body) = split_head(part);
+            parse_part(&parse_headers(head), body, message)?;
+        }
+        return Ok(());
+    }
+
+    let (disposition, disposition_params) =
+        parse_params(find(headers, "Content-Disposition").unwrap_or_default());
+    let filename = param(&disposition_params, "filename").or_else(|| param(&type_params, "name"));
+    let data = decode_body(headers, body);
+
+    let is_attachment = disposition == "attachment" || filename.is_some();
+    match content_type.as_str() {
+        "text/plain" if !is_attachment && message.text.is_none() => {
+            message.text = Some(String::from_utf8_lossy(&data).into_owned());
+        }
+        "text/html" if !is_attachment && message.html.is_none() => {
+            message.html = Some(String::from_utf8_lossy(&data).into_owned());
+        }
+        _ => message.attachments.push(ParsedAttachment {
+            filename,
+            content_type,
+            content_id: find(headers, "Content-ID")
+                .map(|id| id.trim_matches(|c| c == '<' || c == '>').to_owned()),
+            inline: disposition == "inline",
+            data,
+        }),
+    }
+    Ok(())
+}
+
+/// Splits a single mailbox header value (`Name <address>`) into its decoded
+/// display name and address
+///
+/// # Returns
+/// The display name, if any, and the address
+pub fn parse_mailbox(value: &str) -> (Option<String>, String) {
+    match (value.rfind('<'), value.rfind('>')) {
+        (Some(start), Some(end)) if start < end => {
+            let name = value[..start].trim().trim_matches('"').trim();
+            let name = (!name.is_empty()).then(|| decode_header(name));
+            (name, value[start + 1..end].trim().to_owned())
+        }
+        _ => (None, value.trim().to_owned()),
+    }
+}
@@ -0,0 +1,98 @@
+//! HTTP API mail providers module
+//!
+//! This module implements transports delivering through the HTTP APIs of mail
+//! providers (SendGrid, Mailgun and Amazon SES), for deployments without SMTP
+//! egress. They sit behind the same [`MailTransport`] trait as the SMTP
+//! transport, so the send pipeline, the queue and the profiles work unchanged.
+//!
+//! [`MailTransport`]: crate::transport::MailTransport
+
+use std::time::Duration;
+
+use reqwest::{Client, RequestBuilder};
+use serde::Deserialize;
+
+use crate::transport::TransportError;
+
+/// Mailgun messages API transport
+pub mod mailgun;
+
+/// MIME parsing of built messages
+pub mod mime;
+
+/// SendGrid v3 Mail Send API transport
+pub mod sendgrid;
+
+/// Amazon SES v2 API transport
+pub mod ses;
+
+/// Timeout of one API request
+const PROVIDER_REQUEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Longest part of an error response kept in the error message
+const ERROR_SNIPPET_LEN: usize = 200;
+
+/// Builds the HTTP client of a provider transport
+///
+/// The client keeps its connections alive, so successive sends reuse them.
+fn http_client() -> Result<Client, String> {
+    Client::builder()
+        .timeout(PROVIDER_REQUEST_TIMEOUT)
+        .build()
+        .map_err(|e| e.to_string())
+}
+
+/// Error body shared by the providers: `{"message": ...}` (Mailgun, SES) or
+/// `{"errors": [{"message": ...}]}` (SendGrid)
+#[derive(Deserialize)]
+struct ErrorRes {
+    #[serde(alias = "Message")]
+    message: Option<String>,
+    #[serde(default)]
+    errors: Vec<ErrorItem>,
+}
+
+/// One error of a SendGrid error response
+#[derive(Deserialize)]
+struct ErrorItem {
+    message: String,
+}
+
+/// Extracts a readable message from an error response body
+fn error_message(body: &[u8]) -> String {
+    if let Ok(res) = serde_json::from_slice::<ErrorRes>(body) {
+        let mut messages: Vec<String> = res.errors.into_iter().map(|e| e.message).collect();
+        messages.extend(res.message);
+        if !messages.is_empty() {
+            return messages.join("; ");
+        }
+    }
+    String::from_utf8_lossy(body)
+        .chars()
+        .take(ERROR_SNIPPET_LEN)
+        .collect::<String>()
+        .trim()
+        .to_owned()
+}
+
+/// Sends an API request, mapping failures to transport errors
+///
+/// # Returns
+/// * `Ok(())` - The provider accepted the message (2xx)
+/// * `Err(TransportError::Http)` - The provider is unreachable or refused the message
+async fn submit(request: RequestBuilder) -> Result<(), TransportError> {
+    let res = request.send().await.map_err(|e| TransportError::Http {
+        status: None,
+        message: e.to_string(),
+    })?;
+    let status = res.status();
+    if status.is_success() {
+        return Ok(());
+    }
+
+    let body = res.bytes().await.unwrap_or_default();
+    Err(TransportError::Http {
+        status: Some(status.as_u16()),
+        message: format!("{}: {}", status, error_message(&body)),
+    })
+}
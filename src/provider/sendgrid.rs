@@ -0,0 +1,242 @@
+//! SendGrid v3 Mail Send API transport
+//!
+//! SendGrid takes the message as structured JSON rather than MIME, so the
+//! formatted message is parsed back into its subject, bodies, attachments and
+//! headers. SendGrid rebuilds the MIME message and signs it with its own DKIM
+//! key: a signature added by the send pipeline does not survive.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::future::BoxFuture;
+use lettre::address::Envelope;
+use reqwest::Client;
+use serde::Serialize;
+use serde_json::{Map, Value};
+
+use crate::provider::mime::{ParsedMessage, decode_header, parse_mailbox, parse_message};
+use crate::provider::{http_client, submit};
+use crate::settings::ApiTransportConfig;
+use crate::transport::{MailTransport, TransportError};
+
+/// Default API base URL
+const DEFAULT_SENDGRID_URL: &str = "https://api.sendgrid.com";
+
+/// Headers set from dedicated fields or rejected by SendGrid
+const RESERVED_HEADERS: &[&str] = &[
+    "from",
+    "to",
+    "cc",
+    "bcc",
+    "reply-to",
+    "subject",
+    "date",
+    "mime-version",
+    "content-type",
+    "content-transfer-encoding",
+    "dkim-signature",
+    "received",
+];
+
+/// Mail Send request body
+#[derive(Serialize)]
+struct MailSendReq {
+    personalizations: Vec<Personalization>,
+    from: EmailAddress,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    reply_to: Option<EmailAddress>,
+    subject: String,
+    content: Vec<Content>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    attachments: Vec<SendGridAttachment>,
+    #[serde(skip_serializing_if = "Map::is_empty")]
+    headers: Map<String, Value>,
+}
+
+/// Recipients of the message
+#[derive(Serialize)]
+struct Personalization {
+    to: Vec<EmailAddress>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    cc: Vec<EmailAddress>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc: Vec<EmailAddress>,
+}
+
+/// Mailbox with its optional display name
+#[derive(Serialize)]
+struct EmailAddress {
+    email: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    name: Option<String>,
+}
+
+/// Body of the message in one MIME type
+#[derive(Serialize)]
+struct Content {
+    #[serde(rename = "type")]
+    content_type: &'static str,
+    value: String,
+}
+
+/// Attachment, base64 encoded
+#[derive(Serialize)]
+struct SendGridAttachment {
+    content: String,
+    #[serde(rename = "type")]
+    content_type: String,
+    filename: String,
+    disposition: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    content_id: Option<String>,
+}
+
+/// Transport delivering through the SendGrid v3 Mail Send API
+pub struct SendGridTransport {
+    /// Shared HTTP client
+    client: Client,
+
+    /// API key, sent as bearer token
+    api_key: String,
+
+    /// Mail Send endpoint
+    endpoint: String,
+}
+
+impl SendGridTransport {
+    /// Builds the transport from the API settings of a profile
+    ///
+    /// # Returns
+    /// * `Ok(SendGridTransport)` - The transport
+    /// * `Err(String)` - The API key is missing or the HTTP client cannot be built
+    pub fn new(config: &ApiTransportConfig) -> Result<Self, String> {
+        let api_key = config
+            .key
+            .clone()
+            .ok_or("SendGrid transport requires SENDGRID_API_KEY")?;
+        let base = config.url.as_deref().unwrap_or(DEFAULT_SENDGRID_URL);
+
+        Ok(SendGridTransport {
+            client: http_client()?,
+            api_key,
+            endpoint: format!("{}/v3/mail/send", base.trim_end_matches('/')),
+        })
+    }
+}
+
+/// Returns `true` when a recipient address is listed in an address header
+fn listed_in(message: &ParsedMessage, header: &str, address: &str) -> bool {
+    message
+        .header(header)
+        .is_some_and(|v| v.to_lowercase().contains(&address.to_lowercase()))
+}
+
+/// Maps a formatted message onto a Mail Send request
+///
+/// Envelope recipients listed in `To` or `Cc` keep their field; the others are
+/// sent as `bcc`, so they stay hidden as with SMTP.
+fn mail_send_req(envelope: &Envelope, email: &[u8]) -> Result<MailSendReq, TransportError> {
+    let message = parse_message(email).map_err(|e| TransportError::Http {
+        status: None,
+        message: e,
+    })?;
+
+    let (mut to, mut cc, mut bcc) = (Vec::new(), Vec::new(), Vec::new());
+    for recipient in envelope.to() {
+        let address = recipient.to_string();
+        let list = if listed_in(&message, "To", &address) {
+            &mut to
+        } else if listed_in(&message, "Cc", &address) {
+            &mut cc
+        } else {
+            &mut bcc
+        };
+        list.push(EmailAddress {
+            email: address,
+            name: None,
+        });
+    }
+    // SendGrid requires a `to` recipient
+    if to.is_empty() {
+        to = std::mem::take(&mut bcc);
+    }
+
+    let (name, address) = parse_mailbox(message.header("From").unwrap_or_default());
+    let from = EmailAddress {
+        email: envelope.from().map(|a| a.to_string()).unwrap_or(address),
+        name,
+    };
+    let reply_to = message.header("Reply-To").map(|v| {
+        let (name, email) = parse_mailbox(v);
+        EmailAddress { email, name }
+    });
+
+    let mut content = Vec::new();
+    if let Some(text) = &message.text {
+        content.push(Content {
+            content_type: "text/plain",
+            value: text.clone(),
+        });
+    }
+    if let Some(html) = &message.html {
+        content.push(Content {
+            content_type: "text/html",
+            value: html.clone(),
+        });
+    }
+
+    let attachments = message
+        .attachments
+        .iter()
+        .map(|a| SendGridAttachment {
+            content: STANDARD.encode(&a.data),
+            content_type: a.content_type.clone(),
+            filename: a
+                .filename
+                .clone()
+                .unwrap_or_else(|| "attachment".to_owned()),
+            disposition: if a.inline { "inline" } else { "attachment" },
+            content_id: a.content_id.clone(),
+        })
+        .collect();
+
+    let headers = message
+        .headers
+        .iter()
+        .filter(|(name, _)| !RESERVED_HEADERS.contains(&name.to_lowercase().as_str()))
+        .map(|(name, value)| (name.clone(), Value::String(decode_header(value))))
+        .collect();
+
+    Ok(MailSendReq {
+        personalizations: vec![Personalization { to, cc, bcc }],
+        from,
+        reply_to,
+        subject: decode_header(message.header("Subject").unwrap_or_default()),
+        content,
+        attachments,
+        headers,
+    })
+}
+
+impl MailTransport for SendGridTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            let body = serde_json::to_vec(&mail_send_req(envelope, email)?).map_err(|e| {
+                TransportError::Http {
+                    status: None,
+                    message: e.to_string(),
+                }
+            })?;
+            let request = self
+                .client
+                .post(&self.endpoint)
+                .bearer_auth(&self.api_key)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body);
+            submit(request).await
+        })
+    }
+}
@@ -0,0 +1,309 @@
+//! Amazon SES v2 API transport
+//!
+//! The formatted message is sent as raw content, so the DKIM signature of the
+//! send pipeline is preserved; the recipients are those of the envelope.
+//! Requests are signed with AWS Signature Version 4.
+
+use base64::Engine;
+use base64::engine::general_purpose::STANDARD;
+use futures_util::future::BoxFuture;
+use lettre::address::Envelope;
+use reqwest::{Client, Url};
+use serde::Serialize;
+use time::OffsetDateTime;
+
+use crate::crypto::{hex, hmac_sha256, sha256};
+use crate::provider::{http_client, submit};
+use crate::settings::ApiTransportConfig;
+use crate::transport::{MailTransport, TransportError};
+
+/// Path of the SendEmail operation
+const SES_SEND_PATH: &str = "/v2/email/outbound-emails";
+
+/// Service name in the signature scope
+const SES_SERVICE: &str = "ses";
+
+/// SendEmail request body with raw content
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct SendEmailReq {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    from_email_address: Option<String>,
+    destination: Destination,
+    content: RawContent,
+}
+
+/// Recipients of the message
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct Destination {
+    to_addresses: Vec<String>,
+}
+
+/// Raw MIME content
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawContent {
+    raw: RawMessage,
+}
+
+/// Base64 encoded message
+#[derive(Serialize)]
+#[serde(rename_all = "PascalCase")]
+struct RawMessage {
+    data: String,
+}
+
+/// Transport delivering through the Amazon SES v2 API
+pub struct SesTransport {
+    /// Shared HTTP client
+    client: Client,
+
+    /// SendEmail endpoint
+    endpoint: Url,
+
+    /// AWS region, part of the signature scope
+    region: String,
+
+    /// AWS access key ID
+    access_key_id: String,
+
+    /// AWS secret access key
+    secret_access_key: String,
+
+    /// Session token of temporary credentials
+    session_token: Option<String>,
+}
+
+impl SesTransport {
+    /// Builds the transport from the API settings of a profile
+    ///
+    /// # Returns
+    /// * `Ok(SesTransport)` - The transport
+    /// * `Err(String)` - The region or the credentials are missing, the URL is
+    ///   invalid or the HTTP client cannot be built
+    pub fn new(config: &ApiTransportConfig) -> Result<Self, String> {
+        let region = config
+            .region
+            .clone()
+            .ok_or("SES transport requires SES_REGION")?;
+        let access_key_id = config
+            .key
+            .clone()
+            .ok_or("SES transport requires SES_ACCESS_KEY_ID")?;
+        let secret_access_key = config
+            .secret
+            .clone()
+            .ok_or("SES transport requires SES_SECRET_ACCESS_KEY")?;
+        let base = match &config.url {
+            Some(url) => url.trim_end_matches('/').to_owned(),
+            None => format!("https://email.{}.amazonaws.com", region),
+        };
+        let endpoint = Url::parse(&format!("{}{}", base, SES_SEND_PATH))
+            .map_err(|e| format!("Invalid SES_API_URL {}: {}", base, e))?;
+
+        Ok(SesTransport {
+            client: http_client()?,
+            endpoint,
+            region,
+            access_key_id,
+            secret_access_key,
+            session_token: config.session_token.clone(),
+        })
+    }
+
+    /// Computes the `Authorization` header of a request (SigV4)
+    ///
+    /// # Arguments
+    /// * `host` - Value of the `Host` header
+    /// * `amz_date` - Request time, `YYYYMMDDTHHMMSSZ`
+    /// * `body` - Request body
+    fn authorization(&self, host: &str, amz_date: &str, body: &[u8]) -> String {
+        let date = &amz_date[..8];
+        let mut headers = vec![
+            ("content-type", "application/json"),
+            ("host", host),
+            ("x-amz-date", amz_date),
+        ];
+        if let Some(token) = &self.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+
+        let canonical_request = format!(
+            "POST\n{}\n\n{}\n{}\n{}",
+            self.endpoint.path(),
+            canonical_headers,
+            signed_headers,
+            hex(&sha256(body))
+        );
+        let scope = format!("{}/{}/{}/aws4_request", date, self.region, SES_SERVICE);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&sha256(canonical_request.as_bytes()))
+        );
+
+        let secret = format!("AWS4{}", self.secret_access_key);
+        let key = hmac_sha256(secret.as_bytes(), date.as_bytes());
+        let key = hmac_sha256(&key, self.region.as_bytes());
+        let key = hmac_sha256(&key, SES_SERVICE.as_bytes());
+        let key = hmac_sha256(&key, b"aws4_request");
+        let signature = hex(&hmac_sha256(&key, string_to_sign.as_bytes()));
+
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            self.access_key_id, scope, signed_headers, signature
+        )
+    }
+}
+
+/// Formats a time as a SigV4 timestamp (`YYYYMMDDTHHMMSSZ`)
+fn amz_date(now: OffsetDateTime) -> String {
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        now.year(),
+        u8::from(now.month()),
+        now.day(),
+        now.hour(),
+        now.minute(),
+        now.second()
+    )
+}
+
+impl MailTransport for SesTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            let req = SendEmailReq {
+                from_email_address: envelope.from().map(|a| a.to_string()),
+                destination: Destination {
+                    to_addresses: envelope.to().iter().map(|a| a.to_string()).collect(),
+                },
+                content: RawContent {
+                    raw: RawMessage {
+                        data: STANDARD.encode(email),
+                    },
+                },
+            };
+            let body = serde_json::to_vec(&req).map_err(|e| TransportError::Http {
+                status: None,
+                message: e.to_string(),
+            })?;
+
+            let host = self.endpoint.host_str().unwrap_or_default();
+            let host = match self.endpoint.port() {
+                Some(port) => format!("{}:{}", host, port),
+                None => host.to_owned(),
+            };
+            let amz_date = amz_date(OffsetDateTime::now_utc());
+            let mut request = self
+                .client
+                .post(self.endpoint.clone())
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("x-amz-date", &amz_date)
+                .header(
+                    reqwest::header::AUTHORIZATION,
+                    self.authorization(&host, &amz_date, &body),
+                );
+            if let Some(token) = &self.session_token {
+                request = request.header("x-amz-security-token", token);
+            }
+            submit(request.body(body)).await
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn transport(session_token: Option<&str>) -> SesTransport {
+        SesTransport::new(&ApiTransportConfig {
+            key: Some("AKIDEXAMPLE".into()),
+            secret: Some("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".into()),
+            session_token: session_token.map(str::to_owned),
+            domain: None,
+            region: Some("us-east-1".into()),
+            url: None,
+        })
+        .unwrap()
+    }
+
+    #[test]
+    fn signs_requests_with_sigv4() {
+        let header = transport(None).authorization(
+            "email.us-east-1.amazonaws.com",
+            "20150830T123600Z",
+            b"{}",
+        );
+        assert_eq!(
+            header,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/ses/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date, \
+             Signature=b503625e599c445a22475858513d0eeea1be5207ca0536be15345410d08b51b1"
+        );
+    }
+
+    #[test]
+    fn signs_the_session_token() {
+        let header = transport(Some("session")).authorization(
+            "email.us-east-1.amazonaws.com",
+            "20150830T123600Z",
+            b"{}",
+        );
+        assert_eq!(
+            header,
+            "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/ses/aws4_request, \
+             SignedHeaders=content-type;host;x-amz-date;x-amz-security-token, \
+             Signature=7238808415a45221eea0c4fe1ccbfd7a8bf9d2ef00e336050a31d7236087abef"
+        );
+    }
+
+    #[test]
+    fn signature_covers_the_body() {
+        let transport = transport(None);
+        let host = "email.us-east-1.amazonaws.com";
+        assert_ne!(
+            transport.authorization(host, "20150830T123600Z", b"{}"),
+            transport.authorization(host, "20150830T123600Z", b"{ }")
+        );
+    }
+
+    #[test]
+    fn formats_amz_dates() {
+        assert_eq!(
+            amz_date(OffsetDateTime::from_unix_timestamp(1440938160).unwrap()),
+            "20150830T123600Z"
+        );
+        assert_eq!(
+            amz_date(OffsetDateTime::from_unix_timestamp(1704164645).unwrap()),
+            "20240102T030405Z"
+        );
+    }
+
+    #[test]
+    fn requires_region_and_credentials() {
+        let config = ApiTransportConfig {
+            key: Some("AKIDEXAMPLE".into()),
+            secret: None,
+            session_token: None,
+            domain: None,
+            region: Some("us-east-1".into()),
+            url: None,
+        };
+        assert!(SesTransport::new(&config).is_err());
+    }
+}
@@ -0,0 +1,205 @@
+//! Versioned schema migrations of the queue database
+//!
+//! The schema version is stored in SQLite's `user_version`: version `n` means
+//! the first `n` migrations are applied. Each migration runs in its own
+//! transaction together with the version bump, so an interrupted upgrade
+//! resumes where it stopped. Migrations are append-only: never edit one that
+//! was released, add a new one instead.
+
+use rusqlite::Connection;
+
+/// Schema migrations, in order
+const MIGRATIONS: &[&str] = &[
+    // 1: queued messages
+    "CREATE TABLE queue (
+        id TEXT PRIMARY KEY,
+        envelope_from TEXT,
+        recipients TEXT NOT NULL,
+        message BLOB NOT NULL,
+        status TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_at INTEGER NOT NULL,
+        last_error TEXT,
+        created_at INTEGER NOT NULL,
+        updated_at INTEGER NOT NULL
+    );
+    CREATE INDEX queue_due ON queue (status, next_attempt_at);",
+    // 2: SMTP profiles
+    "ALTER TABLE queue ADD COLUMN profile TEXT;",
+    // 3: scheduled delivery
+    "ALTER TABLE queue ADD COLUMN send_at INTEGER;",
+    // 4: cost accounting
+    "ALTER TABLE queue ADD COLUMN tenant TEXT;",
+    // 5: delivery outcome webhooks
+    "ALTER TABLE queue ADD COLUMN callback_url TEXT;",
+    // 6: attachment conversion
+    "ALTER TABLE queue ADD COLUMN payload TEXT;",
+    // 7: fault injection requested by admin keys
+    "ALTER TABLE queue ADD COLUMN fault TEXT;",
+];
+
+/// Returns the schema version this binary expects
+pub fn latest_version() -> u32 {
+    MIGRATIONS.len() as u32
+}
+
+/// Returns `true` when the queue table has the given column
+fn has_column(conn: &Connection, column: &str) -> bool {
+    conn.prepare(&format!("SELECT {} FROM queue LIMIT 0", column))
+        .is_ok()
+}
+
+/// Returns the schema version of a database
+///
+/// Databases created before versioned migrations have no version yet; their
+/// version is derived from the columns they already have.
+///
+/// # Returns
+/// * `Ok(u32)` - The schema version, 0 for an empty database
+/// * `Err(String)` - The database cannot be read
+pub fn schema_version(conn: &Connection) -> Result<u32, String> {
+    let version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(|e| e.to_string())?;
+    if version > 0 || !has_column(conn, "id") {
+        return Ok(version);
+    }
+
+    Ok(if has_column(conn, "callback_url") {
+        5
+    } else if has_column(conn, "tenant") {
+        4
+    } else if has_column(conn, "send_at") {
+        3
+    } else if has_column(conn, "profile") {
+        2
+    } else {
+        1
+    })
+}
+
+/// Applies the pending migrations
+///
+/// # Arguments
+/// * `conn` - Connection to the queue database
+///
+/// # Returns
+/// * `Ok((from, to))` - The schema version before and after the migration
+/// * `Err(String)` - A migration failed, or the database was written by a newer version
+pub fn migrate(conn: &mut Connection) -> Result<(u32, u32), String> {
+    let from = check_version(conn)?;
+    for (index, sql) in MIGRATIONS.iter().enumerate().skip(from as usize) {
+        let version = index as u32 + 1;
+        let failed = |e: rusqlite::Error| format!("Queue migration {} failed: {}", version, e);
+        let tx = conn.transaction().map_err(failed)?;
+        tx.execute_batch(sql).map_err(failed)?;
+        tx.pragma_update(None, "user_version", version)
+            .map_err(failed)?;
+        tx.commit().map_err(failed)?;
+    }
+    // Databases created before versioned migrations get their version recorded
+    conn.pragma_update(None, "user_version", latest_version())
+        .map_err(|e| e.to_string())?;
+    Ok((from, latest_version()))
+}
+
+/// Returns the schema version, failing when it is newer than this binary
+///
+/// # Returns
+/// * `Ok(u32)` - The schema version
+/// * `Err(String)` - The database cannot be read or was written by a newer version
+pub fn check_version(conn: &Connection) -> Result<u32, String> {
+    let version = schema_version(conn)?;
+    if version > latest_version() {
+        return Err(format!(
+            "Queue database schema version {} is newer than supported version {}",
+            version,
+            latest_version()
+        ));
+    }
+    Ok(version)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn user_version(conn: &Connection) -> u32 {
+        conn.query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn migrates_an_empty_database() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 0);
+
+        assert_eq!(migrate(&mut conn).unwrap(), (0, latest_version()));
+        assert_eq!(user_version(&conn), latest_version());
+        assert!(has_column(&conn, "payload"));
+    }
+
+    #[test]
+    fn migrating_again_does_nothing() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        migrate(&mut conn).unwrap();
+        assert_eq!(
+            migrate(&mut conn).unwrap(),
+            (latest_version(), latest_version())
+        );
+    }
+
+    #[test]
+    fn resumes_from_a_recorded_version() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.pragma_update(None, "user_version", 1).unwrap();
+
+        assert_eq!(migrate(&mut conn).unwrap(), (1, latest_version()));
+        assert!(has_column(&conn, "profile"));
+    }
+
+    #[test]
+    fn derives_the_version_of_unversioned_databases() {
+        let conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        assert_eq!(schema_version(&conn).unwrap(), 1);
+
+        for (version, sql) in MIGRATIONS.iter().enumerate().take(5).skip(1) {
+            conn.execute_batch(sql).unwrap();
+            assert_eq!(schema_version(&conn).unwrap(), version as u32 + 1);
+        }
+    }
+
+    #[test]
+    fn upgrades_unversioned_databases_and_keeps_their_rows() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.execute_batch(MIGRATIONS[0]).unwrap();
+        conn.execute_batch(MIGRATIONS[1]).unwrap();
+        conn.execute(
+            "INSERT INTO queue (id, recipients, message, status, next_attempt_at, created_at,
+                updated_at, profile)
+             VALUES ('a', '[]', x'', 'pending', 0, 0, 0, 'marketing')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(migrate(&mut conn).unwrap(), (2, latest_version()));
+        let profile: String = conn
+            .query_row("SELECT profile FROM queue WHERE id = 'a'", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(profile, "marketing");
+    }
+
+    #[test]
+    fn refuses_a_newer_schema() {
+        let mut conn = Connection::open_in_memory().unwrap();
+        conn.pragma_update(None, "user_version", latest_version() + 1)
+            .unwrap();
+
+        assert!(check_version(&conn).unwrap_err().contains("newer"));
+        assert!(migrate(&mut conn).is_err());
+    }
+}
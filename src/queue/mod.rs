@@ -0,0 +1,14 @@
+//! Persistent outbound queue module
+//!
+//! This module stores built messages in SQLite and delivers them from a
+//! background worker, retrying with exponential backoff while the SMTP server
+//! is unreachable.
+
+/// Versioned schema migrations of the queue database
+pub mod migrations;
+
+/// SQLite storage of queued messages
+pub mod store;
+
+/// Background delivery worker
+pub mod worker;
@@ -0,0 +1,805 @@
+//! SQLite storage of queued messages
+//!
+//! Each queued message keeps its SMTP envelope and the fully formatted RFC822
+//! bytes, so delivery does not depend on the request that produced it. Requests
+//! whose attachments must be converted are stored first, as converting rows,
+//! and become pending messages once converted.
+//! Several processes may share the database (e.g. during a binary upgrade):
+//! messages are leased when picked so that only one process delivers them.
+
+use std::sync::Mutex;
+use std::time::Duration;
+
+use lettre::address::Envelope;
+use log::info;
+use rusqlite::{Connection, params, params_from_iter};
+
+use crate::fault::FaultRequest;
+use crate::queue::migrations;
+
+/// Delivery status of a queued message
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum QueueStatus {
+    /// Waiting for its attachments to be converted
+    Converting,
+
+    /// Waiting for its next delivery attempt
+    Pending,
+
+    /// Accepted by the SMTP server
+    Sent,
+
+    /// Permanently failed or out of attempts
+    Failed,
+}
+
+impl QueueStatus {
+    /// Returns the value stored in the database
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueueStatus::Converting => "converting",
+            QueueStatus::Pending => "pending",
+            QueueStatus::Sent => "sent",
+            QueueStatus::Failed => "failed",
+        }
+    }
+
+    /// Parses a value stored in the database
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "converting" => Some(QueueStatus::Converting),
+            "pending" => Some(QueueStatus::Pending),
+            "sent" => Some(QueueStatus::Sent),
+            "failed" => Some(QueueStatus::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// Message due for delivery
+pub struct QueuedMessage {
+    /// Queue identifier
+    pub id: String,
+
+    /// SMTP envelope (sender and recipients)
+    pub envelope: Envelope,
+
+    /// Formatted RFC822 message
+    pub message: Vec<u8>,
+
+    /// Delivery attempts made so far
+    pub attempts: u32,
+
+    /// SMTP profile to deliver through, `None` for the default profile
+    pub profile: Option<String>,
+
+    /// Tenant charged for the message, `None` for the default tenant
+    pub tenant: Option<String>,
+
+    /// URL notified of the delivery outcome
+    pub callback_url: Option<String>,
+
+    /// Simulated failures requested for the delivery attempts
+    pub fault: Option<FaultRequest>,
+}
+
+/// Request waiting for its attachments to be converted
+pub struct PendingConversion {
+    /// Queue identifier, kept by the message once converted
+    pub id: String,
+
+    /// The email request, as JSON
+    pub payload: String,
+
+    /// Tenant charged for the message, `None` for the default tenant
+    pub tenant: Option<String>,
+
+    /// URL notified of the delivery outcome
+    pub callback_url: Option<String>,
+}
+
+/// Delivery options of a message being queued
+#[derive(Default)]
+pub struct QueueOptions<'a> {
+    /// SMTP profile to deliver through, `None` for the default profile
+    pub profile: Option<&'a str>,
+
+    /// Unix timestamp of a scheduled delivery, `None` to deliver immediately
+    pub send_at: Option<u64>,
+
+    /// Tenant charged for the message, `None` for the default tenant
+    pub tenant: Option<&'a str>,
+
+    /// URL notified of the delivery outcome
+    pub callback_url: Option<&'a str>,
+
+    /// Simulated failures requested for the delivery attempts
+    pub fault: Option<&'a FaultRequest>,
+}
+
+/// Message waiting for its scheduled delivery time
+pub struct ScheduledMessage {
+    /// Queue identifier
+    pub id: String,
+
+    /// Envelope sender
+    pub from: Option<String>,
+
+    /// Envelope recipients
+    pub to: Vec<String>,
+
+    /// SMTP profile to deliver through, `None` for the default profile
+    pub profile: Option<String>,
+
+    /// Unix timestamp of the scheduled delivery
+    pub send_at: u64,
+}
+
+/// Delivery state of a queued message
+pub struct MessageState {
+    /// Queue identifier
+    pub id: String,
+
+    /// Delivery status
+    pub status: QueueStatus,
+
+    /// Delivery attempts made so far
+    pub attempts: u32,
+
+    /// Error of the last failed attempt, if any
+    pub last_error: Option<String>,
+
+    /// Unix timestamp of a scheduled delivery
+    pub send_at: Option<u64>,
+
+    /// Unix timestamp of the last status change
+    pub updated_at: u64,
+}
+
+/// SQLite-backed message queue
+///
+/// The connection is guarded by a mutex; callers in async context should run
+/// these methods on the blocking thread pool.
+pub struct QueueStore {
+    /// Database connection
+    conn: Mutex<Connection>,
+}
+
+/// Serializes the faults of queue options for their column
+fn fault_json(options: &QueueOptions) -> Option<String> {
+    options.fault.and_then(|f| serde_json::to_string(f).ok())
+}
+
+impl QueueStore {
+    /// Opens (or creates) the queue database
+    ///
+    /// # Arguments
+    /// * `path` - SQLite database file path
+    /// * `migrate` - Apply pending schema migrations; when `false` a database
+    ///   whose schema is not up to date is refused
+    ///
+    /// # Returns
+    /// * `Ok(QueueStore)` - The store, with an up to date schema
+    /// * `Err(String)` - The database cannot be opened or migrated
+    pub fn open(path: &str, migrate: bool) -> Result<Self, String> {
+        let mut conn = Connection::open(path).map_err(|e| e.to_string())?;
+        // Wait for the lock held by another process instead of failing at once
+        conn.busy_timeout(Duration::from_secs(5))
+            .and_then(|_| conn.execute_batch("PRAGMA journal_mode = WAL;"))
+            .map_err(|e| e.to_string())?;
+
+        if migrate {
+            let (from, to) = migrations::migrate(&mut conn)?;
+            if from < to {
+                info!("Queue database migrated from version {} to {}", from, to);
+            }
+        } else {
+            let version = migrations::check_version(&conn)?;
+            if version < migrations::latest_version() {
+                return Err(format!(
+                    "Queue database schema version {} is older than {}: run `rustmail migrate`",
+                    version,
+                    migrations::latest_version()
+                ));
+            }
+        }
+
+        Ok(QueueStore {
+            conn: Mutex::new(conn),
+        })
+    }
+
+    /// Locks the connection, recovering from a poisoned mutex
+    fn conn(&self) -> std::sync::MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Adds a message to the queue
+    ///
+    /// # Arguments
+    /// * `envelope` - SMTP envelope of the message
+    /// * `message` - Formatted RFC822 message
+    /// * `options` - Profile, delivery time, tenant, callback and faults of the message
+    /// * `now` - Current Unix timestamp in seconds
+    ///
+    /// # Returns
+    /// The queue identifier of the message
+    pub fn enqueue(
+        &self,
+        envelope: &Envelope,
+        message: &[u8],
+        options: &QueueOptions,
+        now: u64,
+    ) -> rusqlite::Result<String> {
+        let id = format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        );
+        let recipients: Vec<String> = envelope.to().iter().map(|a| a.to_string()).collect();
+        let recipients = serde_json::to_string(&recipients).unwrap_or_default();
+        self.conn().execute(
+            "INSERT INTO queue (id, envelope_from, recipients, message, status, attempts,
+                next_attempt_at, created_at, updated_at, profile, send_at, tenant, callback_url,
+                fault)
+             VALUES (?1, ?2, ?3, ?4, ?5, 0, ?6, ?7, ?7, ?8, ?9, ?10, ?11, ?12)",
+            params![
+                id,
+                envelope.from().map(|a| a.to_string()),
+                recipients,
+                message,
+                QueueStatus::Pending.as_str(),
+                options.send_at.unwrap_or(now) as i64,
+                now as i64,
+                options.profile,
+                options.send_at.map(|t| t as i64),
+                options.tenant,
+                options.callback_url,
+                fault_json(options)
+            ],
+        )?;
+        Ok(id)
+    }
+
+    /// Claims pending messages whose next attempt is due
+    ///
+    /// The next attempt of the claimed messages is pushed back by the lease, so
+    /// other processes sharing the database do not pick them. The delivery
+    /// outcome recorded with `update` replaces the lease; a message whose
+    /// process died mid-delivery becomes due again once the lease expires.
+    ///
+    /// # Arguments
+    /// * `now` - Current Unix timestamp in seconds
+    /// * `limit` - Maximum number of messages returned
+    /// * `lease` - Time the claimed messages stay hidden from other processes
+    pub fn claim(
+        &self,
+        now: u64,
+        limit: usize,
+        lease: Duration,
+    ) -> rusqlite::Result<Vec<QueuedMessage>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "UPDATE queue SET next_attempt_at = ?4
+             WHERE id IN (
+                SELECT id FROM queue WHERE status = ?1 AND next_attempt_at <= ?2
+                ORDER BY next_attempt_at LIMIT ?3
+             )
+             RETURNING id, envelope_from, recipients, message, attempts, profile, tenant,
+                callback_url, fault",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                QueueStatus::Pending.as_str(),
+                now as i64,
+                limit as i64,
+                (now + lease.as_secs()) as i64
+            ],
+            |row| {
+                Ok((
+                    row.get::<_, String>(0)?,
+                    row.get::<_, Option<String>>(1)?,
+                    row.get::<_, String>(2)?,
+                    row.get::<_, Vec<u8>>(3)?,
+                    row.get::<_, u32>(4)?,
+                    row.get::<_, Option<String>>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                    row.get::<_, Option<String>>(8)?,
+                ))
+            },
+        )?;
+
+        let mut due = Vec::new();
+        for row in rows {
+            let (id, from, recipients, message, attempts, profile, tenant, callback_url, fault) =
+                row?;
+            let to: Vec<String> = serde_json::from_str(&recipients).unwrap_or_default();
+            let to = to.iter().filter_map(|a| a.parse().ok()).collect();
+            let from = from.and_then(|f| f.parse().ok());
+            // Rows always come from a valid message, an invalid envelope is skipped
+            if let Ok(envelope) = Envelope::new(from, to) {
+                due.push(QueuedMessage {
+                    id,
+                    envelope,
+                    message,
+                    attempts,
+                    profile,
+                    tenant,
+                    callback_url,
+                    fault: fault.and_then(|f| serde_json::from_str(&f).ok()),
+                });
+            }
+        }
+        Ok(due)
+    }
+
+    /// Adds a request whose attachments must be converted before it is built
+    ///
+    /// The row keeps the request until [`QueueStore::complete_conversion`]
+    /// replaces it with the built message, under the same identifier.
+    ///
+    /// # Arguments
+    /// * `recipients` - Recipients of the request
+    /// * `payload` - The email request, as JSON
+    /// * `options` - Profile, delivery time, tenant, callback and faults of the message
+    /// * `now` - Current Unix timestamp in seconds
+    ///
+    /// # Returns
+    /// The queue identifier of the message
+    pub fn enqueue_conversion(
+        &self,
+        recipients: &[String],
+        payload: &str,
+        options: &QueueOptions,
+        now: u64,
+    ) -> rusqlite::Result<String> {
+        let id = format!(
+            "{:016x}{:016x}",
+            rand::random::<u64>(),
+            rand::random::<u64>()
+        );
+        let recipients = serde_json::to_string(recipients).unwrap_or_default();
+        self.conn().execute(
+            "INSERT INTO queue (id, recipients, message, status, attempts, next_attempt_at,
+                created_at, updated_at, profile, send_at, tenant, callback_url, payload, fault)
+             VALUES (?1, ?2, x'', ?3, 0, ?4, ?4, ?4, ?5, ?6, ?7, ?8, ?9, ?10)",
+            params![
+                id,
+                recipients,
+                QueueStatus::Converting.as_str(),
+                now as i64,
+                options.profile,
+                options.send_at.map(|t| t as i64),
+                options.tenant,
+                options.callback_url,
+                payload,
+                fault_json(options)
+            ],
+        )?;
+        Ok(id)
+    }
+
+    /// Claims requests waiting for their attachments to be converted
+    ///
+    /// Leased like [`QueueStore::claim`]: a request whose process died while
+    /// converting is picked again once the lease expires.
+    ///
+    /// # Arguments
+    /// * `now` - Current Unix timestamp in seconds
+    /// * `limit` - Maximum number of requests returned
+    /// * `lease` - Time the claimed requests stay hidden from other processes
+    pub fn claim_conversions(
+        &self,
+        now: u64,
+        limit: usize,
+        lease: Duration,
+    ) -> rusqlite::Result<Vec<PendingConversion>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "UPDATE queue SET next_attempt_at = ?4
+             WHERE id IN (
+                SELECT id FROM queue WHERE status = ?1 AND next_attempt_at <= ?2
+                ORDER BY next_attempt_at LIMIT ?3
+             )
+             RETURNING id, payload, tenant, callback_url",
+        )?;
+        let rows = stmt.query_map(
+            params![
+                QueueStatus::Converting.as_str(),
+                now as i64,
+                limit as i64,
+                (now + lease.as_secs()) as i64
+            ],
+            |row| {
+                Ok(PendingConversion {
+                    id: row.get(0)?,
+                    payload: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+                    tenant: row.get(2)?,
+                    callback_url: row.get(3)?,
+                })
+            },
+        )?;
+        rows.collect()
+    }
+
+    /// Replaces a converting request with its built message, now due for delivery
+    ///
+    /// # Arguments
+    /// * `id` - Queue identifier of the converting request
+    /// * `envelope` - SMTP envelope of the message
+    /// * `message` - Formatted RFC822 message
+    /// * `send_at` - Unix timestamp of a scheduled delivery, `None` to deliver immediately
+    /// * `now` - Current Unix timestamp in seconds
+    ///
+    /// # Returns
+    /// `true` when the request was replaced, `false` when it is no longer converting
+    pub fn complete_conversion(
+        &self,
+        id: &str,
+        envelope: &Envelope,
+        message: &[u8],
+        send_at: Option<u64>,
+        now: u64,
+    ) -> rusqlite::Result<bool> {
+        let recipients: Vec<String> = envelope.to().iter().map(|a| a.to_string()).collect();
+        let recipients = serde_json::to_string(&recipients).unwrap_or_default();
+        let updated = self.conn().execute(
+            "UPDATE queue SET envelope_from = ?3, recipients = ?4, message = ?5, status = ?6,
+                next_attempt_at = ?7, send_at = ?8, updated_at = ?9, payload = NULL
+             WHERE id = ?1 AND status = ?2",
+            params![
+                id,
+                QueueStatus::Converting.as_str(),
+                envelope.from().map(|a| a.to_string()),
+                recipients,
+                message,
+                QueueStatus::Pending.as_str(),
+                send_at.unwrap_or(now) as i64,
+                send_at.map(|t| t as i64),
+                now as i64
+            ],
+        )?;
+        Ok(updated > 0)
+    }
+
+    /// Returns the messages whose scheduled delivery time is still ahead
+    ///
+    /// # Arguments
+    /// * `now` - Current Unix timestamp in seconds
+    pub fn scheduled(&self, now: u64) -> rusqlite::Result<Vec<ScheduledMessage>> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare(
+            "SELECT id, envelope_from, recipients, profile, send_at FROM queue
+             WHERE status = ?1 AND send_at > ?2
+             ORDER BY send_at",
+        )?;
+        let rows = stmt.query_map(params![QueueStatus::Pending.as_str(), now as i64], |row| {
+            let recipients: String = row.get(2)?;
+            Ok(ScheduledMessage {
+                id: row.get(0)?,
+                from: row.get(1)?,
+                to: serde_json::from_str(&recipients).unwrap_or_default(),
+                profile: row.get(3)?,
+                send_at: row.get::<_, i64>(4)? as u64,
+            })
+        })?;
+        rows.collect()
+    }
+
+    /// Removes a message whose scheduled delivery time is still ahead
+    ///
+    /// # Arguments
+    /// * `id` - Queue identifier
+    /// * `now` - Current Unix timestamp in seconds
+    ///
+    /// # Returns
+    /// `true` when the message was cancelled, `false` when no such message is scheduled
+    pub fn cancel_scheduled(&self, id: &str, now: u64) -> rusqlite::Result<bool> {
+        let deleted = self.conn().execute(
+            "DELETE FROM queue WHERE id = ?1 AND status = ?2 AND send_at > ?3",
+            params![id, QueueStatus::Pending.as_str(), now as i64],
+        )?;
+        Ok(deleted > 0)
+    }
+
+    /// Returns the delivery state of messages
+    ///
+    /// # Arguments
+    /// * `ids` - Queue identifiers of the messages
+    ///
+    /// # Returns
+    /// The state of the messages found, in no particular order; unknown
+    /// identifiers are left out
+    pub fn states(&self, ids: &[String]) -> rusqlite::Result<Vec<MessageState>> {
+        if ids.is_empty() {
+            return Ok(Vec::new());
+        }
+        let conn = self.conn();
+        let placeholders = vec!["?"; ids.len()].join(", ");
+        let mut stmt = conn.prepare(&format!(
+            "SELECT id, status, attempts, last_error, send_at, updated_at FROM queue
+             WHERE id IN ({})",
+            placeholders
+        ))?;
+        let rows = stmt.query_map(params_from_iter(ids), |row| {
+            Ok((
+                row.get::<_, String>(0)?,
+                row.get::<_, String>(1)?,
+                row.get::<_, u32>(2)?,
+                row.get::<_, Option<String>>(3)?,
+                row.get::<_, Option<i64>>(4)?,
+                row.get::<_, i64>(5)?,
+            ))
+        })?;
+
+        let mut states = Vec::new();
+        for row in rows {
+            let (id, status, attempts, last_error, send_at, updated_at) = row?;
+            // Rows are only written with known statuses
+            if let Some(status) = QueueStatus::parse(&status) {
+                states.push(MessageState {
+                    id,
+                    status,
+                    attempts,
+                    last_error,
+                    send_at: send_at.map(|t| t as u64),
+                    updated_at: updated_at as u64,
+                });
+            }
+        }
+        Ok(states)
+    }
+
+    /// Returns the number of messages waiting for delivery, converting ones included
+    pub fn pending_count(&self) -> rusqlite::Result<usize> {
+        self.conn()
+            .query_row(
+                "SELECT COUNT(*) FROM queue WHERE status IN (?1, ?2)",
+                params![
+                    QueueStatus::Pending.as_str(),
+                    QueueStatus::Converting.as_str()
+                ],
+                |row| row.get::<_, i64>(0),
+            )
+            .map(|count| count as usize)
+    }
+
+    /// Makes claimed messages due again without counting an attempt
+    ///
+    /// # Arguments
+    /// * `ids` - Queue identifiers of the messages to release
+    /// * `at` - Unix timestamp in seconds the messages are due again
+    pub fn release(&self, ids: &[String], at: u64) -> rusqlite::Result<()> {
+        let conn = self.conn();
+        let mut stmt = conn.prepare("UPDATE queue SET next_attempt_at = ?2 WHERE id = ?1")?;
+        for id in ids {
+            stmt.execute(params![id, at as i64])?;
+        }
+        Ok(())
+    }
+
+    /// Records the outcome of a delivery attempt
+    ///
+    /// # Arguments
+    /// * `id` - Queue identifier
+    /// * `status` - New delivery status
+    /// * `attempts` - Delivery attempts made so far
+    /// * `next_attempt_at` - Unix timestamp of the next attempt (pending only)
+    /// * `error` - Error of the last attempt, if any
+    /// * `now` - Current Unix timestamp in seconds
+    pub fn update(
+        &self,
+        id: &str,
+        status: QueueStatus,
+        attempts: u32,
+        next_attempt_at: u64,
+        error: Option<&str>,
+        now: u64,
+    ) -> rusqlite::Result<()> {
+        self.conn().execute(
+            "UPDATE queue SET status = ?2, attempts = ?3, next_attempt_at = ?4,
+                last_error = ?5, updated_at = ?6
+             WHERE id = ?1",
+            params![
+                id,
+                status.as_str(),
+                attempts,
+                next_attempt_at as i64,
+                error,
+                now as i64
+            ],
+        )?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NOW: u64 = 1_700_000_000;
+    const LEASE: Duration = Duration::from_secs(300);
+
+    fn store() -> QueueStore {
+        QueueStore::open(":memory:", true).unwrap()
+    }
+
+    fn envelope() -> Envelope {
+        Envelope::new(
+            Some("from@example.com".parse().unwrap()),
+            vec!["to@example.com".parse().unwrap()],
+        )
+        .unwrap()
+    }
+
+    fn enqueue(store: &QueueStore, options: &QueueOptions) -> String {
+        store
+            .enqueue(&envelope(), b"message", options, NOW)
+            .unwrap()
+    }
+
+    fn state(store: &QueueStore, id: &str) -> MessageState {
+        store.states(&[id.to_owned()]).unwrap().remove(0)
+    }
+
+    #[test]
+    fn claims_due_messages() {
+        let store = store();
+        let id = enqueue(&store, &QueueOptions::default());
+
+        let due = store.claim(NOW, 10, LEASE).unwrap();
+        assert_eq!(due.len(), 1);
+        assert_eq!(due[0].id, id);
+        assert_eq!(due[0].message, b"message");
+        assert_eq!(due[0].attempts, 0);
+        assert_eq!(due[0].envelope.to()[0].to_string(), "to@example.com");
+    }
+
+    #[test]
+    fn claimed_messages_are_leased() {
+        let store = store();
+        enqueue(&store, &QueueOptions::default());
+        assert_eq!(store.claim(NOW, 10, LEASE).unwrap().len(), 1);
+
+        assert!(store.claim(NOW, 10, LEASE).unwrap().is_empty());
+        assert!(
+            store
+                .claim(NOW + LEASE.as_secs() - 1, 10, LEASE)
+                .unwrap()
+                .is_empty()
+        );
+        // The process delivering it died: the message is due again once the lease expires
+        assert_eq!(
+            store.claim(NOW + LEASE.as_secs(), 10, LEASE).unwrap().len(),
+            1
+        );
+    }
+
+    #[test]
+    fn claims_at_most_the_limit() {
+        let store = store();
+        for _ in 0..3 {
+            enqueue(&store, &QueueOptions::default());
+        }
+        assert_eq!(store.claim(NOW, 2, LEASE).unwrap().len(), 2);
+        assert_eq!(store.claim(NOW, 2, LEASE).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn scheduled_messages_wait_for_their_time() {
+        let store = store();
+        let options = QueueOptions {
+            send_at: Some(NOW + 60),
+            ..Default::default()
+        };
+        let id = enqueue(&store, &options);
+
+        assert!(store.claim(NOW, 10, LEASE).unwrap().is_empty());
+        assert_eq!(store.scheduled(NOW).unwrap()[0].id, id);
+        assert_eq!(store.claim(NOW + 60, 10, LEASE).unwrap()[0].id, id);
+    }
+
+    #[test]
+    fn claimed_messages_keep_their_faults() {
+        let store = store();
+        let fault = FaultRequest {
+            drop_percent: Some(100),
+            ..Default::default()
+        };
+        let options = QueueOptions {
+            fault: Some(&fault),
+            ..Default::default()
+        };
+        enqueue(&store, &options);
+        enqueue(&store, &QueueOptions::default());
+
+        let due = store.claim(NOW, 10, LEASE).unwrap();
+        let faults: Vec<Option<u32>> = due
+            .iter()
+            .map(|m| m.fault.as_ref().and_then(|f| f.drop_percent))
+            .collect();
+        assert_eq!(faults.len(), 2);
+        assert!(faults.contains(&Some(100)));
+        assert!(faults.contains(&None));
+    }
+
+    #[test]
+    fn release_keeps_the_attempts() {
+        let store = store();
+        let id = enqueue(&store, &QueueOptions::default());
+        store
+            .update(&id, QueueStatus::Pending, 2, NOW, Some("busy"), NOW)
+            .unwrap();
+        store.claim(NOW, 10, LEASE).unwrap();
+
+        store.release(std::slice::from_ref(&id), NOW + 30).unwrap();
+        assert!(store.claim(NOW + 29, 10, LEASE).unwrap().is_empty());
+        let due = store.claim(NOW + 30, 10, LEASE).unwrap();
+        assert_eq!(due[0].attempts, 2);
+    }
+
+    #[test]
+    fn delivered_messages_are_not_claimed() {
+        let store = store();
+        let id = enqueue(&store, &QueueOptions::default());
+        store.claim(NOW, 10, LEASE).unwrap();
+        store
+            .update(&id, QueueStatus::Sent, 1, NOW, None, NOW + 1)
+            .unwrap();
+
+        assert!(store.claim(NOW + 3600, 10, LEASE).unwrap().is_empty());
+        let state = state(&store, &id);
+        assert_eq!(state.status, QueueStatus::Sent);
+        assert_eq!(state.attempts, 1);
+        assert_eq!(state.updated_at, NOW + 1);
+        assert_eq!(store.pending_count().unwrap(), 0);
+    }
+
+    #[test]
+    fn conversions_become_pending_messages() {
+        let store = store();
+        let id = store
+            .enqueue_conversion(
+                &["to@example.com".to_owned()],
+                "{}",
+                &QueueOptions::default(),
+                NOW,
+            )
+            .unwrap();
+        assert_eq!(store.pending_count().unwrap(), 1);
+        assert!(store.claim(NOW, 10, LEASE).unwrap().is_empty());
+
+        let converting = store.claim_conversions(NOW, 10, LEASE).unwrap();
+        assert_eq!(converting[0].id, id);
+        assert_eq!(converting[0].payload, "{}");
+        assert!(store.claim_conversions(NOW, 10, LEASE).unwrap().is_empty());
+
+        assert!(
+            store
+                .complete_conversion(&id, &envelope(), b"built", None, NOW + 1)
+                .unwrap()
+        );
+        assert!(
+            !store
+                .complete_conversion(&id, &envelope(), b"built", None, NOW + 1)
+                .unwrap()
+        );
+        let due = store.claim(NOW + 1, 10, LEASE).unwrap();
+        assert_eq!(due[0].id, id);
+        assert_eq!(due[0].message, b"built");
+    }
+
+    #[test]
+    fn unknown_ids_have_no_state() {
+        let store = store();
+        assert!(store.states(&["missing".to_owned()]).unwrap().is_empty());
+        assert!(store.states(&[]).unwrap().is_empty());
+    }
+
+    #[test]
+    fn refuses_an_outdated_schema_without_migrating() {
+        let err = QueueStore::open(":memory:", false).err().unwrap();
+        assert!(err.contains("rustmail migrate"));
+    }
+}
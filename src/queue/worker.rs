@@ -0,0 +1,336 @@
+//! Background delivery worker
+//!
+//! The worker polls the queue for due messages and hands them to the SMTP
+//! transport. Transient failures are retried with exponential backoff; permanent
+//! SMTP rejections and messages out of attempts are marked failed. Delivery
+//! concurrency backs off when the relay throttles and ramps up again gradually.
+//! Messages with a callback URL notify it once sent or failed for good.
+//! On shutdown the worker finishes the deliveries in flight and stops.
+
+use std::sync::Arc;
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use actix_web::rt::{self, task::JoinHandle, time::timeout};
+use actix_web::web;
+use futures_util::future::join_all;
+use log::{debug, error, info, warn};
+use tokio::sync::watch;
+
+use crate::fault::inject_fault;
+use crate::metrics::domains::AttemptOutcome;
+use crate::metrics::registry::Metrics;
+use crate::queue::store::{QueueStatus, QueueStore, QueuedMessage};
+use crate::settings::{CostConfig, DEFAULT_TENANT, FaultConfig, QueueConfig};
+use crate::transport::{Mailers, TransportError};
+use crate::webhook::{DeliveryEvent, DeliveryOutcome, Webhooks};
+
+/// Returns the current Unix timestamp in seconds
+pub fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// Computes the delay before the next attempt
+///
+/// The delay doubles after each failed attempt, starting from the base delay
+/// and capped at the maximum delay.
+fn backoff(config: &QueueConfig, attempts: u32) -> Duration {
+    let factor = 2u32.saturating_pow(attempts.saturating_sub(1));
+    config
+        .retry_base
+        .saturating_mul(factor)
+        .min(config.retry_max)
+}
+
+/// Returns `true` when the relay asks to slow down
+///
+/// Relays signal overload with a 421 reply or with transient replies such as
+/// "too many connections" or "too many messages".
+fn is_throttling(err: &TransportError) -> bool {
+    err.status().is_some_and(|code| code.to_string() == "421")
+        || (err.is_transient() && err.to_string().to_lowercase().contains("too many"))
+}
+
+/// Adjusts the delivery concurrency after a round of deliveries
+///
+/// Halves the concurrency when the relay throttled, otherwise raises it by one,
+/// up to the configured maximum.
+fn adapt_concurrency(current: usize, throttled: bool, max: usize) -> usize {
+    let next = if throttled {
+        (current / 2).max(1)
+    } else {
+        (current + 1).min(max)
+    };
+    if next < current {
+        warn!("Relay is throttling, queue concurrency reduced to {}", next);
+    } else if next > current {
+        info!("Queue concurrency raised to {}", next);
+    }
+    next
+}
+
+/// State owned by the delivery worker
+struct Delivery {
+    /// Queue storage
+    store: Arc<QueueStore>,
+
+    /// SMTP transports of the profiles used for delivery
+    mailers: Mailers,
+
+    /// Queue configuration (polling, concurrency, retries)
+    config: QueueConfig,
+
+    /// Fault injection configuration applied to deliveries
+    fault_config: FaultConfig,
+
+    /// Sending cost rates charged for the deliveries
+    cost_config: CostConfig,
+
+    /// Application metrics updated with the delivery outcomes
+    metrics: Arc<Metrics>,
+
+    /// Sender of the delivery outcome callbacks
+    webhooks: Arc<Webhooks>,
+}
+
+/// Attempts to deliver one queued message and records the outcome
+///
+/// Returns `true` when the relay throttled the delivery.
+async fn deliver(delivery: &Delivery, msg: QueuedMessage) -> bool {
+    let Delivery {
+        mailers,
+        config,
+        fault_config,
+        cost_config,
+        metrics,
+        ..
+    } = delivery;
+    // Simulated faults are always treated as transient
+    let mut throttled = false;
+    // Profiles removed from the configuration cannot be delivered anymore
+    let result = match mailers.get(msg.profile.as_deref()) {
+        None => Err((
+            format!(
+                "unknown SMTP profile {}",
+                msg.profile.as_deref().unwrap_or_default()
+            ),
+            true,
+        )),
+        Some(mailer) => match inject_fault(fault_config, msg.fault.as_ref()).await {
+            Err(err) => Err((err, false)),
+            Ok(()) => {
+                let started = Instant::now();
+                let sent = mailer.send_raw(&msg.envelope, &msg.message).await;
+                // The relay was not contacted: the message waits for the circuit to
+                // half-open without using up an attempt, and so does the rest of the batch
+                if let Err(err @ TransportError::CircuitOpen { retry_in, .. }) = &sent {
+                    debug!("Queued mail {} waits: {}", msg.id, err);
+                    let at = unix_now() + retry_in.as_secs().max(1);
+                    release(&delivery.store, vec![msg.id], at).await;
+                    return true;
+                }
+                metrics.observe_smtp(&msg.envelope, started.elapsed(), AttemptOutcome::of(&sent));
+                sent.map_err(|e| {
+                    throttled = is_throttling(&e);
+                    (e.to_string(), e.is_permanent())
+                })
+            }
+        },
+    };
+
+    let attempts = msg.attempts + 1;
+    let now = unix_now();
+    let (status, next_attempt_at, error) = match result {
+        Ok(()) => {
+            metrics.record_delivery(&msg.envelope, true);
+            metrics.record_cost(
+                msg.tenant.as_deref().unwrap_or(DEFAULT_TENANT),
+                &msg.envelope,
+                cost_config.rate(msg.profile.as_deref()),
+            );
+            info!("Queued mail {} sent", msg.id);
+            (QueueStatus::Sent, now, None)
+        }
+        Err((err, permanent)) if permanent || attempts >= config.max_attempts => {
+            metrics.record_delivery(&msg.envelope, false);
+            error!(
+                "Queued mail {} failed after {} attempt(s): {}",
+                msg.id, attempts, err
+            );
+            (QueueStatus::Failed, now, Some(err))
+        }
+        Err((err, _)) => {
+            let delay = backoff(config, attempts);
+            warn!(
+                "Queued mail {} attempt {} failed, retry in {:?}: {}",
+                msg.id, attempts, delay, err
+            );
+            (QueueStatus::Pending, now + delay.as_secs(), Some(err))
+        }
+    };
+
+    // Settled deliveries notify their callback once the outcome is stored
+    let event = msg.callback_url.and_then(|url| {
+        let outcome = match status {
+            QueueStatus::Sent => DeliveryOutcome::Sent,
+            QueueStatus::Failed => DeliveryOutcome::Failed,
+            _ => return None,
+        };
+        Some((
+            url,
+            DeliveryEvent::new(&msg.id, outcome, attempts, error.clone()),
+        ))
+    });
+
+    let id = msg.id;
+    let store = Arc::clone(&delivery.store);
+    let updated = web::block(move || {
+        store.update(
+            &id,
+            status,
+            attempts,
+            next_attempt_at,
+            error.as_deref(),
+            now,
+        )
+    })
+    .await;
+    match updated {
+        Ok(Ok(())) => {
+            if let Some((url, event)) = event {
+                delivery.webhooks.notify(url, event);
+            }
+        }
+        Ok(Err(err)) => error!("Cannot update queue: {}", err),
+        Err(err) => error!("Cannot update queue: {}", err),
+    }
+    throttled
+}
+
+/// Handle of the running delivery worker
+pub struct QueueWorker {
+    /// Signals the worker to stop
+    stop: watch::Sender<bool>,
+
+    /// Worker task
+    handle: JoinHandle<()>,
+}
+
+impl QueueWorker {
+    /// Stops the worker once the deliveries in flight are done
+    ///
+    /// Messages still being delivered when the timeout elapses are delivered
+    /// again once their lease expires, by this or another process.
+    ///
+    /// # Arguments
+    /// * `grace` - Maximum time to wait for the deliveries in flight
+    pub async fn shutdown(self, grace: Duration) {
+        let _ = self.stop.send(true);
+        match timeout(grace, self.handle).await {
+            Ok(_) => info!("Queue worker stopped"),
+            Err(_) => warn!(
+                "Queue worker still delivering after {:?}, unfinished messages retry after their lease",
+                grace
+            ),
+        }
+    }
+}
+
+/// Delivers all messages currently due
+///
+/// Messages are delivered in rounds of `concurrency` parallel deliveries; the
+/// concurrency is adapted after every round. When the relay throttles or the
+/// worker is stopping, the rest of the batch is released for the next poll.
+async fn process_due(delivery: &Delivery, concurrency: &mut usize, stop: &watch::Receiver<bool>) {
+    let (store, config) = (&delivery.store, &delivery.config);
+    let due_store = Arc::clone(store);
+    let (batch_size, lease) = (config.batch_size, config.lease);
+    let due = match web::block(move || due_store.claim(unix_now(), batch_size, lease)).await {
+        Ok(Ok(due)) => due,
+        Ok(Err(err)) => {
+            error!("Cannot read queue: {}", err);
+            return;
+        }
+        Err(err) => {
+            error!("Cannot read queue: {}", err);
+            return;
+        }
+    };
+
+    let mut due = due.into_iter().peekable();
+    while due.peek().is_some() {
+        let round = due
+            .by_ref()
+            .take(*concurrency)
+            .map(|msg| deliver(delivery, msg));
+        let throttled = join_all(round).await.into_iter().any(|t| t);
+        *concurrency = adapt_concurrency(*concurrency, throttled, config.concurrency);
+        if throttled || *stop.borrow() {
+            break;
+        }
+    }
+
+    // Messages left in the batch were claimed but not attempted
+    let left: Vec<String> = due.map(|msg| msg.id).collect();
+    if !left.is_empty() {
+        release(store, left, unix_now()).await;
+    }
+}
+
+/// Makes claimed messages due again at the given time, without counting an attempt
+async fn release(store: &Arc<QueueStore>, ids: Vec<String>, at: u64) {
+    let store = Arc::clone(store);
+    match web::block(move || store.release(&ids, at)).await {
+        Ok(Ok(())) => {}
+        Ok(Err(err)) => error!("Cannot release queued messages: {}", err),
+        Err(err) => error!("Cannot release queued messages: {}", err),
+    }
+}
+
+/// Starts the background delivery worker
+///
+/// # Arguments
+/// * `store` - Queue storage
+/// * `mailers` - SMTP transports of the profiles used for delivery
+/// * `config` - Queue configuration (polling, concurrency, retries)
+/// * `fault_config` - Fault injection configuration applied to deliveries
+/// * `cost_config` - Sending cost rates charged for the deliveries
+/// * `metrics` - Application metrics updated with the delivery outcomes
+/// * `webhooks` - Sender of the delivery outcome callbacks
+///
+/// # Returns
+/// The handle used to stop the worker on shutdown
+pub fn start_queue_worker(
+    store: Arc<QueueStore>,
+    mailers: Mailers,
+    config: QueueConfig,
+    fault_config: FaultConfig,
+    cost_config: CostConfig,
+    metrics: Arc<Metrics>,
+    webhooks: Arc<Webhooks>,
+) -> QueueWorker {
+    let (stop, mut stopped) = watch::channel(false);
+    let delivery = Delivery {
+        store,
+        mailers,
+        config,
+        fault_config,
+        cost_config,
+        metrics,
+        webhooks,
+    };
+    let handle = rt::spawn(async move {
+        let mut concurrency = delivery.config.concurrency;
+        while !*stopped.borrow() {
+            process_due(&delivery, &mut concurrency, &stopped).await;
+            // Wake up early when asked to stop; a dropped handle stops the worker too
+            let poll_interval = delivery.config.poll_interval;
+            if let Ok(Err(_)) = timeout(poll_interval, stopped.changed()).await {
+                break;
+            }
+        }
+    });
+    QueueWorker { stop, handle }
+}
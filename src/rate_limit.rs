@@ -0,0 +1,261 @@
+//! Request rate limiting module
+//!
+//! This module throttles the send endpoints with one token bucket per client,
+//! protecting the SMTP upstream from abusive or misbehaving callers. Clients are
+//! identified by their API key once it has been verified, otherwise by their IP
+//! address.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::middleware::Next;
+use actix_web::{Error, HttpResponse, web};
+use log::warn;
+
+use crate::auth::verified_key;
+use crate::request_id::current_request_id;
+use crate::settings::{RateLimitConfig, RustMailRes, Status};
+
+/// Path prefix of the rate limited endpoints
+const LIMITED_PATH_PREFIX: &str = "/send";
+
+/// Interval between two prunings of the buckets that refilled completely
+const SWEEP_INTERVAL: Duration = Duration::from_secs(60);
+
+/// Token bucket of one client
+struct Bucket {
+    /// Tokens left, one is taken per request
+    tokens: f64,
+
+    /// Time the tokens were last refilled
+    updated: Instant,
+}
+
+/// Client buckets and the time they were last pruned
+struct Buckets {
+    /// Client key to its bucket
+    clients: HashMap<String, Bucket>,
+
+    /// Time the buckets that refilled completely were last pruned
+    swept: Instant,
+}
+
+/// Per-client token bucket rate limiter
+///
+/// Shared across all workers; buckets that refilled completely are pruned at
+/// most once per [`SWEEP_INTERVAL`], so a request does not scan every client.
+pub struct RateLimiter {
+    /// Limiter configuration (rate and burst)
+    pub config: RateLimitConfig,
+
+    /// Client buckets
+    buckets: Mutex<Buckets>,
+}
+
+impl RateLimiter {
+    /// Creates a limiter without any client bucket
+    pub fn new(config: RateLimitConfig) -> Self {
+        RateLimiter {
+            config,
+            buckets: Mutex::new(Buckets {
+                clients: HashMap::new(),
+                swept: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes one token from the bucket of a client
+    ///
+    /// # Arguments
+    /// * `client` - Client key (API key or IP address)
+    ///
+    /// # Returns
+    /// * `Ok(())` - The request may proceed
+    /// * `Err(Duration)` - The request is throttled; time until a token is available
+    pub fn acquire(&self, client: &str) -> Result<(), Duration> {
+        let rate = f64::from(self.config.per_minute) / 60.0;
+        let capacity = f64::from(self.config.burst.max(1));
+        let now = Instant::now();
+
+        let mut buckets = self.buckets.lock().unwrap_or_else(|e| e.into_inner());
+        if now.duration_since(buckets.swept) >= SWEEP_INTERVAL {
+            buckets.clients.retain(|_, b| {
+                b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < capacity
+            });
+            buckets.swept = now;
+        }
+
+        let bucket = buckets.clients.entry(client.to_owned()).or_insert(Bucket {
+            tokens: capacity,
+            updated: now,
+        });
+        let elapsed = now.duration_since(bucket.updated).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(capacity);
+        bucket.updated = now;
+
+        if bucket.tokens < 1.0 {
+            return Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate));
+        }
+        bucket.tokens -= 1.0;
+        Ok(())
+    }
+}
+
+/// Returns the key identifying the client of a request
+///
+/// The API key verified by the authentication middleware is preferred so that
+/// clients behind a shared proxy get their own bucket. An unverified header is
+/// ignored, as any caller could pick a fresh value to get a fresh bucket; the
+/// peer address is used instead.
+fn client_key(req: &ServiceRequest) -> String {
+    if let Some(key) = verified_key(req.request()) {
+        return format!("key:{}", key);
+    }
+    let ip = req
+        .peer_addr()
+        .map(|a| a.ip().to_string())
+        .unwrap_or_default();
+    format!("ip:{}", ip)
+}
+
+/// Builds the HTTP 429 fail response, telling the client when to retry
+fn too_many_requests(retry_after: Duration) -> Error {
+    let seconds = retry_after.as_secs() + 1;
+    let res = RustMailRes {
+        status: Status::Fail,
+        message: format!("Rate limit exceeded, retry in {} s", seconds),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        res.message.clone(),
+        HttpResponse::TooManyRequests()
+            .insert_header((RETRY_AFTER, seconds.to_string()))
+            .json(res),
+    )
+    .into()
+}
+
+/// Middleware throttling the send endpoints
+///
+/// Registered with `actix_web::middleware::from_fn`. Requests pass through
+/// unchanged when `RATE_LIMIT_PER_MINUTE` is 0 or the path is not a send endpoint.
+///
+/// # Returns
+/// * `Ok(ServiceResponse)` - The response of the wrapped service
+/// * `Err(actix_web::Error)` - JSON fail response with HTTP 429 and `Retry-After` when throttled
+pub async fn rate_limit(
+    limiter: web::Data<RateLimiter>,
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    if limiter.config.per_minute == 0 || !req.path().starts_with(LIMITED_PATH_PREFIX) {
+        return next.call(req).await;
+    }
+
+    let client = client_key(&req);
+    if let Err(retry_after) = limiter.acquire(&client) {
+        warn!("Rate limit exceeded on {} for {}", req.path(), client);
+        return Err(too_many_requests(retry_after));
+    }
+
+    next.call(req).await
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    fn limiter(per_minute: u32, burst: u32) -> RateLimiter {
+        RateLimiter::new(RateLimitConfig { per_minute, burst })
+    }
+
+    /// Moves the last refill of a client bucket back in time
+    fn age_bucket(limiter: &RateLimiter, client: &str, by: Duration) {
+        let mut buckets = limiter.buckets.lock().unwrap();
+        let bucket = buckets.clients.get_mut(client).unwrap();
+        bucket.updated -= by;
+    }
+
+    #[test]
+    fn burst_then_throttle() {
+        let limiter = limiter(60, 2);
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_ok());
+
+        let retry_after = limiter.acquire("a").unwrap_err();
+        assert!(retry_after > Duration::ZERO);
+        assert!(retry_after <= Duration::from_secs(1));
+    }
+
+    #[test]
+    fn clients_have_their_own_bucket() {
+        let limiter = limiter(60, 1);
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_err());
+        assert!(limiter.acquire("b").is_ok());
+    }
+
+    #[test]
+    fn zero_burst_allows_one_request() {
+        let limiter = limiter(60, 0);
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_err());
+    }
+
+    #[test]
+    fn tokens_refill_with_time_up_to_the_burst() {
+        let limiter = limiter(60, 2);
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_ok());
+
+        age_bucket(&limiter, "a", Duration::from_secs(1));
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_err());
+
+        // A long pause refills no more than the burst
+        age_bucket(&limiter, "a", Duration::from_secs(10));
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_ok());
+        assert!(limiter.acquire("a").is_err());
+    }
+
+    #[test]
+    fn sweep_prunes_refilled_buckets_only() {
+        let limiter = limiter(60, 5);
+        assert!(limiter.acquire("idle").is_ok());
+        age_bucket(&limiter, "idle", Duration::from_secs(60));
+        assert!(limiter.acquire("busy").is_ok());
+        limiter.buckets.lock().unwrap().swept -= SWEEP_INTERVAL;
+
+        assert!(limiter.acquire("other").is_ok());
+        let buckets = limiter.buckets.lock().unwrap();
+        assert!(!buckets.clients.contains_key("idle"));
+        assert!(buckets.clients.contains_key("busy"));
+        assert!(buckets.clients.contains_key("other"));
+    }
+
+    #[test]
+    fn sweep_waits_for_the_interval() {
+        let limiter = limiter(60, 5);
+        assert!(limiter.acquire("idle").is_ok());
+        age_bucket(&limiter, "idle", Duration::from_secs(60));
+
+        assert!(limiter.acquire("other").is_ok());
+        assert!(limiter.buckets.lock().unwrap().clients.contains_key("idle"));
+    }
+
+    #[test]
+    fn unverified_api_key_is_ignored() {
+        let req = TestRequest::default()
+            .insert_header(("X-Api-Key", "made-up"))
+            .peer_addr("192.0.2.7:4000".parse().unwrap())
+            .to_srv_request();
+        assert_eq!(client_key(&req), "ip:192.0.2.7");
+    }
+}
@@ -0,0 +1,91 @@
+//! Request ID propagation
+//!
+//! Every request gets an ID, taken from the `X-Request-Id` header when the
+//! client sends a valid one and generated otherwise. The ID is available to
+//! everything running in the request task: it is appended to the log lines
+//! written while handling the request, included in `RustMailRes` bodies and
+//! returned in the `X-Request-Id` response header.
+
+use actix_web::Error;
+use actix_web::body::MessageBody;
+use actix_web::dev::{ServiceRequest, ServiceResponse};
+use actix_web::error::InternalError;
+use actix_web::http::header::{HeaderName, HeaderValue};
+use actix_web::middleware::Next;
+
+/// Header carrying the request ID
+pub const REQUEST_ID_HEADER: &str = "x-request-id";
+
+/// Maximum length of a request ID supplied by the client
+const MAX_REQUEST_ID_LEN: usize = 128;
+
+tokio::task_local! {
+    /// ID of the request handled by the current task
+    static REQUEST_ID: String;
+}
+
+/// Returns the ID of the request handled by the current task, if any
+pub fn current_request_id() -> Option<String> {
+    REQUEST_ID.try_with(|id| id.clone()).ok()
+}
+
+/// Returns `true` for client supplied IDs that are safe to log and echo
+///
+/// Only short IDs made of letters, digits and `-_.:` are accepted, which
+/// covers UUIDs and the usual tracing formats without allowing log injection.
+fn is_valid(id: &str) -> bool {
+    !id.is_empty()
+        && id.len() <= MAX_REQUEST_ID_LEN
+        && id
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.' | ':'))
+}
+
+/// Generates a new random request ID
+fn generate() -> String {
+    format!(
+        "{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    )
+}
+
+/// Middleware assigning an ID to every request
+///
+/// Registered with `actix_web::middleware::from_fn`, outside the other
+/// middlewares so that their responses carry the ID too.
+///
+/// # Returns
+/// The response of the wrapped service with the `X-Request-Id` header set
+pub async fn request_id(
+    req: ServiceRequest,
+    next: Next<impl MessageBody>,
+) -> Result<ServiceResponse<impl MessageBody>, Error> {
+    let id = req
+        .headers()
+        .get(REQUEST_ID_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .filter(|v| is_valid(v))
+        .map(str::to_owned)
+        .unwrap_or_else(generate);
+
+    let header = HeaderValue::from_str(&id).ok();
+    let result = REQUEST_ID.scope(id, next.call(req)).await;
+    let Some(value) = header else {
+        return result;
+    };
+    match result {
+        Ok(mut res) => {
+            res.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            Ok(res)
+        }
+        // Errors of inner middlewares (e.g. authentication) carry their response
+        Err(err) => {
+            let mut res = err.error_response();
+            res.headers_mut()
+                .insert(HeaderName::from_static(REQUEST_ID_HEADER), value);
+            Err(InternalError::from_response(err.to_string(), res).into())
+        }
+    }
+}
@@ -0,0 +1,68 @@
+//! Sender domain alignment check
+//!
+//! This module rejects emails whose From domain aligns neither with the DKIM
+//! signing domain nor with a domain whose SPF record authorizes the selected
+//! relay: receivers enforcing DMARC would fail them whatever their content.
+//! Alignment is relaxed: a domain aligns with itself, its subdomains and its
+//! parent domains.
+
+use actix_web::Result;
+use lettre::message::Mailbox;
+
+use crate::send::dto::Sender;
+use crate::settings::json_fail;
+
+/// Returns `true` when two domains are in relaxed alignment
+///
+/// Without the public suffix list the organizational domain is approximated:
+/// one domain must equal the other or be one of its subdomains.
+fn aligned(a: &str, b: &str) -> bool {
+    let (a, b) = (a.to_lowercase(), b.to_lowercase());
+    a == b || a.ends_with(&format!(".{}", b)) || b.ends_with(&format!(".{}", a))
+}
+
+/// Returns the domain of the sender address
+fn sender_domain(from: &Sender) -> Option<String> {
+    let address = match from {
+        Sender::Address(address) | Sender::Named { address, .. } => address,
+    };
+    let mailbox: Mailbox = address.parse().ok()?;
+    Some(mailbox.email.domain().to_owned())
+}
+
+/// Checks that the sender domain can pass DMARC through the selected relay
+///
+/// # Arguments
+/// * `from` - Sender of the email
+/// * `dkim_domain` - DKIM signing domain, `None` when signing is disabled
+/// * `spf_domains` - Domains whose SPF record authorizes the selected relay
+///
+/// # Returns
+/// * `Ok(())` - The From domain aligns with the DKIM or an SPF domain, or the
+///   sender cannot be parsed (reported when the message is built)
+/// * `Err(actix_web::Error)` - JSON fail response naming the misaligned domains
+pub fn check_alignment(
+    from: &Sender,
+    dkim_domain: Option<&str>,
+    spf_domains: &[String],
+) -> Result<()> {
+    let Some(domain) = sender_domain(from) else {
+        return Ok(());
+    };
+    if dkim_domain.is_some_and(|d| aligned(&domain, d))
+        || spf_domains.iter().any(|d| aligned(&domain, d))
+    {
+        return Ok(());
+    }
+
+    let spf = match spf_domains {
+        [] => "none".to_owned(),
+        domains => domains.join(", "),
+    };
+    Err(json_fail(format!(
+        "Sender domain {} is not aligned with the DKIM domain ({}) nor the SPF domains of the relay ({}): the email would fail DMARC",
+        domain,
+        dkim_domain.unwrap_or("signing disabled"),
+        spf
+    )))
+}
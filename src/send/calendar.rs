@@ -0,0 +1,172 @@
+//! iCalendar meeting invitations
+//!
+//! This module turns the `calendar` field of a request into an iCalendar
+//! (RFC 5545) `REQUEST`, sent as a `text/calendar` alternative of the body so
+//! that Outlook and Gmail show the invitation with its accept and decline
+//! buttons.
+
+use actix_web::Result;
+use lettre::message::Mailbox;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::send::dto::CalendarInvite;
+use crate::settings::json_fail;
+
+/// Longest content line, in octets, before it is folded (RFC 5545 section 3.1)
+const MAX_LINE_OCTETS: usize = 75;
+
+/// Formats a time as an iCalendar UTC date-time (e.g. `20260131T090000Z`)
+fn ical_time(time: OffsetDateTime) -> String {
+    let t = time.to_offset(time::UtcOffset::UTC);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        t.year(),
+        u8::from(t.month()),
+        t.day(),
+        t.hour(),
+        t.minute(),
+        t.second()
+    )
+}
+
+/// Escapes a TEXT property value
+fn escape_text(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace(['\r', '\n'], "\\n")
+}
+
+/// Formats a `CN` parameter, quoted and without the characters a parameter cannot hold
+fn common_name(name: &str) -> String {
+    let name: String = name
+        .chars()
+        .filter(|c| *c != '"' && !c.is_control())
+        .collect();
+    format!(";CN=\"{}\"", name)
+}
+
+/// Folds a content line into lines of at most 75 octets, never splitting a character
+fn fold(line: &str, out: &mut String) {
+    let mut width = 0;
+    for c in line.chars() {
+        if width + c.len_utf8() > MAX_LINE_OCTETS {
+            out.push_str("\r\n ");
+            // The leading space counts towards the continuation line
+            width = 1;
+        }
+        out.push(c);
+        width += c.len_utf8();
+    }
+    out.push_str("\r\n");
+}
+
+/// Parses a date-time field of the invitation
+fn parse_time(field: &str, value: &str) -> Result<OffsetDateTime> {
+    OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|e| json_fail(format!("Invalid calendar {} {}: {}", field, value, e)))
+}
+
+/// Parses an organizer or attendee address, with its optional display name
+fn parse_mailbox(field: &str, value: &str) -> Result<Mailbox> {
+    value
+        .parse()
+        .map_err(|_| json_fail(format!("Invalid calendar {} {}", field, value)))
+}
+
+/// Builds the iCalendar object of a meeting invitation
+///
+/// The event gets a random UID unless the request gives one; sending again with
+/// the same `uid` and a higher `sequence` updates the event in the calendars of
+/// the attendees.
+///
+/// # Arguments
+/// * `invite` - The `calendar` field of the request
+///
+/// # Returns
+/// * `Ok(String)` - The iCalendar object, with CRLF line endings
+/// * `Err(actix_web::Error)` - JSON fail response when a field is invalid
+pub fn build_invite(invite: &CalendarInvite) -> Result<String> {
+    if invite.summary.trim().is_empty() {
+        return Err(json_fail("Calendar summary is required"));
+    }
+    let start = parse_time("start", &invite.start)?;
+    let end = parse_time("end", &invite.end)?;
+    if end <= start {
+        return Err(json_fail("Calendar end must be after its start"));
+    }
+    let organizer = parse_mailbox("organizer", &invite.organizer)?;
+    if invite.attendees.is_empty() {
+        return Err(json_fail("Calendar attendees are required"));
+    }
+    let attendees = invite
+        .attendees
+        .iter()
+        .map(|a| parse_mailbox("attendee", a))
+        .collect::<Result<Vec<_>>>()?;
+
+    let uid = match &invite.uid {
+        Some(uid) => uid.trim().to_owned(),
+        None => format!(
+            "{:016x}{:016x}@{}",
+            rand::random::<u64>(),
+            rand::random::<u64>(),
+            organizer.email.domain()
+        ),
+    };
+
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_owned(),
+        "VERSION:2.0".to_owned(),
+        "PRODID:-//rustmail//EN".to_owned(),
+        "CALSCALE:GREGORIAN".to_owned(),
+        "METHOD:REQUEST".to_owned(),
+        "BEGIN:VEVENT".to_owned(),
+        format!("UID:{}", escape_text(&uid)),
+        format!("DTSTAMP:{}", ical_time(OffsetDateTime::now_utc())),
+        format!("DTSTART:{}", ical_time(start)),
+        format!("DTEND:{}", ical_time(end)),
+        format!("SEQUENCE:{}", invite.sequence),
+        format!("SUMMARY:{}", escape_text(&invite.summary)),
+    ];
+    if let Some(location) = &invite.location {
+        lines.push(format!("LOCATION:{}", escape_text(location)));
+    }
+    if let Some(description) = &invite.description {
+        lines.push(format!("DESCRIPTION:{}", escape_text(description)));
+    }
+    lines.push(format!(
+        "ORGANIZER{}:mailto:{}",
+        organizer
+            .name
+            .as_deref()
+            .map(common_name)
+            .unwrap_or_default(),
+        organizer.email
+    ));
+    for attendee in &attendees {
+        lines.push(format!(
+            "ATTENDEE{};ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:mailto:{}",
+            attendee
+                .name
+                .as_deref()
+                .map(common_name)
+                .unwrap_or_default(),
+            attendee.email
+        ));
+    }
+    lines.extend([
+        "STATUS:CONFIRMED".to_owned(),
+        "END:VEVENT".to_owned(),
+        "END:VCALENDAR".to_owned(),
+    ]);
+
+    let mut ics = String::new();
+    for line in &lines {
+        fold(line, &mut ics);
+    }
+    Ok(ics)
+}
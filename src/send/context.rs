@@ -0,0 +1,104 @@
+//! Shared state of the email sending endpoints
+//!
+//! This module groups the transport, configuration and in-memory registries used
+//! by the send handlers into a single structure shared across all workers.
+
+use std::sync::Arc;
+
+use crate::dedup::DuplicateGuard;
+use crate::dkim::DkimSigner;
+use crate::metrics::registry::Metrics;
+use crate::queue::store::QueueStore;
+use crate::send::idempotency::IdempotencyStore;
+use crate::settings::{
+    AlignmentConfig, BlackholeConfig, BulkLimits, CircuitBreakerConfig, ContentDetectionConfig,
+    ConvertConfig, CostConfig, DarkModeConfig, FaultConfig, FetchPolicy, LinkCheckConfig,
+    LoadSheddingConfig, MimeLimits, PrivacyConfig, RecipientPolicy, ResponsePolicy, SandboxConfig,
+    SmtpOverrideConfig, SubjectPolicy,
+};
+use crate::transport::Mailers;
+use crate::webhook::Webhooks;
+
+/// State shared by the email sending endpoints
+///
+/// Built once at startup and registered as `web::Data<SendContext>`, so that
+/// registries such as the duplicate guard are shared by every worker.
+pub struct SendContext {
+    /// Asynchronous pooled SMTP transports, one per profile
+    pub mailers: Mailers,
+
+    /// Link validation configuration
+    pub link_check_config: LinkCheckConfig,
+
+    /// Outbound fetch restrictions
+    pub fetch_policy: FetchPolicy,
+
+    /// Fault injection configuration
+    pub fault_config: FaultConfig,
+
+    /// Registry of recently sent messages
+    pub duplicate_guard: DuplicateGuard,
+
+    /// Responses of completed sends by idempotency key
+    pub idempotency: IdempotencyStore,
+
+    /// Monitoring recipient domains
+    pub blackhole_config: BlackholeConfig,
+
+    /// Limits on the MIME structure of outgoing messages
+    pub mime_limits: MimeLimits,
+
+    /// Attachment converters
+    pub convert_config: ConvertConfig,
+
+    /// Bulk send limits
+    pub bulk_limits: BulkLimits,
+
+    /// DKIM signer, when signing is configured
+    pub dkim: Option<Arc<DkimSigner>>,
+
+    /// Privacy mode configuration
+    pub privacy_config: PrivacyConfig,
+
+    /// Subject prefix and suffix policy
+    pub subject_policy: SubjectPolicy,
+
+    /// Allowed and denied recipient domains
+    pub recipient_policy: RecipientPolicy,
+
+    /// Redaction applied to API responses
+    pub response_policy: ResponsePolicy,
+
+    /// Sandbox mode configuration
+    pub sandbox_config: SandboxConfig,
+
+    /// Per-request SMTP override configuration
+    pub smtp_override_config: SmtpOverrideConfig,
+
+    /// Sender domain alignment check configuration
+    pub alignment_config: AlignmentConfig,
+
+    /// Body language and charset detection configuration
+    pub content_detection_config: ContentDetectionConfig,
+
+    /// Dark mode support of HTML bodies
+    pub dark_mode_config: DarkModeConfig,
+
+    /// Sending cost rates of the profiles
+    pub cost_config: CostConfig,
+
+    /// Load shedding policy of bulk and low priority mail
+    pub load_shedding_config: LoadSheddingConfig,
+
+    /// SMTP circuit breaker configuration
+    pub circuit_breaker_config: CircuitBreakerConfig,
+
+    /// Persistent outbound queue, shared with the delivery worker
+    pub queue: Arc<QueueStore>,
+
+    /// Application metrics, shared with the delivery worker
+    pub metrics: Arc<Metrics>,
+
+    /// Sender of the delivery outcome callbacks, shared with the delivery worker
+    pub webhooks: Arc<Webhooks>,
+}
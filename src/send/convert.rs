@@ -0,0 +1,317 @@
+//! Attachment format conversion
+//!
+//! Attachments selecting a converter with their `convert` field are handed to
+//! the configured external command before the message is built, so recipients
+//! always get the same format (e.g. PDF) whatever the client uploaded.
+//!
+//! Such requests are not converted while the client waits: they are stored in
+//! the queue, and the conversion worker converts their attachments, then builds
+//! the message, which stays queued for delivery. The commands run as child
+//! processes in a private directory, at most `ATTACHMENT_CONVERT_CONCURRENCY`
+//! at once. A conversion that fails or times out is logged and the original
+//! file is attached instead.
+
+use std::path::{Path, PathBuf};
+use std::process::Stdio;
+use std::sync::Arc;
+use std::time::Duration;
+
+use actix_web::rt::{self, task::JoinHandle, time::timeout};
+use actix_web::{Result, web};
+use futures_util::future::join_all;
+use futures_util::{StreamExt, stream};
+use lettre::message::header::ContentType;
+use log::{debug, error, info, warn};
+use tokio::process::Command;
+use tokio::sync::{Semaphore, watch};
+
+use crate::queue::store::{PendingConversion, QueueStatus, QueueStore};
+use crate::queue::worker::unix_now;
+use crate::send::context::SendContext;
+use crate::send::dto::SendOutcome;
+use crate::send::message::{MailAttachment, MailBody};
+use crate::send::send_controller::process_conversion;
+use crate::settings::{AttachmentConverter, ConvertConfig, QueueConfig, json_fail};
+use crate::webhook::{DeliveryEvent, DeliveryOutcome};
+
+/// Checks that every converter requested by the attachments is configured
+///
+/// # Returns
+/// * `Ok(())` - All requested converters exist
+/// * `Err(actix_web::Error)` - JSON fail response naming the unknown converter
+pub fn check_converters(body: &MailBody, config: &ConvertConfig) -> Result<()> {
+    for name in body.attachments.iter().filter_map(|a| a.convert.as_deref()) {
+        if config.get(name).is_none() {
+            return Err(json_fail(format!("Unknown attachment converter {}", name)));
+        }
+    }
+    Ok(())
+}
+
+/// Returns `true` when an attachment of the body requests a converter
+pub fn needs_conversion(body: &MailBody) -> bool {
+    body.attachments.iter().any(|a| a.convert.is_some())
+}
+
+/// Returns the file name of a converted attachment, with the converter extension
+fn converted_filename(filename: &str, extension: &str) -> String {
+    Path::new(filename)
+        .with_extension(extension)
+        .to_string_lossy()
+        .into_owned()
+}
+
+/// Runs one converter on the attachment content
+///
+/// The input is written to a private temporary directory, named after the
+/// original extension so that tools detecting the format by name work, and the
+/// command is called with the input and output paths as last arguments. The
+/// conversion waits for one of the `permits` first.
+///
+/// # Returns
+/// * `Ok(Vec<u8>)` - Content of the converted file
+/// * `Err(String)` - The command failed, timed out or wrote no output
+async fn run_converter(
+    attachment: &MailAttachment,
+    converter: &AttachmentConverter,
+    config: &ConvertConfig,
+    permits: &Semaphore,
+) -> Result<Vec<u8>, String> {
+    // The semaphore is never closed
+    let _permit = permits.acquire().await;
+    let dir = std::env::temp_dir().join(format!(
+        "rustmail-convert-{:016x}{:016x}",
+        rand::random::<u64>(),
+        rand::random::<u64>()
+    ));
+    let input_ext = Path::new(&attachment.filename)
+        .extension()
+        .map(|e| e.to_string_lossy().into_owned())
+        .unwrap_or_else(|| "bin".to_owned());
+    let input = dir.join(format!("input.{}", input_ext));
+    let output = dir.join(format!("output.{}", converter.extension));
+
+    let converted = async {
+        let (work_dir, input_path, data) = (dir.clone(), input.clone(), attachment.data.clone());
+        web::block(move || {
+            // Only the service account may read the files of its clients
+            #[cfg(unix)]
+            std::os::unix::fs::DirBuilderExt::mode(&mut std::fs::DirBuilder::new(), 0o700)
+                .create(&work_dir)?;
+            #[cfg(not(unix))]
+            std::fs::create_dir(&work_dir)?;
+            std::fs::write(&input_path, data)
+        })
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+        let child = Command::new(&converter.command[0])
+            .args(&converter.command[1..])
+            .arg(&input)
+            .arg(&output)
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .output();
+        let result = timeout(config.timeout, child)
+            .await
+            .map_err(|_| format!("timed out after {}s", config.timeout.as_secs()))?
+            .map_err(|e| e.to_string())?;
+        if !result.status.success() {
+            let stderr = String::from_utf8_lossy(&result.stderr);
+            return Err(match stderr.trim() {
+                "" => result.status.to_string(),
+                stderr => format!("{}: {}", result.status, stderr),
+            });
+        }
+
+        let output = output.clone();
+        web::block(move || std::fs::read(&output))
+            .await
+            .map_err(|e| e.to_string())?
+            .map_err(|e| format!("no output file: {}", e))
+    }
+    .await;
+
+    remove_dir(dir).await;
+    converted
+}
+
+/// Removes a temporary conversion directory, logging failures
+async fn remove_dir(dir: PathBuf) {
+    let removed = web::block(move || match std::fs::remove_dir_all(&dir) {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => Err((dir, e)),
+        _ => Ok(()),
+    })
+    .await;
+    if let Ok(Err((dir, e))) = removed {
+        warn!("Cannot remove {}: {}", dir.display(), e);
+    }
+}
+
+/// Converts the attachments that request a converter
+///
+/// Converted attachments get the converter content type and extension.
+/// Attachments whose conversion fails are left unchanged.
+///
+/// # Arguments
+/// * `body` - The decoded body, whose attachments are converted in place
+/// * `config` - Attachment conversion configuration
+/// * `permits` - Permits limiting the converter commands running at once
+pub async fn convert_attachments(body: &mut MailBody, config: &ConvertConfig, permits: &Semaphore) {
+    let conversions = body.attachments.iter_mut().filter_map(|attachment| {
+        let converter = config.get(attachment.convert.as_deref()?)?;
+        Some(async move {
+            let content_type = ContentType::parse(&converter.content_type)
+                .map_err(|e| format!("invalid content type: {}", e));
+            let converted = match content_type {
+                Ok(content_type) => run_converter(attachment, converter, config, permits)
+                    .await
+                    .map(|data| (content_type, data)),
+                Err(err) => Err(err),
+            };
+            match converted {
+                Ok((content_type, data)) => {
+                    debug!(
+                        "Attachment {} converted with {}",
+                        attachment.filename, converter.name
+                    );
+                    attachment.filename =
+                        converted_filename(&attachment.filename, &converter.extension);
+                    attachment.content_type = content_type;
+                    attachment.data = data;
+                }
+                Err(err) => warn!(
+                    "Conversion of attachment {} with {} failed, original attached: {}",
+                    attachment.filename, converter.name, err
+                ),
+            }
+        })
+    });
+    join_all(conversions).await;
+}
+
+/// Handle of the running conversion worker
+pub struct ConversionWorker {
+    /// Signals the worker to stop
+    stop: watch::Sender<bool>,
+
+    /// Worker task
+    handle: JoinHandle<()>,
+}
+
+impl ConversionWorker {
+    /// Stops the worker once the requests being converted are done
+    ///
+    /// # Arguments
+    /// * `grace` - Maximum time to wait for the conversions in flight
+    pub async fn shutdown(self, grace: Duration) {
+        let _ = self.stop.send(true);
+        match timeout(grace, self.handle).await {
+            Ok(_) => info!("Conversion worker stopped"),
+            Err(_) => warn!(
+                "Conversion worker still converting after {:?}, unfinished requests retry after their lease",
+                grace
+            ),
+        }
+    }
+}
+
+/// Converts and builds one queued request, recording requests that cannot be delivered
+///
+/// A request failing with a server error (e.g. queue database unavailable) is
+/// left alone and converted again once its lease expires.
+async fn convert_request(
+    store: &Arc<QueueStore>,
+    conversion: PendingConversion,
+    ctx: &SendContext,
+    permits: &Semaphore,
+) {
+    let (status, error) = match process_conversion(&conversion, ctx, permits).await {
+        Ok(SendOutcome::Queued(_)) => {
+            debug!("Queued mail {} converted", conversion.id);
+            return;
+        }
+        // Skipped by the duplicate guard or blackholed: nothing left to deliver
+        Ok(_) => (QueueStatus::Sent, None),
+        Err(err) if err.as_response_error().status_code().is_server_error() => {
+            warn!(
+                "Queued mail {} not converted, retrying after its lease: {}",
+                conversion.id, err
+            );
+            return;
+        }
+        Err(err) => {
+            error!("Queued mail {} failed: {}", conversion.id, err);
+            (QueueStatus::Failed, Some(err.to_string()))
+        }
+    };
+
+    let event = conversion.callback_url.map(|url| {
+        let outcome = match status {
+            QueueStatus::Sent => DeliveryOutcome::Sent,
+            _ => DeliveryOutcome::Failed,
+        };
+        (
+            url,
+            DeliveryEvent::new(&conversion.id, outcome, 0, error.clone()),
+        )
+    });
+    let (id, store) = (conversion.id, Arc::clone(store));
+    let now = unix_now();
+    match web::block(move || store.update(&id, status, 0, now, error.as_deref(), now)).await {
+        Ok(Ok(())) => {
+            if let Some((url, event)) = event {
+                ctx.webhooks.notify(url, event);
+            }
+        }
+        Ok(Err(err)) => error!("Cannot update converted mail: {}", err),
+        Err(err) => error!("Cannot update converted mail: {}", err),
+    }
+}
+
+/// Starts the background conversion worker
+///
+/// # Arguments
+/// * `store` - Persistent queue holding the requests to convert
+/// * `ctx` - Shared send state, the converted requests go through the same pipeline as `/send`
+/// * `config` - Queue configuration (polling, batch size, lease)
+///
+/// # Returns
+/// The handle used to stop the worker on shutdown
+pub fn start_conversion_worker(
+    store: Arc<QueueStore>,
+    ctx: web::Data<SendContext>,
+    config: QueueConfig,
+) -> ConversionWorker {
+    let (stop, mut stopped) = watch::channel(false);
+    let permits = Semaphore::new(ctx.convert_config.concurrency);
+    let handle = rt::spawn(async move {
+        while !*stopped.borrow() {
+            let claim_store = Arc::clone(&store);
+            let (batch_size, lease) = (config.batch_size, config.lease);
+            let claimed =
+                web::block(move || claim_store.claim_conversions(unix_now(), batch_size, lease))
+                    .await;
+            match claimed {
+                Ok(Ok(conversions)) => {
+                    // Requests are converted side by side, their commands share the permits
+                    stream::iter(conversions)
+                        .for_each_concurrent(None, |conversion| {
+                            convert_request(&store, conversion, &ctx, &permits)
+                        })
+                        .await;
+                }
+                Ok(Err(err)) => error!("Cannot read requests to convert: {}", err),
+                Err(err) => error!("Cannot read requests to convert: {}", err),
+            }
+            // Wake up early when asked to stop; a dropped handle stops the worker too
+            if let Ok(Err(_)) = timeout(config.poll_interval, stopped.changed()).await {
+                break;
+            }
+        }
+    });
+    ConversionWorker { stop, handle }
+}
@@ -1,17 +1,141 @@
-use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::fault::FaultRequest;
+use crate::settings::{RustMailRes, Status};
 
 fn default_content_type() -> String {
     "plain".to_owned()
 }
 
+/// Email sender
+///
+/// Accepts either a plain address string (e.g. "sender@example.com") or a
+/// structured object carrying a display name.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum Sender {
+    /// Plain address, optionally in "Name <address>" form
+    Address(String),
+
+    /// Address with an optional display name
+    Named {
+        /// Sender email address
+        address: String,
+
+        /// Display name shown by mail clients (e.g. "Example Support")
+        name: Option<String>,
+    },
+}
+
+/// Email attachment
+///
+/// File attached to the email, transferred as base64 encoded data.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct Attachment {
+    /// File name shown to the recipient (e.g., "invoice.pdf")
+    pub filename: String,
+
+    /// MIME type of the file (e.g., "application/pdf")
+    pub content_type: String,
+
+    /// Base64 encoded file content
+    // Shared, not copied, by the messages of a bulk request
+    #[schema(value_type = String)]
+    pub data: Arc<str>,
+
+    /// Optional converter applied before sending (e.g., "pdf"); the original file
+    /// is attached when the conversion fails
+    pub convert: Option<String>,
+
+    /// Optional SHA-256 of the decoded file, hexadecimal; the request is rejected
+    /// when the content does not match
+    pub sha256: Option<String>,
+}
+
+/// Inline image
+///
+/// Image embedded in the HTML body, referenced from it with a `cid:` URL
+/// (e.g., `<img src="cid:logo">`).
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct InlineImage {
+    /// Content ID referenced by the HTML body, without angle brackets (e.g., "logo")
+    pub content_id: String,
+
+    /// MIME type of the image (e.g., "image/png")
+    pub content_type: String,
+
+    /// Base64 encoded image content
+    // Shared, not copied, by the messages of a bulk request
+    #[schema(value_type = String)]
+    pub data: Arc<str>,
+}
+
+/// Meeting invitation
+///
+/// Sent as a `text/calendar; method=REQUEST` alternative of the body, which
+/// mail clients show as an invitation the attendees can accept or decline.
+#[derive(Clone, Serialize, Deserialize, ToSchema)]
+pub struct CalendarInvite {
+    /// Title of the meeting
+    pub summary: String,
+
+    /// Start time (RFC3339, e.g. "2026-01-31T09:00:00Z")
+    pub start: String,
+
+    /// End time (RFC3339), after the start
+    pub end: String,
+
+    /// Organizer address, optionally in "Name <address>" form
+    pub organizer: String,
+
+    /// Attendee addresses, optionally in "Name <address>" form
+    pub attendees: Vec<String>,
+
+    /// Optional meeting place or link
+    pub location: Option<String>,
+
+    /// Optional meeting description
+    pub description: Option<String>,
+
+    /// Optional event identifier; reuse it with a higher `sequence` to update the event
+    pub uid: Option<String>,
+
+    /// Revision of the event, 0 for a new invitation
+    #[serde(default)]
+    pub sequence: u32,
+}
+
+/// Importance of a message
+///
+/// Shown by Outlook, Thunderbird and other clients with a flag next to the
+/// subject. Clients ignore it for filtering, so it does not affect delivery.
+#[derive(Clone, Copy, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    /// Urgent message
+    High,
+    /// Default importance
+    Normal,
+    /// Message that can wait
+    Low,
+}
+
 /// Email payload structure containing all email details
 ///
 /// This structure represents the actual email content and metadata
 /// that will be sent through the SMTP server.
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SendMailPayload {
-    /// Sender email address (e.g., "sender@example.com")
-    pub from: String,
+    /// Sender email address (e.g., "sender@example.com") or `{ "address", "name" }` object
+    pub from: Sender,
+
+    /// Optional address replies should be sent to instead of the sender
+    pub reply_to: Option<String>,
 
     /// List of recipient email addresses
     pub to: Vec<String>,
@@ -22,19 +146,460 @@ pub struct SendMailPayload {
     /// Email body text (can be plain text or base64 encoded)
     pub text: String,
 
-    /// Encoding type for the text field (e.g., "plain" or "base64")
+    /// Optional HTML body; when present `text` becomes the plain text alternative
+    /// and the message is sent as `multipart/alternative`
+    pub html: Option<String>,
+
+    /// Encoding type for the text and html fields (e.g., "plain" or "base64")
     pub encoding: String,
 
     /// Content type of the email body (e.g., "plain" or "html"). Defaults to "plain".
     #[serde(default = "default_content_type")]
     pub content_type: String,
+
+    /// Optional preview text shown by inbox clients next to the subject (HTML bodies only)
+    pub preheader: Option<String>,
+
+    /// Files attached to the email; when present the message is sent as `multipart/mixed`
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+
+    /// Images embedded in the HTML body; when present the HTML part is sent as
+    /// `multipart/related`
+    #[serde(default)]
+    pub inline_images: Vec<InlineImage>,
+
+    /// Optional meeting invitation, sent as a `text/calendar` alternative of the body
+    pub calendar: Option<CalendarInvite>,
+
+    /// Extra headers by name (e.g., `{"X-Campaign-Id": "spring", "List-Unsubscribe": "<mailto:...>"}`);
+    /// values cannot contain line breaks
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Optional importance ("high", "normal" or "low"), set as the `X-Priority`,
+    /// `Importance` and `X-MSMail-Priority` headers
+    pub priority: Option<Priority>,
+}
+
+/// Relay given inline in a request instead of a configured SMTP profile
+///
+/// Reserved to admin keys when `SMTP_OVERRIDE_ENABLED` is set.
+#[derive(Deserialize, ToSchema)]
+pub struct SmtpOverride {
+    /// SMTP server hostname or IP address
+    pub host: String,
+
+    /// SMTP server port. Defaults to 587.
+    pub port: Option<u16>,
+
+    /// Connection encryption ("none", "starttls" or "tls"); the usual mode of the port when absent
+    pub tls_mode: Option<String>,
+
+    /// Authentication username
+    pub username: Option<String>,
+
+    /// Authentication password
+    pub password: Option<String>,
 }
 
 /// Request wrapper for sending an email
 ///
 /// This is the top-level structure received from the HTTP POST request.
-#[derive(Deserialize)]
+#[derive(Serialize, Deserialize, ToSchema)]
 pub struct SendMailReq {
     /// The email payload containing all email details
     pub mail: SendMailPayload,
+
+    /// Queue the email for background delivery (with retries) instead of sending it now
+    #[serde(default)]
+    pub queue: bool,
+
+    /// SMTP profile to send through (see `SMTP_PROFILES`); the default profile when absent
+    pub profile: Option<String>,
+
+    /// Delivery time (RFC3339, e.g. "2026-01-31T09:00:00Z"); a future time schedules the email
+    pub send_at: Option<String>,
+
+    /// URL notified of the delivery outcome (see `WEBHOOK_SECRET`); queued or scheduled emails only
+    pub callback_url: Option<String>,
+
+    /// Validate and build the email without sending or queueing it
+    #[serde(default)]
+    pub dry_run: bool,
+
+    /// Relay to send through instead of a profile (admin keys only, see `SMTP_OVERRIDE_ENABLED`);
+    /// cannot be queued or scheduled
+    // Never stored with a converting request: the override cannot be queued
+    #[serde(skip_serializing)]
+    pub smtp: Option<SmtpOverride>,
+
+    /// Simulated SMTP failures for this send and its queued delivery attempts
+    /// (admin keys only, see `FAULT_INJECTION_ENABLED`)
+    // Kept in its own queue column, not with a converting request
+    #[serde(skip_serializing)]
+    pub fault: Option<FaultRequest>,
+
+    /// Key identifying the request across retries; the `Idempotency-Key` header takes precedence
+    pub idempotency_key: Option<String>,
+
+    /// Tenant charged for the email, set from the API key of the request
+    #[serde(skip)]
+    pub tenant: Option<String>,
+}
+
+/// Response returned when an email is accepted into the queue
+#[derive(Clone, Serialize, ToSchema)]
+pub struct QueuedRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// Human-readable message describing the result
+    pub message: String,
+
+    /// Queue identifier of the email
+    pub id: String,
+}
+
+/// Delivery result of one recipient
+#[derive(Clone, Serialize, ToSchema)]
+pub struct RecipientRes {
+    /// Recipient email address
+    pub address: String,
+
+    /// Status of the delivery to this recipient (ok or error)
+    pub status: Status,
+
+    /// SMTP error, when the delivery failed
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+}
+
+/// Response returned when an email was sent to several recipients
+#[derive(Clone, Serialize, ToSchema)]
+pub struct SentRes {
+    /// Response status (ok when at least one recipient accepted the email)
+    pub status: Status,
+
+    /// Human-readable message describing the result
+    pub message: String,
+
+    /// One result per recipient, absent when responses only give recipient counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<RecipientRes>>,
+
+    /// ID of the request, for correlation with the server logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Response returned for a dry run
+#[derive(Clone, Serialize, ToSchema)]
+pub struct DryRunRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// Human-readable message describing the result
+    pub message: String,
+
+    /// Size in bytes of the formatted RFC822 message
+    pub size: usize,
+
+    /// Envelope recipients, absent when responses only give recipient counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub recipients: Option<Vec<String>>,
+}
+
+/// Email waiting for its scheduled delivery time
+#[derive(Serialize, ToSchema)]
+pub struct ScheduledItem {
+    /// Queue identifier of the email
+    pub id: String,
+
+    /// Envelope sender
+    pub from: Option<String>,
+
+    /// Envelope recipients, absent when responses only give recipient counts
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub to: Option<Vec<String>>,
+
+    /// Number of envelope recipients
+    pub recipients: usize,
+
+    /// SMTP profile the email is sent through, absent for the default profile
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub profile: Option<String>,
+
+    /// Scheduled delivery time (RFC3339)
+    pub send_at: String,
+}
+
+/// Response listing the scheduled emails
+#[derive(Serialize, ToSchema)]
+pub struct ScheduledRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// Scheduled emails, earliest first
+    pub scheduled: Vec<ScheduledItem>,
+}
+
+/// Request querying the delivery status of queued emails
+#[derive(Deserialize, ToSchema)]
+pub struct SendStatusReq {
+    /// Queue identifiers returned when the emails were queued or scheduled
+    pub ids: Vec<String>,
+}
+
+/// State of the circuit breaker of a profile
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitStatus {
+    /// Sends go through
+    Closed,
+
+    /// Sends fail fast until the cooldown is over
+    Open,
+
+    /// The cooldown is over, the next send is a trial
+    HalfOpen,
+}
+
+/// Circuit breaker of one SMTP profile
+#[derive(Serialize, ToSchema)]
+pub struct CircuitItem {
+    /// Profile name
+    pub profile: String,
+
+    /// State of the circuit
+    pub state: CircuitStatus,
+
+    /// Failed attempts in a row
+    pub failures: u32,
+
+    /// Seconds before a trial send, while the circuit is open
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub retry_in: Option<u64>,
+}
+
+/// Health check response
+#[derive(Serialize, ToSchema)]
+pub struct HealthRes {
+    /// Response status, `ok` while the service is up
+    pub status: Status,
+
+    /// Human-readable message
+    pub message: String,
+
+    /// ID of the request, for correlation with the server logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+
+    /// Circuit breaker of each SMTP profile; absent when the breaker is disabled
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub circuits: Vec<CircuitItem>,
+}
+
+/// Delivery state of a queued email
+#[derive(Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryState {
+    /// Waiting for its attachments to be converted
+    Converting,
+
+    /// Waiting for its next delivery attempt
+    Pending,
+
+    /// Waiting for its scheduled delivery time
+    Scheduled,
+
+    /// Accepted by the SMTP server
+    Sent,
+
+    /// Permanently failed or out of attempts
+    Failed,
+
+    /// No queued email has this id (never queued, cancelled, or purged)
+    NotFound,
+}
+
+/// Delivery status of one queued email
+#[derive(Serialize, ToSchema)]
+pub struct MessageStatusItem {
+    /// Queue identifier of the email
+    pub id: String,
+
+    /// Delivery state
+    pub state: DeliveryState,
+
+    /// Delivery attempts made so far
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attempts: Option<u32>,
+
+    /// Error of the last failed attempt
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Scheduled delivery time (RFC3339), for scheduled emails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub send_at: Option<String>,
+
+    /// Time of the last status change (RFC3339)
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub updated_at: Option<String>,
+}
+
+/// Response giving the delivery status of queued emails
+#[derive(Serialize, ToSchema)]
+pub struct SendStatusRes {
+    /// Response status (always ok)
+    pub status: Status,
+
+    /// One status per requested id, in request order
+    pub messages: Vec<MessageStatusItem>,
+}
+
+/// Outcome of an email run through the send pipeline
+#[derive(Clone)]
+pub enum SendOutcome {
+    /// Sent, skipped as duplicate or discarded (blackhole)
+    Done(RustMailRes),
+
+    /// Sent to several recipients, with the result of each of them
+    Sent(SentRes),
+
+    /// Accepted into the persistent queue
+    Queued(QueuedRes),
+
+    /// Validated and built but not sent (dry run or sandbox mode)
+    DryRun(DryRunRes),
+}
+
+/// Recipient of a templated bulk send
+#[derive(Deserialize, ToSchema)]
+pub struct BulkRecipient {
+    /// Recipient email address
+    pub to: String,
+
+    /// Variables of this recipient, merged over the shared context
+    #[serde(default)]
+    pub context: Value,
+}
+
+/// Request for sending many emails at once
+///
+/// Either a list of complete emails, or one template rendered for each recipient.
+#[derive(Deserialize, ToSchema)]
+#[serde(untagged)]
+pub enum SendBulkReq {
+    /// Independent emails, each with the same fields as a `/send` request
+    Mails {
+        /// The emails to send
+        mails: Vec<SendMailPayload>,
+
+        /// Queue the emails for background delivery instead of sending them now
+        #[serde(default)]
+        queue: bool,
+
+        /// SMTP profile to send through; the default profile when absent
+        profile: Option<String>,
+
+        /// Delivery time (RFC3339) of every email; a future time schedules them
+        send_at: Option<String>,
+
+        /// URL notified of the delivery outcome of every queued or scheduled email
+        callback_url: Option<String>,
+
+        /// Validate and build the emails without sending or queueing them
+        #[serde(default)]
+        dry_run: bool,
+    },
+
+    /// One template sent to many recipients, one email per recipient
+    Template {
+        /// Template name, i.e. its sub-directory in `TEMPLATES_DIR`
+        template: String,
+
+        /// Variables shared by all recipients
+        #[serde(default)]
+        context: Value,
+
+        /// Sender email address or `{ "address", "name" }` object
+        from: Box<Sender>,
+
+        /// Optional address replies should be sent to instead of the sender
+        reply_to: Option<String>,
+
+        /// Optional preview text shown by inbox clients next to the subject
+        preheader: Option<String>,
+
+        /// Files attached to every email
+        #[serde(default)]
+        attachments: Vec<Attachment>,
+
+        /// Images embedded in the HTML body of every email
+        #[serde(default)]
+        inline_images: Vec<InlineImage>,
+
+        /// Meeting invitation sent with every email
+        calendar: Option<Box<CalendarInvite>>,
+
+        /// Extra headers of every email
+        #[serde(default)]
+        headers: BTreeMap<String, String>,
+
+        /// Importance of every email
+        priority: Option<Priority>,
+
+        /// Recipients with their own variables
+        recipients: Vec<BulkRecipient>,
+
+        /// Queue the emails for background delivery instead of sending them now
+        #[serde(default)]
+        queue: bool,
+
+        /// SMTP profile to send through; the default profile when absent
+        profile: Option<String>,
+
+        /// Delivery time (RFC3339) of every email; a future time schedules them
+        send_at: Option<String>,
+
+        /// URL notified of the delivery outcome of every queued or scheduled email
+        callback_url: Option<String>,
+
+        /// Validate and build the emails without sending or queueing them
+        #[serde(default)]
+        dry_run: bool,
+    },
+}
+
+/// Result of one message of a bulk send
+#[derive(Serialize, ToSchema)]
+pub struct BulkItemRes {
+    /// Position of the message in the request
+    pub index: usize,
+
+    /// Status of this message (ok, fail or error)
+    pub status: Status,
+
+    /// Human-readable message describing the result
+    pub message: String,
+
+    /// Queue identifier, when the message was queued
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub id: Option<String>,
+
+    /// Per-recipient results, when the message was sent to several recipients
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub results: Option<Vec<RecipientRes>>,
+}
+
+/// Response of a bulk send
+#[derive(Serialize, ToSchema)]
+pub struct BulkRes {
+    /// Response status (ok once the request was processed, whatever the per-message results)
+    pub status: Status,
+
+    /// One result per message, in request order
+    pub results: Vec<BulkItemRes>,
 }
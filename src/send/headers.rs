@@ -0,0 +1,204 @@
+//! Custom headers of outgoing mail
+//!
+//! This module checks the extra headers given by a request (tracking ids,
+//! `List-Unsubscribe` and other compliance headers) before they are added to
+//! the message. Names and values holding line breaks are rejected, so a value
+//! can never smuggle in a header or a body of its own. It also maps the
+//! `priority` of a request to the importance headers read by mail clients.
+
+use std::collections::BTreeMap;
+
+use actix_web::Result;
+
+use crate::send::dto::Priority;
+use crate::settings::json_fail;
+
+/// Headers set by the service, which a request cannot override
+const RESERVED_HEADERS: &[&str] = &[
+    "bcc",
+    "cc",
+    "content-language",
+    "date",
+    "dkim-signature",
+    "from",
+    "importance",
+    "message-id",
+    "mime-version",
+    "received",
+    "reply-to",
+    "return-path",
+    "sender",
+    "subject",
+    "to",
+    "x-msmail-priority",
+    "x-priority",
+];
+
+/// Longest header line accepted, name and value included (RFC 5322 section 2.1.1)
+const MAX_HEADER_LINE_LEN: usize = 998;
+
+/// Returns `true` for a valid field name: printable ASCII without colon (RFC 5322 section 3.6.8)
+fn valid_name(name: &str) -> bool {
+    !name.is_empty() && name.bytes().all(|b| (33..=126).contains(&b) && b != b':')
+}
+
+/// Checks the custom headers of a request
+///
+/// # Arguments
+/// * `headers` - Header values by name
+///
+/// # Returns
+/// * `Ok(())` - Every header can be added to the message
+/// * `Err(actix_web::Error)` - JSON fail response naming the first invalid header: a
+///   malformed name, a value with line breaks or control characters, a header set by
+///   the service (e.g. `From`, `Content-Type`), a name given twice or an overlong line
+pub fn check_headers(headers: &BTreeMap<String, String>) -> Result<()> {
+    let mut seen: Vec<String> = Vec::with_capacity(headers.len());
+    for (name, value) in headers {
+        if !valid_name(name) {
+            return Err(json_fail(format!("Invalid header name {:?}", name)));
+        }
+        let lower = name.to_ascii_lowercase();
+        if RESERVED_HEADERS.contains(&lower.as_str()) || lower.starts_with("content-") {
+            return Err(json_fail(format!(
+                "Header {} is set by the service and cannot be given",
+                name
+            )));
+        }
+        if seen.contains(&lower) {
+            return Err(json_fail(format!("Header {} is given twice", name)));
+        }
+        if value.chars().any(|c| c.is_control() && c != '\t') {
+            return Err(json_fail(format!(
+                "Header {} contains line breaks or control characters",
+                name
+            )));
+        }
+        if name.len() + 2 + value.len() > MAX_HEADER_LINE_LEN {
+            return Err(json_fail(format!(
+                "Header {} is longer than {} characters",
+                name, MAX_HEADER_LINE_LEN
+            )));
+        }
+        seen.push(lower);
+    }
+    Ok(())
+}
+
+/// Returns the importance headers of a priority
+///
+/// `X-Priority` is read by most clients, `Importance` (RFC 2156) by Outlook and
+/// `X-MSMail-Priority` by older Microsoft clients, so all three are set.
+///
+/// # Arguments
+/// * `priority` - Importance requested for the message
+///
+/// # Returns
+/// The header names with their values
+pub fn priority_headers(priority: Priority) -> [(&'static str, &'static str); 3] {
+    let (x_priority, importance, ms_priority) = match priority {
+        Priority::High => ("1 (Highest)", "high", "High"),
+        Priority::Normal => ("3 (Normal)", "normal", "Normal"),
+        Priority::Low => ("5 (Lowest)", "low", "Low"),
+    };
+    [
+        ("X-Priority", x_priority),
+        ("Importance", importance),
+        ("X-MSMail-Priority", ms_priority),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn check(name: &str, value: &str) -> Result<()> {
+        check_headers(&BTreeMap::from([(name.to_owned(), value.to_owned())]))
+    }
+
+    fn rejection(name: &str, value: &str) -> String {
+        check(name, value).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn accepts_compliance_and_tracking_headers() {
+        let headers = BTreeMap::from([
+            (
+                "List-Unsubscribe".to_owned(),
+                "<mailto:unsubscribe@example.com>, <https://example.com/u/42>".to_owned(),
+            ),
+            (
+                "List-Unsubscribe-Post".to_owned(),
+                "List-Unsubscribe=One-Click".to_owned(),
+            ),
+            ("X-Campaign-Id".to_owned(), "spring-2026".to_owned()),
+        ]);
+        assert!(check_headers(&headers).is_ok());
+        assert!(check("X-Note", "tab\tseparated").is_ok());
+    }
+
+    #[test]
+    fn rejects_line_breaks_in_values() {
+        for value in ["a\r\nBcc: victim@example.com", "a\nBcc: x", "a\rb", "a\0b"] {
+            assert!(
+                rejection("X-Campaign-Id", value).contains("line breaks"),
+                "{:?} accepted",
+                value
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_line_breaks_and_colons_in_names() {
+        for name in ["X-Id\r\nBcc", "X-Id\n", "X\rId", "X-Id: a", "X Id", ""] {
+            assert!(
+                rejection(name, "value").contains("Invalid header name"),
+                "{:?} accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_reserved_headers_in_any_case() {
+        for name in [
+            "From",
+            "to",
+            "BCC",
+            "Cc",
+            "Subject",
+            "Message-ID",
+            "content-type",
+            "Content-Transfer-Encoding",
+            "CONTENT-DISPOSITION",
+            "X-Priority",
+        ] {
+            assert!(
+                rejection(name, "value").contains("set by the service"),
+                "{} accepted",
+                name
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_names_given_twice() {
+        let headers = BTreeMap::from([
+            ("X-Campaign-Id".to_owned(), "a".to_owned()),
+            ("x-campaign-id".to_owned(), "b".to_owned()),
+        ]);
+        assert!(
+            check_headers(&headers)
+                .unwrap_err()
+                .to_string()
+                .contains("given twice")
+        );
+    }
+
+    #[test]
+    fn rejects_lines_over_998_characters() {
+        // "X-Id: " takes 6 characters of the line
+        assert!(check("X-Id", &"a".repeat(992)).is_ok());
+        assert!(rejection("X-Id", &"a".repeat(993)).contains("longer than 998"));
+    }
+}
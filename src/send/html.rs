@@ -0,0 +1,184 @@
+//! HTML body post-processing
+//!
+//! This module applies transformations to HTML email bodies before they are
+//! handed to the message builder.
+
+use crate::settings::DarkModeConfig;
+
+/// Invisible characters appended after the preheader so that inbox previews do
+/// not pull in the beginning of the visible body text.
+const PREHEADER_PADDING: &str = "&#847;&zwnj;&nbsp;";
+
+/// Number of padding sequences appended after the preheader
+const PREHEADER_PADDING_REPEAT: usize = 90;
+
+/// Escapes text for safe inclusion in HTML content
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Injects a hidden preview text (preheader) into an HTML body
+///
+/// The preheader is placed right after the opening `<body>` tag, or at the very
+/// beginning of the document when there is none, inside an element hidden from
+/// the rendered message but picked up by inbox previews.
+///
+/// # Arguments
+/// * `html` - The HTML body
+/// * `preheader` - The preview text, HTML-escaped before injection
+///
+/// # Returns
+/// The HTML body containing the preheader
+pub fn inject_preheader(html: &str, preheader: &str) -> String {
+    let snippet = format!(
+        "<div style=\"display:none;font-size:1px;line-height:1px;max-height:0px;max-width:0px;opacity:0;overflow:hidden;mso-hide:all;\">{}{}</div>",
+        escape_html(preheader),
+        PREHEADER_PADDING.repeat(PREHEADER_PADDING_REPEAT)
+    );
+
+    // Insert after the end of the opening body tag, attributes included; ASCII
+    // lowercasing keeps byte offsets, so positions apply to both strings
+    let lower = html.to_ascii_lowercase();
+    match after_opening_tag(&lower, "<body") {
+        Some(pos) => format!("{}{}{}", &html[..pos], snippet, &html[pos..]),
+        None => format!("{}{}", snippet, html),
+    }
+}
+
+/// Returns the byte offset right after the opening tag `tag` (e.g. `<head`), attributes included
+fn after_opening_tag(lower: &str, tag: &str) -> Option<usize> {
+    let mut from = 0;
+    while let Some(start) = lower[from..].find(tag).map(|i| from + i) {
+        let rest = &lower[start + tag.len()..];
+        // `<head` must not match `<header`
+        if rest.starts_with(['>', ' ', '\t', '\r', '\n', '/']) {
+            return rest.find('>').map(|end| start + tag.len() + end + 1);
+        }
+        from = start + tag.len();
+    }
+    None
+}
+
+/// Declares light and dark color scheme support in an HTML body
+///
+/// Apple Mail and Gmail only keep the designed colors in dark mode when the
+/// document declares that it supports both schemes; otherwise they invert them
+/// on their own. The `color-scheme` meta tags and `:root` style go at the start
+/// of `<head>`, which is created when missing. With configured dark colors, a
+/// `prefers-color-scheme: dark` rule sets the body background and text color.
+/// Documents that already declare a color scheme are returned unchanged.
+///
+/// # Arguments
+/// * `html` - The HTML body
+/// * `config` - Dark mode configuration (optional dark colors)
+///
+/// # Returns
+/// The HTML body declaring its color scheme support
+pub fn inject_dark_mode(html: &str, config: &DarkModeConfig) -> String {
+    // ASCII lowercasing keeps byte offsets, so positions apply to both strings
+    let lower = html.to_ascii_lowercase();
+    if lower.contains("name=\"color-scheme\"") || lower.contains("name='color-scheme'") {
+        return html.to_owned();
+    }
+
+    let mut dark = Vec::new();
+    if let Some(background) = &config.background {
+        dark.push(format!("background-color: {} !important;", background));
+    }
+    if let Some(text) = &config.text {
+        dark.push(format!("color: {} !important;", text));
+    }
+    let dark_rule = if dark.is_empty() {
+        String::new()
+    } else {
+        format!(
+            " @media (prefers-color-scheme: dark) {{ body {{ {} }} }}",
+            dark.join(" ")
+        )
+    };
+    let snippet = format!(
+        "<meta name=\"color-scheme\" content=\"light dark\">\
+         <meta name=\"supported-color-schemes\" content=\"light dark\">\
+         <style>:root {{ color-scheme: light dark; supported-color-schemes: light dark; }}{}</style>",
+        dark_rule
+    );
+
+    if let Some(pos) = after_opening_tag(&lower, "<head") {
+        return format!("{}{}{}", &html[..pos], snippet, &html[pos..]);
+    }
+    match after_opening_tag(&lower, "<html") {
+        Some(pos) => format!("{}<head>{}</head>{}", &html[..pos], snippet, &html[pos..]),
+        None => format!("{}{}", snippet, html),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Returns the text of the injected preheader element
+    fn preheader_text(html: &str) -> &str {
+        let start = html.find("mso-hide:all;\">").unwrap() + "mso-hide:all;\">".len();
+        let end = start + html[start..].find(PREHEADER_PADDING).unwrap();
+        &html[start..end]
+    }
+
+    #[test]
+    fn injects_after_the_opening_body_tag() {
+        let html = inject_preheader(
+            "<html><body class=\"main\"><p>Hello</p></body></html>",
+            "Preview",
+        );
+        assert!(html.starts_with("<html><body class=\"main\"><div style=\"display:none;"));
+        assert!(html.ends_with("</div><p>Hello</p></body></html>"));
+        assert_eq!(preheader_text(&html), "Preview");
+    }
+
+    #[test]
+    fn matches_the_body_tag_case_insensitively() {
+        let html = inject_preheader("<HTML><BODY><p>Hello</p></BODY></HTML>", "Preview");
+        assert!(html.starts_with("<HTML><BODY><div"));
+    }
+
+    #[test]
+    fn prepends_without_a_body_tag() {
+        let html = inject_preheader("<p>Hello</p>", "Preview");
+        assert!(html.starts_with("<div style=\"display:none;"));
+        assert!(html.ends_with("</div><p>Hello</p>"));
+    }
+
+    #[test]
+    fn skips_tags_starting_like_body() {
+        let html = inject_preheader("<bodyguard></bodyguard><body><p>Hello</p>", "Preview");
+        assert!(html.starts_with("<bodyguard></bodyguard><body><div"));
+    }
+
+    #[test]
+    fn keeps_offsets_after_non_ascii_text() {
+        // Lowercasing these changes their byte length
+        let source = "<html><head><title>İSTANBUL ẞ</title></head><body><p>Grüße</p></body></html>";
+        let html = inject_preheader(source, "Preview");
+        assert!(html.starts_with("<html><head><title>İSTANBUL ẞ</title></head><body><div"));
+        assert!(html.ends_with("</div><p>Grüße</p></body></html>"));
+    }
+
+    #[test]
+    fn escapes_the_preheader() {
+        let html = inject_preheader("<body>", "<b>Tom & \"Jerry\"</b>");
+        assert_eq!(
+            preheader_text(&html),
+            "&lt;b&gt;Tom &amp; &quot;Jerry&quot;&lt;/b&gt;"
+        );
+    }
+}
@@ -0,0 +1,124 @@
+//! Idempotency keys of send requests
+//!
+//! This module remembers the outcome of completed sends by the idempotency
+//! key the client gave, so a retried request gets the original response back
+//! instead of sending the email again. Keys are held in memory for a
+//! configurable window.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::send::dto::SendOutcome;
+use crate::settings::IdempotencyConfig;
+
+/// Request header carrying the idempotency key
+pub const IDEMPOTENCY_KEY_HEADER: &str = "idempotency-key";
+
+/// Response header marking a replayed response
+pub const IDEMPOTENT_REPLAYED_HEADER: &str = "idempotent-replayed";
+
+/// Maximum length of an idempotency key
+pub const MAX_IDEMPOTENCY_KEY_LEN: usize = 255;
+
+/// State of an idempotency key
+enum Entry {
+    /// A request with this key is being processed
+    InProgress,
+
+    /// A request with this key completed at the given time
+    Completed(Instant, SendOutcome),
+}
+
+/// Result of claiming an idempotency key
+pub enum Claim<'a> {
+    /// First request with this key: process it, then complete the guard
+    New(IdempotencyGuard<'a>),
+
+    /// A request with this key is still being processed
+    InProgress,
+
+    /// A request with this key already completed
+    Completed(SendOutcome),
+}
+
+/// In-memory registry of idempotency keys
+///
+/// Shared across all workers; completed entries older than the configured
+/// window are pruned on every access.
+pub struct IdempotencyStore {
+    /// Registry configuration (window)
+    pub config: IdempotencyConfig,
+
+    /// Scoped key to state
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl IdempotencyStore {
+    /// Creates an empty registry with the given configuration
+    pub fn new(config: IdempotencyConfig) -> Self {
+        IdempotencyStore {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Claims an idempotency key before processing its request
+    ///
+    /// # Arguments
+    /// * `key` - Idempotency key, scoped to the caller
+    ///
+    /// # Returns
+    /// A guard for a new key, or the state of the request that claimed it first
+    pub fn claim(&self, key: String) -> Claim<'_> {
+        let mut entries = self.entries.lock().unwrap_or_else(|e| e.into_inner());
+        let window = self.config.window;
+        entries.retain(|_, entry| match entry {
+            Entry::InProgress => true,
+            Entry::Completed(at, _) => at.elapsed() < window,
+        });
+
+        match entries.get(&key) {
+            Some(Entry::InProgress) => Claim::InProgress,
+            Some(Entry::Completed(_, outcome)) => Claim::Completed(outcome.clone()),
+            None => {
+                entries.insert(key.clone(), Entry::InProgress);
+                Claim::New(IdempotencyGuard {
+                    store: self,
+                    key: Some(key),
+                })
+            }
+        }
+    }
+}
+
+/// Claim on a new idempotency key
+///
+/// Releases the key when dropped without completing, e.g. when the request
+/// failed or the client disconnected, so a retry is processed again.
+pub struct IdempotencyGuard<'a> {
+    /// Registry holding the claim
+    store: &'a IdempotencyStore,
+
+    /// Claimed key, taken once completed
+    key: Option<String>,
+}
+
+impl IdempotencyGuard<'_> {
+    /// Records the outcome of the completed request for its duplicates
+    pub fn complete(mut self, outcome: SendOutcome) {
+        if let Some(key) = self.key.take() {
+            let mut entries = self.store.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.insert(key, Entry::Completed(Instant::now(), outcome));
+        }
+    }
+}
+
+impl Drop for IdempotencyGuard<'_> {
+    fn drop(&mut self) {
+        if let Some(key) = self.key.take() {
+            let mut entries = self.store.entries.lock().unwrap_or_else(|e| e.into_inner());
+            entries.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,155 @@
+//! Body language and charset detection
+//!
+//! This module detects the language of an email body, announced with the
+//! `Content-Language` header, and picks the narrowest charset that represents
+//! each text part: some localized clients pick fonts and hyphenation from the
+//! language and render `us-ascii` parts without guessing.
+
+use lettre::message::header::{Header, HeaderName, HeaderValue};
+use whatlang::Lang;
+
+use crate::send::message::MailBody;
+
+/// `Content-Language` header (RFC 3282)
+#[derive(Clone, Debug)]
+pub struct ContentLanguage(pub String);
+
+impl Header for ContentLanguage {
+    fn name() -> HeaderName {
+        HeaderName::new_from_ascii_str("Content-Language")
+    }
+
+    fn parse(s: &str) -> Result<Self, Box<dyn std::error::Error + Send + Sync>> {
+        Ok(ContentLanguage(s.trim().to_owned()))
+    }
+
+    fn display(&self) -> HeaderValue {
+        HeaderValue::new(Self::name(), self.0.clone())
+    }
+}
+
+/// Returns the BCP 47 tag (ISO 639-1 code) of a detected language
+fn language_tag(lang: Lang) -> &'static str {
+    match lang {
+        Lang::Afr => "af",
+        Lang::Aka => "ak",
+        Lang::Amh => "am",
+        Lang::Ara => "ar",
+        Lang::Aze => "az",
+        Lang::Bel => "be",
+        Lang::Ben => "bn",
+        Lang::Bul => "bg",
+        Lang::Cat => "ca",
+        Lang::Ces => "cs",
+        Lang::Cmn => "zh",
+        Lang::Cym => "cy",
+        Lang::Dan => "da",
+        Lang::Deu => "de",
+        Lang::Ell => "el",
+        Lang::Eng => "en",
+        Lang::Epo => "eo",
+        Lang::Est => "et",
+        Lang::Fin => "fi",
+        Lang::Fra => "fr",
+        Lang::Guj => "gu",
+        Lang::Heb => "he",
+        Lang::Hin => "hi",
+        Lang::Hrv => "hr",
+        Lang::Hun => "hu",
+        Lang::Hye => "hy",
+        Lang::Ind => "id",
+        Lang::Ita => "it",
+        Lang::Jav => "jv",
+        Lang::Jpn => "ja",
+        Lang::Kan => "kn",
+        Lang::Kat => "ka",
+        Lang::Khm => "km",
+        Lang::Kor => "ko",
+        Lang::Lat => "la",
+        Lang::Lav => "lv",
+        Lang::Lit => "lt",
+        Lang::Mal => "ml",
+        Lang::Mar => "mr",
+        Lang::Mkd => "mk",
+        Lang::Mya => "my",
+        Lang::Nep => "ne",
+        Lang::Nld => "nl",
+        Lang::Nob => "nb",
+        Lang::Ori => "or",
+        Lang::Pan => "pa",
+        Lang::Pes => "fa",
+        Lang::Pol => "pl",
+        Lang::Por => "pt",
+        Lang::Ron => "ro",
+        Lang::Rus => "ru",
+        Lang::Sin => "si",
+        Lang::Slk => "sk",
+        Lang::Slv => "sl",
+        Lang::Sna => "sn",
+        Lang::Spa => "es",
+        Lang::Srp => "sr",
+        Lang::Swe => "sv",
+        Lang::Tam => "ta",
+        Lang::Tel => "te",
+        Lang::Tgl => "tl",
+        Lang::Tha => "th",
+        Lang::Tuk => "tk",
+        Lang::Tur => "tr",
+        Lang::Ukr => "uk",
+        Lang::Urd => "ur",
+        Lang::Uzb => "uz",
+        Lang::Vie => "vi",
+        Lang::Yid => "yi",
+        Lang::Zul => "zu",
+    }
+}
+
+/// Returns the text of an HTML document, without tags, styles and scripts
+fn visible_text(html: &str) -> String {
+    // ASCII lowercasing keeps byte offsets, so positions apply to both strings
+    let lower = html.to_ascii_lowercase();
+    let mut text = String::with_capacity(html.len());
+    let mut pos = 0;
+    while let Some(start) = lower[pos..].find('<').map(|i| pos + i) {
+        text.push_str(&html[pos..start]);
+        text.push(' ');
+        let close = if lower[start..].starts_with("<style") {
+            "</style>"
+        } else if lower[start..].starts_with("<script") {
+            "</script>"
+        } else {
+            ">"
+        };
+        pos = match lower[start..].find(close) {
+            Some(end) => start + end + close.len(),
+            None => html.len(),
+        };
+    }
+    text.push_str(&html[pos..]);
+    text
+}
+
+/// Detects the language of an email body
+///
+/// The plain text part is preferred; HTML-only bodies are reduced to their
+/// visible text.
+///
+/// # Arguments
+/// * `body` - Decoded body parts
+///
+/// # Returns
+/// The BCP 47 tag of the language, or `None` when the detection is not reliable
+pub fn detect_language(body: &MailBody) -> Option<&'static str> {
+    let text = match (&body.plain, &body.html) {
+        (Some(plain), _) if !plain.trim().is_empty() => plain.clone(),
+        (_, Some(html)) => visible_text(html),
+        _ => return None,
+    };
+    let info = whatlang::detect(&text)?;
+    info.is_reliable().then(|| language_tag(info.lang()))
+}
+
+/// Returns the narrowest charset that represents a text part
+pub fn text_charset(text: &str) -> &'static str {
+    if text.is_ascii() { "us-ascii" } else { "utf-8" }
+}
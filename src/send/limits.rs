@@ -0,0 +1,72 @@
+//! MIME structure limits
+//!
+//! This module rejects messages whose structure exceeds the configured limits
+//! on attachment count, total parts and multipart nesting depth.
+
+use actix_web::{HttpResponse, error::InternalError};
+use serde::Serialize;
+use utoipa::ToSchema;
+
+use crate::send::message::MailBody;
+use crate::settings::{MimeLimits, Status};
+
+/// Response returned when a MIME limit is exceeded
+#[derive(Serialize, ToSchema)]
+pub struct LimitExceededRes {
+    /// Response status (always fail)
+    pub status: Status,
+
+    /// Human-readable message describing the violation
+    pub message: String,
+
+    /// Name of the exceeded limit (`attachments`, `parts` or `depth`)
+    pub limit: &'static str,
+
+    /// Configured maximum
+    pub max: usize,
+
+    /// Value found in the request
+    pub actual: usize,
+}
+
+/// Builds the HTTP 400 fail response for an exceeded limit
+fn limit_exceeded(limit: &'static str, max: usize, actual: usize) -> actix_web::Error {
+    let res = LimitExceededRes {
+        status: Status::Fail,
+        message: format!("Too many MIME {}: {} (max {})", limit, actual, max),
+        limit,
+        max,
+        actual,
+    };
+    InternalError::from_response(res.message.clone(), HttpResponse::BadRequest().json(res)).into()
+}
+
+/// Checks the MIME structure of a message against the configured limits
+///
+/// # Arguments
+/// * `body` - The decoded body and attachments
+/// * `limits` - Configured MIME structure limits
+///
+/// # Returns
+/// * `Ok(())` - The message is within limits
+/// * `Err(actix_web::Error)` - Structured JSON fail response naming the exceeded limit
+pub fn check_mime_limits(body: &MailBody, limits: &MimeLimits) -> actix_web::Result<()> {
+    let attachments = body.attachments.len();
+    if attachments > limits.max_attachments {
+        return Err(limit_exceeded(
+            "attachments",
+            limits.max_attachments,
+            attachments,
+        ));
+    }
+
+    let (parts, depth) = body.mime_shape();
+    if parts > limits.max_parts {
+        return Err(limit_exceeded("parts", limits.max_parts, parts));
+    }
+    if depth > limits.max_depth {
+        return Err(limit_exceeded("depth", limits.max_depth, depth));
+    }
+
+    Ok(())
+}
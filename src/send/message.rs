@@ -0,0 +1,549 @@
+//! Email message construction
+//!
+//! This module turns a validated `SendMailPayload` into a lettre `Message`:
+//! it decodes the body parts and attachments and assembles the MIME structure.
+
+use actix_web::Result;
+use base64::{Engine, prelude::BASE64_STANDARD};
+use lettre::Message;
+use lettre::message::header::{ContentType, HeaderName, HeaderValue};
+use lettre::message::{Attachment, Mailbox, MultiPart, SinglePart};
+
+use crate::crypto::{hex, sha256};
+use crate::send::calendar::build_invite;
+use crate::send::dto::{SendMailPayload, Sender};
+use crate::send::headers::priority_headers;
+use crate::send::language::{ContentLanguage, detect_language, text_charset};
+use crate::settings::{ContentDetectionConfig, PrivacyConfig, json_error, json_fail};
+
+/// Decoded email attachment
+pub struct MailAttachment {
+    /// File name shown to the recipient
+    pub filename: String,
+
+    /// Parsed MIME type of the file
+    pub content_type: ContentType,
+
+    /// Raw file content
+    pub data: Vec<u8>,
+
+    /// Converter requested for the file
+    pub convert: Option<String>,
+}
+
+/// Decoded inline image
+pub struct MailInlineImage {
+    /// Content ID referenced by the HTML body, without angle brackets
+    pub content_id: String,
+
+    /// Parsed MIME type of the image
+    pub content_type: ContentType,
+
+    /// Raw image content
+    pub data: Vec<u8>,
+}
+
+/// Decoded body parts and attachments of an email
+///
+/// At least one of the two body parts is always present.
+pub struct MailBody {
+    /// Plain text part
+    pub plain: Option<String>,
+
+    /// HTML part
+    pub html: Option<String>,
+
+    /// Attached files
+    pub attachments: Vec<MailAttachment>,
+
+    /// Images embedded in the HTML part
+    pub inline_images: Vec<MailInlineImage>,
+
+    /// iCalendar meeting invitation, sent as an alternative of the text parts
+    pub calendar: Option<String>,
+}
+
+impl MailBody {
+    /// Returns the MIME shape of the message built from this body
+    ///
+    /// # Returns
+    /// A tuple of (number of leaf parts, multipart nesting depth), matching the
+    /// structure produced by [`build_message`]
+    pub fn mime_shape(&self) -> (usize, usize) {
+        let text_parts = usize::from(self.plain.is_some()) + usize::from(self.html.is_some());
+        let body_parts = text_parts.max(1) + usize::from(self.calendar.is_some());
+        let alternative = body_parts > 1;
+        let mut depth = usize::from(alternative);
+        if !self.inline_images.is_empty() {
+            depth += 1;
+        }
+        if !self.attachments.is_empty() {
+            depth += 1;
+        }
+        (
+            body_parts + self.inline_images.len() + self.attachments.len(),
+            depth,
+        )
+    }
+
+    /// Returns a single string covering every part, used to fingerprint the content
+    ///
+    /// Attachments and inline images are covered by their name and the SHA-256
+    /// of their content, so the same text with another file is not a duplicate.
+    pub fn fingerprint_source(&self) -> String {
+        let mut source = format!(
+            "{}\u{0}{}\u{0}{}",
+            self.plain.as_deref().unwrap_or_default(),
+            self.html.as_deref().unwrap_or_default(),
+            self.calendar.as_deref().unwrap_or_default()
+        );
+        for attachment in &self.attachments {
+            source.push_str(&format!(
+                "\u{0}attachment\u{0}{}\u{0}{}",
+                attachment.filename,
+                hex(&sha256(&attachment.data))
+            ));
+        }
+        for image in &self.inline_images {
+            source.push_str(&format!(
+                "\u{0}inline\u{0}{}\u{0}{}",
+                image.content_id,
+                hex(&sha256(&image.data))
+            ));
+        }
+        source
+    }
+}
+
+/// Decodes a body field according to the payload encoding
+///
+/// # Encoding Support
+/// * `plain` - Text is returned as-is
+/// * `base64` - Text is base64 decoded and must be valid UTF-8
+fn decode_text(value: &str, encoding: &str) -> Result<String> {
+    if encoding.eq("base64") {
+        let x = BASE64_STANDARD.decode(value).map_err(json_error)?;
+        return String::from_utf8(x).map_err(json_error);
+    }
+    Ok(value.to_owned())
+}
+
+/// Checks decoded attachment data against the SHA-256 given by the caller
+///
+/// # Returns
+/// * `Ok(())` - The digest matches, case-insensitively
+/// * `Err(actix_web::Error)` - JSON fail response with both digests
+fn verify_sha256(filename: &str, data: &[u8], expected: &str) -> Result<()> {
+    let actual = hex(&sha256(data));
+    if actual.eq_ignore_ascii_case(expected.trim()) {
+        return Ok(());
+    }
+    Err(json_fail(format!(
+        "Attachment {} does not match its SHA-256: expected {}, got {}",
+        filename,
+        expected.trim(),
+        actual
+    )))
+}
+
+/// Decodes the attachments of a payload
+///
+/// Attachment data is always base64, regardless of the payload `encoding`.
+/// Attachments carrying a `sha256` are verified after decoding.
+fn decode_attachments(mail: &SendMailPayload) -> Result<Vec<MailAttachment>> {
+    mail.attachments
+        .iter()
+        .map(|a| {
+            let data = BASE64_STANDARD
+                .decode(a.data.as_bytes())
+                .map_err(json_error)?;
+            if let Some(expected) = &a.sha256 {
+                verify_sha256(&a.filename, &data, expected)?;
+            }
+            Ok(MailAttachment {
+                filename: a.filename.clone(),
+                content_type: ContentType::parse(&a.content_type).map_err(json_error)?,
+                data,
+                convert: a.convert.clone(),
+            })
+        })
+        .collect()
+}
+
+/// Decodes the inline images of a payload
+///
+/// Image data is always base64, regardless of the payload `encoding`. Content
+/// IDs must be unique and may not contain angle brackets.
+fn decode_inline_images(mail: &SendMailPayload) -> Result<Vec<MailInlineImage>> {
+    let mut images: Vec<MailInlineImage> = Vec::with_capacity(mail.inline_images.len());
+    for image in &mail.inline_images {
+        let content_id = image.content_id.trim();
+        if content_id.is_empty()
+            || content_id.contains(['<', '>'])
+            || content_id.contains(char::is_whitespace)
+        {
+            return Err(json_fail(format!(
+                "Invalid inline image content id: {}",
+                image.content_id
+            )));
+        }
+        if images.iter().any(|i| i.content_id == content_id) {
+            return Err(json_fail(format!(
+                "Duplicate inline image content id: {}",
+                content_id
+            )));
+        }
+        images.push(MailInlineImage {
+            content_id: content_id.to_owned(),
+            content_type: ContentType::parse(&image.content_type).map_err(json_error)?,
+            data: BASE64_STANDARD
+                .decode(image.data.as_bytes())
+                .map_err(json_error)?,
+        });
+    }
+    Ok(images)
+}
+
+/// Decodes the body parts and attachments of a payload
+///
+/// When `html` is supplied, `text` is the plain text alternative. Otherwise
+/// `text` is the only part, treated as HTML when `content_type` is `html`.
+/// Inline images require an HTML part.
+///
+/// # Arguments
+/// * `mail` - The email payload
+///
+/// # Returns
+/// The decoded body, or a JSON error response when decoding fails
+pub fn decode_body(mail: &SendMailPayload) -> Result<MailBody> {
+    let text = decode_text(&mail.text, &mail.encoding)?;
+    let attachments = decode_attachments(mail)?;
+    let inline_images = decode_inline_images(mail)?;
+
+    let (plain, html) = match &mail.html {
+        Some(html) => (Some(text), Some(decode_text(html, &mail.encoding)?)),
+        None if mail.content_type.eq("html") => (None, Some(text)),
+        None => (Some(text), None),
+    };
+
+    if html.is_none() && !inline_images.is_empty() {
+        return Err(json_fail("Inline images require an HTML body"));
+    }
+    let calendar = mail.calendar.as_ref().map(build_invite).transpose()?;
+
+    Ok(MailBody {
+        plain,
+        html,
+        attachments,
+        inline_images,
+        calendar,
+    })
+}
+
+/// Parses the sender into a mailbox, including its display name when given
+fn sender_mailbox(from: &Sender) -> Result<Mailbox> {
+    match from {
+        Sender::Address(address) => address.parse().map_err(json_error),
+        Sender::Named { address, name } => {
+            let address = address.parse().map_err(json_error)?;
+            Ok(Mailbox::new(name.clone(), address))
+        }
+    }
+}
+
+/// Builds a Message-ID that does not reveal the local hostname
+///
+/// Uses the configured privacy domain, or the sender's domain when unset.
+fn private_message_id(from: &Mailbox, privacy: &PrivacyConfig) -> String {
+    let domain = privacy
+        .domain
+        .clone()
+        .unwrap_or_else(|| from.email.domain().to_owned());
+    format!(
+        "<{:016x}{:016x}@{}>",
+        rand::random::<u64>(),
+        rand::random::<u64>(),
+        domain
+    )
+}
+
+/// Builds a text part of the given MIME type (`plain` or `html`)
+///
+/// With detection enabled the charset is the narrowest one that fits the text,
+/// otherwise always `utf-8`.
+fn text_part(subtype: &str, text: &str, detect: bool) -> SinglePart {
+    let charset = if detect { text_charset(text) } else { "utf-8" };
+    let content_type = format!("text/{}; charset={}", subtype, charset);
+    SinglePart::builder()
+        .header(ContentType::parse(&content_type).unwrap_or(ContentType::TEXT_PLAIN))
+        .body(text.to_owned())
+}
+
+/// Body of a message, before attachments
+enum BodyPart {
+    /// Single text part
+    Single(SinglePart),
+
+    /// Multipart body: `multipart/alternative` with the plain text and HTML
+    /// versions, or `multipart/related` with the HTML and its inline images
+    Multi(MultiPart),
+}
+
+/// Builds the HTML part, wrapped in `multipart/related` with its inline images
+fn html_part(html: &str, images: &[MailInlineImage], detect: bool) -> BodyPart {
+    let html = text_part("html", html, detect);
+    if images.is_empty() {
+        return BodyPart::Single(html);
+    }
+    let mut related = MultiPart::related().singlepart(html);
+    for image in images {
+        related = related.singlepart(
+            Attachment::new_inline(image.content_id.clone())
+                .body(image.data.clone(), image.content_type.clone()),
+        );
+    }
+    BodyPart::Multi(related)
+}
+
+/// Builds the `text/calendar` part of a meeting invitation
+fn calendar_part(ics: &str) -> SinglePart {
+    SinglePart::builder()
+        .header(
+            ContentType::parse("text/calendar; charset=utf-8; method=REQUEST")
+                .unwrap_or(ContentType::TEXT_PLAIN),
+        )
+        .body(ics.to_owned())
+}
+
+/// Builds the body of the message from its decoded parts
+///
+/// A meeting invitation is added as the last alternative, so clients that do
+/// not understand it show the text parts.
+fn body_part(body: &MailBody, detect: bool) -> BodyPart {
+    let Some(ics) = &body.calendar else {
+        return text_body_part(body, detect);
+    };
+    let alternative = match text_body_part(body, detect) {
+        BodyPart::Single(single) => MultiPart::alternative().singlepart(single),
+        BodyPart::Multi(multi) if body.plain.is_some() && body.html.is_some() => multi,
+        BodyPart::Multi(related) => MultiPart::alternative().multipart(related),
+    };
+    BodyPart::Multi(alternative.singlepart(calendar_part(ics)))
+}
+
+/// Builds the text parts of the message from its decoded parts
+fn text_body_part(body: &MailBody, detect: bool) -> BodyPart {
+    match (&body.plain, &body.html) {
+        (Some(plain), Some(html)) => {
+            let alternative =
+                MultiPart::alternative().singlepart(text_part("plain", plain, detect));
+            BodyPart::Multi(match html_part(html, &body.inline_images, detect) {
+                BodyPart::Single(single) => alternative.singlepart(single),
+                BodyPart::Multi(related) => alternative.multipart(related),
+            })
+        }
+        (None, Some(html)) => html_part(html, &body.inline_images, detect),
+        (Some(plain), None) => BodyPart::Single(text_part("plain", plain, detect)),
+        (None, None) => BodyPart::Single(text_part("plain", "", detect)),
+    }
+}
+
+/// Builds the email message
+///
+/// A plain text and an HTML part together produce a `multipart/alternative`
+/// message; a single part produces a single-part message. Inline images wrap the
+/// HTML part in a `multipart/related` part, and a meeting invitation is one more
+/// alternative. Attachments wrap the body in a
+/// `multipart/mixed` message with one part per file.
+///
+/// # Arguments
+/// * `mail` - The email payload (sender, reply-to and subject)
+/// * `body` - The decoded body parts
+/// * `recipients` - Recipient addresses the message is sent to
+/// * `privacy` - Privacy mode configuration (Message-ID domain)
+/// * `detection` - Language and charset detection configuration
+///
+/// # Returns
+/// The built message, or a JSON error response when an address is invalid
+pub fn build_message(
+    mail: &SendMailPayload,
+    body: &MailBody,
+    recipients: &[String],
+    privacy: &PrivacyConfig,
+    detection: &ContentDetectionConfig,
+) -> Result<Message> {
+    let mail_from = sender_mailbox(&mail.from)?;
+
+    // Parse all recipients
+    let mail_to: Vec<_> = recipients
+        .iter()
+        .map(|addr| addr.parse())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(json_error)?;
+
+    // Set a Message-ID on a public domain so relays do not derive one from internal hosts
+    let message_id = privacy
+        .enabled
+        .then(|| private_message_id(&mail_from, privacy));
+
+    // Build email with multiple recipients
+    let mut email_builder = Message::builder()
+        .from(mail_from)
+        .subject(mail.subject.clone());
+
+    if message_id.is_some() {
+        email_builder = email_builder.message_id(message_id);
+    }
+
+    for recipient in mail_to {
+        email_builder = email_builder.to(recipient);
+    }
+
+    // Route replies to a different mailbox when requested
+    if let Some(reply_to) = &mail.reply_to {
+        email_builder = email_builder.reply_to(reply_to.parse().map_err(json_error)?);
+    }
+
+    // Add the caller's headers, already checked against injection
+    for (name, value) in &mail.headers {
+        let name = HeaderName::new_from_ascii(name.clone()).map_err(json_fail)?;
+        email_builder = email_builder.raw_header(HeaderValue::new(name, value.clone()));
+    }
+
+    // Flag the importance in every header clients read it from
+    if let Some(priority) = mail.priority {
+        for (name, value) in priority_headers(priority) {
+            let name = HeaderName::new_from_ascii_str(name);
+            email_builder = email_builder.raw_header(HeaderValue::new(name, value.to_owned()));
+        }
+    }
+
+    // Announce the body language so clients pick matching fonts and hyphenation
+    if detection.enabled
+        && let Some(language) = detect_language(body)
+    {
+        email_builder = email_builder.header(ContentLanguage(language.to_owned()));
+    }
+
+    let body_part = body_part(body, detection.enabled);
+    if !body.attachments.is_empty() {
+        let mut mixed = match body_part {
+            BodyPart::Single(single) => MultiPart::mixed().singlepart(single),
+            BodyPart::Multi(multi) => MultiPart::mixed().multipart(multi),
+        };
+        for attachment in &body.attachments {
+            mixed = mixed.singlepart(
+                Attachment::new(attachment.filename.clone())
+                    .body(attachment.data.clone(), attachment.content_type.clone()),
+            );
+        }
+        return email_builder.multipart(mixed).map_err(json_error);
+    }
+
+    let email = match body_part {
+        BodyPart::Single(single) => email_builder.singlepart(single),
+        BodyPart::Multi(multi) => email_builder.multipart(multi),
+    };
+
+    email.map_err(json_error)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(plain: &str) -> MailBody {
+        MailBody {
+            plain: Some(plain.to_owned()),
+            html: None,
+            attachments: Vec::new(),
+            inline_images: Vec::new(),
+            calendar: None,
+        }
+    }
+
+    fn attachment(filename: &str, data: &[u8]) -> MailAttachment {
+        MailAttachment {
+            filename: filename.to_owned(),
+            content_type: ContentType::parse("application/pdf").unwrap(),
+            data: data.to_vec(),
+            convert: None,
+        }
+    }
+
+    fn with_attachment(filename: &str, data: &[u8]) -> MailBody {
+        let mut body = body("Your invoice");
+        body.attachments.push(attachment(filename, data));
+        body
+    }
+
+    #[test]
+    fn same_content_has_the_same_fingerprint() {
+        assert_eq!(
+            with_attachment("invoice.pdf", b"A").fingerprint_source(),
+            with_attachment("invoice.pdf", b"A").fingerprint_source()
+        );
+    }
+
+    #[test]
+    fn attachments_are_fingerprinted() {
+        let source = with_attachment("invoice.pdf", b"A").fingerprint_source();
+        assert_ne!(source, body("Your invoice").fingerprint_source());
+        assert_ne!(
+            source,
+            with_attachment("invoice.pdf", b"B").fingerprint_source()
+        );
+        assert_ne!(
+            source,
+            with_attachment("receipt.pdf", b"A").fingerprint_source()
+        );
+    }
+
+    #[test]
+    fn inline_images_are_fingerprinted() {
+        let image = |data: &[u8]| MailInlineImage {
+            content_id: "logo".to_owned(),
+            content_type: ContentType::parse("image/png").unwrap(),
+            data: data.to_vec(),
+        };
+        let mut first = body("Hello");
+        first.inline_images.push(image(b"A"));
+        let mut second = body("Hello");
+        second.inline_images.push(image(b"B"));
+
+        assert_ne!(first.fingerprint_source(), second.fingerprint_source());
+        assert_ne!(
+            first.fingerprint_source(),
+            body("Hello").fingerprint_source()
+        );
+    }
+
+    #[test]
+    fn calendar_is_fingerprinted() {
+        let mut invite = body("Meeting");
+        invite.calendar = Some("BEGIN:VCALENDAR".to_owned());
+        assert_ne!(
+            invite.fingerprint_source(),
+            body("Meeting").fingerprint_source()
+        );
+    }
+
+    #[test]
+    fn body_parts_are_kept_apart() {
+        let mut html = body("");
+        html.plain = None;
+        html.html = Some("Hello".to_owned());
+        assert_ne!(
+            html.fingerprint_source(),
+            body("Hello").fingerprint_source()
+        );
+    }
+
+    #[test]
+    fn verifies_attachment_digests() {
+        let digest = "BA7816BF8F01CFEA414140DE5DAE2223B00361A396177A9CB410FF61F20015AD";
+        assert!(verify_sha256("a.txt", b"abc", digest).is_ok());
+        assert!(verify_sha256("a.txt", b"abc", &format!(" {} ", digest.to_lowercase())).is_ok());
+        assert!(verify_sha256("a.txt", b"abd", digest).is_err());
+    }
+}
@@ -3,8 +3,50 @@
 //! This module contains all components related to email sending functionality,
 //! including data transfer objects (DTOs) and HTTP controllers.
 
+/// Sender domain alignment check
+pub mod alignment;
+
+/// iCalendar meeting invitations
+pub mod calendar;
+
+/// Shared state of the email sending endpoints
+pub mod context;
+
 /// Data transfer objects for email requests and responses
 pub mod dto;
 
+/// Custom headers of outgoing mail
+pub mod headers;
+
+/// HTML body post-processing
+pub mod html;
+
+/// MIME structure limits
+pub mod limits;
+
+/// Attachment format conversion
+pub mod convert;
+
+/// Idempotency keys of send requests
+pub mod idempotency;
+
+/// Body language and charset detection
+pub mod language;
+
+/// Email message construction
+pub mod message;
+
+/// Recipient domain policy
+pub mod recipients;
+
+/// Health-aware load shedding
+pub mod shedding;
+
+/// Per-request SMTP relay override
+pub mod smtp_override;
+
+/// Subject prefix and suffix policy
+pub mod subject;
+
 /// HTTP controllers for email sending endpoints
 pub mod send_controller;
@@ -0,0 +1,43 @@
+//! Recipient domain policy
+//!
+//! This module restricts the domains an email may be sent to, so a staging
+//! deployment can only ever reach internal mailboxes. A domain entry matches the
+//! domain itself and its subdomains.
+
+use crate::settings::RecipientPolicy;
+
+/// Returns `true` when a domain is one of the entries or one of their subdomains
+fn matches(domain: &str, entries: &[String]) -> bool {
+    entries
+        .iter()
+        .any(|e| domain == e || domain.ends_with(&format!(".{}", e)))
+}
+
+/// Returns the recipients the domain policy does not allow
+///
+/// A recipient is blocked when its domain is denied, or when an allowlist is
+/// configured and its domain is not on it. The denylist wins over the allowlist.
+///
+/// # Arguments
+/// * `recipients` - Recipient addresses of the email
+/// * `policy` - Configured allowed and denied domains
+///
+/// # Returns
+/// The blocked recipients, in request order; empty when every recipient is allowed
+pub fn blocked_recipients(recipients: &[String], policy: &RecipientPolicy) -> Vec<String> {
+    recipients
+        .iter()
+        .filter(|r| {
+            let domain = r
+                .rsplit('@')
+                .next()
+                .unwrap_or_default()
+                .trim()
+                .trim_end_matches('>')
+                .to_lowercase();
+            matches(&domain, &policy.deny_domains)
+                || (!policy.allow_domains.is_empty() && !matches(&domain, &policy.allow_domains))
+        })
+        .cloned()
+        .collect()
+}
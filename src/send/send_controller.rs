@@ -2,22 +2,99 @@
 //!
 //! This module provides the HTTP handlers for health checks and email sending functionality.
 
-use crate::send::dto::SendMailReq;
-use crate::settings::{RustMailRes, SmtpConfig, Status, json_error};
-use actix_web::{HttpRequest, HttpResponse, Result, get, head, post, web};
-use base64::{Engine, prelude::BASE64_STANDARD};
-use lettre::transport::smtp::authentication::Credentials;
-use lettre::message::SinglePart;
-use lettre::{Message, SmtpTransport, Transport};
-use log::{debug, info};
+use std::collections::HashMap;
+use std::time::{Duration, Instant, UNIX_EPOCH};
+
+use crate::auth::{tenant, verified_key};
+use crate::circuit_breaker::CircuitState;
+use crate::codec::{Payload, respond, respond_with};
+use crate::dedup::DuplicateClaim;
+use crate::fault::{check_fault, inject_fault};
+use crate::link_check::check_links;
+use crate::metrics::domains::AttemptOutcome;
+use crate::openapi::{SWAGGER_UI_HTML, api_doc};
+use crate::queue::store::{MessageState, PendingConversion, QueueOptions, QueueStatus};
+use crate::queue::worker::unix_now;
+use crate::request_id::current_request_id;
+use crate::send::alignment::check_alignment;
+use crate::send::context::SendContext;
+use crate::send::convert::{check_converters, convert_attachments, needs_conversion};
+use crate::send::dto::{
+    BulkItemRes, BulkRes, CircuitItem, CircuitStatus, DeliveryState, DryRunRes, HealthRes,
+    MessageStatusItem, QueuedRes, RecipientRes, ScheduledItem, ScheduledRes, SendBulkReq,
+    SendMailReq, SendOutcome, SendStatusReq, SendStatusRes, SentRes,
+};
+use crate::send::headers::check_headers;
+use crate::send::html::{inject_dark_mode, inject_preheader};
+use crate::send::idempotency::{
+    Claim, IDEMPOTENCY_KEY_HEADER, IDEMPOTENT_REPLAYED_HEADER, MAX_IDEMPOTENCY_KEY_LEN,
+};
+use crate::send::limits::{LimitExceededRes, check_mime_limits};
+use crate::send::message::{MailBody, build_message, decode_body};
+use crate::send::recipients::blocked_recipients;
+use crate::send::shedding::{open_circuit, overload, shed, sheddable};
+use crate::send::smtp_override::{check_smtp_override, override_mailer};
+use crate::send::subject::apply_subject_policy;
+use crate::settings::{
+    DEFAULT_TENANT, DuplicateGuardMode, FetchPolicy, LinkCheckConfig, LinkCheckMode,
+    RecipientDisplay, ResponsePolicy, RustMailRes, Status, json_conflict, json_error, json_fail,
+    json_not_found,
+};
+use crate::template::dto::SendTemplateReq;
+use crate::template::mail::render_mail;
+use crate::template::registry::TemplateRegistry;
+use crate::transport::{Mailer, partition_blackhole};
+use actix_web::http::StatusCode;
+use actix_web::http::header::{ContentType, HeaderName, HeaderValue};
+use actix_web::{HttpRequest, HttpResponse, Result, delete, get, head, post, web};
+use futures_util::{StreamExt, stream};
+use lettre::Message;
+use lettre::address::{AddressError, Envelope};
+use lettre::message::Mailbox;
+use lettre::message::header::Date;
+use log::{debug, info, warn};
+use serde_json::Value;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+use tokio::sync::Semaphore;
+use utoipa::OpenApi;
 
 /// Performs health check and returns service status
 ///
-/// Returns a JSON response indicating the service is up and running.
-fn do_health_check() -> Result<HttpResponse> {
-    let x = RustMailRes {
+/// Returns a JSON response indicating the service is up and running, with the
+/// circuit breaker state of each SMTP profile when the breaker is enabled.
+fn do_health_check(ctx: &SendContext) -> Result<HttpResponse> {
+    let circuits: Vec<CircuitItem> = ctx
+        .mailers
+        .circuits()
+        .into_iter()
+        .map(|(profile, circuit)| CircuitItem {
+            profile,
+            state: match circuit.state {
+                CircuitState::Closed => CircuitStatus::Closed,
+                CircuitState::Open => CircuitStatus::Open,
+                CircuitState::HalfOpen => CircuitStatus::HalfOpen,
+            },
+            failures: circuit.failures,
+            retry_in: circuit.retry_in.map(|d| d.as_secs() + 1),
+        })
+        .collect();
+    let open: Vec<&str> = circuits
+        .iter()
+        .filter(|c| c.state == CircuitStatus::Open)
+        .map(|c| c.profile.as_str())
+        .collect();
+    let message = if open.is_empty() {
+        "Rust mail up".to_owned()
+    } else {
+        format!("Rust mail up, SMTP circuit open for {}", open.join(", "))
+    };
+
+    let x = HealthRes {
         status: Status::Ok,
-        message: "Rust mail up".to_owned(),
+        message,
+        request_id: current_request_id(),
+        circuits,
     };
     Ok(HttpResponse::Ok().json(x))
 }
@@ -25,17 +102,31 @@ fn do_health_check() -> Result<HttpResponse> {
 /// GET endpoint for health check
 ///
 /// Maps to the root path and returns the service status.
+#[utoipa::path(
+    get,
+    path = "/",
+    tag = "health",
+    security(()),
+    responses((status = 200, description = "Service is up", body = HealthRes))
+)]
 #[get("")]
-async fn health_check_get() -> Result<HttpResponse> {
-    do_health_check()
+async fn health_check_get(ctx: web::Data<SendContext>) -> Result<HttpResponse> {
+    do_health_check(&ctx)
 }
 
 /// HEAD endpoint for health check
 ///
 /// Maps to the root path and returns the service status headers without body.
+#[utoipa::path(
+    head,
+    path = "/",
+    tag = "health",
+    security(()),
+    responses((status = 200, description = "Service is up"))
+)]
 #[head("")]
-async fn health_check_head() -> Result<HttpResponse> {
-    do_health_check()
+async fn health_check_head(ctx: web::Data<SendContext>) -> Result<HttpResponse> {
+    do_health_check(&ctx)
 }
 
 /// POST endpoint for sending emails
@@ -44,21 +135,97 @@ async fn health_check_head() -> Result<HttpResponse> {
 ///
 /// # Arguments
 /// * `req` - HTTP request containing headers for logging
-/// * `body` - JSON payload containing email details (from, to, subject, text, encoding)
-/// * `smtp_config` - SMTP server configuration injected by Actix
+/// * `body` - JSON, MessagePack or form payload containing email details (from, to, subject, text, html, encoding)
+/// * `ctx` - Shared send state (SMTP transport, configuration, registries) injected by Actix
 ///
 /// # Returns
-/// * `Ok(HttpResponse)` - JSON response with success message on successful send
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) response with success message on successful send,
+///   or HTTP 202 with the queue identifier when `queue` is set
 /// * `Err(actix_web::Error)` - JSON error response on failure (invalid email, SMTP errors, etc.)
 ///
 /// # Encoding Support
 /// * `plain` - Text is sent as-is
-/// * `base64` - Text is base64 decoded before sending
+/// * `base64` - Text (and html) is base64 decoded before sending
+///
+/// # Body Parts
+/// When both `text` and `html` are supplied a `multipart/alternative` message is sent.
+///
+/// # Form Submissions
+/// `application/x-www-form-urlencoded` bodies are accepted too, with field names
+/// mirroring the JSON structure (e.g. `mail[subject]`, `mail[to][]`).
+///
+/// # Link Validation
+/// HTML bodies are scanned for broken or unsafe links when `LINK_CHECK_MODE` is
+/// `warn` (problems are logged) or `block` (the request fails with status `fail`).
+///
+/// # Duplicate Guard
+/// When `DUPLICATE_GUARD_MODE` is `skip`, recipients that received the same subject
+/// and body within the window are removed; if none remain nothing is sent.
+/// With `flag` duplicates are logged and sent anyway.
+///
+/// # Blackhole Recipients
+/// Recipients in a `BLACKHOLE_DOMAINS` domain go through the whole pipeline but
+/// the message is discarded for them instead of being sent.
+///
+/// # Subject Policy
+/// `SUBJECT_PREFIX` and `SUBJECT_SUFFIX` are added to the subject unless already present.
+///
+/// # SMTP Profiles
+/// `"profile"` selects one of the `SMTP_PROFILES` relays; unknown profiles fail.
+///
+/// # SMTP Override
+/// With `SMTP_OVERRIDE_ENABLED`, callers using an `ADMIN_API_KEYS` key may give
+/// the relay inline in `"smtp"` (host, port, tls_mode, username, password).
+/// Other callers get HTTP 403; the override cannot be queued or scheduled.
+///
+/// # DKIM
+/// When `DKIM_DOMAIN` is set every message is signed before being sent or queued.
+///
+/// # Queued Delivery
+/// With `"queue": true` the built message is stored in the persistent queue and
+/// delivered by the background worker with exponential-backoff retries.
+///
+/// # Dry Run
+/// With `"dry_run": true`, or always when `SANDBOX` is set, the message is
+/// validated and built but neither sent nor queued; the response gives its
+/// size and envelope recipients.
+///
+/// # Scheduled Delivery
+/// A `"send_at"` time in the future stores the message in the queue until that
+/// time; the message is dated with it. A past `send_at` sends immediately.
+///
+/// # Idempotency
+/// An `Idempotency-Key` header (or `"idempotency_key"` field) makes retries
+/// safe: the response of the completed request is returned again and nothing
+/// is sent twice.
+#[utoipa::path(
+    post,
+    path = "/send",
+    tag = "send",
+    request_body(content(
+        (SendMailReq = "application/json"),
+        (SendMailReq = "application/msgpack"),
+        (SendMailReq = "application/x-www-form-urlencoded")
+    )),
+    params(("Idempotency-Key" = Option<String>, Header, description = "Key identifying the request across retries")),
+    responses(
+        (status = 200, description = "Email sent, skipped or discarded; `SentRes` with one result \
+            per recipient when sent to several recipients, `DryRunRes` for dry runs", body = RustMailRes),
+        (status = 202, description = "Email queued or scheduled", body = QueuedRes),
+        (status = 400, description = "Invalid request, or `LimitExceededRes` when a MIME limit is exceeded", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 403, description = "SMTP override without an admin API key", body = RustMailRes),
+        (status = 409, description = "A request with the same idempotency key is in progress", body = RustMailRes),
+        (status = 429, description = "Rate limit exceeded", body = RustMailRes),
+        (status = 500, description = "SMTP or internal error", body = RustMailRes),
+        (status = 503, description = "Service overloaded, low priority mail shed", body = RustMailRes)
+    )
+)]
 #[post("send")]
 async fn send(
     req: HttpRequest,
-    body: web::Json<SendMailReq>,
-    smtp_config: web::Data<SmtpConfig>,
+    body: Payload<SendMailReq>,
+    ctx: web::Data<SendContext>,
 ) -> Result<HttpResponse> {
     let host_header = req.headers().iter().find(|x| x.0.eq("host"));
     if let Some(header) = host_header {
@@ -67,87 +234,1211 @@ async fn send(
         info!("No host header found in the request");
     }
 
-    let payload = body.into_inner();
+    send_mail(&req, body.into_inner(), &ctx).await
+}
+
+/// A message of a bulk request, before rendering
+enum BulkJob {
+    /// Complete email
+    Mail(Box<SendMailReq>),
+
+    /// Email still to be rendered from a template
+    Template(Box<SendTemplateReq>),
+}
 
-    // Decode email text based on encoding type
-    let mut text = payload.mail.text.to_owned();
-    if payload.mail.encoding.eq("base64") {
-        // Decode base64 encoded text
-        let x = BASE64_STANDARD
-            .decode(payload.mail.text)
-            .map_err(json_error)?;
-        text = String::from_utf8(x).map_err(json_error)?;
+/// Merges the variables of one recipient over the shared template context
+fn merge_context(shared: &Value, own: Value) -> Value {
+    match (shared, own) {
+        (Value::Object(shared), Value::Object(own)) => {
+            let mut merged = shared.clone();
+            merged.extend(own);
+            Value::Object(merged)
+        }
+        (shared, Value::Null) => shared.clone(),
+        (_, own) => own,
     }
+}
 
-    debug!("{}", text);
+/// Converts the outcome of one bulk message into its result entry
+fn bulk_item(index: usize, outcome: Result<SendOutcome>) -> BulkItemRes {
+    match outcome {
+        Ok(SendOutcome::Done(x)) => BulkItemRes {
+            index,
+            status: x.status,
+            message: x.message,
+            id: None,
+            results: None,
+        },
+        Ok(SendOutcome::Sent(x)) => BulkItemRes {
+            index,
+            status: x.status,
+            message: x.message,
+            id: None,
+            results: x.results,
+        },
+        Ok(SendOutcome::Queued(x)) => BulkItemRes {
+            index,
+            status: x.status,
+            message: x.message,
+            id: Some(x.id),
+            results: None,
+        },
+        Ok(SendOutcome::DryRun(x)) => BulkItemRes {
+            index,
+            status: x.status,
+            message: x.message,
+            id: None,
+            results: None,
+        },
+        Err(err) => {
+            let status = if err.as_response_error().status_code().is_server_error() {
+                Status::Error
+            } else {
+                Status::Fail
+            };
+            BulkItemRes {
+                index,
+                status,
+                message: err.to_string(),
+                id: None,
+                results: None,
+            }
+        }
+    }
+}
 
-    let mail_from = payload.mail.from.parse().map_err(json_error)?;
+/// POST endpoint for sending many emails in one request
+///
+/// Accepts either a list of emails (`mails`) or one template with a list of
+/// recipients, each receiving its own email rendered with the shared context
+/// merged with the recipient's variables.
+///
+/// Every message goes through the same pipeline as `/send`, over the shared
+/// pooled SMTP connections, up to `BULK_CONCURRENCY` at a time. A failing
+/// message does not stop the others.
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) response with one result per message, in request order
+/// * `Err(actix_web::Error)` - JSON fail response when the request exceeds `BULK_MAX_MESSAGES`
+///   or uses a template while templates are not configured
+#[utoipa::path(
+    post,
+    path = "/send/bulk",
+    tag = "send",
+    request_body = SendBulkReq,
+    responses(
+        (status = 200, description = "One result per message, in request order", body = BulkRes),
+        (status = 400, description = "Too many messages or templates not configured", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 429, description = "Rate limit exceeded", body = RustMailRes)
+    )
+)]
+#[post("send/bulk")]
+async fn send_bulk(
+    req: HttpRequest,
+    body: Payload<SendBulkReq>,
+    templates: Option<web::Data<TemplateRegistry>>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    let bulk = body.into_inner();
 
-    // Parse all recipients
-    let mail_to: Vec<_> = payload
-        .mail
-        .to
-        .iter()
-        .map(|addr| addr.parse())
-        .collect::<Result<Vec<_>, _>>()
+    // Refuse oversized requests before building one message per recipient
+    let count = match &bulk {
+        SendBulkReq::Mails { mails, .. } => mails.len(),
+        SendBulkReq::Template { recipients, .. } => recipients.len(),
+    };
+    let limits = &ctx.bulk_limits;
+    if count > limits.max_messages {
+        return Err(json_fail(format!(
+            "Too many messages: {} (max {})",
+            count, limits.max_messages
+        )));
+    }
+
+    // Attachment and image contents are shared, not copied, by the messages
+    let jobs: Vec<BulkJob> = match bulk {
+        SendBulkReq::Mails {
+            mails,
+            queue,
+            profile,
+            send_at,
+            callback_url,
+            dry_run,
+        } => mails
+            .into_iter()
+            .map(|mail| {
+                BulkJob::Mail(Box::new(SendMailReq {
+                    mail,
+                    queue,
+                    profile: profile.clone(),
+                    send_at: send_at.clone(),
+                    callback_url: callback_url.clone(),
+                    dry_run,
+                    smtp: None,
+                    fault: None,
+                    idempotency_key: None,
+                    tenant: None,
+                }))
+            })
+            .collect(),
+        SendBulkReq::Template {
+            template,
+            context,
+            from,
+            reply_to,
+            preheader,
+            attachments,
+            inline_images,
+            calendar,
+            headers,
+            priority,
+            recipients,
+            queue,
+            profile,
+            send_at,
+            callback_url,
+            dry_run,
+        } => {
+            if templates.is_none() {
+                return Err(json_fail("Templates are not configured (TEMPLATES_DIR)"));
+            }
+            recipients
+                .into_iter()
+                .map(|r| {
+                    BulkJob::Template(Box::new(SendTemplateReq {
+                        template: template.clone(),
+                        context: merge_context(&context, r.context),
+                        from: from.as_ref().clone(),
+                        reply_to: reply_to.clone(),
+                        to: vec![r.to],
+                        preheader: preheader.clone(),
+                        attachments: attachments.clone(),
+                        inline_images: inline_images.clone(),
+                        calendar: calendar.as_deref().cloned(),
+                        headers: headers.clone(),
+                        priority,
+                        queue,
+                        profile: profile.clone(),
+                        send_at: send_at.clone(),
+                        callback_url: callback_url.clone(),
+                        dry_run,
+                    }))
+                })
+                .collect()
+        }
+    };
+
+    info!("send bulk request with {} message(s)", jobs.len());
+
+    // Checked once for the whole request; the circuit is checked for the profile of each message
+    let overload = if ctx.load_shedding_config.enabled() {
+        overload(&ctx).await
+    } else {
+        None
+    };
+
+    let ctx = &ctx;
+    let templates = &templates;
+    let tenant = &tenant(&req);
+    let overload = &overload;
+    let results: Vec<BulkItemRes> = stream::iter(jobs.into_iter().enumerate())
+        .map(|(index, job)| async move {
+            let outcome = async {
+                let mut mail = match (job, templates) {
+                    (BulkJob::Mail(mail), _) => *mail,
+                    (BulkJob::Template(t), Some(templates)) => {
+                        render_mail(templates.clone(), *t).await?
+                    }
+                    (BulkJob::Template(_), None) => {
+                        return Err(json_fail("Templates are not configured (TEMPLATES_DIR)"));
+                    }
+                };
+                mail.tenant = Some(tenant.clone());
+                if ctx.load_shedding_config.enabled() && sheddable(&mail, true) {
+                    let circuit = open_circuit(ctx, mail.profile.as_deref());
+                    if let Some(reason) = circuit.as_ref().or(overload.as_ref()) {
+                        shed(&mut mail, reason, &ctx.load_shedding_config)?;
+                    }
+                }
+                process_mail(mail, ctx).await
+            }
+            .await;
+            bulk_item(index, outcome)
+        })
+        .buffered(limits.concurrency)
+        .collect()
+        .await;
+
+    let x = BulkRes {
+        status: Status::Ok,
+        results,
+    };
+    respond(&req, &x)
+}
+
+/// Parses the `send_at` delivery time of a request
+///
+/// # Returns
+/// * `Ok(u64)` - The delivery time as Unix timestamp in seconds
+/// * `Err(actix_web::Error)` - JSON fail response when the time is not RFC3339
+fn parse_send_at(value: &str) -> Result<u64> {
+    let time = OffsetDateTime::parse(value, &Rfc3339)
+        .map_err(|e| json_fail(format!("Invalid send_at {}: {}", value, e)))?;
+    Ok(time.unix_timestamp().max(0) as u64)
+}
+
+/// Describes recipients in a response message according to the response policy
+fn describe_recipients(recipients: &[String], policy: &ResponsePolicy) -> String {
+    match policy.recipients {
+        RecipientDisplay::List => recipients.join(", "),
+        RecipientDisplay::Count => format!("{} recipient(s)", recipients.len()),
+    }
+}
+
+/// Formats a Unix timestamp as RFC3339
+fn format_unix(secs: u64) -> String {
+    OffsetDateTime::from_unix_timestamp(secs as i64)
+        .ok()
+        .and_then(|t| t.format(&Rfc3339).ok())
+        .unwrap_or_default()
+}
+
+/// GET endpoint listing the emails scheduled for later delivery
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) list of the scheduled emails, earliest first
+/// * `Err(actix_web::Error)` - JSON error response when the queue cannot be read
+#[utoipa::path(
+    get,
+    path = "/send/scheduled",
+    tag = "send",
+    responses(
+        (status = 200, description = "Scheduled emails, earliest first", body = ScheduledRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 500, description = "Queue database error", body = RustMailRes)
+    )
+)]
+#[get("send/scheduled")]
+async fn list_scheduled(req: HttpRequest, ctx: web::Data<SendContext>) -> Result<HttpResponse> {
+    let queue = ctx.queue.clone();
+    let scheduled = web::block(move || queue.scheduled(unix_now()))
+        .await
+        .map_err(json_error)?
+        .map_err(json_error)?;
+
+    let x = ScheduledRes {
+        status: Status::Ok,
+        scheduled: scheduled
+            .into_iter()
+            .map(|m| ScheduledItem {
+                id: m.id,
+                from: m.from,
+                recipients: m.to.len(),
+                to: (ctx.response_policy.recipients == RecipientDisplay::List).then_some(m.to),
+                profile: m.profile,
+                send_at: format_unix(m.send_at),
+            })
+            .collect(),
+    };
+    respond(&req, &x)
+}
+
+/// DELETE endpoint cancelling a scheduled email
+///
+/// Only emails whose delivery time is still ahead can be cancelled.
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) success response
+/// * `Err(actix_web::Error)` - JSON fail response with HTTP 404 when no such email is scheduled
+#[utoipa::path(
+    delete,
+    path = "/send/scheduled/{id}",
+    tag = "send",
+    params(("id" = String, Path, description = "Queue identifier of the scheduled email")),
+    responses(
+        (status = 200, description = "Scheduled email cancelled", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 404, description = "No such email is scheduled", body = RustMailRes),
+        (status = 500, description = "Queue database error", body = RustMailRes)
+    )
+)]
+#[delete("send/scheduled/{id}")]
+async fn cancel_scheduled(
+    req: HttpRequest,
+    path: web::Path<String>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    let id = path.into_inner();
+    let queue = ctx.queue.clone();
+    let target = id.clone();
+    let cancelled = web::block(move || queue.cancel_scheduled(&target, unix_now()))
+        .await
+        .map_err(json_error)?
         .map_err(json_error)?;
+    if !cancelled {
+        return Err(json_not_found(format!("No scheduled mail with id {}", id)));
+    }
 
-    // Build email with multiple recipients
-    let mut email_builder = Message::builder()
-        .from(mail_from)
-        .subject(payload.mail.subject);
+    let message = format!("Scheduled mail {} cancelled", id);
+    info!("{}", message);
+    let x = RustMailRes {
+        status: Status::Ok,
+        message,
+        request_id: current_request_id(),
+    };
+    respond(&req, &x)
+}
 
-    for recipient in mail_to {
-        email_builder = email_builder.to(recipient);
+/// POST endpoint returning the delivery status of several queued emails
+///
+/// Takes the ids returned when the emails were queued or scheduled, so callers
+/// tracking a large batch need a single call. Ids may be repeated; each gets
+/// its own entry.
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - JSON (or MessagePack) response with one status per id, in request order
+/// * `Err(actix_web::Error)` - JSON fail response when the request has no id or exceeds
+///   `STATUS_MAX_IDS`, JSON error response when the queue cannot be read
+#[utoipa::path(
+    post,
+    path = "/send/status",
+    tag = "send",
+    request_body = SendStatusReq,
+    responses(
+        (status = 200, description = "One status per id, in request order", body = SendStatusRes),
+        (status = 400, description = "No id or too many ids", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 500, description = "Queue database error", body = RustMailRes)
+    )
+)]
+#[post("send/status")]
+async fn send_status(
+    req: HttpRequest,
+    body: Payload<SendStatusReq>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    let ids = body.into_inner().ids;
+    if ids.is_empty() {
+        return Err(json_fail("At least one message id is required"));
+    }
+    let max = ctx.bulk_limits.max_status_ids;
+    if ids.len() > max {
+        return Err(json_fail(format!(
+            "Too many message ids: {} (max {})",
+            ids.len(),
+            max
+        )));
     }
 
-    let email = if payload.mail.content_type.eq("html") {
-        email_builder.singlepart(SinglePart::html(text)).map_err(json_error)?
-    } else {
-        email_builder.body(text).map_err(json_error)?
+    let queue = ctx.queue.clone();
+    let mut unique = ids.clone();
+    unique.sort();
+    unique.dedup();
+    let states: HashMap<String, MessageState> = web::block(move || queue.states(&unique))
+        .await
+        .map_err(json_error)?
+        .map_err(json_error)?
+        .into_iter()
+        .map(|s| (s.id.clone(), s))
+        .collect();
+
+    let now = unix_now();
+    let messages = ids
+        .into_iter()
+        .map(|id| match states.get(&id) {
+            None => MessageStatusItem {
+                id,
+                state: DeliveryState::NotFound,
+                attempts: None,
+                error: None,
+                send_at: None,
+                updated_at: None,
+            },
+            Some(s) => {
+                let scheduled =
+                    s.status == QueueStatus::Pending && s.send_at.is_some_and(|t| t > now);
+                MessageStatusItem {
+                    id,
+                    state: match s.status {
+                        QueueStatus::Converting => DeliveryState::Converting,
+                        QueueStatus::Pending if scheduled => DeliveryState::Scheduled,
+                        QueueStatus::Pending => DeliveryState::Pending,
+                        QueueStatus::Sent => DeliveryState::Sent,
+                        QueueStatus::Failed => DeliveryState::Failed,
+                    },
+                    attempts: Some(s.attempts),
+                    error: s.last_error.clone(),
+                    send_at: s.send_at.filter(|_| scheduled).map(format_unix),
+                    updated_at: Some(format_unix(s.updated_at)),
+                }
+            }
+        })
+        .collect();
+
+    let x = SendStatusRes {
+        status: Status::Ok,
+        messages,
     };
+    respond(&req, &x)
+}
 
-    // Build SMTP transport with configuration
-    let mailer = if smtp_config.use_tls {
-        // Use relay with STARTTLS
-        let mut mailer_builder = SmtpTransport::relay(&smtp_config.host)
-            .map_err(json_error)?
-            .port(smtp_config.port);
+/// Returns the idempotency key of a request, scoped to the caller's verified API key
+///
+/// The `Idempotency-Key` header takes precedence over the `idempotency_key` field.
+///
+/// # Returns
+/// * `Ok(Some(key))` - The scoped key
+/// * `Ok(None)` - The request has no key
+/// * `Err(actix_web::Error)` - JSON fail response when the key is empty or too long
+fn idempotency_key(req: &HttpRequest, payload: &SendMailReq) -> Result<Option<String>> {
+    let header = req
+        .headers()
+        .get(IDEMPOTENCY_KEY_HEADER)
+        .map(|v| v.to_str().map_err(json_fail))
+        .transpose()?;
+    let Some(key) = header.or(payload.idempotency_key.as_deref()) else {
+        return Ok(None);
+    };
+    let key = key.trim();
+    if key.is_empty() || key.len() > MAX_IDEMPOTENCY_KEY_LEN {
+        return Err(json_fail(format!(
+            "Idempotency key must be 1 to {} characters",
+            MAX_IDEMPOTENCY_KEY_LEN
+        )));
+    }
+
+    // Only a key checked by the auth middleware identifies the caller: the raw
+    // header of an unauthenticated request could claim another caller's keys
+    let caller = verified_key(req).unwrap_or_default();
+    Ok(Some(format!("{}\u{0}{}", caller, key)))
+}
+
+/// Builds the HTTP response of a send outcome (202 when queued, 500 when every recipient failed)
+fn outcome_response(req: &HttpRequest, outcome: &SendOutcome) -> Result<HttpResponse> {
+    match outcome {
+        SendOutcome::Done(x) => respond(req, x),
+        SendOutcome::Sent(x) if matches!(x.status, Status::Error) => {
+            respond_with(req, StatusCode::INTERNAL_SERVER_ERROR, x)
+        }
+        SendOutcome::Sent(x) => respond(req, x),
+        SendOutcome::Queued(x) => respond_with(req, StatusCode::ACCEPTED, x),
+        SendOutcome::DryRun(x) => respond(req, x),
+    }
+}
+
+/// Runs an email request through the send pipeline and builds the HTTP response
+///
+/// Shared by every endpoint that sends a single email. A request carrying an
+/// idempotency key already completed gets the original response back, marked
+/// with the `Idempotent-Replayed` header, and nothing is sent again; failed
+/// requests are not remembered, so their retries are processed.
+///
+/// # Arguments
+/// * `req` - HTTP request, used to negotiate the response format
+/// * `payload` - The email request
+/// * `ctx` - Shared send state
+///
+/// # Returns
+/// The HTTP response for the caller (202 when queued), or a JSON error/fail
+/// response (409 while a request with the same idempotency key is in progress)
+pub async fn send_mail(
+    req: &HttpRequest,
+    mut payload: SendMailReq,
+    ctx: &SendContext,
+) -> Result<HttpResponse> {
+    check_smtp_override(req, &payload, &ctx.smtp_override_config)?;
+    check_fault(req, &payload, &ctx.fault_config)?;
+    payload.tenant = Some(tenant(req));
 
-        // Add credentials if provided
-        if let (Some(username), Some(password)) = (&smtp_config.username, &smtp_config.password) {
-            let creds = Credentials::new(username.clone(), password.clone());
-            mailer_builder = mailer_builder.credentials(creds);
+    // Keep the capacity for high priority mail while the service is overloaded
+    if ctx.load_shedding_config.enabled() && sheddable(&payload, false) {
+        let reason = match open_circuit(ctx, payload.profile.as_deref()) {
+            Some(reason) => Some(reason),
+            None => overload(ctx).await,
+        };
+        if let Some(reason) = reason {
+            shed(&mut payload, &reason, &ctx.load_shedding_config)?;
         }
+    }
 
-        mailer_builder.build()
+    let key = if ctx.idempotency.config.window.is_zero() {
+        None
     } else {
-        // Use plain SMTP without TLS
-        let mut mailer_builder =
-            SmtpTransport::builder_dangerous(&smtp_config.host).port(smtp_config.port);
-
-        // Add credentials if provided
-        if let (Some(username), Some(password)) = (&smtp_config.username, &smtp_config.password) {
-            let creds = Credentials::new(username.clone(), password.clone());
-            mailer_builder = mailer_builder.credentials(creds);
+        idempotency_key(req, &payload)?
+    };
+    let guard = match key.map(|key| ctx.idempotency.claim(key)) {
+        None => None,
+        Some(Claim::New(guard)) => Some(guard),
+        Some(Claim::InProgress) => {
+            return Err(json_conflict(
+                "A request with this idempotency key is in progress",
+            ));
+        }
+        Some(Claim::Completed(outcome)) => {
+            info!("Idempotency key replayed, mail not sent again");
+            let mut res = outcome_response(req, &outcome)?;
+            res.headers_mut().insert(
+                HeaderName::from_static(IDEMPOTENT_REPLAYED_HEADER),
+                HeaderValue::from_static("true"),
+            );
+            return Ok(res);
+        }
+    };
+
+    let outcome = process_mail(payload, ctx).await?;
+    let response = outcome_response(req, &outcome)?;
+    if let Some(guard) = guard
+        && response.status() != StatusCode::INTERNAL_SERVER_ERROR
+    {
+        guard.complete(outcome);
+    }
+    Ok(response)
+}
+
+/// Runs an email request through the send pipeline
+///
+/// Applies the subject policy and MIME limits. Requests with attachments to
+/// convert are then queued for the conversion worker (see [`process_conversion`]);
+/// the others go on through the preheader, dark mode, link validation,
+/// duplicate guard and blackhole routing, then are sent or queued.
+///
+/// # Arguments
+/// * `payload` - The email request
+/// * `ctx` - Shared send state
+///
+/// # Returns
+/// The outcome of the request, or a JSON error/fail response
+pub async fn process_mail(mut payload: SendMailReq, ctx: &SendContext) -> Result<SendOutcome> {
+    // Tag the subject for the current environment
+    payload.mail.subject = apply_subject_policy(&payload.mail.subject, &ctx.subject_policy);
+
+    // Resolve the SMTP profile, or the relay given inline, before doing any work
+    let mailer = match &payload.smtp {
+        Some(smtp) => override_mailer(smtp, &ctx.privacy_config)?,
+        None => ctx.mailers.get(payload.profile.as_deref()).ok_or_else(|| {
+            json_fail(format!(
+                "Unknown SMTP profile {}",
+                payload.profile.as_deref().unwrap_or_default()
+            ))
+        })?,
+    };
+
+    // Queue rather than fail fast while the circuit of the profile is open
+    if ctx.circuit_breaker_config.queue
+        && payload.smtp.is_none()
+        && !payload.dry_run
+        && ctx.mailers.circuit_open(payload.profile.as_deref())
+    {
+        info!("SMTP circuit open, mail deferred to the queue");
+        payload.queue = true;
+    }
+
+    // Refuse senders that receivers enforcing DMARC would reject; an inline relay has no SPF domains
+    if ctx.alignment_config.enabled {
+        let spf_domains = match payload.smtp {
+            Some(_) => &[],
+            None => ctx.alignment_config.spf_domains(payload.profile.as_deref()),
+        };
+        check_alignment(
+            &payload.mail.from,
+            ctx.dkim.as_ref().map(|d| d.domain()),
+            spf_domains,
+        )?;
+    }
+
+    // Refuse recipients outside the allowed domains before doing any work
+    let blocked = blocked_recipients(&payload.mail.to, &ctx.recipient_policy);
+    if !blocked.is_empty() {
+        warn!("Recipient domain not allowed for {}", blocked.join(", "));
+        return Err(json_fail(format!(
+            "Recipient domain not allowed for {}",
+            describe_recipients(&blocked, &ctx.response_policy)
+        )));
+    }
+
+    // Refuse header injection before doing any work
+    check_headers(&payload.mail.headers)?;
+
+    // Refuse callbacks that could not be signed or would reach an internal host
+    if let Some(url) = &payload.callback_url {
+        ctx.webhooks.check_url(url).await.map_err(json_fail)?;
+    }
+
+    // A delivery time in the future schedules the email through the queue
+    let now = unix_now();
+    let send_at = payload
+        .send_at
+        .as_deref()
+        .map(parse_send_at)
+        .transpose()?
+        .filter(|t| *t > now);
+
+    // Decode email body parts based on encoding type
+    let mail_body = decode_body(&payload.mail)?;
+
+    // Reject pathological MIME structures before doing any further work
+    check_mime_limits(&mail_body, &ctx.mime_limits)?;
+
+    // Converters are external commands: the conversion worker runs them, off the request
+    check_converters(&mail_body, &ctx.convert_config)?;
+    if needs_conversion(&mail_body) && !payload.dry_run && !ctx.sandbox_config.enabled {
+        return queue_conversion(&payload, send_at, now, ctx).await;
+    }
+
+    finish_mail(payload, &mailer, mail_body, send_at, now, None, ctx).await
+}
+
+/// Queues a request whose attachments must be converted before it is built
+///
+/// The conversion worker later converts the attachments and builds the
+/// message, which keeps the queue identifier returned here.
+///
+/// # Returns
+/// The queued outcome, or a JSON fail response for an inline relay, which cannot be queued
+async fn queue_conversion(
+    payload: &SendMailReq,
+    send_at: Option<u64>,
+    now: u64,
+    ctx: &SendContext,
+) -> Result<SendOutcome> {
+    if payload.smtp.is_some() {
+        return Err(json_fail(
+            "Attachment conversion cannot be combined with an SMTP override",
+        ));
+    }
+    let request = serde_json::to_string(payload).map_err(json_error)?;
+    let queue = ctx.queue.clone();
+    let recipients = payload.mail.to.clone();
+    let profile = payload.profile.clone();
+    let tenant = payload.tenant.clone();
+    let callback_url = payload.callback_url.clone();
+    let fault = payload.fault.clone();
+    let id = web::block(move || {
+        let options = QueueOptions {
+            profile: profile.as_deref(),
+            send_at,
+            tenant: tenant.as_deref(),
+            callback_url: callback_url.as_deref(),
+            fault: fault.as_ref(),
+        };
+        queue.enqueue_conversion(&recipients, &request, &options, now)
+    })
+    .await
+    .map_err(json_error)?
+    .map_err(json_error)?;
+
+    let message = format!(
+        "Mail queued for {}, attachments are converted before delivery",
+        describe_recipients(&payload.mail.to, &ctx.response_policy)
+    );
+    info!("{} with id {}", message, id);
+    Ok(SendOutcome::Queued(QueuedRes {
+        status: Status::Ok,
+        message,
+        id,
+    }))
+}
+
+/// Converts the attachments of a request queued by [`process_mail`], then builds and queues it
+///
+/// The request was checked when it was accepted; the message takes the place
+/// of the converting request in the queue, under the same identifier.
+///
+/// # Arguments
+/// * `conversion` - The converting request
+/// * `ctx` - Shared send state
+/// * `permits` - Permits limiting the converter commands running at once
+///
+/// # Returns
+/// The queued outcome, another outcome when nothing is left to deliver (e.g.
+/// duplicates skipped), or a JSON error/fail response
+pub async fn process_conversion(
+    conversion: &PendingConversion,
+    ctx: &SendContext,
+    permits: &Semaphore,
+) -> Result<SendOutcome> {
+    let mut payload: SendMailReq = serde_json::from_str(&conversion.payload)
+        .map_err(|e| json_fail(format!("Invalid converting request: {}", e)))?;
+    payload.tenant = conversion.tenant.clone();
+    payload.queue = true;
+    let mailer = ctx.mailers.get(payload.profile.as_deref()).ok_or_else(|| {
+        json_fail(format!(
+            "Unknown SMTP profile {}",
+            payload.profile.as_deref().unwrap_or_default()
+        ))
+    })?;
+    let now = unix_now();
+    let send_at = payload
+        .send_at
+        .as_deref()
+        .map(parse_send_at)
+        .transpose()?
+        .filter(|t| *t > now);
+
+    // Convert attachments to the requested formats, keeping originals on failure
+    let mut mail_body = decode_body(&payload.mail)?;
+    convert_attachments(&mut mail_body, &ctx.convert_config, permits).await;
+
+    finish_mail(
+        payload,
+        &mailer,
+        mail_body,
+        send_at,
+        now,
+        Some(&conversion.id),
+        ctx,
+    )
+    .await
+}
+
+/// Runs the decoded email through the rest of the send pipeline
+///
+/// Applies the preheader, dark mode, link validation, duplicate guard and
+/// blackhole routing, then builds, signs and sends or queues the message.
+///
+/// # Arguments
+/// * `payload` - The email request
+/// * `mailer` - Transport of the selected profile or inline relay
+/// * `mail_body` - The decoded body, attachments converted
+/// * `send_at` - Unix timestamp of a scheduled delivery
+/// * `now` - Current Unix timestamp in seconds
+/// * `conversion` - Queue identifier of the converting request the message replaces, if any
+/// * `ctx` - Shared send state
+///
+/// # Returns
+/// The outcome of the request, or a JSON error/fail response
+async fn finish_mail(
+    payload: SendMailReq,
+    mailer: &Mailer,
+    mut mail_body: MailBody,
+    send_at: Option<u64>,
+    now: u64,
+    conversion: Option<&str>,
+    ctx: &SendContext,
+) -> Result<SendOutcome> {
+    // Add the hidden preview text to HTML bodies
+    if let Some(preheader) = &payload.mail.preheader {
+        match &mail_body.html {
+            Some(html) => mail_body.html = Some(inject_preheader(html, preheader)),
+            None => debug!("Preheader ignored for non-HTML body"),
         }
+    }
 
-        mailer_builder.build()
+    // Keep the designed colors in dark mode clients
+    if ctx.dark_mode_config.enabled
+        && let Some(html) = &mail_body.html
+    {
+        mail_body.html = Some(inject_dark_mode(html, &ctx.dark_mode_config));
+    }
+
+    debug!("{:?} {:?}", mail_body.plain, mail_body.html);
+
+    // Validate links in HTML bodies before anything is sent
+    if let Some(html) = &mail_body.html {
+        validate_links(html, &ctx.link_check_config, &ctx.fetch_policy).await?;
+    }
+
+    // Detect recipients that already received this exact content; hashing the files is skipped when off
+    let fingerprint = match ctx.duplicate_guard.config.mode {
+        DuplicateGuardMode::Off => String::new(),
+        _ => mail_body.fingerprint_source(),
+    };
+    // Recipients are claimed until sent, so an identical request meanwhile is a duplicate too
+    let mut recipients = payload.mail.to.clone();
+    let (duplicates, claim) = match ctx.duplicate_guard.config.mode {
+        DuplicateGuardMode::Off => (Vec::new(), None),
+        _ => {
+            let (duplicates, claim) =
+                ctx.duplicate_guard
+                    .claim(&recipients, &payload.mail.subject, &fingerprint);
+            (duplicates, Some(claim))
+        }
     };
+    if !duplicates.is_empty() {
+        warn!("Duplicate mail detected for {}", duplicates.join(", "));
+        if ctx.duplicate_guard.config.mode == DuplicateGuardMode::Skip {
+            recipients.retain(|r| !duplicates.contains(r));
+            if recipients.is_empty() {
+                let x = RustMailRes {
+                    status: Status::Ok,
+                    message: format!(
+                        "Duplicate mail skipped for {}",
+                        describe_recipients(&duplicates, &ctx.response_policy)
+                    ),
+                    request_id: current_request_id(),
+                };
+                return Ok(SendOutcome::Done(x));
+            }
+        }
+    }
+
+    // Monitoring recipients exercise the pipeline without delivering real mail
+    let (recipients, blackholed) = partition_blackhole(recipients, &ctx.blackhole_config);
+    let deliver = !recipients.is_empty();
+    let mut email = build_message(
+        &payload.mail,
+        &mail_body,
+        if deliver { &recipients } else { &blackholed },
+        &ctx.privacy_config,
+        &ctx.content_detection_config,
+    )?;
+
+    // Date scheduled emails with their delivery time
+    if let Some(send_at) = send_at {
+        email
+            .headers_mut()
+            .set(Date::from(UNIX_EPOCH + Duration::from_secs(send_at)));
+    }
+
+    // Sign the complete message so receivers can verify it whichever relay carries it
+    if let Some(dkim) = &ctx.dkim {
+        dkim.sign(&mut email);
+    }
+
+    let discarded = (!blackholed.is_empty()).then(|| {
+        format!(
+            "Mail discarded for {} (blackhole)",
+            describe_recipients(&blackholed, &ctx.response_policy)
+        )
+    });
+
+    // Dry runs stop once the message is built: nothing is sent, queued or recorded
+    if payload.dry_run || ctx.sandbox_config.enabled {
+        let size = email.formatted().len();
+        let to: Vec<String> = email
+            .envelope()
+            .to()
+            .iter()
+            .map(|a| a.to_string())
+            .collect();
+        let message = format!(
+            "Dry run: mail for {} built ({} bytes), not sent",
+            describe_recipients(&to, &ctx.response_policy),
+            size
+        );
+        info!("{}", message);
+
+        let x = DryRunRes {
+            status: Status::Ok,
+            message,
+            size,
+            recipients: (ctx.response_policy.recipients == RecipientDisplay::List).then_some(to),
+        };
+        return Ok(SendOutcome::DryRun(x));
+    }
+
+    // Hand the message to the persistent queue for background delivery
+    if (payload.queue || send_at.is_some()) && deliver {
+        let queue = ctx.queue.clone();
+        let envelope = email.envelope().clone();
+        let formatted = email.formatted();
+        let profile = payload.profile.clone();
+        let tenant = payload.tenant.clone();
+        let callback_url = payload.callback_url.clone();
+        let fault = payload.fault.clone();
+        let conversion = conversion.map(str::to_owned);
+        let id = web::block(move || match conversion {
+            Some(id) => queue
+                .complete_conversion(&id, &envelope, &formatted, send_at, now)
+                .map(|_| id),
+            None => {
+                let options = QueueOptions {
+                    profile: profile.as_deref(),
+                    send_at,
+                    tenant: tenant.as_deref(),
+                    callback_url: callback_url.as_deref(),
+                    fault: fault.as_ref(),
+                };
+                queue.enqueue(&envelope, &formatted, &options, now)
+            }
+        })
+        .await
+        .map_err(json_error)?
+        .map_err(json_error)?;
+
+        if let Some(claim) = claim {
+            claim.complete(&recipients);
+        }
+
+        let mut messages = vec![match send_at {
+            Some(t) => format!(
+                "Mail scheduled for {} at {}",
+                describe_recipients(&recipients, &ctx.response_policy),
+                format_unix(t)
+            ),
+            None => format!(
+                "Mail queued for {}",
+                describe_recipients(&recipients, &ctx.response_policy)
+            ),
+        }];
+        messages.extend(discarded);
+        let message = messages.join("; ");
+        info!("{} with id {}", message, id);
 
-    // Send the email through SMTP
-    mailer.send(&email).map_err(json_error)?;
+        let x = QueuedRes {
+            status: Status::Ok,
+            message,
+            id,
+        };
+        return Ok(SendOutcome::Queued(x));
+    }
+
+    // Simulate the SMTP failures requested by an admin caller
+    inject_fault(&ctx.fault_config, payload.fault.as_ref())
+        .await
+        .map_err(json_error)?;
+
+    // Several recipients get one SMTP transaction each, so that a rejected
+    // recipient does not fail the others and each gets its own result
+    if deliver && recipients.len() > 1 {
+        return Ok(SendOutcome::Sent(
+            send_per_recipient(mailer, &email, &recipients, ctx, &payload, claim, discarded).await,
+        ));
+    }
+
+    let mut messages = Vec::new();
+    if deliver {
+        // Send the email through SMTP
+        let envelope = email.envelope().clone();
+        let started = Instant::now();
+        let sent = mailer.send(email).await;
+        ctx.metrics
+            .observe_smtp(&envelope, started.elapsed(), AttemptOutcome::of(&sent));
+        ctx.metrics.record_delivery(&envelope, sent.is_ok());
+        sent.map_err(json_error)?;
+        ctx.metrics.record_cost(
+            charged_tenant(&payload),
+            &envelope,
+            cost_rate(&payload, ctx),
+        );
+
+        if let Some(claim) = claim {
+            claim.complete(&recipients);
+        }
+        messages.push(format!(
+            "Mail sent to {}",
+            describe_recipients(&recipients, &ctx.response_policy)
+        ));
+    }
+    messages.extend(discarded);
 
-    let message = format!("Mail sent to {}", payload.mail.to.join(", "));
+    let message = messages.join("; ");
     info!("{}", message);
 
     let x = RustMailRes {
         status: Status::Ok,
         message,
+        request_id: current_request_id(),
     };
-    Ok(HttpResponse::Ok().json(x))
+    Ok(SendOutcome::Done(x))
+}
+
+/// Returns the tenant charged for an email request
+fn charged_tenant(payload: &SendMailReq) -> &str {
+    payload.tenant.as_deref().unwrap_or(DEFAULT_TENANT)
+}
+
+/// Returns the price of one recipient of an email request
+///
+/// Relays given inline are not priced: they are not one of the configured providers.
+fn cost_rate(payload: &SendMailReq, ctx: &SendContext) -> f64 {
+    match payload.smtp {
+        Some(_) => 0.0,
+        None => ctx.cost_config.rate(payload.profile.as_deref()),
+    }
 }
 
+/// Sends a message to each recipient in a separate SMTP transaction
+///
+/// The same signed bytes go to every recipient. At most `SMTP_MAX_CONNECTIONS`
+/// of the profile are sent at once, `BULK_CONCURRENCY` for API transports and
+/// one for an inline relay, so a long recipient list does not start a send per
+/// recipient. Accepted recipients are kept by the duplicate guard claim and
+/// charged to the tenant of the request.
+///
+/// # Arguments
+/// * `mailer` - SMTP transport of the selected profile
+/// * `email` - The built (and signed) message
+/// * `recipients` - Recipients the message is sent to
+/// * `ctx` - Shared send state
+/// * `payload` - The email request (tenant and profile for the costs)
+/// * `claim` - Recipients claimed with the duplicate guard, if enabled
+/// * `discarded` - Message about blackholed recipients, if any
+///
+/// # Returns
+/// The overall result (error only when every recipient failed) with one result per recipient
+async fn send_per_recipient(
+    mailer: &Mailer,
+    email: &Message,
+    recipients: &[String],
+    ctx: &SendContext,
+    payload: &SendMailReq,
+    claim: Option<DuplicateClaim<'_>>,
+    discarded: Option<String>,
+) -> SentRes {
+    let (tenant, rate) = (charged_tenant(payload), cost_rate(payload, ctx));
+    let concurrency = match payload.smtp {
+        Some(_) => 1,
+        None => ctx
+            .mailers
+            .max_connections(payload.profile.as_deref())
+            .unwrap_or(ctx.bulk_limits.concurrency),
+    };
+    let formatted = email.formatted();
+    let from = email.envelope().from().cloned();
+    let attempts = recipients.iter().enumerate().map(|(index, recipient)| {
+        let (formatted, from) = (&formatted, from.clone());
+        async move {
+            let sent = async {
+                let mailbox: Mailbox =
+                    recipient.parse().map_err(|e: AddressError| e.to_string())?;
+                let envelope =
+                    Envelope::new(from, vec![mailbox.email]).map_err(|e| e.to_string())?;
+                let started = Instant::now();
+                let sent = mailer.send_raw(&envelope, formatted).await;
+                ctx.metrics
+                    .observe_smtp(&envelope, started.elapsed(), AttemptOutcome::of(&sent));
+                ctx.metrics.record_delivery(&envelope, sent.is_ok());
+                if sent.is_ok() {
+                    ctx.metrics.record_cost(tenant, &envelope, rate);
+                }
+                sent.map_err(|e| e.to_string())
+            }
+            .await;
+            (index, recipient.clone(), sent)
+        }
+    });
+    let mut outcomes: Vec<_> = stream::iter(attempts)
+        .buffer_unordered(concurrency.max(1))
+        .collect()
+        .await;
+    // Report the recipients in the order of the request
+    outcomes.sort_by_key(|(index, _, _)| *index);
+    let outcomes: Vec<_> = outcomes
+        .into_iter()
+        .map(|(_, recipient, sent)| (recipient, sent))
+        .collect();
+
+    let (accepted, failed): (Vec<_>, Vec<_>) = outcomes.iter().partition(|(_, r)| r.is_ok());
+    let accepted: Vec<String> = accepted.into_iter().map(|(a, _)| a.clone()).collect();
+    let failed: Vec<String> = failed.into_iter().map(|(a, _)| a.clone()).collect();
+    if let Some(claim) = claim {
+        claim.complete(&accepted);
+    }
+
+    let mut messages = Vec::new();
+    if !accepted.is_empty() {
+        messages.push(format!(
+            "Mail sent to {}",
+            describe_recipients(&accepted, &ctx.response_policy)
+        ));
+    }
+    if !failed.is_empty() {
+        messages.push(format!(
+            "Mail failed for {}",
+            describe_recipients(&failed, &ctx.response_policy)
+        ));
+    }
+    messages.extend(discarded);
+    let message = messages.join("; ");
+    for (address, sent) in &outcomes {
+        if let Err(err) = sent {
+            warn!("Mail to {} failed: {}", address, err);
+        }
+    }
+    info!("{}", message);
+
+    let results = outcomes
+        .into_iter()
+        .map(|(address, sent)| match sent {
+            Ok(()) => RecipientRes {
+                address,
+                status: Status::Ok,
+                error: None,
+            },
+            Err(err) => RecipientRes {
+                address,
+                status: Status::Error,
+                error: Some(err),
+            },
+        })
+        .collect();
+
+    SentRes {
+        status: if accepted.is_empty() {
+            Status::Error
+        } else {
+            Status::Ok
+        },
+        message,
+        results: (ctx.response_policy.recipients == RecipientDisplay::List).then_some(results),
+        request_id: current_request_id(),
+    }
+}
+
+/// Validates the links of an HTML body according to the link check mode
+///
+/// # Returns
+/// * `Ok(())` - Links are valid, checking is disabled, or problems were only logged
+/// * `Err(actix_web::Error)` - JSON fail response listing the invalid links (block mode)
+async fn validate_links(
+    html: &str,
+    link_check_config: &LinkCheckConfig,
+    fetch_policy: &FetchPolicy,
+) -> Result<()> {
+    if link_check_config.mode == LinkCheckMode::Off {
+        return Ok(());
+    }
+
+    let issues = check_links(html, link_check_config, fetch_policy).await;
+    if issues.is_empty() {
+        return Ok(());
+    }
+
+    let details = issues
+        .iter()
+        .map(|i| format!("{} ({})", i.url, i.reason))
+        .collect::<Vec<_>>()
+        .join(", ");
+    if link_check_config.mode == LinkCheckMode::Block {
+        return Err(json_fail(format!("Invalid links: {}", details)));
+    }
+    warn!("Invalid links: {}", details);
+    Ok(())
+}
+
+/// GET endpoint returning the OpenAPI specification of the API
+///
+/// Served without authentication, like the Swagger UI page using it.
+#[get("openapi.json")]
+async fn openapi_json() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok().json(api_doc()))
+}
+
+/// GET endpoint serving the Swagger UI page for the OpenAPI specification
+#[get("docs")]
+async fn swagger_ui() -> Result<HttpResponse> {
+    Ok(HttpResponse::Ok()
+        .content_type(ContentType::html())
+        .body(SWAGGER_UI_HTML))
+}
+
+/// OpenAPI description of the endpoints of this module
+#[derive(OpenApi)]
+#[openapi(
+    paths(
+        health_check_get,
+        health_check_head,
+        send,
+        send_bulk,
+        list_scheduled,
+        cancel_scheduled,
+        send_status
+    ),
+    components(schemas(SentRes, DryRunRes, LimitExceededRes))
+)]
+pub struct ApiDoc;
+
 /// Configures the Actix-web service routes
 ///
 /// Registers all HTTP endpoints for this module with the application.
@@ -158,4 +1449,59 @@ pub fn config(cfg: &mut web::ServiceConfig) {
     cfg.service(health_check_get);
     cfg.service(health_check_head);
     cfg.service(send);
+    cfg.service(send_bulk);
+    cfg.service(list_scheduled);
+    cfg.service(cancel_scheduled);
+    cfg.service(send_status);
+    cfg.service(openapi_json);
+    cfg.service(swagger_ui);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::test::TestRequest;
+
+    use crate::auth::API_KEY_HEADER;
+
+    /// Parses a request carrying an `idempotency_key` field
+    fn payload(key: &str) -> SendMailReq {
+        serde_json::from_value(serde_json::json!({
+            "mail": {
+                "from": "sender@example.com",
+                "to": ["rcpt@example.com"],
+                "subject": "Hello",
+                "text": "Hello",
+                "encoding": "plain"
+            },
+            "idempotency_key": key
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn scopes_idempotency_keys_by_verified_key_only() {
+        let anonymous = TestRequest::default().to_http_request();
+        let claimed = TestRequest::default()
+            .insert_header((API_KEY_HEADER, "someone-else"))
+            .to_http_request();
+        let key = idempotency_key(&anonymous, &payload("order-1")).unwrap();
+        assert_eq!(key.as_deref(), Some("\u{0}order-1"));
+        // An unchecked key header must not select another caller's scope
+        assert_eq!(idempotency_key(&claimed, &payload("order-1")).unwrap(), key);
+    }
+
+    #[test]
+    fn prefers_the_idempotency_header() {
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, " order-2 "))
+            .to_http_request();
+        let key = idempotency_key(&req, &payload("order-1")).unwrap();
+        assert_eq!(key.as_deref(), Some("\u{0}order-2"));
+
+        let req = TestRequest::default()
+            .insert_header((IDEMPOTENCY_KEY_HEADER, " "))
+            .to_http_request();
+        assert!(idempotency_key(&req, &payload("order-1")).is_err());
+    }
 }
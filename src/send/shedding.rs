@@ -0,0 +1,138 @@
+//! Health-aware load shedding
+//!
+//! This module keeps transactional mail flowing while the service is under
+//! pressure. When the relay keeps deferring attempts, the circuit of the SMTP
+//! profile is open or the queue is deeper than configured, bulk and low
+//! priority mail is rejected with HTTP 503 or
+//! deferred to the queue, depending on the policy; high priority mail is never
+//! shed.
+
+use actix_web::error::InternalError;
+use actix_web::http::header::RETRY_AFTER;
+use actix_web::{HttpResponse, Result, web};
+use log::{info, warn};
+
+use crate::request_id::current_request_id;
+use crate::send::context::SendContext;
+use crate::send::dto::{Priority, SendMailReq};
+use crate::settings::{DEFAULT_SMTP_PROFILE, LoadSheddingConfig, RustMailRes, ShedAction, Status};
+
+/// Returns `true` when a request may be shed while the service is overloaded
+///
+/// Bulk mail is shed unless it has a high priority, other mail only when it
+/// has a low priority. Dry runs, which deliver nothing, and requests with an
+/// inline relay, which do not use the shared relay nor the queue, are never shed.
+///
+/// # Arguments
+/// * `payload` - The email request
+/// * `bulk` - Whether the email is part of a bulk request
+pub fn sheddable(payload: &SendMailReq, bulk: bool) -> bool {
+    if payload.dry_run || payload.smtp.is_some() {
+        return false;
+    }
+    match payload.mail.priority {
+        Some(Priority::High) => false,
+        Some(Priority::Low) => true,
+        Some(Priority::Normal) | None => bulk,
+    }
+}
+
+/// Checks the health signals of the load shedding policy
+///
+/// The queue depth is read from the queue database, the relay failures from
+/// the metrics.
+///
+/// # Arguments
+/// * `ctx` - Shared send state
+///
+/// # Returns
+/// The reason mail is shed, or `None` while the service is healthy
+pub async fn overload(ctx: &SendContext) -> Option<String> {
+    let config = &ctx.load_shedding_config;
+    if let Some(max) = config.max_relay_failures {
+        let failures = ctx.metrics.relay_failures();
+        if failures >= max {
+            return Some(format!("relay failed {} attempts in a row", failures));
+        }
+    }
+    if let Some(max) = config.max_queue_depth {
+        let queue = ctx.queue.clone();
+        match web::block(move || queue.pending_count()).await {
+            Ok(Ok(depth)) => {
+                ctx.metrics.set_queue_depth(depth);
+                if depth >= max {
+                    return Some(format!("queue holds {} messages", depth));
+                }
+            }
+            Ok(Err(err)) => warn!("Cannot read queue depth: {}", err),
+            Err(err) => warn!("Cannot read queue depth: {}", err),
+        }
+    }
+    None
+}
+
+/// Checks the circuit breaker of the profile an email is sent through
+///
+/// Only consulted when load shedding is enabled, like the other health signals.
+///
+/// # Arguments
+/// * `ctx` - Shared send state
+/// * `profile` - SMTP profile of the email, the default profile when `None`
+///
+/// # Returns
+/// The reason mail is shed, or `None` while the circuit is closed
+pub fn open_circuit(ctx: &SendContext, profile: Option<&str>) -> Option<String> {
+    ctx.mailers.circuit_open(profile).then(|| {
+        format!(
+            "SMTP circuit of profile {} is open",
+            profile.unwrap_or(DEFAULT_SMTP_PROFILE)
+        )
+    })
+}
+
+/// Sheds a request while the service is overloaded
+///
+/// # Arguments
+/// * `payload` - The email request, switched to the queue when deferred
+/// * `reason` - Reason returned by [`open_circuit`] or [`overload`]
+/// * `config` - Load shedding policy
+///
+/// # Returns
+/// * `Ok(())` - The request was deferred and goes on through the pipeline
+/// * `Err(actix_web::Error)` - JSON fail response with HTTP 503 and `Retry-After` when rejected
+pub fn shed(payload: &mut SendMailReq, reason: &str, config: &LoadSheddingConfig) -> Result<()> {
+    match config.action {
+        ShedAction::Defer => {
+            info!(
+                "Service overloaded ({}), mail deferred to the queue",
+                reason
+            );
+            payload.queue = true;
+            Ok(())
+        }
+        ShedAction::Reject => {
+            warn!("Service overloaded ({}), mail rejected", reason);
+            Err(service_unavailable(reason, config))
+        }
+    }
+}
+
+/// Builds the HTTP 503 fail response, telling the client when to retry
+fn service_unavailable(reason: &str, config: &LoadSheddingConfig) -> actix_web::Error {
+    let seconds = config.retry_after.as_secs();
+    let res = RustMailRes {
+        status: Status::Fail,
+        message: format!(
+            "Service overloaded ({}), only high priority mail is accepted, retry in {} s",
+            reason, seconds
+        ),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        res.message.clone(),
+        HttpResponse::ServiceUnavailable()
+            .insert_header((RETRY_AFTER, seconds.to_string()))
+            .json(res),
+    )
+    .into()
+}
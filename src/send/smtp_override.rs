@@ -0,0 +1,95 @@
+//! Per-request SMTP relay override
+//!
+//! Admin callers may name the relay of a `/send` request inline, with its
+//! credentials, to route an email ad hoc or to test a relay before configuring
+//! it as a profile. The override is off by default and never reaches the queue,
+//! so inline credentials are not persisted.
+
+use actix_web::{HttpRequest, Result};
+use log::info;
+
+use crate::auth::is_admin;
+use crate::send::dto::{SendMailReq, SmtpOverride};
+use crate::settings::{
+    PrivacyConfig, SmtpAuth, SmtpConfig, SmtpOverrideConfig, TlsMode, json_fail, json_forbidden,
+};
+use crate::transport::{Mailer, build_smtp_transport};
+
+/// Port used when the override gives none (submission)
+const DEFAULT_OVERRIDE_PORT: u16 = 587;
+
+/// Checks that the caller may use the SMTP override of the request
+///
+/// # Returns
+/// * `Ok(())` - The request has no override, or an allowed one
+/// * `Err(actix_web::Error)` - JSON fail response when the override is disabled or
+///   combined with a profile, queueing or scheduling; HTTP 403 for non-admin callers
+pub fn check_smtp_override(
+    req: &HttpRequest,
+    payload: &SendMailReq,
+    config: &SmtpOverrideConfig,
+) -> Result<()> {
+    if payload.smtp.is_none() {
+        return Ok(());
+    }
+    if !config.enabled {
+        return Err(json_fail(
+            "SMTP override is disabled (SMTP_OVERRIDE_ENABLED)",
+        ));
+    }
+    if !is_admin(req) {
+        return Err(json_forbidden("SMTP override requires an admin API key"));
+    }
+    if payload.profile.is_some() {
+        return Err(json_fail("smtp and profile cannot be combined"));
+    }
+    if payload.queue || payload.send_at.is_some() {
+        return Err(json_fail("SMTP override cannot be queued or scheduled"));
+    }
+    Ok(())
+}
+
+/// Builds a one-off transport for the relay of an override
+///
+/// # Arguments
+/// * `smtp` - Relay given in the request
+/// * `privacy_config` - Privacy mode configuration (EHLO name)
+///
+/// # Returns
+/// * `Ok(Mailer)` - Transport dropped with the request
+/// * `Err(actix_web::Error)` - JSON fail response for an unknown TLS mode or
+///   invalid TLS parameters
+pub fn override_mailer(smtp: &SmtpOverride, privacy_config: &PrivacyConfig) -> Result<Mailer> {
+    let port = smtp.port.unwrap_or(DEFAULT_OVERRIDE_PORT);
+    let tls_mode = match smtp.tls_mode.as_deref() {
+        None => TlsMode::default_for_port(port),
+        Some(mode) => TlsMode::parse(mode)
+            .ok_or_else(|| json_fail(format!("Unsupported SMTP TLS mode {}", mode)))?,
+    };
+    let auth = match (&smtp.username, &smtp.password) {
+        (Some(username), Some(password)) => SmtpAuth::Password {
+            username: username.clone(),
+            password: password.clone(),
+        },
+        _ => SmtpAuth::None,
+    };
+
+    let config = SmtpConfig {
+        host: smtp.host.clone(),
+        port,
+        auth,
+        tls_mode,
+        tls_ca_file: None,
+        tls_accept_invalid_certs: false,
+        max_connections: 1,
+        auth_mechanisms: Vec::new(),
+    };
+    info!(
+        "SMTP override: host {} port {} tls_mode {:?} auth {}",
+        config.host,
+        config.port,
+        config.tls_mode,
+        config.auth.name()
+    );
+    build_smtp_transport(&config, privacy_config).map_err(json_fail)
+}
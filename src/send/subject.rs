@@ -0,0 +1,33 @@
+//! Subject policy application
+//!
+//! This module tags email subjects with the configured prefix and suffix.
+
+use crate::settings::SubjectPolicy;
+
+/// Applies the subject policy to a subject
+///
+/// Tags already present are not added again, so applying the policy to a
+/// retried or forwarded subject never stacks prefixes or suffixes.
+///
+/// # Arguments
+/// * `subject` - The subject as requested by the caller
+/// * `policy` - Configured prefix and suffix
+///
+/// # Returns
+/// The subject with prefix and suffix, separated by a space
+pub fn apply_subject_policy(subject: &str, policy: &SubjectPolicy) -> String {
+    let mut tagged = subject.trim().to_owned();
+
+    if let Some(prefix) = &policy.prefix
+        && !tagged.starts_with(prefix.as_str())
+    {
+        tagged = format!("{} {}", prefix, tagged);
+    }
+    if let Some(suffix) = &policy.suffix
+        && !tagged.ends_with(suffix.as_str())
+    {
+        tagged = format!("{} {}", tagged, suffix);
+    }
+
+    tagged.trim().to_owned()
+}
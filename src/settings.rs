@@ -1,19 +1,77 @@
 //! Application settings and configuration module
 //!
 //! This module handles all configuration loading from environment variables,
-//! logging initialization, and provides common response structures.
+//! optionally merged over a TOML or YAML config file, logging initialization,
+//! and provides common response structures.
 
+use std::collections::{BTreeMap, HashMap};
 use std::env;
+use std::fmt;
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::OnceLock;
+use std::time::Duration;
 
 use actix_web::{HttpResponse, error::InternalError};
+use lettre::transport::smtp::authentication::Mechanism;
+use log::warn;
 
-use serde::Serialize;
+use serde::de::{self, Deserializer, MapAccess, SeqAccess, Visitor};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+use crate::request_id::current_request_id;
 
 // Default configuration constants
 const DEFAULT_PORT: u16 = 3333;
 const DEFAULT_ADDRESS: &str = "0.0.0.0";
+const DEFAULT_SHUTDOWN_TIMEOUT_SECS: u64 = 30;
 const DEFAULT_SMTP_HOST: &str = "localhost";
 const DEFAULT_SMTP_PORT: u16 = 25;
+const DEFAULT_SMTP_MAX_CONNECTIONS: u32 = 10;
+/// Name selecting the default SMTP profile
+pub const DEFAULT_SMTP_PROFILE: &str = "default";
+const DEFAULT_SENDMAIL_COMMAND: &str = "sendmail";
+const DEFAULT_CANARY_FROM: &str = "rustmail-canary@localhost";
+const DEFAULT_CANARY_INTERVAL_SECS: u64 = 900;
+const DEFAULT_CANARY_TIMEOUT_SECS: u64 = 300;
+const DEFAULT_CANARY_POLL_SECS: u64 = 15;
+const DEFAULT_CANARY_IMAP_PORT: u16 = 993;
+const DEFAULT_CANARY_SPAM_FOLDER: &str = "Junk";
+const DEFAULT_CONVERT_TIMEOUT_SECS: u64 = 60;
+const DEFAULT_CONVERT_CONCURRENCY: usize = 2;
+const DEFAULT_MAX_ATTACHMENTS: usize = 20;
+const DEFAULT_MAX_MIME_PARTS: usize = 50;
+const DEFAULT_MAX_MIME_DEPTH: usize = 5;
+const DEFAULT_BULK_MAX_MESSAGES: usize = 100;
+const DEFAULT_BULK_CONCURRENCY: usize = 4;
+const DEFAULT_STATUS_MAX_IDS: usize = 500;
+const DEFAULT_QUEUE_DB_PATH: &str = "rustmail.db";
+const DEFAULT_QUEUE_POLL_SECS: u64 = 5;
+const DEFAULT_QUEUE_BATCH_SIZE: usize = 100;
+const DEFAULT_QUEUE_CONCURRENCY: usize = 4;
+const DEFAULT_QUEUE_MAX_ATTEMPTS: u32 = 8;
+const DEFAULT_QUEUE_RETRY_BASE_SECS: u64 = 30;
+const DEFAULT_QUEUE_RETRY_MAX_SECS: u64 = 3600;
+const DEFAULT_QUEUE_LEASE_SECS: u64 = 300;
+const DEFAULT_OUTBOX_POLL_SECS: u64 = 5;
+const DEFAULT_OUTBOX_BATCH_SIZE: usize = 100;
+const DEFAULT_BLACKHOLE_DOMAIN: &str = "blackhole.invalid";
+const DEFAULT_DUPLICATE_WINDOW_SECS: u64 = 300;
+
+/// Default time completed sends are remembered by idempotency key (24 hours)
+const DEFAULT_IDEMPOTENCY_WINDOW_SECS: u64 = 86_400;
+/// Tenant of the sends made without a key mapped in `API_KEY_TENANTS`
+pub const DEFAULT_TENANT: &str = "default";
+const DEFAULT_COST_CURRENCY: &str = "USD";
+const DEFAULT_SHED_RETRY_AFTER_SECS: u64 = 60;
+const DEFAULT_CIRCUIT_COOLDOWN_SECS: u64 = 30;
+const DEFAULT_WEBHOOK_TIMEOUT_SECS: u64 = 10;
+const DEFAULT_WEBHOOK_MAX_ATTEMPTS: u32 = 5;
+const DEFAULT_WEBHOOK_RETRY_BASE_SECS: u64 = 10;
+const DEFAULT_LINK_CHECK_TIMEOUT_MS: u64 = 5000;
+const DEFAULT_LINK_CHECK_CONCURRENCY: usize = 8;
 
 /// Server binding configuration
 ///
@@ -23,154 +81,2758 @@ pub struct ServerBind {
     /// Server bind address (e.g., "0.0.0.0")
     pub addr: String,
 
-    /// Server listening port
-    pub port: u16,
+    /// Server listening port
+    pub port: u16,
+
+    /// Number of worker threads
+    pub workers: usize,
+
+    /// Bind with `SO_REUSEPORT` so a new process can listen on the same port
+    pub reuse_port: bool,
+
+    /// Time given to in-flight requests and deliveries to finish on shutdown
+    pub shutdown_timeout: Duration,
+}
+
+/// Backend delivering the messages of a profile
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TransportKind {
+    /// Connect to an SMTP relay
+    Smtp,
+
+    /// Pipe messages to the local sendmail command
+    Sendmail,
+
+    /// SendGrid v3 Mail Send API
+    SendGrid,
+
+    /// Mailgun messages API
+    Mailgun,
+
+    /// Amazon SES v2 API
+    Ses,
+}
+
+impl FromStr for TransportKind {
+    type Err = String;
+
+    /// Parses a backend name (`smtp`, `sendmail`, `sendgrid`, `mailgun` or `ses`, case-insensitive)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "smtp" => Ok(TransportKind::Smtp),
+            "sendmail" => Ok(TransportKind::Sendmail),
+            "sendgrid" => Ok(TransportKind::SendGrid),
+            "mailgun" => Ok(TransportKind::Mailgun),
+            "ses" => Ok(TransportKind::Ses),
+            _ => Err("expected smtp, sendmail, sendgrid, mailgun or ses".to_owned()),
+        }
+    }
+}
+
+/// Mail transport configuration of a profile
+#[derive(Clone)]
+pub struct TransportConfig {
+    /// Backend of the profile
+    pub kind: TransportKind,
+
+    /// Sendmail command, looked up in `PATH` unless absolute
+    pub sendmail_command: String,
+
+    /// Credentials and endpoint of the HTTP API backends
+    pub api: ApiTransportConfig,
+}
+
+/// Settings of an HTTP API backend (SendGrid, Mailgun or SES)
+///
+/// Only the variables of the selected backend are read.
+#[derive(Clone, Default)]
+pub struct ApiTransportConfig {
+    /// API key (SendGrid, Mailgun) or access key ID (SES)
+    pub key: Option<String>,
+
+    /// Secret access key (SES)
+    pub secret: Option<String>,
+
+    /// Session token of temporary credentials (SES, optional)
+    pub session_token: Option<String>,
+
+    /// Sending domain (Mailgun)
+    pub domain: Option<String>,
+
+    /// AWS region (SES)
+    pub region: Option<String>,
+
+    /// Base URL of the API, e.g. a regional endpoint (optional)
+    pub url: Option<String>,
+}
+
+/// SMTP server configuration
+///
+/// Contains all settings required to connect and authenticate
+/// with an SMTP server for sending emails.
+#[derive(Clone)]
+pub struct SmtpConfig {
+    /// SMTP server hostname or IP address
+    pub host: String,
+
+    /// SMTP server port (typically 25, 587, or 465)
+    pub port: u16,
+
+    /// Authentication method
+    pub auth: SmtpAuth,
+
+    /// How the connection to the SMTP server is encrypted
+    pub tls_mode: TlsMode,
+
+    /// PEM file of an additional CA trusted for the server certificate (e.g. a private CA)
+    pub tls_ca_file: Option<String>,
+
+    /// Accept invalid server certificates (expired, self-signed, wrong host); unsafe
+    pub tls_accept_invalid_certs: bool,
+
+    /// Maximum number of simultaneous connections opened to the SMTP server;
+    /// further sends wait for one to be free
+    pub max_connections: u32,
+
+    /// Allowed authentication mechanisms in order of preference;
+    /// empty lets the transport negotiate among its defaults
+    pub auth_mechanisms: Vec<Mechanism>,
+}
+
+/// SMTP connection encryption
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum TlsMode {
+    /// Plain SMTP, without encryption
+    None,
+
+    /// Plain connection upgraded with STARTTLS (typically port 587)
+    StartTls,
+
+    /// Implicit TLS from the first byte, a.k.a. SMTPS (typically port 465)
+    Tls,
+}
+
+impl TlsMode {
+    /// Returns the usual mode of a port: `none` on 25, `tls` on 465, `starttls` otherwise
+    pub fn default_for_port(port: u16) -> Self {
+        match port {
+            25 => TlsMode::None,
+            465 => TlsMode::Tls,
+            _ => TlsMode::StartTls,
+        }
+    }
+
+    /// Parses a mode name (`none`, `starttls` or `tls`, case-insensitive)
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.to_lowercase().as_str() {
+            "none" => Some(TlsMode::None),
+            "starttls" => Some(TlsMode::StartTls),
+            "tls" => Some(TlsMode::Tls),
+            _ => None,
+        }
+    }
+}
+
+impl FromStr for TlsMode {
+    type Err = String;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        TlsMode::parse(value).ok_or_else(|| "expected none, starttls or tls".to_owned())
+    }
+}
+
+/// OAuth2 client used to obtain XOAUTH2 access tokens
+///
+/// Access tokens are short-lived: they are requested from the token endpoint
+/// with the refresh token, at startup and again before they expire.
+#[derive(Clone)]
+pub struct OAuth2Config {
+    /// Token endpoint of the provider (e.g. "https://oauth2.googleapis.com/token")
+    pub token_url: String,
+
+    /// OAuth2 client ID
+    pub client_id: String,
+
+    /// OAuth2 client secret, absent for public clients
+    pub client_secret: Option<String>,
+
+    /// Long-lived refresh token of the mailbox account
+    pub refresh_token: String,
+
+    /// Scope requested with the access token, when the provider needs one
+    pub scope: Option<String>,
+}
+
+/// SMTP authentication method
+#[derive(Clone)]
+pub enum SmtpAuth {
+    /// No authentication
+    None,
+
+    /// Static credentials; with the `XOAUTH2` mechanism the password is an access token
+    Password {
+        /// Login username
+        username: String,
+
+        /// Login password
+        password: String,
+    },
+
+    /// XOAUTH2 with access tokens refreshed automatically
+    OAuth2 {
+        /// Mailbox address the tokens were issued for
+        username: String,
+
+        /// OAuth2 client obtaining the access tokens
+        client: OAuth2Config,
+    },
+}
+
+impl SmtpAuth {
+    /// Returns the name of the method, for logging
+    pub fn name(&self) -> &'static str {
+        match self {
+            SmtpAuth::None => "none",
+            SmtpAuth::Password { .. } => "password",
+            SmtpAuth::OAuth2 { .. } => "oauth2",
+        }
+    }
+}
+
+/// Pre-send link validation mode
+///
+/// Controls what happens when the HTML body contains broken or unsafe links.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum LinkCheckMode {
+    /// Links are not checked
+    Off,
+
+    /// Problems are logged and the email is sent anyway
+    Warn,
+
+    /// Problems reject the request before the email is sent
+    Block,
+}
+
+impl FromStr for LinkCheckMode {
+    type Err = String;
+
+    /// Parses a mode name (`off`, `warn` or `block`, case-insensitive)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "off" => Ok(LinkCheckMode::Off),
+            "warn" => Ok(LinkCheckMode::Warn),
+            "block" => Ok(LinkCheckMode::Block),
+            _ => Err("expected off, warn or block".to_owned()),
+        }
+    }
+}
+
+/// Pre-send link validation configuration
+///
+/// Contains the settings used to verify links found in HTML bodies.
+#[derive(Clone)]
+pub struct LinkCheckConfig {
+    /// What to do when a link is broken or unsafe
+    pub mode: LinkCheckMode,
+
+    /// Timeout applied to each HEAD request
+    pub timeout: Duration,
+
+    /// Maximum number of HEAD requests in flight at once
+    pub concurrency: usize,
+}
+
+/// Outbound fetch policy
+///
+/// Restricts which remote hosts the service may contact on behalf of a caller
+/// (e.g. when validating links), preventing server-side request forgery.
+#[derive(Clone)]
+pub struct FetchPolicy {
+    /// Hosts that may be fetched; empty means any host not denied
+    pub allow_hosts: Vec<String>,
+
+    /// Hosts that may never be fetched, checked before the allowlist
+    pub deny_hosts: Vec<String>,
+
+    /// Whether hosts resolving to private, loopback or link-local addresses are allowed
+    pub allow_private_ips: bool,
+}
+
+/// Fault injection configuration
+///
+/// Simulated SMTP failures used to validate resilience in staging, applied
+/// to the requests of admin keys that ask for them. Never enable it in production.
+#[derive(Clone)]
+pub struct FaultConfig {
+    /// Whether admin keys may request faults
+    pub enabled: bool,
+
+    /// Default percentage (0-100) of SMTP sends that are dropped
+    pub drop_percent: u32,
+
+    /// Default percentage (0-100) of SMTP sends rejected with a transient 4xx error
+    pub transient_percent: u32,
+
+    /// Default latency added to each SMTP send in milliseconds
+    pub latency_ms: u64,
+}
+
+/// Duplicate-content send guard mode
+///
+/// Controls what happens when the same content is sent again to a recipient.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum DuplicateGuardMode {
+    /// Duplicates are not detected
+    Off,
+
+    /// Recipients that already received the content are skipped
+    Skip,
+
+    /// Duplicates are logged and sent anyway
+    Flag,
+}
+
+impl FromStr for DuplicateGuardMode {
+    type Err = String;
+
+    /// Parses a mode name (`off`, `skip` or `flag`, case-insensitive)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "off" => Ok(DuplicateGuardMode::Off),
+            "skip" => Ok(DuplicateGuardMode::Skip),
+            "flag" => Ok(DuplicateGuardMode::Flag),
+            _ => Err("expected off, skip or flag".to_owned()),
+        }
+    }
+}
+
+/// Duplicate-content send guard configuration
+#[derive(Clone)]
+pub struct DuplicateGuardConfig {
+    /// What to do with duplicate messages
+    pub mode: DuplicateGuardMode,
+
+    /// Time window within which identical messages are considered duplicates
+    pub window: Duration,
+}
+
+/// Idempotency key registry configuration
+#[derive(Clone)]
+pub struct IdempotencyConfig {
+    /// Time completed sends are remembered; idempotency keys are ignored when zero
+    pub window: Duration,
+}
+
+/// Blackhole transport configuration
+///
+/// Recipients in these domains are reserved for monitoring sends: the message
+/// is fully built but never delivered.
+#[derive(Clone)]
+pub struct BlackholeConfig {
+    /// Recipient domains routed to the blackhole (lowercase)
+    pub domains: Vec<String>,
+}
+
+/// Seed mailbox probed by the deliverability canary
+#[derive(Clone)]
+pub struct CanarySeed {
+    /// Seed name, used as environment variable prefix and in results
+    pub name: String,
+
+    /// Mailbox address the probes are sent to
+    pub address: String,
+
+    /// IMAP server hostname (implicit TLS)
+    pub imap_host: String,
+
+    /// IMAP server port
+    pub imap_port: u16,
+
+    /// IMAP login username
+    pub imap_username: String,
+
+    /// IMAP login password
+    pub imap_password: String,
+
+    /// Folder where the provider files spam
+    pub spam_folder: String,
+}
+
+/// Deliverability canary configuration
+#[derive(Clone)]
+pub struct CanaryConfig {
+    /// Seed mailboxes; the canary is disabled when empty
+    pub seeds: Vec<CanarySeed>,
+
+    /// Sender address of the probes
+    pub from: String,
+
+    /// Pause between two probes to the same seed
+    pub interval: Duration,
+
+    /// Time after which a probe not found is reported missing
+    pub timeout: Duration,
+
+    /// Pause between two IMAP checks while waiting for a probe
+    pub poll_interval: Duration,
+}
+
+/// External command converting attachments to another format
+#[derive(Clone)]
+pub struct AttachmentConverter {
+    /// Converter name, selected by the `convert` field of an attachment
+    pub name: String,
+
+    /// Program and arguments; the input and output file paths are appended
+    pub command: Vec<String>,
+
+    /// MIME type of the converted file (e.g., "application/pdf")
+    pub content_type: String,
+
+    /// File extension given to the converted file (e.g., "pdf")
+    pub extension: String,
+}
+
+/// Attachment conversion configuration
+#[derive(Clone)]
+pub struct ConvertConfig {
+    /// Available converters; conversion is disabled when empty
+    pub converters: Vec<AttachmentConverter>,
+
+    /// Maximum run time of one conversion
+    pub timeout: Duration,
+
+    /// Maximum number of converter commands running at once
+    pub concurrency: usize,
+}
+
+impl ConvertConfig {
+    /// Returns the converter with the given name (case insensitive)
+    pub fn get(&self, name: &str) -> Option<&AttachmentConverter> {
+        self.converters
+            .iter()
+            .find(|c| c.name.eq_ignore_ascii_case(name))
+    }
+}
+
+/// Limits on the MIME structure of outgoing messages
+///
+/// Protects the service against pathological payloads.
+#[derive(Clone)]
+pub struct MimeLimits {
+    /// Maximum number of attachments
+    pub max_attachments: usize,
+
+    /// Maximum number of leaf parts (body parts and attachments)
+    pub max_parts: usize,
+
+    /// Maximum nesting depth of multipart containers
+    pub max_depth: usize,
+}
+
+/// DKIM signing configuration
+pub struct DkimConfig {
+    /// Signing domain (`d=` tag); signing is disabled when unset
+    pub domain: Option<String>,
+
+    /// Selector of the public key record in DNS (`s=` tag)
+    pub selector: Option<String>,
+
+    /// Inline private key
+    pub private_key: Option<String>,
+
+    /// File holding the private key, used when no inline key is set
+    pub private_key_file: Option<String>,
+
+    /// Signing algorithm: `rsa` or `ed25519`
+    pub algorithm: String,
+}
+
+/// Bulk send and status query limits
+#[derive(Clone)]
+pub struct BulkLimits {
+    /// Maximum number of messages per bulk request
+    pub max_messages: usize,
+
+    /// Maximum number of messages of one request processed at once
+    pub concurrency: usize,
+
+    /// Maximum number of message ids per status query
+    pub max_status_ids: usize,
+}
+
+/// Privacy mode configuration
+///
+/// Removes internal hostnames from generated mail so that outgoing messages
+/// cannot be fingerprinted back to the sending infrastructure.
+#[derive(Clone)]
+pub struct PrivacyConfig {
+    /// Whether privacy mode is active
+    pub enabled: bool,
+
+    /// Public domain used in Message-ID headers and as SMTP EHLO name;
+    /// when unset the sender's domain is used for Message-ID
+    pub domain: Option<String>,
+}
+
+/// Sandbox mode configuration
+///
+/// In sandbox mode every request is a dry run: messages are validated and
+/// built but never sent or queued.
+#[derive(Clone)]
+pub struct SandboxConfig {
+    /// Whether sandbox mode is active
+    pub enabled: bool,
+}
+
+/// XML compatibility endpoint configuration
+///
+/// The `/send/xml` endpoint serves clients of legacy gateways that only emit XML.
+#[derive(Clone)]
+pub struct XmlEndpointConfig {
+    /// Whether the `/send/xml` endpoint is registered
+    pub enabled: bool,
+}
+
+/// Subject prefix and suffix policy
+///
+/// Tags every outgoing subject, e.g. with `[STAGING]` on non-production environments.
+#[derive(Clone)]
+pub struct SubjectPolicy {
+    /// Text placed before the subject
+    pub prefix: Option<String>,
+
+    /// Text placed after the subject
+    pub suffix: Option<String>,
+}
+
+/// Recipient domain policy
+///
+/// Restricts the domains email may be sent to, e.g. to internal mailboxes on a
+/// staging deployment.
+#[derive(Clone)]
+pub struct RecipientPolicy {
+    /// Only these domains and their subdomains may receive email (lowercase); any when empty
+    pub allow_domains: Vec<String>,
+
+    /// These domains and their subdomains never receive email (lowercase)
+    pub deny_domains: Vec<String>,
+}
+
+/// How recipients appear in API responses
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum RecipientDisplay {
+    /// Recipient addresses are listed
+    List,
+
+    /// Only the number of recipients is given
+    Count,
+}
+
+impl FromStr for RecipientDisplay {
+    type Err = String;
+
+    /// Parses a display name (`list` or `count`, case-insensitive)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "list" => Ok(RecipientDisplay::List),
+            "count" => Ok(RecipientDisplay::Count),
+            _ => Err("expected list or count".to_owned()),
+        }
+    }
+}
+
+/// Redaction applied to API responses
+///
+/// Lets operators minimize the personal data returned to API consumers.
+#[derive(Clone)]
+pub struct ResponsePolicy {
+    /// How recipients appear in response messages and listings
+    pub recipients: RecipientDisplay,
+
+    /// Fields removed from response bodies, at any depth (`status` is always kept)
+    pub omit_fields: Vec<String>,
+}
+
+/// API key authentication configuration
+pub struct AuthConfig {
+    /// Keys accepted in the `X-Api-Key` header; authentication is disabled when
+    /// there are neither API keys nor admin keys
+    pub api_keys: Vec<String>,
+
+    /// Keys accepted like API keys that also grant admin-only features
+    pub admin_keys: Vec<String>,
+
+    /// Tenant charged for the sends of each key, by key; other keys belong to
+    /// the default tenant
+    pub tenants: HashMap<String, String>,
+}
+
+/// Per-request SMTP override configuration
+///
+/// Lets admin callers send through a relay given inline in the request, for
+/// ad-hoc routing and relay testing.
+#[derive(Clone)]
+pub struct SmtpOverrideConfig {
+    /// Whether requests may carry an `smtp` override
+    pub enabled: bool,
+}
+
+/// Dark mode support of HTML bodies
+///
+/// Declares light and dark color scheme support so that Apple Mail and Gmail
+/// keep the designed colors instead of inverting them.
+#[derive(Clone)]
+pub struct DarkModeConfig {
+    /// Whether HTML bodies declare their color scheme support
+    pub enabled: bool,
+
+    /// Body background color in dark mode (CSS color)
+    pub background: Option<String>,
+
+    /// Body text color in dark mode (CSS color)
+    pub text: Option<String>,
+}
+
+/// Body language and charset detection configuration
+#[derive(Clone)]
+pub struct ContentDetectionConfig {
+    /// Whether `Content-Language` and the text part charsets are set from the body
+    pub enabled: bool,
+}
+
+/// Sender domain alignment check configuration
+///
+/// DMARC passes only when the From domain aligns with the DKIM signing domain
+/// or with an SPF-authorized envelope domain; the check rejects the sends that
+/// cannot pass, before they reach the relay.
+#[derive(Clone)]
+pub struct AlignmentConfig {
+    /// Whether misaligned senders are rejected
+    pub enabled: bool,
+
+    /// Domains whose SPF record authorizes the relay, by lowercase profile name
+    pub spf_domains: HashMap<String, Vec<String>>,
+}
+
+impl AlignmentConfig {
+    /// Returns the SPF-authorized domains of a profile
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name (case-insensitive); `None` selects the default profile
+    pub fn spf_domains(&self, profile: Option<&str>) -> &[String] {
+        let name = profile.map_or_else(|| DEFAULT_SMTP_PROFILE.to_owned(), str::to_lowercase);
+        self.spf_domains.get(&name).map_or(&[], Vec::as_slice)
+    }
+}
+
+/// Sending cost accounting configuration
+///
+/// Each profile has a price per delivered recipient, so sends can be charged
+/// back to the tenant that made them.
+#[derive(Clone)]
+pub struct CostConfig {
+    /// Currency of the rates, reported with the costs (e.g. "USD")
+    pub currency: String,
+
+    /// Price of one delivered recipient, by lowercase profile name
+    pub rates: HashMap<String, f64>,
+}
+
+impl CostConfig {
+    /// Returns the price of one delivered recipient through a profile
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name (case-insensitive); `None` selects the default profile
+    pub fn rate(&self, profile: Option<&str>) -> f64 {
+        let name = profile.map_or_else(|| DEFAULT_SMTP_PROFILE.to_owned(), str::to_lowercase);
+        self.rates.get(&name).copied().unwrap_or(0.0)
+    }
+}
+
+/// Service manager integration
+///
+/// Lets rustmail run as a classic Unix daemon: detached from the terminal,
+/// recording its PID and running under an unprivileged account once the
+/// listening socket is bound. Windows services only use the PID file.
+#[derive(Clone, Default)]
+pub struct DaemonConfig {
+    /// Fork into the background once the service is listening
+    pub detach: bool,
+
+    /// File the PID of the service is written to
+    pub pid_file: Option<String>,
+
+    /// Account the service runs as after binding its socket
+    pub user: Option<String>,
+
+    /// Group the service runs as; the primary group of `user` when unset
+    pub group: Option<String>,
+}
+
+/// Delivery outcome webhook configuration
+#[derive(Clone)]
+pub struct WebhookConfig {
+    /// Key signing the events; callbacks are disabled when unset
+    pub secret: Option<String>,
+
+    /// Timeout of one callback request
+    pub timeout: Duration,
+
+    /// Callback requests made for one event before giving up
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled after each failed request
+    pub retry_base: Duration,
+}
+
+/// SMTP circuit breaker configuration
+///
+/// After `threshold` failed attempts in a row, sends through a profile fail
+/// fast for the cooldown instead of waiting on a dead relay.
+#[derive(Clone)]
+pub struct CircuitBreakerConfig {
+    /// Failed attempts in a row that open the circuit of a profile; the breaker is disabled when 0
+    pub threshold: u32,
+
+    /// Time an open circuit fails fast before a trial send is let through
+    pub cooldown: Duration,
+
+    /// Whether direct sends are queued instead of failing while the circuit is open
+    pub queue: bool,
+}
+
+/// Action taken on low priority mail while the service is overloaded
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub enum ShedAction {
+    /// The request fails with HTTP 503 and a `Retry-After` header
+    Reject,
+
+    /// The email is queued instead of being sent now
+    Defer,
+}
+
+impl FromStr for ShedAction {
+    type Err = String;
+
+    /// Parses an action name (`reject` or `defer`, case-insensitive)
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value.to_lowercase().as_str() {
+            "reject" => Ok(ShedAction::Reject),
+            "defer" => Ok(ShedAction::Defer),
+            _ => Err("expected reject or defer".to_owned()),
+        }
+    }
+}
+
+/// Load shedding configuration
+///
+/// While the relay keeps failing or the queue is deep, bulk and low priority
+/// mail is rejected or deferred so transactional mail keeps going through.
+#[derive(Clone)]
+pub struct LoadSheddingConfig {
+    /// Queue depth from which mail is shed; not checked when `None`
+    pub max_queue_depth: Option<usize>,
+
+    /// Consecutive failed relay attempts from which mail is shed; not checked when `None`
+    pub max_relay_failures: Option<u32>,
+
+    /// What happens to the shed mail
+    pub action: ShedAction,
+
+    /// Delay rejected clients are told to wait before retrying
+    pub retry_after: Duration,
+}
+
+impl LoadSheddingConfig {
+    /// Returns `true` when at least one health signal is checked
+    pub fn enabled(&self) -> bool {
+        self.max_queue_depth.is_some() || self.max_relay_failures.is_some()
+    }
+}
+
+/// Rate limiting configuration
+#[derive(Clone)]
+pub struct RateLimitConfig {
+    /// Requests allowed per minute and client; rate limiting is disabled when 0
+    pub per_minute: u32,
+
+    /// Maximum burst of requests (bucket capacity)
+    pub burst: u32,
+}
+
+/// Template rendering configuration
+pub struct TemplateConfig {
+    /// Directory holding one sub-directory per template; templates are disabled when unset
+    pub dir: Option<String>,
+
+    /// Whether HTML bodies get their CSS inlined, unless a template says otherwise
+    pub inline_css: bool,
+}
+
+/// Persistent outbound queue configuration
+#[derive(Clone)]
+pub struct QueueConfig {
+    /// SQLite database file path
+    pub db_path: String,
+
+    /// Pause between two polls for due messages
+    pub poll_interval: Duration,
+
+    /// Maximum number of messages picked per poll
+    pub batch_size: usize,
+
+    /// Maximum number of deliveries in flight at once
+    pub concurrency: usize,
+
+    /// Attempts after which a message is marked failed
+    pub max_attempts: u32,
+
+    /// Delay before the first retry, doubled after each failure
+    pub retry_base: Duration,
+
+    /// Upper bound of the retry delay
+    pub retry_max: Duration,
+
+    /// How long a picked message is hidden from other workers while being delivered
+    pub lease: Duration,
+
+    /// Apply pending schema migrations at startup
+    pub auto_migrate: bool,
+}
+
+/// Application outbox relayer configuration
+#[derive(Clone)]
+pub struct OutboxConfig {
+    /// SQLite database of the application holding the outbox table; the relayer
+    /// is disabled when `None`
+    pub db_path: Option<String>,
+
+    /// Pause between two polls for new outbox rows
+    pub poll_interval: Duration,
+
+    /// Maximum number of rows picked per poll
+    pub batch_size: usize,
+}
+
+/// API response status enumeration
+///
+/// Represents the status of an API operation using JSend-style conventions.
+#[derive(Clone, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Status {
+    /// Successful operation
+    Ok,
+
+    /// Failed operation (client error)
+    Fail,
+
+    /// Error operation (server error)
+    Error,
+}
+
+/// Standard JSON response structure
+///
+/// Provides a consistent response format for all API endpoints.
+#[derive(Clone, Serialize, ToSchema)]
+pub struct RustMailRes {
+    /// Response status (ok, fail, or error)
+    pub status: Status,
+
+    /// Human-readable message describing the result
+    pub message: String,
+
+    /// ID of the request, for correlation with the server logs
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+}
+
+/// Initializes the logger with environment variable configuration
+///
+/// Uses `RUST_LOG` environment variable, defaults to `debug` level.
+/// Lines logged while handling a request end their header with the request ID.
+/// Logs go to `LOG_FILE` when set, e.g. for a Windows service that has no
+/// standard error, and to standard error otherwise or when it cannot be opened.
+/// This should be called once at application startup.
+pub fn init_logger() {
+    // Initialize the env_logger with default debug level, RUST_LOG may come from the config file
+    let filter = config_var("RUST_LOG").unwrap_or_else(|_| "debug".to_owned());
+    let mut builder =
+        env_logger::Builder::from_env(env_logger::Env::default().default_filter_or(filter));
+    builder.format(|buf, record| {
+        let level = buf.default_level_style(record.level());
+        let request = current_request_id()
+            .map(|id| format!(" {}", id))
+            .unwrap_or_default();
+        writeln!(
+            buf,
+            "[{} {level}{:<5}{level:#} {}{}] {}",
+            buf.timestamp(),
+            record.level(),
+            record.target(),
+            request,
+            record.args()
+        )
+    });
+
+    let log_file = config_var("LOG_FILE").ok().filter(|v| !v.is_empty());
+    let mut open_error = None;
+    if let Some(path) = &log_file {
+        match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            Ok(file) => {
+                builder.target(env_logger::Target::Pipe(Box::new(file)));
+            }
+            Err(err) => open_error = Some(err),
+        }
+    }
+    builder.init();
+
+    if let (Some(path), Some(err)) = (log_file, open_error) {
+        warn!(
+            "Cannot open LOG_FILE {}, logging to standard error: {}",
+            path, err
+        );
+    }
+}
+
+/// Settings read from the config file, by environment variable name
+static CONFIG_FILE_VALUES: OnceLock<HashMap<String, String>> = OnceLock::new();
+
+/// Value of a config file entry
+///
+/// Sections nest: `[smtp] host = "..."` sets `SMTP_HOST`. Lists become
+/// comma-separated values.
+enum ConfigValue {
+    /// String, number or boolean
+    Scalar(String),
+
+    /// List of strings, numbers or booleans
+    List(Vec<String>),
+
+    /// Nested section
+    Section(BTreeMap<String, ConfigValue>),
+}
+
+/// Scalar entry of a config file list
+struct ConfigScalar(String);
+
+impl<'de> Deserialize<'de> for ConfigScalar {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ScalarVisitor;
+
+        impl Visitor<'_> for ScalarVisitor {
+            type Value = ConfigScalar;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, number or boolean")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(ConfigScalar(v.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(ConfigScalar(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(ConfigScalar(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(ConfigScalar(v.to_string()))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(ConfigScalar(v.to_owned()))
+            }
+        }
+
+        deserializer.deserialize_any(ScalarVisitor)
+    }
+}
+
+impl<'de> Deserialize<'de> for ConfigValue {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct ValueVisitor;
+
+        impl<'de> Visitor<'de> for ValueVisitor {
+            type Value = ConfigValue;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                f.write_str("a string, number, boolean, list or section")
+            }
+
+            fn visit_bool<E: de::Error>(self, v: bool) -> Result<Self::Value, E> {
+                Ok(ConfigValue::Scalar(v.to_string()))
+            }
+
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(ConfigValue::Scalar(v.to_string()))
+            }
+
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(ConfigValue::Scalar(v.to_string()))
+            }
+
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                Ok(ConfigValue::Scalar(v.to_string()))
+            }
+
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                Ok(ConfigValue::Scalar(v.to_owned()))
+            }
+
+            fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+                let mut items = Vec::new();
+                while let Some(ConfigScalar(item)) = seq.next_element()? {
+                    items.push(item);
+                }
+                Ok(ConfigValue::List(items))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<Self::Value, A::Error> {
+                let mut section = BTreeMap::new();
+                while let Some((key, value)) = map.next_entry::<String, ConfigValue>()? {
+                    section.insert(key, value);
+                }
+                Ok(ConfigValue::Section(section))
+            }
+        }
+
+        deserializer.deserialize_any(ValueVisitor)
+    }
+}
+
+/// Flattens config file sections into environment variable names
+///
+/// `smtp.host`, `[smtp] host` and `SMTP_HOST` all name the `SMTP_HOST` variable.
+fn flatten_config(
+    prefix: &str,
+    section: BTreeMap<String, ConfigValue>,
+    values: &mut HashMap<String, String>,
+) -> Result<(), String> {
+    for (key, value) in section {
+        let name = format!("{}{}", prefix, key)
+            .to_uppercase()
+            .replace(['-', '.'], "_");
+        let value = match value {
+            ConfigValue::Section(section) => {
+                flatten_config(&format!("{}_", name), section, values)?;
+                continue;
+            }
+            ConfigValue::Scalar(value) => value,
+            ConfigValue::List(items) => items.join(","),
+        };
+        if values.insert(name.clone(), value).is_some() {
+            return Err(format!("{} is set twice", name));
+        }
+    }
+    Ok(())
+}
+
+/// Configuration error found at startup
+#[derive(Debug)]
+pub enum ConfigError {
+    /// The config file names a setting that does not exist
+    Unknown { name: String },
+
+    /// A setting has a value that its setting does not accept
+    Invalid {
+        name: String,
+        value: String,
+        reason: String,
+    },
+
+    /// The file holding a credential cannot be read
+    Unreadable {
+        name: String,
+        path: String,
+        reason: String,
+    },
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ConfigError::Unknown { name } => write!(f, "unknown setting {}", name),
+            ConfigError::Invalid {
+                name,
+                value,
+                reason,
+            } => write!(f, "{}: invalid value {:?} ({})", name, value, reason),
+            ConfigError::Unreadable { name, path, reason } => {
+                write!(f, "Cannot read {}_FILE {}: {}", name, path, reason)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// Check of a setting value, giving the reason of a refusal
+type Check = fn(&str) -> Result<(), String>;
+
+/// Setting name, or name suffix, with the check of its values
+type Setting = (&'static str, Check);
+
+/// Accepts any text
+fn text(_: &str) -> Result<(), String> {
+    Ok(())
+}
+
+/// Accepts the values that parse as `T`
+fn parses<T: FromStr>(value: &str) -> Result<(), String>
+where
+    T::Err: fmt::Display,
+{
+    value.parse::<T>().map(|_| ()).map_err(|e| e.to_string())
+}
+
+/// Accepts the numbers above zero
+fn positive<T: FromStr + Default + PartialOrd>(value: &str) -> Result<(), String>
+where
+    T::Err: fmt::Display,
+{
+    match value.parse::<T>() {
+        Ok(v) if v > T::default() => Ok(()),
+        Ok(_) => Err("must be at least 1".to_owned()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Parses an SMTP auth mechanism name (`plain`, `login` or `xoauth2`, case-insensitive)
+///
+/// lettre implements no other mechanism, CRAM-MD5 in particular.
+fn parse_mechanism(name: &str) -> Result<Mechanism, String> {
+    match name.trim().to_lowercase().as_str() {
+        "plain" => Ok(Mechanism::Plain),
+        "login" => Ok(Mechanism::Login),
+        "xoauth2" => Ok(Mechanism::Xoauth2),
+        other => Err(format!(
+            "unsupported mechanism {}, expected PLAIN, LOGIN or XOAUTH2",
+            other.to_uppercase()
+        )),
+    }
+}
+
+/// Accepts comma-separated SMTP auth mechanisms
+fn mechanisms(value: &str) -> Result<(), String> {
+    value
+        .split(',')
+        .filter(|m| !m.trim().is_empty())
+        .try_for_each(|m| parse_mechanism(m).map(|_| ()))
+}
+
+/// Settings read once, by their full name
+const SETTINGS: &[Setting] = &[
+    ("RUST_LOG", text),
+    ("LOG_FILE", text),
+    ("BIND_ADDR", text),
+    ("BIND_PORT", parses::<u16>),
+    ("BIND_WORKERS", parses::<usize>),
+    ("BIND_REUSE_PORT", parses::<bool>),
+    ("SHUTDOWN_TIMEOUT_SECS", parses::<u64>),
+    ("SMTP_PROFILES", text),
+    ("SMTP_OVERRIDE_ENABLED", parses::<bool>),
+    ("LINK_CHECK_MODE", parses::<LinkCheckMode>),
+    ("LINK_CHECK_TIMEOUT_MS", parses::<u64>),
+    ("LINK_CHECK_CONCURRENCY", positive::<usize>),
+    ("FETCH_ALLOW_HOSTS", text),
+    ("FETCH_DENY_HOSTS", text),
+    ("FETCH_ALLOW_PRIVATE_IPS", parses::<bool>),
+    ("FAULT_INJECTION_ENABLED", parses::<bool>),
+    ("FAULT_DROP_PERCENT", parses::<u32>),
+    ("FAULT_TRANSIENT_PERCENT", parses::<u32>),
+    ("FAULT_LATENCY_MS", parses::<u64>),
+    ("DUPLICATE_GUARD_MODE", parses::<DuplicateGuardMode>),
+    ("DUPLICATE_GUARD_WINDOW_SECS", parses::<u64>),
+    ("IDEMPOTENCY_WINDOW_SECS", parses::<u64>),
+    ("BLACKHOLE_DOMAINS", text),
+    ("MAX_ATTACHMENTS", parses::<usize>),
+    ("MAX_MIME_PARTS", parses::<usize>),
+    ("MAX_MIME_DEPTH", parses::<usize>),
+    ("DKIM_DOMAIN", text),
+    ("DKIM_SELECTOR", text),
+    ("DKIM_PRIVATE_KEY", text),
+    ("DKIM_PRIVATE_KEY_FILE", text),
+    ("DKIM_ALGORITHM", text),
+    ("BULK_MAX_MESSAGES", positive::<usize>),
+    ("BULK_CONCURRENCY", positive::<usize>),
+    ("STATUS_MAX_IDS", positive::<usize>),
+    ("PRIVACY_MODE", parses::<bool>),
+    ("PRIVACY_DOMAIN", text),
+    ("SANDBOX", parses::<bool>),
+    ("XML_ENDPOINT", parses::<bool>),
+    ("SUBJECT_PREFIX", text),
+    ("SUBJECT_SUFFIX", text),
+    ("RECIPIENT_ALLOW_DOMAINS", text),
+    ("RECIPIENT_DENY_DOMAINS", text),
+    ("RESPONSE_RECIPIENTS", parses::<RecipientDisplay>),
+    ("RESPONSE_OMIT_FIELDS", text),
+    ("API_KEYS", text),
+    ("API_KEYS_FILE", text),
+    ("ADMIN_API_KEYS", text),
+    ("API_KEY_TENANTS", text),
+    ("HTML_DARK_MODE", parses::<bool>),
+    ("HTML_DARK_MODE_BACKGROUND", text),
+    ("HTML_DARK_MODE_TEXT", text),
+    ("CONTENT_DETECTION", parses::<bool>),
+    ("ALIGNMENT_CHECK", parses::<bool>),
+    ("COST_CURRENCY", text),
+    ("DAEMON", parses::<bool>),
+    ("DAEMON_PID_FILE", text),
+    ("DAEMON_USER", text),
+    ("DAEMON_GROUP", text),
+    ("WEBHOOK_SECRET", text),
+    ("WEBHOOK_TIMEOUT_SECS", positive::<u64>),
+    ("WEBHOOK_MAX_ATTEMPTS", positive::<u32>),
+    ("WEBHOOK_RETRY_BASE_SECS", parses::<u64>),
+    ("CIRCUIT_BREAKER_THRESHOLD", parses::<u32>),
+    ("CIRCUIT_BREAKER_COOLDOWN_SECS", positive::<u64>),
+    ("CIRCUIT_BREAKER_QUEUE", parses::<bool>),
+    ("SHED_QUEUE_DEPTH", parses::<usize>),
+    ("SHED_RELAY_FAILURES", parses::<u32>),
+    ("SHED_ACTION", parses::<ShedAction>),
+    ("SHED_RETRY_AFTER_SECS", parses::<u64>),
+    ("RATE_LIMIT_PER_MINUTE", parses::<u32>),
+    ("RATE_LIMIT_BURST", parses::<u32>),
+    ("TEMPLATES_DIR", text),
+    ("TEMPLATES_INLINE_CSS", parses::<bool>),
+    ("QUEUE_DB_PATH", text),
+    ("QUEUE_POLL_SECS", parses::<u64>),
+    ("QUEUE_BATCH_SIZE", positive::<usize>),
+    ("QUEUE_CONCURRENCY", positive::<usize>),
+    ("QUEUE_MAX_ATTEMPTS", positive::<u32>),
+    ("QUEUE_RETRY_BASE_SECS", parses::<u64>),
+    ("QUEUE_RETRY_MAX_SECS", parses::<u64>),
+    ("QUEUE_LEASE_SECS", parses::<u64>),
+    ("QUEUE_AUTO_MIGRATE", parses::<bool>),
+    ("OUTBOX_DB_PATH", text),
+    ("OUTBOX_POLL_SECS", parses::<u64>),
+    ("OUTBOX_BATCH_SIZE", positive::<usize>),
+    ("CANARY_SEEDS", text),
+    ("CANARY_FROM", text),
+    ("CANARY_INTERVAL_SECS", parses::<u64>),
+    ("CANARY_TIMEOUT_SECS", parses::<u64>),
+    ("CANARY_POLL_SECS", parses::<u64>),
+    ("ATTACHMENT_CONVERTERS", text),
+    ("ATTACHMENT_CONVERT_TIMEOUT_SECS", parses::<u64>),
+    ("ATTACHMENT_CONVERT_CONCURRENCY", positive::<usize>),
+];
+
+/// SMTP settings of a profile, after `SMTP_` for the default profile and
+/// `SMTP_<NAME>_` for the named ones
+const SMTP_SETTINGS: &[Setting] = &[
+    ("HOST", text),
+    ("PORT", parses::<u16>),
+    ("USERNAME", text),
+    ("USERNAME_FILE", text),
+    ("PASSWORD", text),
+    ("PASSWORD_FILE", text),
+    ("OAUTH2_CLIENT_ID", text),
+    ("OAUTH2_CLIENT_SECRET", text),
+    ("OAUTH2_REFRESH_TOKEN", text),
+    ("OAUTH2_TOKEN_URL", text),
+    ("OAUTH2_SCOPE", text),
+    ("USE_TLS", parses::<bool>),
+    ("TLS_MODE", parses::<TlsMode>),
+    ("TLS_CA_FILE", text),
+    ("TLS_ACCEPT_INVALID_CERTS", parses::<bool>),
+    ("MAX_CONNECTIONS", positive::<u32>),
+    ("AUTH_MECHANISMS", mechanisms),
+    ("SPF_DOMAINS", text),
+    ("COST_PER_RECIPIENT", parses::<f64>),
+];
+
+/// Transport settings of a profile, unprefixed for the default profile and
+/// after `SMTP_<NAME>_` for the named ones
+const TRANSPORT_SETTINGS: &[Setting] = &[
+    ("TRANSPORT", parses::<TransportKind>),
+    ("SENDMAIL_COMMAND", text),
+    ("SENDGRID_API_KEY", text),
+    ("SENDGRID_API_URL", text),
+    ("MAILGUN_API_KEY", text),
+    ("MAILGUN_DOMAIN", text),
+    ("MAILGUN_API_URL", text),
+    ("SES_ACCESS_KEY_ID", text),
+    ("SES_SECRET_ACCESS_KEY", text),
+    ("SES_SESSION_TOKEN", text),
+    ("SES_REGION", text),
+    ("SES_API_URL", text),
+];
+
+/// Settings of a `CANARY_SEEDS` seed, after `CANARY_<NAME>_`
+const CANARY_SEED_SETTINGS: &[Setting] = &[
+    ("ADDRESS", text),
+    ("IMAP_HOST", text),
+    ("IMAP_PORT", parses::<u16>),
+    ("IMAP_USERNAME", text),
+    ("IMAP_PASSWORD", text),
+    ("SPAM_FOLDER", text),
+];
+
+/// Settings of an `ATTACHMENT_CONVERTERS` converter, after `CONVERTER_<NAME>_`
+const CONVERTER_SETTINGS: &[Setting] = &[
+    ("COMMAND", text),
+    ("CONTENT_TYPE", text),
+    ("EXTENSION", text),
+];
+
+/// Known settings: each table with the prefix of its names
+///
+/// Profiles, canary seeds and converters repeat their table under the prefix
+/// of each declared name.
+struct SettingTables(Vec<(String, &'static [Setting])>);
+
+impl SettingTables {
+    /// Builds the tables of the declared profiles, canary seeds and converters
+    ///
+    /// # Arguments
+    /// * `list` - Reads the `SMTP_PROFILES`, `CANARY_SEEDS` and `ATTACHMENT_CONVERTERS` lists
+    fn new(list: impl Fn(&str) -> Option<String>) -> Self {
+        let names = |name: &str| -> Vec<String> {
+            list(name)
+                .unwrap_or_default()
+                .split(',')
+                .map(|v| v.trim().to_uppercase())
+                .filter(|v| !v.is_empty())
+                .collect()
+        };
+
+        let mut tables: Vec<(String, &'static [Setting])> = vec![
+            (String::new(), SETTINGS),
+            (String::new(), TRANSPORT_SETTINGS),
+            ("SMTP_".to_owned(), SMTP_SETTINGS),
+        ];
+        // Prefixes are built like the settings builders build them
+        for name in names("SMTP_PROFILES") {
+            if name.eq_ignore_ascii_case(DEFAULT_SMTP_PROFILE) {
+                continue;
+            }
+            let prefix = format!("SMTP_{}_", name.replace('-', "_"));
+            tables.push((prefix.clone(), SMTP_SETTINGS));
+            tables.push((prefix, TRANSPORT_SETTINGS));
+        }
+        for name in names("CANARY_SEEDS") {
+            tables.push((format!("CANARY_{}_", name), CANARY_SEED_SETTINGS));
+        }
+        for name in names("ATTACHMENT_CONVERTERS") {
+            let prefix = format!("CONVERTER_{}_", name.replace('-', "_"));
+            tables.push((prefix, CONVERTER_SETTINGS));
+        }
+        SettingTables(tables)
+    }
+
+    /// Finds the check of a setting, `None` when the setting is unknown
+    fn find(&self, name: &str) -> Option<Check> {
+        self.0.iter().find_map(|(prefix, table)| {
+            let key = name.strip_prefix(prefix.as_str())?;
+            table
+                .iter()
+                .find(|(setting, _)| *setting == key)
+                .map(|(_, check)| *check)
+        })
+    }
+
+    /// Checks the value of a known setting
+    ///
+    /// Empty values leave the setting unset, like the settings builders read them.
+    fn check(check: Check, name: &str, value: &str) -> Result<(), ConfigError> {
+        if value.trim().is_empty() {
+            return Ok(());
+        }
+        check(value).map_err(|reason| ConfigError::Invalid {
+            name: name.to_owned(),
+            value: value.to_owned(),
+            reason,
+        })
+    }
+}
+
+/// Checks the flattened config file against the setting tables
+///
+/// Profiles, canary seeds and converters are taken from the lists given in the
+/// environment or in the file, and their settings checked by prefix. Any other
+/// setting must be a known setting.
+///
+/// # Returns
+/// * `Ok(HashMap)` - The settings, by environment variable name
+/// * `Err(ConfigError)` - A setting is unknown or its value is not accepted
+fn check_config(values: HashMap<String, String>) -> Result<HashMap<String, String>, ConfigError> {
+    let tables =
+        SettingTables::new(|list| env::var(list).ok().or_else(|| values.get(list).cloned()));
+    // Sorted, so that the first error reported does not depend on the hash order
+    for (name, value) in values.iter().collect::<BTreeMap<_, _>>() {
+        let check = tables
+            .find(name)
+            .ok_or_else(|| ConfigError::Unknown { name: name.clone() })?;
+        SettingTables::check(check, name, value)?;
+    }
+    Ok(values)
+}
+
+/// Checks the settings given in the environment
+///
+/// Environment variables override the config file, so their values are checked
+/// against the same tables: an invalid value stops the startup instead of
+/// falling back to the default. Variables that name no setting are ignored, as
+/// the environment also holds variables of other programs. Must be called after
+/// [`load_config_file`].
+///
+/// # Returns
+/// * `Ok(())` - Every setting of the environment has a valid value
+/// * `Err(ConfigError)` - A setting of the environment has an invalid value
+pub fn check_environment() -> Result<(), ConfigError> {
+    let tables = SettingTables::new(setting_value);
+    let mut vars: Vec<(String, String)> = env::vars_os()
+        .filter_map(|(name, value)| Some((name.into_string().ok()?, value.into_string().ok()?)))
+        .collect();
+    vars.sort();
+    for (name, value) in vars {
+        if let Some(check) = tables.find(&name) {
+            SettingTables::check(check, &name, &value)?;
+        }
+    }
+    Ok(())
+}
+
+/// Loads the config file named by `CONFIG_FILE`, if any
+///
+/// The file is TOML (`.toml`) or YAML (`.yaml`, `.yml`). Its keys name the
+/// same settings as the environment variables, optionally grouped in sections,
+/// and environment variables take precedence over the file. Must be called once,
+/// before the settings are built.
+///
+/// # Environment Variables
+/// - `CONFIG_FILE` - Path of the config file (default: none)
+///
+/// # Returns
+/// * `Ok(Some(path))` - The file was loaded
+/// * `Ok(None)` - No config file is configured
+/// * `Err(String)` - The file cannot be read, has an unknown extension, an unknown setting
+///   or a value that does not have the type of its setting
+pub fn load_config_file() -> Result<Option<String>, String> {
+    let Some(path) = env::var("CONFIG_FILE")
+        .ok()
+        .filter(|v| !v.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let content = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Cannot read config file {}: {}", path, e))?;
+
+    let extension = Path::new(&path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_lowercase();
+    let root: BTreeMap<String, ConfigValue> = match extension.as_str() {
+        "toml" => toml::from_str(&content).map_err(|e| e.to_string()),
+        "yaml" | "yml" => serde_yaml_ng::from_str(&content).map_err(|e| e.to_string()),
+        _ => Err("unsupported format, expected .toml, .yaml or .yml".to_owned()),
+    }
+    .map_err(|e| format!("Invalid config file {}: {}", path, e.trim_end()))?;
+
+    let mut values = HashMap::new();
+    flatten_config("", root, &mut values)
+        .map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    values.remove("CONFIG_FILE");
+    let values =
+        check_config(values).map_err(|e| format!("Invalid config file {}: {}", path, e))?;
+    CONFIG_FILE_VALUES
+        .set(values)
+        .map_err(|_| "Config file already loaded".to_owned())?;
+    Ok(Some(path))
+}
+
+/// Reads a setting from the environment, then from the config file
+///
+/// # Arguments
+/// * `name` - Environment variable name
+///
+/// # Returns
+/// The value, or the error of the environment lookup when neither source sets it
+fn config_var<K: AsRef<str>>(name: K) -> Result<String, env::VarError> {
+    let name = name.as_ref();
+    // Settings are checked at startup against the tables, a setting read here must be listed
+    debug_assert!(
+        SettingTables::new(setting_value).find(name).is_some(),
+        "{} is missing from the setting tables",
+        name
+    );
+    env::var(name).or_else(|err| {
+        CONFIG_FILE_VALUES
+            .get()
+            .and_then(|values| values.get(name).cloned())
+            .ok_or(err)
+    })
+}
+
+/// Reads a setting from the environment, then from the config file, without checking its name
+fn setting_value(name: &str) -> Option<String> {
+    env::var(name)
+        .ok()
+        .or_else(|| CONFIG_FILE_VALUES.get()?.get(name).cloned())
+}
+
+/// Reads a credential from its variable or from the file named by `<name>_FILE`
+///
+/// Files let credentials come from mounted Docker or Kubernetes secrets rather
+/// than the environment, which leaks through `/proc` and crash dumps. The
+/// variable takes precedence; trailing line breaks of the file are dropped.
+///
+/// # Arguments
+/// * `name` - Variable name, e.g. `SMTP_PASSWORD`
+///
+/// # Returns
+/// * `Ok(Some(String))` - The credential
+/// * `Ok(None)` - Neither the variable nor the file is set
+/// * `Err(ConfigError)` - The file is set but cannot be read
+fn secret_var(name: &str) -> Result<Option<String>, ConfigError> {
+    if let Ok(value) = config_var(name) {
+        return Ok(Some(value));
+    }
+    let Ok(path) = config_var(format!("{}_FILE", name)) else {
+        return Ok(None);
+    };
+    // Starting without the credential would only fail later, at the first send
+    std::fs::read_to_string(&path)
+        .map(|content| Some(content.trim_end_matches(['\r', '\n']).to_owned()))
+        .map_err(|err| ConfigError::Unreadable {
+            name: name.to_owned(),
+            path,
+            reason: err.to_string(),
+        })
+}
+
+/// Builds server bind configuration from environment variables
+///
+/// # Environment Variables
+/// - `BIND_ADDR` - Server bind address (default: 0.0.0.0)
+/// - `BIND_PORT` - Server port (default: 3333)
+/// - `BIND_WORKERS` - Number of worker threads (default: number of CPU cores)
+/// - `BIND_REUSE_PORT` - Bind with `SO_REUSEPORT` for zero-downtime upgrades (default: false)
+/// - `SHUTDOWN_TIMEOUT_SECS` - Drain time for in-flight work on shutdown (default: 30)
+///
+/// # Returns
+/// A `ServerBind` struct containing the server configuration
+pub fn build_server_bind() -> ServerBind {
+    // Read server bind address from environment or use default
+    let addr = match config_var("BIND_ADDR") {
+        Ok(v) => v,
+        Err(_) => DEFAULT_ADDRESS.into(),
+    };
+
+    // Read server port from environment or use default
+    let port = match config_var("BIND_PORT") {
+        Ok(v) => v.parse::<u16>().unwrap_or(DEFAULT_PORT),
+        Err(_) => DEFAULT_PORT,
+    };
+
+    // Get the number of CPU threads, fallback to 1 if unavailable
+    let default_workers = std::thread::available_parallelism()
+        .map(|w| w.get())
+        .unwrap_or(1);
+
+    // Use environment variable for workers or fallback to CPU count
+    let workers = config_var("BIND_WORKERS")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .unwrap_or(default_workers);
+
+    let reuse_port = config_var("BIND_REUSE_PORT")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    ServerBind {
+        addr,
+        port,
+        workers,
+        reuse_port,
+        shutdown_timeout: env_secs("SHUTDOWN_TIMEOUT_SECS", DEFAULT_SHUTDOWN_TIMEOUT_SECS),
+    }
+}
+
+/// Builds SMTP configuration from environment variables
+///
+/// This is the default profile, used when a request selects none.
+///
+/// # Environment Variables
+/// - `SMTP_HOST` - SMTP server hostname or IP (default: localhost)
+/// - `SMTP_PORT` - SMTP server port (default: 25)
+/// - `SMTP_USERNAME` - SMTP authentication username (optional)
+/// - `SMTP_USERNAME_FILE` - File holding the username, read when `SMTP_USERNAME` is not set (optional)
+/// - `SMTP_PASSWORD` - SMTP authentication password (optional)
+/// - `SMTP_PASSWORD_FILE` - File holding the password, read when `SMTP_PASSWORD` is not set (optional)
+/// - `SMTP_TLS_MODE` - Connection encryption: `none`, `starttls` or `tls` (default: `none`
+///   for port 25, `tls` for port 465, `starttls` for others)
+/// - `SMTP_USE_TLS` - Legacy switch, used when `SMTP_TLS_MODE` is not set: `false` selects
+///   `none`, `true` the port default among `starttls` and `tls`
+/// - `SMTP_TLS_CA_FILE` - PEM file of an additional trusted CA (optional)
+/// - `SMTP_TLS_ACCEPT_INVALID_CERTS` - Accept invalid server certificates (default: false)
+/// - `SMTP_MAX_CONNECTIONS` - Maximum simultaneous connections to the SMTP server (default: 10)
+/// - `SMTP_AUTH_MECHANISMS` - Comma-separated auth mechanisms in order of preference:
+///   `PLAIN`, `LOGIN`, `XOAUTH2` (default: negotiated); any other mechanism, CRAM-MD5 included,
+///   stops startup
+/// - `SMTP_OAUTH2_TOKEN_URL` - OAuth2 token endpoint of the provider
+/// - `SMTP_OAUTH2_CLIENT_ID` - OAuth2 client ID
+/// - `SMTP_OAUTH2_CLIENT_SECRET` - OAuth2 client secret (optional)
+/// - `SMTP_OAUTH2_REFRESH_TOKEN` - Refresh token of the `SMTP_USERNAME` account
+/// - `SMTP_OAUTH2_SCOPE` - Scope requested with the access tokens (optional)
+///
+/// # Returns
+/// * `Ok(SmtpConfig)` - The SMTP configuration
+/// * `Err(ConfigError)` - `SMTP_USERNAME_FILE` or `SMTP_PASSWORD_FILE` cannot be read, or
+///   `SMTP_AUTH_MECHANISMS` names a mechanism that is not supported
+///
+/// # Notes
+/// TLS is automatically enabled for all ports except 25 (plain SMTP) unless
+/// explicitly overridden by `SMTP_TLS_MODE` or `SMTP_USE_TLS`.
+/// With an OAuth2 client ID and refresh token the relay is authenticated with
+/// `XOAUTH2` and refreshed access tokens instead of `SMTP_PASSWORD`.
+pub fn build_smtp_config() -> Result<SmtpConfig, ConfigError> {
+    smtp_config_from("SMTP_")
+}
+
+/// Builds mail transport configuration of the default profile from environment variables
+///
+/// # Environment Variables
+/// - `TRANSPORT` - `smtp`, `sendmail`, `sendgrid`, `mailgun` or `ses` (default: smtp)
+/// - `SENDMAIL_COMMAND` - Sendmail binary used by the `sendmail` transport (default: sendmail)
+/// - `SENDGRID_API_KEY` - SendGrid API key
+/// - `SENDGRID_API_URL` - SendGrid API base URL (default: https://api.sendgrid.com)
+/// - `MAILGUN_API_KEY` - Mailgun API key
+/// - `MAILGUN_DOMAIN` - Mailgun sending domain
+/// - `MAILGUN_API_URL` - Mailgun API base URL, e.g. https://api.eu.mailgun.net (default: https://api.mailgun.net)
+/// - `SES_REGION` - AWS region of SES, e.g. `eu-west-1`
+/// - `SES_ACCESS_KEY_ID` - AWS access key ID
+/// - `SES_SECRET_ACCESS_KEY` - AWS secret access key
+/// - `SES_SESSION_TOKEN` - AWS session token of temporary credentials (optional)
+/// - `SES_API_URL` - SES API base URL (default: https://email.<region>.amazonaws.com)
+///
+/// # Returns
+/// A `TransportConfig` struct containing the transport configuration
+pub fn build_transport_config() -> TransportConfig {
+    transport_config_from("")
+}
+
+/// Builds the mail transport configuration of the named profiles
+///
+/// Each profile reads the variables of [`build_transport_config`] with its
+/// `SMTP_<NAME>_` prefix, e.g. `SMTP_MARKETING_TRANSPORT` and
+/// `SMTP_MARKETING_SENDGRID_API_KEY`.
+///
+/// # Returns
+/// The profile names (lowercase) with their transport configuration
+pub fn build_transport_profiles() -> Vec<(String, TransportConfig)> {
+    env_list("SMTP_PROFILES")
+        .into_iter()
+        .filter(|name| name != DEFAULT_SMTP_PROFILE)
+        .map(|name| {
+            let prefix = format!("SMTP_{}_", name.to_uppercase().replace('-', "_"));
+            (name, transport_config_from(&prefix))
+        })
+        .collect()
+}
+
+/// Reads one transport configuration from the variables starting with `prefix`
+fn transport_config_from(prefix: &str) -> TransportConfig {
+    let var = |name: &str| {
+        config_var(format!("{}{}", prefix, name))
+            .ok()
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+    };
+
+    let kind = var("TRANSPORT")
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(TransportKind::Smtp);
+
+    let sendmail_command =
+        var("SENDMAIL_COMMAND").unwrap_or_else(|| DEFAULT_SENDMAIL_COMMAND.into());
+
+    let api = match kind {
+        TransportKind::SendGrid => ApiTransportConfig {
+            key: var("SENDGRID_API_KEY"),
+            url: var("SENDGRID_API_URL"),
+            ..Default::default()
+        },
+        TransportKind::Mailgun => ApiTransportConfig {
+            key: var("MAILGUN_API_KEY"),
+            domain: var("MAILGUN_DOMAIN"),
+            url: var("MAILGUN_API_URL"),
+            ..Default::default()
+        },
+        TransportKind::Ses => ApiTransportConfig {
+            key: var("SES_ACCESS_KEY_ID"),
+            secret: var("SES_SECRET_ACCESS_KEY"),
+            session_token: var("SES_SESSION_TOKEN"),
+            region: var("SES_REGION"),
+            url: var("SES_API_URL"),
+            ..Default::default()
+        },
+        TransportKind::Smtp | TransportKind::Sendmail => ApiTransportConfig::default(),
+    };
+
+    TransportConfig {
+        kind,
+        sendmail_command,
+        api,
+    }
+}
+
+/// Builds SMTP profiles from environment variables
+///
+/// Each named profile reads the same variables as the default profile, with the
+/// profile name inserted after `SMTP_`: the `marketing` profile reads
+/// `SMTP_MARKETING_HOST`, `SMTP_MARKETING_PORT`, and so on.
+///
+/// # Environment Variables
+/// - `SMTP_PROFILES` - Comma-separated profile names (default: none)
+///
+/// # Returns
+/// * `Ok(Vec)` - The profile names (lowercase) with their SMTP configuration
+/// * `Err(ConfigError)` - The credential file of a profile cannot be read, or its
+///   `AUTH_MECHANISMS` names a mechanism that is not supported
+pub fn build_smtp_profiles() -> Result<Vec<(String, SmtpConfig)>, ConfigError> {
+    env_list("SMTP_PROFILES")
+        .into_iter()
+        .filter(|name| name != DEFAULT_SMTP_PROFILE)
+        .map(|name| {
+            let prefix = format!("SMTP_{}_", name.to_uppercase().replace('-', "_"));
+            smtp_config_from(&prefix).map(|config| (name, config))
+        })
+        .collect()
+}
+
+/// Reads one SMTP configuration from the variables starting with `prefix`
+///
+/// # Returns
+/// The configuration, or an error when a credential file cannot be read or an
+/// auth mechanism is not supported
+fn smtp_config_from(prefix: &str) -> Result<SmtpConfig, ConfigError> {
+    let var = |name: &str| config_var(format!("{}{}", prefix, name));
+
+    // Read SMTP host from environment or use default
+    let host = var("HOST").unwrap_or_else(|_| DEFAULT_SMTP_HOST.into());
+
+    // Read SMTP port from environment or use default
+    let port = var("PORT")
+        .ok()
+        .and_then(|v| v.parse::<u16>().ok())
+        .unwrap_or(DEFAULT_SMTP_PORT);
+
+    // Read optional authentication credentials; an OAuth2 client takes precedence
+    let username = secret_var(&format!("{}USERNAME", prefix))?;
+    let password = secret_var(&format!("{}PASSWORD", prefix))?;
+    let oauth2 = match (var("OAUTH2_CLIENT_ID"), var("OAUTH2_REFRESH_TOKEN")) {
+        (Ok(client_id), Ok(refresh_token)) => match (&username, var("OAUTH2_TOKEN_URL")) {
+            (Some(_), Ok(token_url)) => Some(OAuth2Config {
+                token_url,
+                client_id,
+                client_secret: var("OAUTH2_CLIENT_SECRET").ok(),
+                refresh_token,
+                scope: var("OAUTH2_SCOPE").ok().filter(|v| !v.is_empty()),
+            }),
+            _ => {
+                warn!(
+                    "{}OAUTH2_* ignored: {}USERNAME and {}OAUTH2_TOKEN_URL are required",
+                    prefix, prefix, prefix
+                );
+                None
+            }
+        },
+        _ => None,
+    };
+    let auth = match (username, password, oauth2) {
+        (Some(username), _, Some(client)) => SmtpAuth::OAuth2 { username, client },
+        (Some(username), Some(password), None) => SmtpAuth::Password { username, password },
+        _ => SmtpAuth::None,
+    };
+
+    // Automatically enable TLS for all ports except 25 (plain SMTP), implicit on 465
+    let legacy_mode = match var("USE_TLS").ok().and_then(|v| v.parse::<bool>().ok()) {
+        Some(false) => TlsMode::None,
+        Some(true) if port == 25 => TlsMode::StartTls,
+        _ => TlsMode::default_for_port(port),
+    };
+    let tls_mode = match var("TLS_MODE").unwrap_or_default().as_str() {
+        "" => legacy_mode,
+        other => TlsMode::parse(other).unwrap_or_else(|| {
+            warn!("Unsupported {}TLS_MODE ignored: {}", prefix, other);
+            legacy_mode
+        }),
+    };
+
+    // Internal relays may use certificates from a private CA
+    let tls_ca_file = var("TLS_CA_FILE").ok().filter(|v| !v.is_empty());
+    let tls_accept_invalid_certs = var("TLS_ACCEPT_INVALID_CERTS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    // Many providers cap concurrent sessions per account, at least one is required
+    let max_connections = var("MAX_CONNECTIONS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_SMTP_MAX_CONNECTIONS);
+
+    // An unsupported mechanism (e.g. CRAM-MD5) would silently change how the relay is authenticated
+    let mechanisms_var = format!("{}AUTH_MECHANISMS", prefix);
+    let auth_mechanisms = env_list(&mechanisms_var)
+        .iter()
+        .map(|m| parse_mechanism(m))
+        .collect::<Result<_, _>>()
+        .map_err(|reason| ConfigError::Invalid {
+            name: mechanisms_var.clone(),
+            value: config_var(&mechanisms_var).unwrap_or_default(),
+            reason,
+        })?;
+
+    Ok(SmtpConfig {
+        host,
+        port,
+        auth,
+        tls_mode,
+        tls_ca_file,
+        tls_accept_invalid_certs,
+        max_connections,
+        auth_mechanisms,
+    })
+}
+
+/// Builds link validation configuration from environment variables
+///
+/// # Environment Variables
+/// - `LINK_CHECK_MODE` - `off`, `warn` or `block` (default: off)
+/// - `LINK_CHECK_TIMEOUT_MS` - Timeout for each HEAD request in milliseconds (default: 5000)
+/// - `LINK_CHECK_CONCURRENCY` - Maximum concurrent HEAD requests (default: 8)
+///
+/// # Returns
+/// A `LinkCheckConfig` struct containing the link validation configuration
+pub fn build_link_check_config() -> LinkCheckConfig {
+    // Unknown values fall back to off so a typo never blocks sending
+    let mode = config_var("LINK_CHECK_MODE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(LinkCheckMode::Off);
+
+    let timeout_ms = config_var("LINK_CHECK_TIMEOUT_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_LINK_CHECK_TIMEOUT_MS);
+
+    // At least one request must be allowed in flight
+    let concurrency = config_var("LINK_CHECK_CONCURRENCY")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_LINK_CHECK_CONCURRENCY);
+
+    LinkCheckConfig {
+        mode,
+        timeout: Duration::from_millis(timeout_ms),
+        concurrency,
+    }
+}
+
+/// Reads a comma-separated list from an environment variable
+///
+/// Entries are trimmed and lowercased; empty entries are dropped.
+fn env_list(name: &str) -> Vec<String> {
+    config_var(name)
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim().to_lowercase())
+        .filter(|v| !v.is_empty())
+        .collect()
+}
+
+/// Builds the outbound fetch policy from environment variables
+///
+/// # Environment Variables
+/// - `FETCH_ALLOW_HOSTS` - Comma-separated hosts that may be fetched, `*.domain` wildcards allowed (default: any)
+/// - `FETCH_DENY_HOSTS` - Comma-separated hosts that may never be fetched (default: none)
+/// - `FETCH_ALLOW_PRIVATE_IPS` - Allow hosts resolving to private/internal addresses (default: false)
+///
+/// # Returns
+/// A `FetchPolicy` struct containing the outbound fetch restrictions
+pub fn build_fetch_policy() -> FetchPolicy {
+    let allow_private_ips = config_var("FETCH_ALLOW_PRIVATE_IPS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    FetchPolicy {
+        allow_hosts: env_list("FETCH_ALLOW_HOSTS"),
+        deny_hosts: env_list("FETCH_DENY_HOSTS"),
+        allow_private_ips,
+    }
+}
+
+/// Builds fault injection configuration from environment variables
+///
+/// # Environment Variables
+/// - `FAULT_INJECTION_ENABLED` - Let admin keys request simulated SMTP failures (default: false)
+/// - `FAULT_DROP_PERCENT` - Default percentage of sends dropped (default: 0)
+/// - `FAULT_TRANSIENT_PERCENT` - Default percentage of sends failing with a 4xx error (default: 0)
+/// - `FAULT_LATENCY_MS` - Default latency added to each send in milliseconds (default: 0)
+///
+/// # Returns
+/// A `FaultConfig` struct containing the fault injection configuration
+pub fn build_fault_config() -> FaultConfig {
+    let enabled = config_var("FAULT_INJECTION_ENABLED")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    // Percentages above 100 are clamped
+    let percent = |name: &str| {
+        config_var(name)
+            .ok()
+            .and_then(|v| v.parse::<u32>().ok())
+            .unwrap_or(0)
+            .min(100)
+    };
+
+    let latency_ms = config_var("FAULT_LATENCY_MS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(0);
+
+    FaultConfig {
+        enabled,
+        drop_percent: percent("FAULT_DROP_PERCENT"),
+        transient_percent: percent("FAULT_TRANSIENT_PERCENT"),
+        latency_ms,
+    }
+}
+
+/// Builds duplicate-content guard configuration from environment variables
+///
+/// # Environment Variables
+/// - `DUPLICATE_GUARD_MODE` - `off`, `skip` or `flag` (default: off)
+/// - `DUPLICATE_GUARD_WINDOW_SECS` - Window in seconds within which identical messages are duplicates (default: 300)
+///
+/// # Returns
+/// A `DuplicateGuardConfig` struct containing the duplicate guard configuration
+pub fn build_duplicate_guard_config() -> DuplicateGuardConfig {
+    let mode = config_var("DUPLICATE_GUARD_MODE")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(DuplicateGuardMode::Off);
+
+    let window_secs = config_var("DUPLICATE_GUARD_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_DUPLICATE_WINDOW_SECS);
+
+    DuplicateGuardConfig {
+        mode,
+        window: Duration::from_secs(window_secs),
+    }
+}
+
+/// Builds idempotency key registry configuration from environment variables
+///
+/// # Environment Variables
+/// - `IDEMPOTENCY_WINDOW_SECS` - Time the response of a send is replayed for its idempotency key,
+///   `0` disables idempotency keys (default: 86400)
+///
+/// # Returns
+/// An `IdempotencyConfig` struct containing the registry configuration
+pub fn build_idempotency_config() -> IdempotencyConfig {
+    let window_secs = config_var("IDEMPOTENCY_WINDOW_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_IDEMPOTENCY_WINDOW_SECS);
+
+    IdempotencyConfig {
+        window: Duration::from_secs(window_secs),
+    }
+}
+
+/// Builds blackhole transport configuration from environment variables
+///
+/// # Environment Variables
+/// - `BLACKHOLE_DOMAINS` - Comma-separated recipient domains whose mail is discarded (default: blackhole.invalid)
+///
+/// # Returns
+/// A `BlackholeConfig` struct containing the blackhole domains
+pub fn build_blackhole_config() -> BlackholeConfig {
+    let mut domains = env_list("BLACKHOLE_DOMAINS");
+    if config_var("BLACKHOLE_DOMAINS").is_err() {
+        domains.push(DEFAULT_BLACKHOLE_DOMAIN.into());
+    }
+    BlackholeConfig { domains }
+}
+
+/// Builds MIME structure limits from environment variables
+///
+/// # Environment Variables
+/// - `MAX_ATTACHMENTS` - Maximum number of attachments per message (default: 20)
+/// - `MAX_MIME_PARTS` - Maximum number of leaf MIME parts per message (default: 50)
+/// - `MAX_MIME_DEPTH` - Maximum multipart nesting depth (default: 5)
+///
+/// # Returns
+/// A `MimeLimits` struct containing the MIME structure limits
+pub fn build_mime_limits() -> MimeLimits {
+    let limit = |name: &str, default: usize| {
+        config_var(name)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .unwrap_or(default)
+    };
+
+    MimeLimits {
+        max_attachments: limit("MAX_ATTACHMENTS", DEFAULT_MAX_ATTACHMENTS),
+        max_parts: limit("MAX_MIME_PARTS", DEFAULT_MAX_MIME_PARTS),
+        max_depth: limit("MAX_MIME_DEPTH", DEFAULT_MAX_MIME_DEPTH),
+    }
+}
+
+/// Builds DKIM signing configuration from environment variables
+///
+/// # Environment Variables
+/// - `DKIM_DOMAIN` - Signing domain (default: none, signing disabled)
+/// - `DKIM_SELECTOR` - DNS selector of the public key (required with `DKIM_DOMAIN`)
+/// - `DKIM_PRIVATE_KEY` - Private key: PKCS#1 PEM for RSA, base64 for Ed25519
+/// - `DKIM_PRIVATE_KEY_FILE` - File holding the private key, when `DKIM_PRIVATE_KEY` is not set
+/// - `DKIM_ALGORITHM` - `rsa` or `ed25519` (default: rsa)
+///
+/// # Returns
+/// A `DkimConfig` struct containing the DKIM configuration
+pub fn build_dkim_config() -> DkimConfig {
+    let value = |name: &str| config_var(name).ok().filter(|v| !v.trim().is_empty());
+
+    DkimConfig {
+        domain: value("DKIM_DOMAIN").map(|v| v.trim().to_lowercase()),
+        selector: value("DKIM_SELECTOR").map(|v| v.trim().to_owned()),
+        private_key: value("DKIM_PRIVATE_KEY"),
+        private_key_file: value("DKIM_PRIVATE_KEY_FILE"),
+        algorithm: value("DKIM_ALGORITHM")
+            .map(|v| v.trim().to_lowercase())
+            .unwrap_or_else(|| "rsa".to_owned()),
+    }
+}
+
+/// Builds bulk send and status query limits from environment variables
+///
+/// # Environment Variables
+/// - `BULK_MAX_MESSAGES` - Maximum messages per bulk request (default: 100)
+/// - `BULK_CONCURRENCY` - Messages of one request processed at once (default: 4)
+/// - `STATUS_MAX_IDS` - Maximum message ids per status query (default: 500)
+///
+/// # Returns
+/// A `BulkLimits` struct containing the configured limits
+pub fn build_bulk_limits() -> BulkLimits {
+    let limit = |name: &str, default: usize| {
+        config_var(name)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(default)
+    };
+
+    BulkLimits {
+        max_messages: limit("BULK_MAX_MESSAGES", DEFAULT_BULK_MAX_MESSAGES),
+        concurrency: limit("BULK_CONCURRENCY", DEFAULT_BULK_CONCURRENCY),
+        max_status_ids: limit("STATUS_MAX_IDS", DEFAULT_STATUS_MAX_IDS),
+    }
+}
+
+/// Builds privacy mode configuration from environment variables
+///
+/// # Environment Variables
+/// - `PRIVACY_MODE` - Strip internal hostnames from generated mail (default: false)
+/// - `PRIVACY_DOMAIN` - Public domain for Message-ID and EHLO (default: sender's domain, EHLO unchanged)
+///
+/// # Returns
+/// A `PrivacyConfig` struct containing the privacy mode configuration
+pub fn build_privacy_config() -> PrivacyConfig {
+    let enabled = config_var("PRIVACY_MODE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    PrivacyConfig {
+        enabled,
+        domain: config_var("PRIVACY_DOMAIN").ok().filter(|v| !v.is_empty()),
+    }
+}
+
+/// Builds sandbox mode configuration from environment variables
+///
+/// # Environment Variables
+/// - `SANDBOX` - Validate and build messages without sending them (default: false)
+///
+/// # Returns
+/// A `SandboxConfig` struct containing the sandbox mode configuration
+pub fn build_sandbox_config() -> SandboxConfig {
+    let enabled = config_var("SANDBOX")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    SandboxConfig { enabled }
+}
+
+/// Builds XML compatibility endpoint configuration from environment variables
+///
+/// # Environment Variables
+/// - `XML_ENDPOINT` - Register the `/send/xml` endpoint (default: false)
+///
+/// # Returns
+/// A `XmlEndpointConfig` struct containing the XML endpoint configuration
+pub fn build_xml_endpoint_config() -> XmlEndpointConfig {
+    let enabled = config_var("XML_ENDPOINT")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    XmlEndpointConfig { enabled }
+}
+
+/// Builds recipient domain policy from environment variables
+///
+/// # Environment Variables
+/// - `RECIPIENT_ALLOW_DOMAINS` - Comma-separated domains that may receive email (default: any)
+/// - `RECIPIENT_DENY_DOMAINS` - Comma-separated domains that never receive email (default: none)
+///
+/// # Returns
+/// A `RecipientPolicy` struct containing the allowed and denied domains
+pub fn build_recipient_policy() -> RecipientPolicy {
+    RecipientPolicy {
+        allow_domains: env_list("RECIPIENT_ALLOW_DOMAINS"),
+        deny_domains: env_list("RECIPIENT_DENY_DOMAINS"),
+    }
+}
+
+/// Builds subject prefix and suffix policy from environment variables
+///
+/// # Environment Variables
+/// - `SUBJECT_PREFIX` - Text placed before every subject (default: none)
+/// - `SUBJECT_SUFFIX` - Text placed after every subject (default: none)
+///
+/// # Returns
+/// A `SubjectPolicy` struct containing the subject policy
+pub fn build_subject_policy() -> SubjectPolicy {
+    let tag = |name: &str| {
+        config_var(name)
+            .ok()
+            .map(|v| v.trim().to_owned())
+            .filter(|v| !v.is_empty())
+    };
+
+    SubjectPolicy {
+        prefix: tag("SUBJECT_PREFIX"),
+        suffix: tag("SUBJECT_SUFFIX"),
+    }
+}
+
+/// Builds the API response redaction policy from environment variables
+///
+/// # Environment Variables
+/// - `RESPONSE_RECIPIENTS` - `list` or `count`, how recipients appear in responses (default: list)
+/// - `RESPONSE_OMIT_FIELDS` - Comma-separated response fields to remove, e.g. `from,to` (default: none)
+///
+/// # Returns
+/// A `ResponsePolicy` struct containing the response redaction policy
+pub fn build_response_policy() -> ResponsePolicy {
+    let recipients = config_var("RESPONSE_RECIPIENTS")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(RecipientDisplay::List);
+
+    ResponsePolicy {
+        recipients,
+        omit_fields: env_list("RESPONSE_OMIT_FIELDS")
+            .into_iter()
+            .filter(|f| f != "status")
+            .collect(),
+    }
+}
+
+/// Builds API key authentication configuration from environment variables
+///
+/// Keys from the variable and from the file are merged. Keys are case-sensitive.
+///
+/// # Environment Variables
+/// - `API_KEYS` - Comma-separated accepted API keys (default: none)
+/// - `API_KEYS_FILE` - File with one accepted API key per line, `#` starts a comment (default: none)
+/// - `ADMIN_API_KEYS` - Comma-separated admin keys, accepted everywhere and required by
+///   admin-only features (default: none)
+/// - `API_KEY_TENANTS` - Comma-separated `tenant:key` pairs naming the tenant charged for
+///   the sends of a key (default: none, every key belongs to the `default` tenant)
+///
+/// # Returns
+/// An `AuthConfig` struct containing the accepted keys
+pub fn build_auth_config() -> AuthConfig {
+    let mut api_keys: Vec<String> = config_var("API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+        .collect();
+
+    if let Ok(path) = config_var("API_KEYS_FILE") {
+        match std::fs::read_to_string(&path) {
+            Ok(content) => api_keys.extend(
+                content
+                    .lines()
+                    .map(str::trim)
+                    .filter(|l| !l.is_empty() && !l.starts_with('#'))
+                    .map(str::to_owned),
+            ),
+            Err(err) => warn!("Cannot read API_KEYS_FILE {}: {}", path, err),
+        }
+    }
+
+    let admin_keys = config_var("ADMIN_API_KEYS")
+        .unwrap_or_default()
+        .split(',')
+        .map(|v| v.trim().to_owned())
+        .filter(|v| !v.is_empty())
+        .collect();
+
+    let mut tenants = HashMap::new();
+    for entry in config_var("API_KEY_TENANTS")
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|v| !v.is_empty())
+    {
+        match entry.split_once(':') {
+            Some((tenant, key)) if !tenant.trim().is_empty() && !key.trim().is_empty() => {
+                tenants.insert(key.trim().to_owned(), tenant.trim().to_owned());
+            }
+            _ => warn!("API_KEY_TENANTS entry ignored: expected tenant:key"),
+        }
+    }
+
+    AuthConfig {
+        api_keys,
+        admin_keys,
+        tenants,
+    }
+}
+
+/// Builds per-request SMTP override configuration from environment variables
+///
+/// # Environment Variables
+/// - `SMTP_OVERRIDE_ENABLED` - Let admin callers give the relay inline in `/send` requests (default: false)
+///
+/// # Returns
+/// A `SmtpOverrideConfig` struct containing the override configuration
+pub fn build_smtp_override_config() -> SmtpOverrideConfig {
+    let enabled = config_var("SMTP_OVERRIDE_ENABLED")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    SmtpOverrideConfig { enabled }
+}
+
+/// Builds dark mode configuration of HTML bodies from environment variables
+///
+/// # Environment Variables
+/// - `HTML_DARK_MODE` - Declare light and dark color scheme support in HTML bodies (default: false)
+/// - `HTML_DARK_MODE_BACKGROUND` - Body background color in dark mode, e.g. `#121212` (default: none)
+/// - `HTML_DARK_MODE_TEXT` - Body text color in dark mode, e.g. `#e8e8e8` (default: none)
+///
+/// # Returns
+/// A `DarkModeConfig` struct containing the dark mode configuration
+pub fn build_dark_mode_config() -> DarkModeConfig {
+    let enabled = config_var("HTML_DARK_MODE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    // Colors end up in a style block: refuse anything that could close it
+    let color = |name: &str| {
+        let value = config_var(name).ok().map(|v| v.trim().to_owned())?;
+        if value.is_empty() {
+            return None;
+        }
+        if value.contains(['<', '>', '{', '}', ';']) {
+            warn!("{} ignored: {:?} is not a CSS color", name, value);
+            return None;
+        }
+        Some(value)
+    };
+
+    DarkModeConfig {
+        enabled,
+        background: color("HTML_DARK_MODE_BACKGROUND"),
+        text: color("HTML_DARK_MODE_TEXT"),
+    }
+}
+
+/// Builds body language and charset detection configuration from environment variables
+///
+/// # Environment Variables
+/// - `CONTENT_DETECTION` - Set `Content-Language` from the detected body language and
+///   declare `us-ascii` for ASCII-only text parts (default: true)
+///
+/// # Returns
+/// A `ContentDetectionConfig` struct containing the detection configuration
+pub fn build_content_detection_config() -> ContentDetectionConfig {
+    let enabled = config_var("CONTENT_DETECTION")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
+
+    ContentDetectionConfig { enabled }
+}
+
+/// Builds sender domain alignment check configuration from environment variables
+///
+/// # Environment Variables
+/// - `ALIGNMENT_CHECK` - Reject senders that would fail DMARC alignment (default: false)
+/// - `SMTP_SPF_DOMAINS` - Comma-separated domains whose SPF record authorizes the default relay (default: none)
+/// - `SMTP_<NAME>_SPF_DOMAINS` - Same for the relay of each profile of `SMTP_PROFILES`
+///
+/// # Returns
+/// An `AlignmentConfig` struct containing the alignment check configuration
+pub fn build_alignment_config() -> AlignmentConfig {
+    let enabled = config_var("ALIGNMENT_CHECK")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    let mut spf_domains = HashMap::new();
+    spf_domains.insert(
+        DEFAULT_SMTP_PROFILE.to_owned(),
+        env_list("SMTP_SPF_DOMAINS"),
+    );
+    for name in env_list("SMTP_PROFILES") {
+        if name == DEFAULT_SMTP_PROFILE {
+            continue;
+        }
+        let var = format!("SMTP_{}_SPF_DOMAINS", name.to_uppercase().replace('-', "_"));
+        spf_domains.insert(name, env_list(&var));
+    }
+
+    AlignmentConfig {
+        enabled,
+        spf_domains,
+    }
+}
+
+/// Builds sending cost accounting configuration from environment variables
+///
+/// # Environment Variables
+/// - `COST_CURRENCY` - Currency of the rates, reported with the costs (default: USD)
+/// - `SMTP_COST_PER_RECIPIENT` - Price of one recipient delivered through the default profile (default: 0)
+/// - `SMTP_<NAME>_COST_PER_RECIPIENT` - Same for each profile of `SMTP_PROFILES`
+///
+/// # Returns
+/// A `CostConfig` struct containing the rates of the profiles
+pub fn build_cost_config() -> CostConfig {
+    let currency = config_var("COST_CURRENCY")
+        .ok()
+        .map(|v| v.trim().to_uppercase())
+        .filter(|v| !v.is_empty())
+        .unwrap_or_else(|| DEFAULT_COST_CURRENCY.to_owned());
+
+    let rate = |var: &str| match config_var(var).ok().map(|v| v.trim().parse::<f64>()) {
+        None => 0.0,
+        Some(Ok(rate)) if rate.is_finite() && rate >= 0.0 => rate,
+        Some(_) => {
+            warn!("{} ignored: expected a non-negative number", var);
+            0.0
+        }
+    };
 
-    /// Number of worker threads
-    pub workers: usize,
+    let mut rates = HashMap::new();
+    rates.insert(
+        DEFAULT_SMTP_PROFILE.to_owned(),
+        rate("SMTP_COST_PER_RECIPIENT"),
+    );
+    for name in env_list("SMTP_PROFILES") {
+        if name == DEFAULT_SMTP_PROFILE {
+            continue;
+        }
+        let var = format!(
+            "SMTP_{}_COST_PER_RECIPIENT",
+            name.to_uppercase().replace('-', "_")
+        );
+        rates.insert(name, rate(&var));
+    }
+
+    CostConfig { currency, rates }
 }
 
-/// SMTP server configuration
+/// Builds service manager integration configuration from environment variables
 ///
-/// Contains all settings required to connect and authenticate
-/// with an SMTP server for sending emails.
-#[derive(Clone)]
-pub struct SmtpConfig {
-    /// SMTP server hostname or IP address
-    pub host: String,
+/// # Environment Variables
+/// - `DAEMON` - Fork into the background once the service is listening (default: false)
+/// - `DAEMON_PID_FILE` - File the PID of the service is written to (default: none)
+/// - `DAEMON_USER` - Account the service switches to after binding its socket (default: none)
+/// - `DAEMON_GROUP` - Group the service switches to (default: the primary group of `DAEMON_USER`)
+///
+/// # Returns
+/// A `DaemonConfig` struct containing the daemon configuration
+pub fn build_daemon_config() -> DaemonConfig {
+    let detach = config_var("DAEMON")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+    let non_empty = |name: &str| config_var(name).ok().filter(|v| !v.is_empty());
 
-    /// SMTP server port (typically 25, 587, or 465)
-    pub port: u16,
+    DaemonConfig {
+        detach,
+        pid_file: non_empty("DAEMON_PID_FILE"),
+        user: non_empty("DAEMON_USER"),
+        group: non_empty("DAEMON_GROUP"),
+    }
+}
+
+/// Builds delivery outcome webhook configuration from environment variables
+///
+/// # Environment Variables
+/// - `WEBHOOK_SECRET` - Key signing the callback events (default: none, callbacks disabled)
+/// - `WEBHOOK_TIMEOUT_SECS` - Timeout of one callback request in seconds (default: 10)
+/// - `WEBHOOK_MAX_ATTEMPTS` - Callback requests made for one event before giving up (default: 5)
+/// - `WEBHOOK_RETRY_BASE_SECS` - Delay before the first retry, doubled after each failure (default: 10)
+///
+/// # Returns
+/// A `WebhookConfig` struct containing the webhook configuration
+pub fn build_webhook_config() -> WebhookConfig {
+    let secret = config_var("WEBHOOK_SECRET").ok().filter(|s| !s.is_empty());
+    let timeout_secs = config_var("WEBHOOK_TIMEOUT_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_WEBHOOK_TIMEOUT_SECS);
+    let max_attempts = config_var("WEBHOOK_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_WEBHOOK_MAX_ATTEMPTS);
+    let retry_base_secs = config_var("WEBHOOK_RETRY_BASE_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_WEBHOOK_RETRY_BASE_SECS);
 
-    /// Optional SMTP authentication username
-    pub username: Option<String>,
+    WebhookConfig {
+        secret,
+        timeout: Duration::from_secs(timeout_secs),
+        max_attempts,
+        retry_base: Duration::from_secs(retry_base_secs),
+    }
+}
 
-    /// Optional SMTP authentication password
-    pub password: Option<String>,
+/// Builds SMTP circuit breaker configuration from environment variables
+///
+/// # Environment Variables
+/// - `CIRCUIT_BREAKER_THRESHOLD` - Failed attempts in a row that open the circuit of a profile
+///   (default: 0, disabled)
+/// - `CIRCUIT_BREAKER_COOLDOWN_SECS` - Time an open circuit fails fast before a trial send (default: 30)
+/// - `CIRCUIT_BREAKER_QUEUE` - Queue direct sends instead of failing them while the circuit is open
+///   (default: false)
+///
+/// # Returns
+/// A `CircuitBreakerConfig` struct containing the circuit breaker configuration
+pub fn build_circuit_breaker_config() -> CircuitBreakerConfig {
+    let threshold = config_var("CIRCUIT_BREAKER_THRESHOLD")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0);
+    let cooldown_secs = config_var("CIRCUIT_BREAKER_COOLDOWN_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_CIRCUIT_COOLDOWN_SECS);
+    let queue = config_var("CIRCUIT_BREAKER_QUEUE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
 
-    /// Whether to use TLS/STARTTLS for secure connection
-    pub use_tls: bool,
+    CircuitBreakerConfig {
+        threshold,
+        cooldown: Duration::from_secs(cooldown_secs),
+        queue,
+    }
 }
 
-/// API response status enumeration
+/// Builds load shedding configuration from environment variables
 ///
-/// Represents the status of an API operation using JSend-style conventions.
-#[derive(Serialize)]
-#[serde(rename_all = "lowercase")]
-pub enum Status {
-    /// Successful operation
-    Ok,
+/// # Environment Variables
+/// - `SHED_QUEUE_DEPTH` - Queue depth from which bulk and low priority mail is shed (default: 0, not checked)
+/// - `SHED_RELAY_FAILURES` - Consecutive failed relay attempts from which bulk and low priority
+///   mail is shed (default: 0, not checked)
+/// - `SHED_ACTION` - `reject` (HTTP 503) or `defer` (queue the mail) (default: reject)
+/// - `SHED_RETRY_AFTER_SECS` - `Retry-After` of rejected requests in seconds (default: 60)
+///
+/// # Returns
+/// A `LoadSheddingConfig` struct containing the load shedding policy
+pub fn build_load_shedding_config() -> LoadSheddingConfig {
+    let max_queue_depth = config_var("SHED_QUEUE_DEPTH")
+        .ok()
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v > 0);
+    let max_relay_failures = config_var("SHED_RELAY_FAILURES")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0);
 
-    /// Failed operation (client error)
-    Fail,
+    let action = config_var("SHED_ACTION")
+        .ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(ShedAction::Reject);
 
-    /// Error operation (server error)
-    Error,
+    let retry_after_secs = config_var("SHED_RETRY_AFTER_SECS")
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(DEFAULT_SHED_RETRY_AFTER_SECS);
+
+    LoadSheddingConfig {
+        max_queue_depth,
+        max_relay_failures,
+        action,
+        retry_after: Duration::from_secs(retry_after_secs),
+    }
 }
 
-/// Standard JSON response structure
+/// Builds rate limiting configuration from environment variables
 ///
-/// Provides a consistent response format for all API endpoints.
-#[derive(Serialize)]
-pub struct RustMailRes {
-    /// Response status (ok, fail, or error)
-    pub status: Status,
+/// # Environment Variables
+/// - `RATE_LIMIT_PER_MINUTE` - Send requests allowed per minute and client (default: 0, disabled)
+/// - `RATE_LIMIT_BURST` - Requests a client may send at once before being throttled (default: the per-minute rate)
+///
+/// # Returns
+/// A `RateLimitConfig` struct containing the rate limiting configuration
+pub fn build_rate_limit_config() -> RateLimitConfig {
+    let per_minute = config_var("RATE_LIMIT_PER_MINUTE")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .unwrap_or(0);
+    let burst = config_var("RATE_LIMIT_BURST")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(per_minute);
 
-    /// Human-readable message describing the result
-    pub message: String,
+    RateLimitConfig { per_minute, burst }
 }
 
-/// Initializes the logger with environment variable configuration
+/// Builds template rendering configuration from environment variables
 ///
-/// Uses `RUST_LOG` environment variable, defaults to `debug` level.
-/// This should be called once at application startup.
-pub fn init_logger() {
-    // Initialize the env_logger with default debug level
-    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("debug")).init();
+/// # Environment Variables
+/// - `TEMPLATES_DIR` - Directory of the email templates (default: none, templates disabled)
+/// - `TEMPLATES_INLINE_CSS` - Inline the `<style>` rules of rendered HTML bodies, unless
+///   the `template.toml` of a template sets `inline_css` (default: false)
+///
+/// # Returns
+/// A `TemplateConfig` struct containing the template configuration
+pub fn build_template_config() -> TemplateConfig {
+    let inline_css = config_var("TEMPLATES_INLINE_CSS")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(false);
+
+    TemplateConfig {
+        dir: config_var("TEMPLATES_DIR").ok().filter(|v| !v.is_empty()),
+        inline_css,
+    }
 }
 
-/// Builds server bind configuration from environment variables
+/// Builds persistent queue configuration from environment variables
 ///
 /// # Environment Variables
-/// - `BIND_ADDR` - Server bind address (default: 0.0.0.0)
-/// - `BIND_PORT` - Server port (default: 3333)
-/// - `BIND_WORKERS` - Number of worker threads (default: number of CPU cores)
+/// - `QUEUE_DB_PATH` - SQLite database file (default: rustmail.db)
+/// - `QUEUE_POLL_SECS` - Pause between polls for due messages (default: 5)
+/// - `QUEUE_BATCH_SIZE` - Messages picked per poll (default: 100)
+/// - `QUEUE_CONCURRENCY` - Deliveries in flight at once (default: 4)
+/// - `QUEUE_MAX_ATTEMPTS` - Attempts before a message is marked failed (default: 8)
+/// - `QUEUE_RETRY_BASE_SECS` - Delay before the first retry, doubled each time (default: 30)
+/// - `QUEUE_RETRY_MAX_SECS` - Maximum retry delay (default: 3600)
+/// - `QUEUE_LEASE_SECS` - Time a message being delivered stays hidden from other processes (default: 300)
+/// - `QUEUE_AUTO_MIGRATE` - Apply pending schema migrations at startup (default: true)
 ///
 /// # Returns
-/// A `ServerBind` struct containing the server configuration
-pub fn build_server_bind() -> ServerBind {
-    // Read server bind address from environment or use default
-    let addr = match env::var("BIND_ADDR") {
-        Ok(v) => v,
-        Err(_) => DEFAULT_ADDRESS.into(),
+/// A `QueueConfig` struct containing the queue configuration
+pub fn build_queue_config() -> QueueConfig {
+    let positive = |name: &str, default: usize| {
+        config_var(name)
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(default)
     };
 
-    // Read server port from environment or use default
-    let port = match env::var("BIND_PORT") {
-        Ok(v) => v.parse::<u16>().unwrap_or(DEFAULT_PORT),
-        Err(_) => DEFAULT_PORT,
-    };
+    let max_attempts = config_var("QUEUE_MAX_ATTEMPTS")
+        .ok()
+        .and_then(|v| v.parse::<u32>().ok())
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_QUEUE_MAX_ATTEMPTS);
 
-    // Get the number of CPU threads, fallback to 1 if unavailable
-    let default_workers = std::thread::available_parallelism()
-        .map(|w| w.get())
-        .unwrap_or(1);
+    let auto_migrate = config_var("QUEUE_AUTO_MIGRATE")
+        .ok()
+        .and_then(|v| v.parse::<bool>().ok())
+        .unwrap_or(true);
 
-    // Use environment variable for workers or fallback to CPU count
-    let workers = env::var("BIND_WORKERS")
+    QueueConfig {
+        db_path: config_var("QUEUE_DB_PATH").unwrap_or_else(|_| DEFAULT_QUEUE_DB_PATH.into()),
+        poll_interval: env_secs("QUEUE_POLL_SECS", DEFAULT_QUEUE_POLL_SECS),
+        batch_size: positive("QUEUE_BATCH_SIZE", DEFAULT_QUEUE_BATCH_SIZE),
+        concurrency: positive("QUEUE_CONCURRENCY", DEFAULT_QUEUE_CONCURRENCY),
+        max_attempts,
+        retry_base: env_secs("QUEUE_RETRY_BASE_SECS", DEFAULT_QUEUE_RETRY_BASE_SECS),
+        retry_max: env_secs("QUEUE_RETRY_MAX_SECS", DEFAULT_QUEUE_RETRY_MAX_SECS),
+        lease: env_secs("QUEUE_LEASE_SECS", DEFAULT_QUEUE_LEASE_SECS),
+        auto_migrate,
+    }
+}
+
+/// Builds application outbox relayer configuration from environment variables
+///
+/// # Environment Variables
+/// - `OUTBOX_DB_PATH` - SQLite database of the application with the `rustmail_outbox` table
+///   (default: none, relayer disabled)
+/// - `OUTBOX_POLL_SECS` - Pause between polls for new rows (default: 5)
+/// - `OUTBOX_BATCH_SIZE` - Rows picked per poll (default: 100)
+///
+/// # Returns
+/// An `OutboxConfig` struct containing the outbox relayer configuration
+pub fn build_outbox_config() -> OutboxConfig {
+    let batch_size = config_var("OUTBOX_BATCH_SIZE")
         .ok()
         .and_then(|v| v.parse::<usize>().ok())
-        .unwrap_or(default_workers);
+        .filter(|v| *v > 0)
+        .unwrap_or(DEFAULT_OUTBOX_BATCH_SIZE);
 
-    ServerBind {
-        addr,
-        port,
-        workers,
+    OutboxConfig {
+        db_path: config_var("OUTBOX_DB_PATH").ok().filter(|v| !v.is_empty()),
+        poll_interval: env_secs("OUTBOX_POLL_SECS", DEFAULT_OUTBOX_POLL_SECS),
+        batch_size,
     }
 }
 
-/// Builds SMTP configuration from environment variables
+/// Reads a number of seconds from an environment variable
+fn env_secs(name: &str, default: u64) -> Duration {
+    let secs = config_var(name)
+        .ok()
+        .and_then(|v| v.parse::<u64>().ok())
+        .unwrap_or(default);
+    Duration::from_secs(secs)
+}
+
+/// Builds deliverability canary configuration from environment variables
 ///
 /// # Environment Variables
-/// - `SMTP_HOST` - SMTP server hostname or IP (default: localhost)
-/// - `SMTP_PORT` - SMTP server port (default: 25)
-/// - `SMTP_USERNAME` - SMTP authentication username (optional)
-/// - `SMTP_PASSWORD` - SMTP authentication password (optional)
-/// - `SMTP_USE_TLS` - Use TLS/STARTTLS (default: false for port 25, true for others)
+/// - `CANARY_SEEDS` - Comma-separated seed names (default: none, canary disabled)
+/// - `CANARY_FROM` - Sender address of the probes (default: rustmail-canary@localhost)
+/// - `CANARY_INTERVAL_SECS` - Pause between probes to the same seed (default: 900)
+/// - `CANARY_TIMEOUT_SECS` - Time after which a probe is reported missing (default: 300)
+/// - `CANARY_POLL_SECS` - Pause between IMAP checks (default: 15)
 ///
-/// # Returns
-/// An `SmtpConfig` struct containing the SMTP configuration
+/// For each seed `NAME`:
+/// - `CANARY_NAME_ADDRESS` - Seed mailbox address (required)
+/// - `CANARY_NAME_IMAP_HOST` - IMAP server hostname, implicit TLS (required)
+/// - `CANARY_NAME_IMAP_PORT` - IMAP server port (default: 993)
+/// - `CANARY_NAME_IMAP_USERNAME` - IMAP username (default: the seed address)
+/// - `CANARY_NAME_IMAP_PASSWORD` - IMAP password
+/// - `CANARY_NAME_SPAM_FOLDER` - Spam folder name (default: Junk)
 ///
-/// # Notes
-/// TLS is automatically enabled for all ports except 25 (plain SMTP) unless
-/// explicitly overridden by the `SMTP_USE_TLS` environment variable.
-pub fn build_smtp_config() -> SmtpConfig {
-    // Read SMTP host from environment or use default
-    let host = env::var("SMTP_HOST").unwrap_or_else(|_| DEFAULT_SMTP_HOST.into());
+/// # Returns
+/// A `CanaryConfig` struct containing the canary configuration
+pub fn build_canary_config() -> CanaryConfig {
+    let mut seeds = Vec::new();
+    for name in env_list("CANARY_SEEDS") {
+        let prefix = format!("CANARY_{}_", name.to_uppercase());
+        let var = |key: &str| config_var(format!("{}{}", prefix, key)).ok();
 
-    // Read SMTP port from environment or use default
-    let port = env::var("SMTP_PORT")
-        .ok()
-        .and_then(|v| v.parse::<u16>().ok())
-        .unwrap_or(DEFAULT_SMTP_PORT);
+        // Seeds without an address or IMAP host cannot be probed
+        let (Some(address), Some(imap_host)) = (var("ADDRESS"), var("IMAP_HOST")) else {
+            warn!("Canary seed {} ignored: address or IMAP host missing", name);
+            continue;
+        };
 
-    // Read optional authentication credentials
-    let username = env::var("SMTP_USERNAME").ok();
-    let password = env::var("SMTP_PASSWORD").ok();
+        seeds.push(CanarySeed {
+            imap_port: var("IMAP_PORT")
+                .and_then(|v| v.parse::<u16>().ok())
+                .unwrap_or(DEFAULT_CANARY_IMAP_PORT),
+            imap_username: var("IMAP_USERNAME").unwrap_or_else(|| address.clone()),
+            imap_password: var("IMAP_PASSWORD").unwrap_or_default(),
+            spam_folder: var("SPAM_FOLDER").unwrap_or_else(|| DEFAULT_CANARY_SPAM_FOLDER.into()),
+            name,
+            address,
+            imap_host,
+        });
+    }
 
-    // Automatically enable TLS for all ports except 25 (plain SMTP)
-    let default_use_tls = port != 25;
-    let use_tls = env::var("SMTP_USE_TLS")
-        .ok()
-        .and_then(|v| v.parse::<bool>().ok())
-        .unwrap_or(default_use_tls);
+    CanaryConfig {
+        seeds,
+        from: config_var("CANARY_FROM").unwrap_or_else(|_| DEFAULT_CANARY_FROM.into()),
+        interval: env_secs("CANARY_INTERVAL_SECS", DEFAULT_CANARY_INTERVAL_SECS),
+        timeout: env_secs("CANARY_TIMEOUT_SECS", DEFAULT_CANARY_TIMEOUT_SECS),
+        poll_interval: env_secs("CANARY_POLL_SECS", DEFAULT_CANARY_POLL_SECS),
+    }
+}
 
-    SmtpConfig {
-        host,
-        port,
-        username,
-        password,
-        use_tls,
+/// Builds attachment conversion configuration from environment variables
+///
+/// # Environment Variables
+/// - `ATTACHMENT_CONVERTERS` - Comma-separated converter names (default: none, conversion disabled)
+/// - `ATTACHMENT_CONVERT_TIMEOUT_SECS` - Maximum run time of one conversion (default: 60)
+/// - `ATTACHMENT_CONVERT_CONCURRENCY` - Maximum converter commands running at once (default: 2)
+///
+/// For each converter `NAME`:
+/// - `CONVERTER_NAME_COMMAND` - Program and arguments, split on whitespace (required)
+/// - `CONVERTER_NAME_CONTENT_TYPE` - MIME type of the converted file (required)
+/// - `CONVERTER_NAME_EXTENSION` - Extension of the converted file (default: the converter name)
+///
+/// # Returns
+/// A `ConvertConfig` struct containing the attachment conversion configuration
+pub fn build_convert_config() -> ConvertConfig {
+    let mut converters = Vec::new();
+    for name in env_list("ATTACHMENT_CONVERTERS") {
+        let prefix = format!("CONVERTER_{}_", name.to_uppercase().replace('-', "_"));
+        let var = |key: &str| config_var(format!("{}{}", prefix, key)).ok();
+
+        let command: Vec<String> = var("COMMAND")
+            .unwrap_or_default()
+            .split_whitespace()
+            .map(str::to_owned)
+            .collect();
+        let content_type = var("CONTENT_TYPE").filter(|v| !v.is_empty());
+        // Converters without a command or content type cannot be used
+        let Some(content_type) = content_type.filter(|_| !command.is_empty()) else {
+            warn!(
+                "Attachment converter {} ignored: command or content type missing",
+                name
+            );
+            continue;
+        };
+
+        converters.push(AttachmentConverter {
+            extension: var("EXTENSION").unwrap_or_else(|| name.clone()),
+            name,
+            command,
+            content_type,
+        });
+    }
+
+    ConvertConfig {
+        converters,
+        timeout: env_secs(
+            "ATTACHMENT_CONVERT_TIMEOUT_SECS",
+            DEFAULT_CONVERT_TIMEOUT_SECS,
+        ),
+        concurrency: config_var("ATTACHMENT_CONVERT_CONCURRENCY")
+            .ok()
+            .and_then(|v| v.parse::<usize>().ok())
+            .filter(|v| *v > 0)
+            .unwrap_or(DEFAULT_CONVERT_CONCURRENCY),
     }
 }
 
@@ -196,6 +2858,7 @@ pub fn json_error<E: std::fmt::Display>(err: E) -> actix_web::Error {
     let error_response = RustMailRes {
         status: Status::Error,
         message: err.to_string(),
+        request_id: current_request_id(),
     };
     InternalError::from_response(
         err.to_string(),
@@ -203,3 +2866,392 @@ pub fn json_error<E: std::fmt::Display>(err: E) -> actix_web::Error {
     )
     .into()
 }
+
+/// Converts a client-side failure into an Actix-web JSON fail response
+///
+/// This is the `fail` counterpart of [`json_error`]: it is used when the request
+/// itself is invalid and produces an HTTP 400 status.
+///
+/// # Arguments
+/// * `message` - Any message type that implements `Display`
+///
+/// # Returns
+/// An `actix_web::Error` that produces a JSON response with the fail message
+///
+/// # Response Format
+/// ```json
+/// {
+///   "status": "fail",
+///   "message": "fail description here"
+/// }
+/// ```
+pub fn json_fail<E: std::fmt::Display>(message: E) -> actix_web::Error {
+    let fail_response = RustMailRes {
+        status: Status::Fail,
+        message: message.to_string(),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::BadRequest().json(fail_response),
+    )
+    .into()
+}
+
+/// Converts an authentication failure into an Actix-web JSON fail response
+///
+/// Same body as [`json_fail`], with an HTTP 401 status.
+///
+/// # Arguments
+/// * `message` - Any message type that implements `Display`
+///
+/// # Returns
+/// An `actix_web::Error` that produces a JSON response with the fail message
+pub fn json_unauthorized<E: std::fmt::Display>(message: E) -> actix_web::Error {
+    let fail_response = RustMailRes {
+        status: Status::Fail,
+        message: message.to_string(),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Unauthorized().json(fail_response),
+    )
+    .into()
+}
+
+/// Converts a permission failure into an Actix-web JSON fail response
+///
+/// Same body as [`json_fail`], with an HTTP 403 status.
+///
+/// # Arguments
+/// * `message` - Any message type that implements `Display`
+///
+/// # Returns
+/// An `actix_web::Error` that produces a JSON response with the fail message
+pub fn json_forbidden<E: std::fmt::Display>(message: E) -> actix_web::Error {
+    let fail_response = RustMailRes {
+        status: Status::Fail,
+        message: message.to_string(),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Forbidden().json(fail_response),
+    )
+    .into()
+}
+
+/// Converts a conflicting request into an Actix-web JSON fail response
+///
+/// Same body as [`json_fail`], with an HTTP 409 status.
+///
+/// # Arguments
+/// * `message` - Any message type that implements `Display`
+///
+/// # Returns
+/// An `actix_web::Error` that produces a JSON response with the fail message
+pub fn json_conflict<E: std::fmt::Display>(message: E) -> actix_web::Error {
+    let fail_response = RustMailRes {
+        status: Status::Fail,
+        message: message.to_string(),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::Conflict().json(fail_response),
+    )
+    .into()
+}
+
+/// Converts a missing resource into an Actix-web JSON fail response
+///
+/// Same body as [`json_fail`], with an HTTP 404 status.
+///
+/// # Arguments
+/// * `message` - Any message type that implements `Display`
+///
+/// # Returns
+/// An `actix_web::Error` that produces a JSON response with the fail message
+pub fn json_not_found<E: std::fmt::Display>(message: E) -> actix_web::Error {
+    let fail_response = RustMailRes {
+        status: Status::Fail,
+        message: message.to_string(),
+        request_id: current_request_id(),
+    };
+    InternalError::from_response(
+        message.to_string(),
+        HttpResponse::NotFound().json(fail_response),
+    )
+    .into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Flattens a TOML config file
+    fn flatten_toml(content: &str) -> Result<HashMap<String, String>, String> {
+        let root: BTreeMap<String, ConfigValue> =
+            toml::from_str(content).map_err(|e| e.to_string())?;
+        let mut values = HashMap::new();
+        flatten_config("", root, &mut values)?;
+        Ok(values)
+    }
+
+    /// Flattens and checks a TOML config file
+    fn load_toml(content: &str) -> Result<HashMap<String, String>, String> {
+        check_config(flatten_toml(content)?).map_err(|e| e.to_string())
+    }
+
+    #[test]
+    fn flattens_sections_into_variable_names() {
+        let values = flatten_toml(
+            r#"
+            rust_log = "info"
+            "smtp.tls-mode" = "none"
+
+            [smtp]
+            host = "mail.example.com"
+            port = 2525
+
+            [smtp.oauth2]
+            scope = "mail"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(values["RUST_LOG"], "info");
+        assert_eq!(values["SMTP_TLS_MODE"], "none");
+        assert_eq!(values["SMTP_HOST"], "mail.example.com");
+        assert_eq!(values["SMTP_PORT"], "2525");
+        assert_eq!(values["SMTP_OAUTH2_SCOPE"], "mail");
+    }
+
+    #[test]
+    fn flattens_lists_and_scalars() {
+        let values = flatten_toml(
+            r#"
+            api_keys = ["first", "second"]
+            bind_reuse_port = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(values["API_KEYS"], "first,second");
+        assert_eq!(values["BIND_REUSE_PORT"], "true");
+    }
+
+    #[test]
+    fn flattens_yaml() {
+        let root: BTreeMap<String, ConfigValue> =
+            serde_yaml_ng::from_str("smtp:\n  host: mail.example.com\n  port: 2525\n").unwrap();
+        let mut values = HashMap::new();
+        flatten_config("", root, &mut values).unwrap();
+        assert_eq!(values["SMTP_HOST"], "mail.example.com");
+        assert_eq!(values["SMTP_PORT"], "2525");
+    }
+
+    #[test]
+    fn refuses_a_setting_set_twice() {
+        let err = flatten_toml(
+            r#"
+            smtp_host = "a.example.com"
+
+            [smtp]
+            host = "b.example.com"
+            "#,
+        )
+        .unwrap_err();
+        assert_eq!(err, "SMTP_HOST is set twice");
+    }
+
+    #[test]
+    fn keeps_valid_settings() {
+        let values = load_toml(
+            r#"
+            [smtp]
+            host = "mail.example.com"
+            port = 2525
+            tls_mode = "starttls"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(values.len(), 3);
+        assert_eq!(values["SMTP_HOST"], "mail.example.com");
+        assert_eq!(values["SMTP_PORT"], "2525");
+        assert_eq!(values["SMTP_TLS_MODE"], "starttls");
+    }
+
+    #[test]
+    fn refuses_unknown_settings() {
+        let err = load_toml("smtp_hots = \"mail.example.com\"").unwrap_err();
+        assert_eq!(err, "unknown setting SMTP_HOTS");
+    }
+
+    #[test]
+    fn refuses_values_of_the_wrong_type() {
+        let err = load_toml("smtp_port = \"abc\"").unwrap_err();
+        assert!(
+            err.starts_with("SMTP_PORT: invalid value \"abc\""),
+            "{}",
+            err
+        );
+
+        let err = load_toml("smtp_port = 70000").unwrap_err();
+        assert!(
+            err.starts_with("SMTP_PORT: invalid value \"70000\""),
+            "{}",
+            err
+        );
+
+        let err = load_toml("smtp_tls_mode = \"ssl\"").unwrap_err();
+        assert!(err.contains("expected none, starttls or tls"), "{}", err);
+    }
+
+    #[test]
+    fn checks_profile_settings_by_prefix() {
+        let config = r#"
+            smtp_profiles = "default,marketing"
+
+            [smtp.marketing]
+            host = "marketing.example.com"
+            port = 587
+            "#;
+        let values = load_toml(config).unwrap();
+        assert_eq!(values["SMTP_MARKETING_HOST"], "marketing.example.com");
+        assert_eq!(values["SMTP_MARKETING_PORT"], "587");
+
+        let err = load_toml(&config.replace("587", "\"abc\"")).unwrap_err();
+        assert!(
+            err.starts_with("SMTP_MARKETING_PORT: invalid value \"abc\""),
+            "{}",
+            err
+        );
+
+        let err = load_toml(&config.replace("host =", "hots =")).unwrap_err();
+        assert_eq!(err, "unknown setting SMTP_MARKETING_HOTS");
+    }
+
+    #[test]
+    fn refuses_settings_of_undeclared_profiles() {
+        let err = load_toml("smtp_marketing_host = \"marketing.example.com\"").unwrap_err();
+        assert_eq!(err, "unknown setting SMTP_MARKETING_HOST");
+    }
+
+    #[test]
+    fn checks_converter_settings_by_prefix() {
+        let values = load_toml(
+            r#"
+            attachment_converters = "to-pdf"
+
+            [converter.to_pdf]
+            command = "soffice --convert-to pdf {input}"
+            extension = "pdf"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(values["CONVERTER_TO_PDF_EXTENSION"], "pdf");
+    }
+
+    #[test]
+    fn checks_overrides_against_the_tables() {
+        let tables =
+            SettingTables::new(|list| (list == "SMTP_PROFILES").then(|| "marketing".to_owned()));
+        let check = |name: &str, value: &str| {
+            let check = tables.find(name).expect(name);
+            SettingTables::check(check, name, value).map_err(|e| e.to_string())
+        };
+
+        let err = check("SMTP_MAX_CONNECTIONS", "abc").unwrap_err();
+        assert!(
+            err.starts_with("SMTP_MAX_CONNECTIONS: invalid value \"abc\""),
+            "{}",
+            err
+        );
+        assert_eq!(
+            check("SMTP_MARKETING_MAX_CONNECTIONS", "0").unwrap_err(),
+            "SMTP_MARKETING_MAX_CONNECTIONS: invalid value \"0\" (must be at least 1)"
+        );
+        assert!(check("SMTP_MARKETING_TRANSPORT", "sendgrid").is_ok());
+        assert!(check("BIND_PORT", "").is_ok());
+        assert!(tables.find("SMTP_OTHER_HOST").is_none());
+        assert!(tables.find("PATH").is_none());
+    }
+
+    #[test]
+    fn builders_read_only_listed_settings() {
+        let values = [
+            ("SMTP_PROFILES", "sendgrid,mailgun,ses"),
+            ("SMTP_SENDGRID_TRANSPORT", "sendgrid"),
+            ("SMTP_MAILGUN_TRANSPORT", "mailgun"),
+            ("SMTP_SES_TRANSPORT", "ses"),
+            ("CANARY_SEEDS", "probe"),
+            ("CANARY_PROBE_ADDRESS", "probe@example.com"),
+            ("CANARY_PROBE_IMAP_HOST", "imap.example.com"),
+            ("ATTACHMENT_CONVERTERS", "to-pdf"),
+            ("CONVERTER_TO_PDF_COMMAND", "soffice"),
+            ("CONVERTER_TO_PDF_CONTENT_TYPE", "application/pdf"),
+        ];
+        CONFIG_FILE_VALUES
+            .set(
+                values
+                    .into_iter()
+                    .map(|(name, value)| (name.to_owned(), value.to_owned()))
+                    .collect(),
+            )
+            .unwrap();
+
+        // config_var asserts that each setting read is listed in the tables
+        build_server_bind();
+        build_smtp_config().unwrap();
+        build_smtp_profiles().unwrap();
+        build_transport_config();
+        build_transport_profiles();
+        build_link_check_config();
+        build_fetch_policy();
+        build_fault_config();
+        build_duplicate_guard_config();
+        build_idempotency_config();
+        build_blackhole_config();
+        build_mime_limits();
+        build_dkim_config();
+        build_bulk_limits();
+        build_privacy_config();
+        build_sandbox_config();
+        build_xml_endpoint_config();
+        build_recipient_policy();
+        build_subject_policy();
+        build_response_policy();
+        build_auth_config();
+        build_smtp_override_config();
+        build_dark_mode_config();
+        build_content_detection_config();
+        build_alignment_config();
+        build_cost_config();
+        build_daemon_config();
+        build_webhook_config();
+        build_circuit_breaker_config();
+        build_load_shedding_config();
+        build_rate_limit_config();
+        build_template_config();
+        build_queue_config();
+        build_outbox_config();
+        assert_eq!(build_canary_config().seeds.len(), 1);
+        assert!(build_convert_config().get("to-pdf").is_some());
+    }
+
+    #[test]
+    fn refuses_unsupported_auth_mechanisms() {
+        assert!(mechanisms("PLAIN, login,xoauth2").is_ok());
+        assert_eq!(
+            mechanisms("plain,cram-md5").unwrap_err(),
+            "unsupported mechanism CRAM-MD5, expected PLAIN, LOGIN or XOAUTH2"
+        );
+
+        let err = load_toml("smtp_auth_mechanisms = [\"LOGIN\", \"CRAM-MD5\"]").unwrap_err();
+        assert!(
+            err.starts_with("SMTP_AUTH_MECHANISMS: invalid value \"LOGIN,CRAM-MD5\""),
+            "{}",
+            err
+        );
+    }
+}
@@ -0,0 +1,68 @@
+use std::collections::BTreeMap;
+
+use serde::Deserialize;
+use serde_json::Value;
+use utoipa::ToSchema;
+
+use crate::send::dto::{Attachment, CalendarInvite, InlineImage, Priority, Sender};
+
+/// Request for sending an email rendered from a template
+///
+/// The subject and body come from the template; the remaining fields have the
+/// same meaning as in a `/send` request.
+#[derive(Deserialize, ToSchema)]
+pub struct SendTemplateReq {
+    /// Template name, i.e. its sub-directory in `TEMPLATES_DIR`
+    pub template: String,
+
+    /// JSON object with the variables available to the template
+    #[serde(default)]
+    pub context: Value,
+
+    /// Sender email address or `{ "address", "name" }` object
+    pub from: Sender,
+
+    /// Optional address replies should be sent to instead of the sender
+    pub reply_to: Option<String>,
+
+    /// List of recipient email addresses
+    pub to: Vec<String>,
+
+    /// Optional preview text shown by inbox clients next to the subject (HTML bodies only)
+    pub preheader: Option<String>,
+
+    /// Files attached to the email
+    #[serde(default)]
+    pub attachments: Vec<Attachment>,
+
+    /// Images embedded in the rendered HTML body, referenced with `cid:` URLs
+    #[serde(default)]
+    pub inline_images: Vec<InlineImage>,
+
+    /// Optional meeting invitation, sent as a `text/calendar` alternative of the body
+    pub calendar: Option<CalendarInvite>,
+
+    /// Extra headers by name; values cannot contain line breaks
+    #[serde(default)]
+    pub headers: BTreeMap<String, String>,
+
+    /// Optional importance ("high", "normal" or "low")
+    pub priority: Option<Priority>,
+
+    /// Queue the email for background delivery (with retries) instead of sending it now
+    #[serde(default)]
+    pub queue: bool,
+
+    /// SMTP profile to send through (see `SMTP_PROFILES`); the default profile when absent
+    pub profile: Option<String>,
+
+    /// Delivery time (RFC3339, e.g. "2026-01-31T09:00:00Z"); a future time schedules the email
+    pub send_at: Option<String>,
+
+    /// URL notified of the delivery outcome (see `WEBHOOK_SECRET`); queued or scheduled emails only
+    pub callback_url: Option<String>,
+
+    /// Validate and build the email without sending or queueing it
+    #[serde(default)]
+    pub dry_run: bool,
+}
@@ -0,0 +1,68 @@
+//! Template request rendering
+//!
+//! This module turns a template request into a regular email request that can
+//! go through the send pipeline.
+
+use actix_web::{Result, web};
+
+use crate::send::dto::{SendMailPayload, SendMailReq};
+use crate::settings::{json_error, json_fail};
+use crate::template::dto::SendTemplateReq;
+use crate::template::registry::TemplateRegistry;
+
+/// Renders a template request into an email request
+///
+/// A template without plain text part is sent as a single HTML part.
+///
+/// # Arguments
+/// * `templates` - Template registry
+/// * `payload` - Template name, context and envelope
+///
+/// # Returns
+/// * `Ok(SendMailReq)` - The email request with rendered subject and body
+/// * `Err(actix_web::Error)` - JSON fail response when the template is unknown or rendering fails
+pub async fn render_mail(
+    templates: web::Data<TemplateRegistry>,
+    payload: SendTemplateReq,
+) -> Result<SendMailReq> {
+    let name = payload.template;
+    let context = payload.context;
+    let rendered = web::block(move || templates.render(&name, &context))
+        .await
+        .map_err(json_error)?
+        .map_err(json_fail)?;
+
+    let (text, html, content_type) = match (rendered.text, rendered.html) {
+        (Some(text), html) => (text, html, "plain"),
+        (None, Some(html)) => (html, None, "html"),
+        (None, None) => (String::new(), None, "plain"),
+    };
+
+    Ok(SendMailReq {
+        mail: SendMailPayload {
+            from: payload.from,
+            reply_to: payload.reply_to,
+            to: payload.to,
+            subject: rendered.subject,
+            text,
+            html,
+            encoding: "plain".to_owned(),
+            content_type: content_type.to_owned(),
+            preheader: payload.preheader,
+            attachments: payload.attachments,
+            inline_images: payload.inline_images,
+            calendar: payload.calendar,
+            headers: payload.headers,
+            priority: payload.priority,
+        },
+        queue: payload.queue,
+        profile: payload.profile,
+        send_at: payload.send_at,
+        callback_url: payload.callback_url,
+        dry_run: payload.dry_run,
+        smtp: None,
+        fault: None,
+        idempotency_key: None,
+        tenant: None,
+    })
+}
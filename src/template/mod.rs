@@ -0,0 +1,16 @@
+//! Template rendering module
+//!
+//! This module renders the subject and body of an email from a named Tera
+//! template and a JSON context, then hands the result to the send pipeline.
+
+/// Data transfer objects for template requests
+pub mod dto;
+
+/// Template request rendering
+pub mod mail;
+
+/// Template loading and rendering
+pub mod registry;
+
+/// HTTP controllers for template endpoints
+pub mod template_controller;
@@ -0,0 +1,264 @@
+//! Template loading and rendering
+//!
+//! Templates live in `TEMPLATES_DIR`, one sub-directory per template:
+//! `subject.txt` is required, `text.txt` and `html.html` provide the body parts
+//! and `template.toml` holds the options of the template.
+//! The directory is checked for changes before every render, so edited files
+//! are picked up without restarting the service.
+
+use std::collections::HashMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::SystemTime;
+
+use css_inline::CSSInliner;
+use log::{info, warn};
+use serde::Deserialize;
+use serde_json::Value;
+use tera::{Context, Tera};
+
+/// Template file holding the subject line
+const SUBJECT_FILE: &str = "subject.txt";
+
+/// Template file holding the plain text body
+const TEXT_FILE: &str = "text.txt";
+
+/// Template file holding the HTML body (auto-escaped)
+const HTML_FILE: &str = "html.html";
+
+/// Template file holding the template options (not rendered)
+const OPTIONS_FILE: &str = "template.toml";
+
+/// Snapshot of the template directory: number of files and latest modification time
+type DirStamp = (usize, Option<SystemTime>);
+
+/// Rendered subject and body parts of a template
+pub struct RenderedTemplate {
+    /// Rendered subject line
+    pub subject: String,
+
+    /// Rendered plain text body, when the template has one
+    pub text: Option<String>,
+
+    /// Rendered HTML body, when the template has one
+    pub html: Option<String>,
+}
+
+/// Options of a template, read from its `template.toml`
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TemplateOptions {
+    /// Move the rules of `<style>` blocks into `style` attributes of the HTML body
+    inline_css: Option<bool>,
+}
+
+/// Compiled templates together with the directory state they were loaded from
+struct Loaded {
+    tera: Tera,
+    options: HashMap<String, TemplateOptions>,
+    stamp: DirStamp,
+}
+
+/// Registry of the email templates found in the templates directory
+pub struct TemplateRegistry {
+    dir: PathBuf,
+    inline_css: bool,
+    loaded: RwLock<Loaded>,
+}
+
+/// Walks a directory recursively and returns its stamp
+fn dir_stamp(dir: &Path) -> DirStamp {
+    let mut stamp: DirStamp = (0, None);
+    let Ok(entries) = fs::read_dir(dir) else {
+        return stamp;
+    };
+    for entry in entries.flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            let (count, modified) = dir_stamp(&path);
+            stamp.0 += count;
+            stamp.1 = stamp.1.max(modified);
+        } else if let Ok(meta) = entry.metadata() {
+            stamp.0 += 1;
+            stamp.1 = stamp.1.max(meta.modified().ok());
+        }
+    }
+    stamp
+}
+
+/// Returns the glob matching every file of the templates directory
+fn glob_of(dir: &Path) -> String {
+    format!("{}/**/*", dir.display())
+}
+
+/// Reads the options of every template that has a `template.toml`
+///
+/// # Returns
+/// The options by template name, or the error of the first invalid file
+fn load_options(dir: &Path) -> Result<HashMap<String, TemplateOptions>, String> {
+    let mut options = HashMap::new();
+    let Ok(entries) = fs::read_dir(dir) else {
+        return Ok(options);
+    };
+    for entry in entries.flatten() {
+        let path = entry.path().join(OPTIONS_FILE);
+        if !path.is_file() {
+            continue;
+        }
+        let content = fs::read_to_string(&path)
+            .map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        let parsed = toml::from_str(&content)
+            .map_err(|e| format!("Invalid {}: {}", path.display(), e.to_string().trim_end()))?;
+        options.insert(entry.file_name().to_string_lossy().into_owned(), parsed);
+    }
+    Ok(options)
+}
+
+/// Moves the rules of the `<style>` blocks of an HTML body into `style` attributes
+///
+/// At-rules such as `@media` queries cannot be inlined and stay in a `<style>`
+/// block; remote stylesheets are never fetched.
+fn inline_css(html: &str) -> Result<String, String> {
+    CSSInliner::options()
+        .load_remote_stylesheets(false)
+        .keep_at_rules(true)
+        .build()
+        .inline(html)
+        .map_err(|e| e.to_string())
+}
+
+/// Formats a Tera error with all of its causes
+///
+/// Tera reports the actual problem (missing variable, syntax error) as the
+/// source of a generic "failed to render" error.
+fn describe(err: &tera::Error) -> String {
+    let mut message = err.to_string();
+    let mut source = err.source();
+    while let Some(cause) = source {
+        message.push_str(": ");
+        message.push_str(&cause.to_string());
+        source = cause.source();
+    }
+    message
+}
+
+impl TemplateRegistry {
+    /// Loads every template found in a directory
+    ///
+    /// # Arguments
+    /// * `dir` - The templates directory
+    /// * `inline_css` - Whether HTML bodies get their CSS inlined, unless a
+    ///   template's `template.toml` says otherwise
+    ///
+    /// # Returns
+    /// The registry, or the error of the first template or options file that fails to parse
+    pub fn open(dir: &str, inline_css: bool) -> Result<Self, String> {
+        let dir = PathBuf::from(dir);
+        let stamp = dir_stamp(&dir);
+        let tera = Tera::new(&glob_of(&dir)).map_err(|e| describe(&e))?;
+        let options = load_options(&dir)?;
+        info!(
+            "Loaded {} template file(s)",
+            tera.get_template_names().count()
+        );
+
+        Ok(Self {
+            dir,
+            inline_css,
+            loaded: RwLock::new(Loaded {
+                tera,
+                options,
+                stamp,
+            }),
+        })
+    }
+
+    /// Reloads the templates when files were added, removed or modified
+    ///
+    /// A reload that fails is logged and the previously loaded templates are kept.
+    fn refresh(&self) {
+        let stamp = dir_stamp(&self.dir);
+        if self.loaded.read().unwrap_or_else(|e| e.into_inner()).stamp == stamp {
+            return;
+        }
+
+        let mut loaded = self.loaded.write().unwrap_or_else(|e| e.into_inner());
+        if loaded.stamp == stamp {
+            return;
+        }
+        loaded.stamp = stamp;
+        let reloaded = Tera::new(&glob_of(&self.dir))
+            .map_err(|e| describe(&e))
+            .and_then(|tera| Ok((tera, load_options(&self.dir)?)));
+        match reloaded {
+            Ok((tera, options)) => {
+                loaded.tera = tera;
+                loaded.options = options;
+                info!("Templates reloaded from {}", self.dir.display());
+            }
+            Err(err) => warn!(
+                "Template reload failed, keeping previous templates: {}",
+                err
+            ),
+        }
+    }
+
+    /// Renders the subject and body parts of a template
+    ///
+    /// # Arguments
+    /// * `name` - Template name, i.e. its sub-directory in the templates directory
+    /// * `context` - JSON object with the template variables (`null` for none)
+    ///
+    /// # Returns
+    /// * `Ok(RenderedTemplate)` - The rendered subject and body parts
+    /// * `Err(String)` - Unknown template, invalid context or rendering failure
+    pub fn render(&self, name: &str, context: &Value) -> Result<RenderedTemplate, String> {
+        self.refresh();
+
+        let context = match context {
+            Value::Null => Context::new(),
+            v => Context::from_value(v.clone()).map_err(|e| describe(&e))?,
+        };
+
+        let loaded = self.loaded.read().unwrap_or_else(|e| e.into_inner());
+        let part = |file: &str| {
+            let template = format!("{}/{}", name, file);
+            if !loaded.tera.get_template_names().any(|t| t == template) {
+                return Ok(None);
+            }
+            loaded
+                .tera
+                .render(&template, &context)
+                .map(Some)
+                .map_err(|e| describe(&e))
+        };
+
+        let subject = part(SUBJECT_FILE)?.ok_or_else(|| format!("Template {} not found", name))?;
+        let text = part(TEXT_FILE)?;
+        let mut html = part(HTML_FILE)?;
+        if text.is_none() && html.is_none() {
+            return Err(format!("Template {} has no body", name));
+        }
+
+        // Many mail clients strip `<style>` blocks, inline styles survive
+        let inline = loaded
+            .options
+            .get(name)
+            .and_then(|o| o.inline_css)
+            .unwrap_or(self.inline_css);
+        if inline && let Some(body) = &html {
+            html = Some(
+                inline_css(body)
+                    .map_err(|e| format!("CSS inlining of template {} failed: {}", name, e))?,
+            );
+        }
+
+        Ok(RenderedTemplate {
+            subject: subject.trim().to_owned(),
+            text,
+            html,
+        })
+    }
+}
@@ -0,0 +1,79 @@
+//! HTTP controllers for template endpoints
+//!
+//! This module renders a template and sends the resulting email.
+
+use crate::codec::Payload;
+use crate::send::context::SendContext;
+use crate::send::dto::QueuedRes;
+use crate::send::send_controller::send_mail;
+use crate::settings::{RustMailRes, json_fail};
+use crate::template::dto::SendTemplateReq;
+use crate::template::mail::render_mail;
+use crate::template::registry::TemplateRegistry;
+use actix_web::{HttpRequest, HttpResponse, Result, post, web};
+use log::info;
+use utoipa::OpenApi;
+
+/// POST endpoint for sending an email rendered from a template
+///
+/// Renders the subject, plain text and HTML parts of the named template with the
+/// given context, then sends the email exactly like `/send` (limits, link
+/// validation, duplicate guard, queueing).
+///
+/// # Arguments
+/// * `req` - HTTP request, used to negotiate the response format
+/// * `body` - JSON or MessagePack payload with template name, context and envelope
+/// * `templates` - Template registry, missing when `TEMPLATES_DIR` is not set
+/// * `ctx` - Shared send state injected by Actix
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - Same response as `/send`
+/// * `Err(actix_web::Error)` - JSON fail response when templates are disabled, the
+///   template is unknown or rendering fails; JSON error response on send failure
+#[utoipa::path(
+    post,
+    path = "/send/template",
+    tag = "template",
+    request_body = SendTemplateReq,
+    responses(
+        (status = 200, description = "Same responses as `/send`", body = RustMailRes),
+        (status = 202, description = "Email queued or scheduled", body = QueuedRes),
+        (status = 400, description = "Templates not configured, unknown template or rendering failure", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 429, description = "Rate limit exceeded", body = RustMailRes),
+        (status = 500, description = "SMTP or internal error", body = RustMailRes),
+        (status = 503, description = "Service overloaded, low priority mail shed", body = RustMailRes)
+    )
+)]
+#[post("send/template")]
+async fn send_template(
+    req: HttpRequest,
+    body: Payload<SendTemplateReq>,
+    templates: Option<web::Data<TemplateRegistry>>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    let Some(templates) = templates else {
+        return Err(json_fail("Templates are not configured (TEMPLATES_DIR)"));
+    };
+
+    let payload = body.into_inner();
+    info!("send template {} request", payload.template);
+
+    let mail = render_mail(templates, payload).await?;
+    send_mail(&req, mail, &ctx).await
+}
+
+/// OpenAPI description of the endpoints of this module
+#[derive(OpenApi)]
+#[openapi(paths(send_template))]
+pub struct ApiDoc;
+
+/// Configures the Actix-web service routes
+///
+/// Registers all HTTP endpoints for this module with the application.
+///
+/// # Arguments
+/// * `cfg` - Actix-web service configuration to register routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(send_template);
+}
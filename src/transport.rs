@@ -0,0 +1,764 @@
+//! Mail transport module
+//!
+//! This module builds the asynchronous transports shared by all requests,
+//! behind the [`MailTransport`] trait. The SMTP transport keeps a pool of
+//! connections to the relay so requests reuse established (TLS) sessions, and
+//! a semaphore shared by the send handlers and the queue worker bounds the
+//! sends in flight, hence the connections open, to the configured maximum.
+//! Each named SMTP profile gets its own transport. Profiles can deliver through the local `sendmail` command or an
+//! HTTP API provider instead, on hosts without SMTP egress.
+//!
+//! Profiles authenticating with OAuth2 get their transport rebuilt with a fresh
+//! access token shortly before the current one expires; connections opened
+//! afterwards use the new token. When the circuit breaker is enabled, each
+//! profile is guarded by its own breaker.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, Weak};
+use std::time::Duration;
+
+use actix_web::rt::{self, time::sleep};
+use futures_util::future::BoxFuture;
+use lettre::address::Envelope;
+use lettre::transport::smtp::PoolConfig;
+use lettre::transport::smtp::authentication::{Credentials, Mechanism};
+use lettre::transport::smtp::client::{Certificate, Tls, TlsParameters};
+use lettre::transport::smtp::extension::ClientId;
+use lettre::transport::smtp::response::Code;
+use lettre::transport::{sendmail, smtp};
+use lettre::{AsyncSendmailTransport, AsyncSmtpTransport, AsyncTransport, Message, Tokio1Executor};
+use log::{error, info, warn};
+use tokio::sync::Semaphore;
+
+use crate::circuit_breaker::{BreakerTransport, CircuitBreaker, CircuitSnapshot};
+use crate::oauth2::refresh_access_token;
+use crate::provider::{mailgun::MailgunTransport, sendgrid::SendGridTransport, ses::SesTransport};
+use crate::settings::{
+    BlackholeConfig, CircuitBreakerConfig, DEFAULT_SMTP_PROFILE, OAuth2Config, PrivacyConfig,
+    SmtpAuth, SmtpConfig, TlsMode, TransportConfig, TransportKind,
+};
+
+/// Time before expiry at which an access token is renewed
+const TOKEN_REFRESH_MARGIN: Duration = Duration::from_secs(300);
+
+/// Pause before retrying a failed access token renewal
+const TOKEN_RETRY_DELAY: Duration = Duration::from_secs(30);
+
+/// Asynchronous pooled SMTP transport running on the Tokio runtime
+type SmtpTransport = AsyncSmtpTransport<Tokio1Executor>;
+
+/// Asynchronous sendmail transport running on the Tokio runtime
+type SendmailTransport = AsyncSendmailTransport<Tokio1Executor>;
+
+/// Error returned by a mail transport
+#[derive(Debug)]
+pub enum TransportError {
+    /// SMTP error: connection, TLS, authentication or server reply
+    Smtp(smtp::Error),
+
+    /// The sendmail command could not be run or exited with an error
+    Sendmail(sendmail::Error),
+
+    /// HTTP API error, with the response status when the provider answered
+    Http {
+        status: Option<u16>,
+        message: String,
+    },
+
+    /// The circuit of the profile is open, the send failed fast
+    CircuitOpen { profile: String, retry_in: Duration },
+}
+
+impl TransportError {
+    /// Returns `true` for a permanent rejection (5xx reply) that must not be retried
+    ///
+    /// Sendmail failures carry no reply code and are never permanent. HTTP API
+    /// refusals are permanent for 4xx statuses other than timeouts and rate limits.
+    pub fn is_permanent(&self) -> bool {
+        match self {
+            TransportError::Smtp(err) => err.is_permanent(),
+            TransportError::Sendmail(_) | TransportError::CircuitOpen { .. } => false,
+            TransportError::Http { status, .. } => {
+                status.is_some_and(|s| (400..500).contains(&s) && s != 408 && s != 429)
+            }
+        }
+    }
+
+    /// Returns `true` for a transient rejection (4xx reply, HTTP 429 or 5xx, open circuit)
+    pub fn is_transient(&self) -> bool {
+        match self {
+            TransportError::Smtp(err) => err.is_transient(),
+            TransportError::Sendmail(_) => false,
+            TransportError::CircuitOpen { .. } => true,
+            TransportError::Http { status, .. } => status.is_some_and(|s| s == 429 || s >= 500),
+        }
+    }
+
+    /// Returns the reply code of the SMTP server, if it answered
+    pub fn status(&self) -> Option<Code> {
+        match self {
+            TransportError::Smtp(err) => err.status(),
+            TransportError::Sendmail(_)
+            | TransportError::Http { .. }
+            | TransportError::CircuitOpen { .. } => None,
+        }
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::Smtp(err) => err.fmt(f),
+            TransportError::Sendmail(err) => write!(f, "sendmail: {}", err),
+            TransportError::Http { message, .. } => write!(f, "HTTP API: {}", message),
+            TransportError::CircuitOpen { profile, retry_in } => write!(
+                f,
+                "SMTP profile {} unavailable (circuit open), retry in {} s",
+                profile,
+                retry_in.as_secs() + 1
+            ),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+/// Backend delivering built messages
+///
+/// Implemented by the SMTP and sendmail transports of lettre and by the HTTP
+/// API providers, so the send pipeline, the queue worker and the canary do not
+/// depend on the backend.
+pub trait MailTransport: Send + Sync {
+    /// Sends an already formatted message to the recipients of the envelope
+    ///
+    /// # Arguments
+    /// * `envelope` - Sender and recipients of the transaction
+    /// * `email` - Formatted message bytes
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>>;
+
+    /// Sends a message to the recipients of its envelope
+    fn send(&self, email: Message) -> BoxFuture<'_, Result<(), TransportError>> {
+        Box::pin(async move {
+            let formatted = email.formatted();
+            self.send_raw(email.envelope(), &formatted).await
+        })
+    }
+}
+
+impl MailTransport for SmtpTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            AsyncTransport::send_raw(self, envelope, email)
+                .await
+                .map(|_| ())
+                .map_err(TransportError::Smtp)
+        })
+    }
+}
+
+impl MailTransport for SendmailTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            AsyncTransport::send_raw(self, envelope, email)
+                .await
+                .map_err(TransportError::Sendmail)
+        })
+    }
+}
+
+/// Mail transport shared across workers
+///
+/// Cheap to clone: clones share the same transport (and connection pool).
+pub type Mailer = Arc<dyn MailTransport>;
+
+/// Transport whose sends wait for a permit of the profile
+///
+/// lettre only caps the idle connections kept in its pool; a send finding no
+/// idle connection opens a new one. Holding a permit for the whole send caps
+/// the sends in flight, and with them the connections open to the relay, at
+/// the number of permits.
+struct LimitedTransport {
+    /// Current transport of the profile
+    inner: Mailer,
+
+    /// Permits shared by every send through the profile
+    permits: Arc<Semaphore>,
+}
+
+impl MailTransport for LimitedTransport {
+    fn send_raw<'a>(
+        &'a self,
+        envelope: &'a Envelope,
+        email: &'a [u8],
+    ) -> BoxFuture<'a, Result<(), TransportError>> {
+        Box::pin(async move {
+            // The semaphore is never closed
+            let _permit = self.permits.acquire().await;
+            let result = self.inner.send_raw(envelope, email).await;
+            // lettre returns the connection to its pool from a spawned task: let it
+            // run before the permit is released, so the next send reuses it
+            tokio::task::yield_now().await;
+            result
+        })
+    }
+}
+
+/// Builds the pooled SMTP transport from the SMTP configuration
+///
+/// # Arguments
+/// * `smtp_config` - SMTP server configuration
+/// * `privacy_config` - Privacy mode configuration (EHLO name)
+///
+/// # Returns
+/// * `Ok(Mailer)` - Transport ready to be shared across workers
+/// * `Err(String)` - TLS parameters for the relay could not be built (e.g. unreadable CA file)
+///
+/// # Notes
+/// OAuth2 profiles get a transport without credentials; their authenticated
+/// transports are built by [`build_mailers`].
+pub fn build_smtp_transport(
+    smtp_config: &SmtpConfig,
+    privacy_config: &PrivacyConfig,
+) -> Result<Mailer, String> {
+    let credentials = match &smtp_config.auth {
+        SmtpAuth::Password { username, password } => {
+            Some(Credentials::new(username.clone(), password.clone()))
+        }
+        _ => None,
+    };
+    build_transport(smtp_config, privacy_config, credentials)
+}
+
+/// Builds custom TLS parameters from the CA file and certificate checks settings
+///
+/// # Returns
+/// * `Ok(None)` - No custom setting, the system defaults apply
+/// * `Ok(Some(TlsParameters))` - Parameters trusting the CA file and/or accepting invalid certificates
+/// * `Err(String)` - The CA file cannot be read or parsed
+fn tls_parameters(smtp_config: &SmtpConfig) -> Result<Option<TlsParameters>, String> {
+    if smtp_config.tls_ca_file.is_none() && !smtp_config.tls_accept_invalid_certs {
+        return Ok(None);
+    }
+
+    let mut builder = TlsParameters::builder(smtp_config.host.clone())
+        .dangerous_accept_invalid_certs(smtp_config.tls_accept_invalid_certs);
+    if let Some(path) = &smtp_config.tls_ca_file {
+        let pem =
+            std::fs::read(path).map_err(|e| format!("Cannot read CA file {}: {}", path, e))?;
+        let certificate =
+            Certificate::from_pem(&pem).map_err(|e| format!("Invalid CA file {}: {}", path, e))?;
+        builder = builder.add_root_certificate(certificate);
+    }
+    builder.build().map(Some).map_err(|e| e.to_string())
+}
+
+/// Builds the pooled SMTP transport with the given credentials
+fn build_transport(
+    smtp_config: &SmtpConfig,
+    privacy_config: &PrivacyConfig,
+    credentials: Option<Credentials>,
+) -> Result<Mailer, String> {
+    let host = &smtp_config.host;
+    let tls = tls_parameters(smtp_config)?;
+    let mut mailer_builder = match (smtp_config.tls_mode, tls) {
+        // Use plain SMTP without TLS
+        (TlsMode::None, _) => SmtpTransport::builder_dangerous(host),
+        // Upgrade the plain connection with STARTTLS
+        (TlsMode::StartTls, None) => {
+            SmtpTransport::starttls_relay(host).map_err(|e| e.to_string())?
+        }
+        (TlsMode::StartTls, Some(tls)) => {
+            SmtpTransport::builder_dangerous(host).tls(Tls::Required(tls))
+        }
+        // Wrap the connection in TLS from the start (SMTPS)
+        (TlsMode::Tls, None) => SmtpTransport::relay(host).map_err(|e| e.to_string())?,
+        (TlsMode::Tls, Some(tls)) => SmtpTransport::builder_dangerous(host).tls(Tls::Wrapper(tls)),
+    }
+    .port(smtp_config.port);
+
+    // Add credentials if provided
+    if let Some(creds) = credentials {
+        mailer_builder = mailer_builder.credentials(creds);
+    }
+
+    // Restrict authentication to the configured mechanisms; access tokens need XOAUTH2
+    if let SmtpAuth::OAuth2 { .. } = smtp_config.auth {
+        mailer_builder = mailer_builder.authentication(vec![Mechanism::Xoauth2]);
+    } else if !smtp_config.auth_mechanisms.is_empty() {
+        mailer_builder = mailer_builder.authentication(smtp_config.auth_mechanisms.clone());
+    }
+
+    // Announce the public domain instead of the local hostname in privacy mode
+    if let (true, Some(domain)) = (privacy_config.enabled, &privacy_config.domain) {
+        mailer_builder = mailer_builder.hello_name(ClientId::Domain(domain.clone()));
+    }
+
+    // Keep at most as many idle connections as sends may run at once (see `LimitedTransport`)
+    let pool_config = PoolConfig::new().max_size(smtp_config.max_connections);
+
+    Ok(Arc::new(mailer_builder.pool_config(pool_config).build()))
+}
+
+/// Transport of one profile, replaced when its OAuth2 access token is renewed
+type SharedMailer = Arc<RwLock<Mailer>>;
+
+/// Returns the current transport of a profile
+fn current(mailer: &SharedMailer) -> Mailer {
+    mailer.read().unwrap_or_else(|e| e.into_inner()).clone()
+}
+
+/// SMTP transports of the default profile and of the named profiles
+///
+/// Cheap to clone: clones share the connection pools.
+#[derive(Clone)]
+pub struct Mailers {
+    /// Transport of the default profile
+    default: SharedMailer,
+
+    /// Transports of the named profiles, by lowercase name
+    profiles: HashMap<String, SharedMailer>,
+
+    /// Circuit breakers by lowercase profile name; empty when the breaker is disabled
+    breakers: HashMap<String, Arc<CircuitBreaker>>,
+
+    /// Send permits of the SMTP profiles (`SMTP_MAX_CONNECTIONS`), by lowercase profile name
+    permits: HashMap<String, Arc<Semaphore>>,
+
+    /// Number of send permits of the SMTP profiles, by lowercase profile name
+    max_connections: HashMap<String, usize>,
+}
+
+impl Mailers {
+    /// Returns the current transport of a profile, limited to its connections
+    /// and behind its circuit breaker
+    ///
+    /// The breaker wraps the limit, so an open circuit fails fast without
+    /// waiting for a permit.
+    fn guarded(&self, name: &str, mailer: &SharedMailer) -> Mailer {
+        let mut inner = current(mailer);
+        if let Some(permits) = self.permits.get(name) {
+            inner = Arc::new(LimitedTransport {
+                inner,
+                permits: Arc::clone(permits),
+            });
+        }
+        match self.breakers.get(name) {
+            Some(breaker) => Arc::new(BreakerTransport {
+                inner,
+                breaker: Arc::clone(breaker),
+            }),
+            None => inner,
+        }
+    }
+
+    /// Returns the current transport of the default profile
+    pub fn default_mailer(&self) -> Mailer {
+        self.guarded(DEFAULT_SMTP_PROFILE, &self.default)
+    }
+
+    /// Returns the current transport of a profile
+    ///
+    /// The transport should be fetched for each send rather than kept: OAuth2
+    /// profiles get a new transport whenever their access token is renewed.
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name (case-insensitive); `None` or `default` selects the default profile
+    ///
+    /// # Returns
+    /// The transport, or `None` when no profile has this name
+    pub fn get(&self, profile: Option<&str>) -> Option<Mailer> {
+        match profile.map(str::to_lowercase) {
+            None => Some(self.default_mailer()),
+            Some(name) if name == DEFAULT_SMTP_PROFILE => Some(self.default_mailer()),
+            Some(name) => self
+                .profiles
+                .get(&name)
+                .map(|mailer| self.guarded(&name, mailer)),
+        }
+    }
+
+    /// Returns the number of sends a profile runs at once
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name (case-insensitive); `None` selects the default profile
+    ///
+    /// # Returns
+    /// `SMTP_MAX_CONNECTIONS` of the profile, or `None` when it is not an SMTP relay
+    pub fn max_connections(&self, profile: Option<&str>) -> Option<usize> {
+        let name = profile.map_or_else(|| DEFAULT_SMTP_PROFILE.to_owned(), str::to_lowercase);
+        self.max_connections.get(&name).copied()
+    }
+
+    /// Returns `true` while the circuit of a profile is open
+    ///
+    /// # Arguments
+    /// * `profile` - Profile name (case-insensitive); `None` selects the default profile
+    pub fn circuit_open(&self, profile: Option<&str>) -> bool {
+        let name = profile.map_or_else(|| DEFAULT_SMTP_PROFILE.to_owned(), str::to_lowercase);
+        self.breakers.get(&name).is_some_and(|b| b.is_open())
+    }
+
+    /// Returns the circuit of every profile, by name
+    ///
+    /// # Returns
+    /// The state of each circuit, sorted by profile; empty when the breaker is disabled
+    pub fn circuits(&self) -> Vec<(String, CircuitSnapshot)> {
+        let mut circuits: Vec<_> = self
+            .breakers
+            .iter()
+            .map(|(name, breaker)| (name.clone(), breaker.state()))
+            .collect();
+        circuits.sort_by(|a, b| a.0.cmp(&b.0));
+        circuits
+    }
+}
+
+/// Returns the pause before renewing an access token valid for `expires_in`
+fn refresh_delay(expires_in: Duration) -> Duration {
+    expires_in
+        .saturating_sub(TOKEN_REFRESH_MARGIN)
+        .max(TOKEN_RETRY_DELAY)
+}
+
+/// Requests an access token and builds a transport authenticating with it
+///
+/// # Returns
+/// * `Ok((Mailer, Duration, Option<String>))` - The transport, the pause before the
+///   next renewal and the rotated refresh token, if any
+/// * `Err(String)` - The token or the transport could not be obtained
+async fn oauth2_transport(
+    smtp_config: &SmtpConfig,
+    privacy_config: &PrivacyConfig,
+    username: &str,
+    client: &OAuth2Config,
+    refresh_token: &str,
+) -> Result<(Mailer, Duration, Option<String>), String> {
+    let token = refresh_access_token(client, refresh_token).await?;
+    let credentials = Credentials::new(username.to_owned(), token.token);
+    let mailer = build_transport(smtp_config, privacy_config, Some(credentials))?;
+    Ok((mailer, refresh_delay(token.expires_in), token.refresh_token))
+}
+
+/// Renews the access token of an OAuth2 profile, swapping its transport
+///
+/// Only holds a weak reference to the transport: renewal stops once the
+/// transports are dropped, and they are never dropped by this task after the
+/// runtime has shut down.
+async fn refresh_oauth2(
+    name: String,
+    smtp_config: SmtpConfig,
+    privacy_config: PrivacyConfig,
+    shared: Weak<RwLock<Mailer>>,
+    mut refresh_token: String,
+    mut delay: Duration,
+) {
+    let SmtpAuth::OAuth2 { username, client } = &smtp_config.auth else {
+        return;
+    };
+    loop {
+        sleep(delay).await;
+        let renewed = oauth2_transport(
+            &smtp_config,
+            &privacy_config,
+            username,
+            client,
+            &refresh_token,
+        )
+        .await;
+        delay = match renewed {
+            Ok((mailer, next, rotated)) => {
+                let Some(shared) = shared.upgrade() else {
+                    return;
+                };
+                *shared.write().unwrap_or_else(|e| e.into_inner()) = mailer;
+                refresh_token = rotated.unwrap_or(refresh_token);
+                info!("SMTP profile {}: OAuth2 access token renewed", name);
+                next
+            }
+            Err(err) => {
+                warn!(
+                    "SMTP profile {}: OAuth2 access token renewal failed, retrying in {:?}: {}",
+                    name, TOKEN_RETRY_DELAY, err
+                );
+                TOKEN_RETRY_DELAY
+            }
+        };
+    }
+}
+
+/// Builds the shared transport of a profile
+///
+/// OAuth2 profiles obtain their first access token before the transport is
+/// returned, then keep renewing it in the background. When the first token
+/// cannot be obtained the profile starts without credentials and the renewal
+/// is retried shortly.
+async fn build_shared_mailer(
+    name: &str,
+    smtp_config: &SmtpConfig,
+    privacy_config: &PrivacyConfig,
+) -> Result<SharedMailer, String> {
+    let SmtpAuth::OAuth2 { username, client } = &smtp_config.auth else {
+        let mailer = build_smtp_transport(smtp_config, privacy_config)?;
+        return Ok(Arc::new(RwLock::new(mailer)));
+    };
+
+    let first = oauth2_transport(
+        smtp_config,
+        privacy_config,
+        username,
+        client,
+        &client.refresh_token,
+    )
+    .await;
+    let (mailer, delay, rotated) = match first {
+        Ok(renewed) => {
+            info!("SMTP profile {}: OAuth2 access token obtained", name);
+            renewed
+        }
+        Err(err) => {
+            error!(
+                "SMTP profile {}: cannot obtain an OAuth2 access token: {}",
+                name, err
+            );
+            let mailer = build_transport(smtp_config, privacy_config, None)?;
+            (mailer, TOKEN_RETRY_DELAY, None)
+        }
+    };
+
+    let shared = Arc::new(RwLock::new(mailer));
+    rt::spawn(refresh_oauth2(
+        name.to_owned(),
+        smtp_config.clone(),
+        privacy_config.clone(),
+        Arc::downgrade(&shared),
+        rotated.unwrap_or_else(|| client.refresh_token.clone()),
+        delay,
+    ));
+    Ok(shared)
+}
+
+/// Builds the transport of a profile with its configured backend
+///
+/// SMTP profiles get a shared pooled transport (renewed for OAuth2); the other
+/// backends do not depend on the SMTP settings.
+async fn build_profile_mailer(
+    name: &str,
+    transport_config: Option<&TransportConfig>,
+    smtp_config: &SmtpConfig,
+    privacy_config: &PrivacyConfig,
+) -> Result<SharedMailer, String> {
+    let Some(transport_config) = transport_config else {
+        return build_shared_mailer(name, smtp_config, privacy_config).await;
+    };
+    let api = &transport_config.api;
+    let mailer: Mailer = match transport_config.kind {
+        TransportKind::Smtp => {
+            return build_shared_mailer(name, smtp_config, privacy_config).await;
+        }
+        TransportKind::Sendmail => Arc::new(SendmailTransport::new_with_command(
+            &transport_config.sendmail_command,
+        )),
+        TransportKind::SendGrid => Arc::new(SendGridTransport::new(api)?),
+        TransportKind::Mailgun => Arc::new(MailgunTransport::new(api)?),
+        TransportKind::Ses => Arc::new(SesTransport::new(api)?),
+    };
+    Ok(Arc::new(RwLock::new(mailer)))
+}
+
+/// Builds the transports of all profiles
+///
+/// Starts the access token renewal of the OAuth2 profiles. Each profile
+/// delivers through its configured backend; profiles without one use SMTP.
+///
+/// # Arguments
+/// * `transport_config` - Backend of the default profile
+/// * `transport_profiles` - Named profiles with their backend
+/// * `smtp_config` - SMTP configuration of the default profile
+/// * `profiles` - Named profiles with their SMTP configuration
+/// * `privacy_config` - Privacy mode configuration (EHLO name)
+/// * `breaker_config` - Circuit breaker configuration
+///
+/// # Returns
+/// * `Ok(Mailers)` - Transports ready to be shared across workers
+/// * `Err(String)` - TLS parameters for a relay could not be built, or an HTTP
+///   API backend is incompletely configured
+pub async fn build_mailers(
+    transport_config: &TransportConfig,
+    transport_profiles: &[(String, TransportConfig)],
+    smtp_config: &SmtpConfig,
+    profiles: &[(String, SmtpConfig)],
+    privacy_config: &PrivacyConfig,
+    breaker_config: &CircuitBreakerConfig,
+) -> Result<Mailers, String> {
+    let default = build_profile_mailer(
+        DEFAULT_SMTP_PROFILE,
+        Some(transport_config),
+        smtp_config,
+        privacy_config,
+    )
+    .await
+    .map_err(|e| format!("SMTP profile {}: {}", DEFAULT_SMTP_PROFILE, e))?;
+    // Only SMTP relays are limited by SMTP_MAX_CONNECTIONS
+    let mut permits = HashMap::new();
+    let mut max_connections = HashMap::new();
+    let mut limit = |name: &str, config: &SmtpConfig| {
+        let size = config.max_connections as usize;
+        permits.insert(name.to_owned(), Arc::new(Semaphore::new(size)));
+        max_connections.insert(name.to_owned(), size);
+    };
+    if transport_config.kind == TransportKind::Smtp {
+        limit(DEFAULT_SMTP_PROFILE, smtp_config);
+    }
+    let mut shared = HashMap::new();
+    for (name, config) in profiles {
+        let transport = transport_profiles
+            .iter()
+            .find(|(n, _)| n == name)
+            .map(|(_, t)| t);
+        let mailer = build_profile_mailer(name, transport, config, privacy_config)
+            .await
+            .map_err(|e| format!("SMTP profile {}: {}", name, e))?;
+        shared.insert(name.clone(), mailer);
+        if transport.is_none_or(|t| t.kind == TransportKind::Smtp) {
+            limit(name, config);
+        }
+    }
+
+    let mut breakers = HashMap::new();
+    if breaker_config.threshold > 0 {
+        for name in shared
+            .keys()
+            .map(String::as_str)
+            .chain([DEFAULT_SMTP_PROFILE])
+        {
+            let breaker = CircuitBreaker::new(name, breaker_config);
+            breakers.insert(name.to_owned(), Arc::new(breaker));
+        }
+    }
+    Ok(Mailers {
+        default,
+        profiles: shared,
+        breakers,
+        permits,
+        max_connections,
+    })
+}
+
+/// Splits recipients between real ones and blackhole ones
+///
+/// Blackhole recipients are synthetic addresses reserved for monitoring: mail
+/// addressed to them goes through the whole send pipeline but is discarded
+/// instead of being handed to the SMTP server.
+///
+/// # Arguments
+/// * `recipients` - Recipient addresses of the message
+/// * `config` - Blackhole domains configuration
+///
+/// # Returns
+/// A tuple of (real recipients, blackhole recipients)
+pub fn partition_blackhole(
+    recipients: Vec<String>,
+    config: &BlackholeConfig,
+) -> (Vec<String>, Vec<String>) {
+    recipients.into_iter().partition(|r| {
+        let domain = r
+            .rsplit('@')
+            .next()
+            .unwrap_or_default()
+            .trim()
+            .to_lowercase();
+        !config.domains.contains(&domain)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use futures_util::future::join_all;
+
+    /// Transport recording the most sends it had in flight at once
+    #[derive(Default)]
+    struct CountingTransport {
+        in_flight: AtomicUsize,
+        peak: AtomicUsize,
+    }
+
+    impl MailTransport for CountingTransport {
+        fn send_raw<'a>(
+            &'a self,
+            _envelope: &'a Envelope,
+            _email: &'a [u8],
+        ) -> BoxFuture<'a, Result<(), TransportError>> {
+            Box::pin(async move {
+                let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                self.peak.fetch_max(now, Ordering::SeqCst);
+                sleep(Duration::from_millis(5)).await;
+                self.in_flight.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            })
+        }
+    }
+
+    fn envelope() -> Envelope {
+        Envelope::new(None, vec!["to@example.com".parse().unwrap()]).unwrap()
+    }
+
+    #[actix_web::test]
+    async fn limited_transport_caps_sends_in_flight() {
+        let counting = Arc::new(CountingTransport::default());
+        let limited = LimitedTransport {
+            inner: counting.clone(),
+            permits: Arc::new(Semaphore::new(2)),
+        };
+        let envelope = envelope();
+
+        let results = join_all((0..8).map(|_| limited.send_raw(&envelope, b"message"))).await;
+        assert!(results.iter().all(Result::is_ok));
+        assert_eq!(counting.peak.load(Ordering::SeqCst), 2);
+        assert_eq!(counting.in_flight.load(Ordering::SeqCst), 0);
+    }
+
+    #[actix_web::test]
+    async fn limited_transport_releases_permits_after_failures() {
+        struct FailingTransport;
+
+        impl MailTransport for FailingTransport {
+            fn send_raw<'a>(
+                &'a self,
+                _envelope: &'a Envelope,
+                _email: &'a [u8],
+            ) -> BoxFuture<'a, Result<(), TransportError>> {
+                Box::pin(async {
+                    Err(TransportError::Http {
+                        status: Some(503),
+                        message: "unavailable".into(),
+                    })
+                })
+            }
+        }
+
+        let permits = Arc::new(Semaphore::new(1));
+        let limited = LimitedTransport {
+            inner: Arc::new(FailingTransport),
+            permits: Arc::clone(&permits),
+        };
+        let envelope = envelope();
+        assert!(limited.send_raw(&envelope, b"message").await.is_err());
+        assert!(limited.send_raw(&envelope, b"message").await.is_err());
+        assert_eq!(permits.available_permits(), 1);
+    }
+}
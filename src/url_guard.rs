@@ -0,0 +1,228 @@
+//! Outbound URL guard module
+//!
+//! This module decides whether the service may fetch a remote URL on behalf of a
+//! caller. Hosts are matched against the configured allowlist and denylist, and
+//! resolved addresses in private, loopback, link-local, multicast or reserved
+//! ranges are rejected to prevent server-side request forgery, also when they
+//! are embedded in an IPv6 address.
+
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+
+use reqwest::Url;
+use tokio::net::lookup_host;
+
+use crate::settings::FetchPolicy;
+
+/// Checks whether a host matches a pattern
+///
+/// Patterns are either an exact host name (`example.com`) or a wildcard
+/// matching any subdomain (`*.example.com`).
+fn host_matches(host: &str, pattern: &str) -> bool {
+    match pattern.strip_prefix("*.") {
+        Some(suffix) => host.ends_with(&format!(".{}", suffix)),
+        None => host == pattern,
+    }
+}
+
+/// Returns `true` for IPv4 addresses that must never be fetched
+fn is_forbidden_ipv4(ip: &Ipv4Addr) -> bool {
+    let [a, b, ..] = ip.octets();
+    ip.is_private()
+        || ip.is_loopback()
+        || ip.is_link_local()
+        || ip.is_documentation()
+        // "This network" (0.0.0.0/8), the unspecified address included
+        || a == 0
+        // Carrier-grade NAT shared address space (100.64.0.0/10)
+        || (a == 100 && (b & 0xc0) == 64)
+        // Benchmarking (198.18.0.0/15)
+        || (a == 198 && (b & 0xfe) == 18)
+        // Multicast (224.0.0.0/4) and reserved (240.0.0.0/4), broadcast included
+        || a >= 224
+}
+
+/// Returns the IPv4 address carried by an IPv6 address, if any
+///
+/// Covers IPv4-mapped (`::ffff:a.b.c.d`) and IPv4-compatible (`::a.b.c.d`)
+/// addresses, NAT64 (`64:ff9b::/96`) and 6to4 (`2002::/16`), which all reach
+/// the embedded IPv4 host.
+fn embedded_ipv4(ip: &Ipv6Addr) -> Option<Ipv4Addr> {
+    let segments = ip.segments();
+    let v4 = |high: u16, low: u16| Ipv4Addr::from((u32::from(high) << 16) | u32::from(low));
+    match segments {
+        [0x64, 0xff9b, 0, 0, 0, 0, high, low] => Some(v4(high, low)),
+        [0x2002, high, low, ..] => Some(v4(high, low)),
+        _ => ip.to_ipv4(),
+    }
+}
+
+/// Returns `true` for IPv6 addresses that must never be fetched
+fn is_forbidden_ipv6(ip: &Ipv6Addr) -> bool {
+    if let Some(v4) = embedded_ipv4(ip) {
+        return is_forbidden_ipv4(&v4);
+    }
+    let first = ip.segments()[0];
+    ip.is_loopback()
+        || ip.is_unspecified()
+        || ip.is_multicast()
+        // Unique local addresses (fc00::/7)
+        || (first & 0xfe00) == 0xfc00
+        // Link-local unicast addresses (fe80::/10)
+        || (first & 0xffc0) == 0xfe80
+}
+
+/// Returns `true` when an address belongs to a private or internal range
+fn is_forbidden_ip(ip: &IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => is_forbidden_ipv4(v4),
+        IpAddr::V6(v6) => is_forbidden_ipv6(v6),
+    }
+}
+
+/// Verifies that a URL may be fetched according to the fetch policy
+///
+/// # Arguments
+/// * `url` - The URL about to be fetched
+/// * `policy` - Host lists and private address settings
+///
+/// # Returns
+/// * `Ok(Vec<SocketAddr>)` - The resolved addresses the request must be pinned to
+/// * `Err(String)` - The reason the URL is rejected
+pub async fn resolve_allowed(url: &Url, policy: &FetchPolicy) -> Result<Vec<SocketAddr>, String> {
+    let host = url
+        .host_str()
+        .ok_or_else(|| "URL has no host".to_owned())?
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .to_lowercase();
+
+    if policy.deny_hosts.iter().any(|p| host_matches(&host, p)) {
+        return Err(format!("host {} is denied", host));
+    }
+    if !policy.allow_hosts.is_empty() && !policy.allow_hosts.iter().any(|p| host_matches(&host, p))
+    {
+        return Err(format!("host {} is not allowed", host));
+    }
+
+    let port = url.port_or_known_default().unwrap_or(80);
+    let addrs: Vec<SocketAddr> = lookup_host((host.as_str(), port))
+        .await
+        .map_err(|e| format!("cannot resolve {}: {}", host, e))?
+        .collect();
+
+    if addrs.is_empty() {
+        return Err(format!("cannot resolve {}", host));
+    }
+    if !policy.allow_private_ips && addrs.iter().any(|a| is_forbidden_ip(&a.ip())) {
+        return Err(format!("host {} resolves to a private address", host));
+    }
+
+    Ok(addrs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn forbidden(ip: &str) -> bool {
+        is_forbidden_ip(&ip.parse().unwrap())
+    }
+
+    #[test]
+    fn forbids_internal_ipv4_ranges() {
+        for ip in [
+            "10.1.2.3",
+            "172.16.0.1",
+            "192.168.1.1",
+            "127.0.0.1",
+            "169.254.169.254",
+            "0.0.0.0",
+            "0.1.2.3",
+            "100.64.0.1",
+            "100.127.255.254",
+            "192.0.2.1",
+            "198.51.100.1",
+            "203.0.113.1",
+            "198.18.0.1",
+            "198.19.255.254",
+            "224.0.0.1",
+            "239.255.255.250",
+            "240.0.0.1",
+            "255.255.255.255",
+        ] {
+            assert!(forbidden(ip), "{} is allowed", ip);
+        }
+    }
+
+    #[test]
+    fn allows_public_ipv4() {
+        for ip in [
+            "8.8.8.8",
+            "93.184.216.34",
+            "100.128.0.1",
+            "198.20.0.1",
+            "223.255.255.254",
+        ] {
+            assert!(!forbidden(ip), "{} is forbidden", ip);
+        }
+    }
+
+    #[test]
+    fn forbids_internal_ipv6_ranges() {
+        for ip in [
+            "::1",
+            "::",
+            "fc00::1",
+            "fd12:3456::1",
+            "fe80::1",
+            "ff02::1",
+            "ff0e::1",
+        ] {
+            assert!(forbidden(ip), "{} is allowed", ip);
+        }
+    }
+
+    #[test]
+    fn checks_embedded_ipv4() {
+        for ip in [
+            // IPv4-mapped
+            "::ffff:127.0.0.1",
+            "::ffff:169.254.169.254",
+            // IPv4-compatible
+            "::127.0.0.1",
+            "::10.0.0.1",
+            // NAT64
+            "64:ff9b::127.0.0.1",
+            "64:ff9b::a9fe:a9fe",
+            // 6to4
+            "2002:7f00:1::",
+            "2002:a9fe:a9fe::1",
+        ] {
+            assert!(forbidden(ip), "{} is allowed", ip);
+        }
+        for ip in [
+            "::ffff:8.8.8.8",
+            "::8.8.8.8",
+            "64:ff9b::8.8.8.8",
+            "2002:808:808::1",
+        ] {
+            assert!(!forbidden(ip), "{} is forbidden", ip);
+        }
+    }
+
+    #[test]
+    fn allows_public_ipv6() {
+        for ip in ["2606:4700:4700::1111", "2a00:1450:4001::200e"] {
+            assert!(!forbidden(ip), "{} is forbidden", ip);
+        }
+    }
+
+    #[test]
+    fn matches_hosts_and_wildcards() {
+        assert!(host_matches("example.com", "example.com"));
+        assert!(!host_matches("www.example.com", "example.com"));
+        assert!(host_matches("cdn.example.com", "*.example.com"));
+        assert!(!host_matches("example.com", "*.example.com"));
+        assert!(!host_matches("badexample.com", "*.example.com"));
+    }
+}
@@ -0,0 +1,245 @@
+//! Delivery outcome webhooks
+//!
+//! This module notifies the `callback_url` of a queued email once its delivery
+//! is settled: accepted by the relay, or failed for good. Each event is a JSON
+//! document POSTed to the URL, signed with an HMAC-SHA256 of the timestamp and
+//! the body keyed by `WEBHOOK_SECRET`, and retried with exponential backoff
+//! until the receiver answers with a 2xx status. Callback URLs go through the
+//! outbound fetch policy, so an event can never be sent to an internal host.
+
+use std::sync::Arc;
+
+use actix_web::rt::{self, time::sleep};
+use log::{error, info, warn};
+use reqwest::{Client, Url, redirect};
+use serde::Serialize;
+use time::OffsetDateTime;
+use time::format_description::well_known::Rfc3339;
+
+use crate::crypto::{hex, hmac_sha256};
+use crate::queue::worker::unix_now;
+use crate::settings::{FetchPolicy, WebhookConfig};
+use crate::url_guard::resolve_allowed;
+
+/// Header carrying the event name
+pub const EVENT_HEADER: &str = "x-rustmail-event";
+
+/// Header carrying the Unix timestamp covered by the signature
+pub const TIMESTAMP_HEADER: &str = "x-rustmail-timestamp";
+
+/// Header carrying the signature of the event (`sha256=<hex>`)
+pub const SIGNATURE_HEADER: &str = "x-rustmail-signature";
+
+/// Outcome of a queued email
+#[derive(Clone, Copy, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DeliveryOutcome {
+    /// Accepted by the relay
+    Sent,
+
+    /// Permanently rejected or out of attempts
+    Failed,
+}
+
+/// Event POSTed to the callback URL of a queued email
+#[derive(Serialize)]
+pub struct DeliveryEvent {
+    /// Event name, `delivery.sent` or `delivery.failed`
+    pub event: &'static str,
+
+    /// Queue identifier of the email, as returned when it was queued
+    pub id: String,
+
+    /// Outcome of the delivery
+    pub outcome: DeliveryOutcome,
+
+    /// Delivery attempts made
+    pub attempts: u32,
+
+    /// Last delivery error, for failed emails
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+
+    /// Time the outcome was settled (RFC3339)
+    pub timestamp: String,
+}
+
+impl DeliveryEvent {
+    /// Creates the event of a settled delivery
+    ///
+    /// # Arguments
+    /// * `id` - Queue identifier of the email
+    /// * `outcome` - Outcome of the delivery
+    /// * `attempts` - Delivery attempts made
+    /// * `error` - Last delivery error, for failed emails
+    pub fn new(id: &str, outcome: DeliveryOutcome, attempts: u32, error: Option<String>) -> Self {
+        let event = match outcome {
+            DeliveryOutcome::Sent => "delivery.sent",
+            DeliveryOutcome::Failed => "delivery.failed",
+        };
+        DeliveryEvent {
+            event,
+            id: id.to_owned(),
+            outcome,
+            attempts,
+            error,
+            timestamp: OffsetDateTime::now_utc()
+                .format(&Rfc3339)
+                .unwrap_or_default(),
+        }
+    }
+}
+
+/// Signs an event body
+///
+/// # Returns
+/// The signature header value: `sha256=` followed by the hexadecimal
+/// HMAC-SHA256 of `<timestamp>.<body>`
+fn sign(secret: &str, timestamp: u64, body: &[u8]) -> String {
+    let mut data = format!("{}.", timestamp).into_bytes();
+    data.extend_from_slice(body);
+    format!("sha256={}", hex(&hmac_sha256(secret.as_bytes(), &data)))
+}
+
+/// Sender of the delivery outcome events
+pub struct Webhooks {
+    /// Signing key, timeout and retries
+    config: WebhookConfig,
+
+    /// Hosts the callbacks may be sent to
+    policy: FetchPolicy,
+}
+
+impl Webhooks {
+    /// Creates the event sender
+    ///
+    /// # Arguments
+    /// * `config` - Webhook configuration
+    /// * `policy` - Outbound fetch policy restricting the callback hosts
+    pub fn new(config: WebhookConfig, policy: FetchPolicy) -> Self {
+        Webhooks { config, policy }
+    }
+
+    /// Returns `true` when callbacks are configured (`WEBHOOK_SECRET`)
+    pub fn enabled(&self) -> bool {
+        self.config.secret.is_some()
+    }
+
+    /// Checks the callback URL of a request
+    ///
+    /// # Returns
+    /// * `Ok(())` - Events can be sent to the URL
+    /// * `Err(String)` - The reason the URL is rejected
+    pub async fn check_url(&self, url: &str) -> Result<(), String> {
+        if !self.enabled() {
+            return Err("Webhook callbacks are not configured (WEBHOOK_SECRET)".to_owned());
+        }
+        let parsed = Url::parse(url).map_err(|e| format!("Invalid callback_url: {}", e))?;
+        if parsed.scheme() != "http" && parsed.scheme() != "https" {
+            return Err("Invalid callback_url: only http and https are supported".to_owned());
+        }
+        resolve_allowed(&parsed, &self.policy)
+            .await
+            .map(|_| ())
+            .map_err(|reason| format!("callback_url not allowed: {}", reason))
+    }
+
+    /// Sends an event to a callback URL in the background
+    ///
+    /// Failed requests are retried with exponential backoff, up to
+    /// `WEBHOOK_MAX_ATTEMPTS` requests. Events still being retried are lost
+    /// when the service stops.
+    ///
+    /// # Arguments
+    /// * `url` - Callback URL of the email
+    /// * `event` - Outcome event
+    pub fn notify(self: &Arc<Self>, url: String, event: DeliveryEvent) {
+        let webhooks = Arc::clone(self);
+        rt::spawn(async move {
+            let body = match serde_json::to_vec(&event) {
+                Ok(body) => body,
+                Err(err) => {
+                    error!("Cannot encode callback event {}: {}", event.id, err);
+                    return;
+                }
+            };
+            let mut delay = webhooks.config.retry_base;
+            for attempt in 1..=webhooks.config.max_attempts {
+                match webhooks.post(&url, event.event, &body).await {
+                    Ok(()) => {
+                        info!("Callback {} for mail {} delivered", event.event, event.id);
+                        return;
+                    }
+                    Err(err) if attempt < webhooks.config.max_attempts => {
+                        warn!(
+                            "Callback for mail {} attempt {} failed, retry in {:?}: {}",
+                            event.id, attempt, delay, err
+                        );
+                        sleep(delay).await;
+                        delay = delay.saturating_mul(2);
+                    }
+                    Err(err) => error!(
+                        "Callback for mail {} failed after {} attempt(s): {}",
+                        event.id, attempt, err
+                    ),
+                }
+            }
+        });
+    }
+
+    /// Makes one callback request
+    ///
+    /// The host is resolved and checked against the fetch policy on every
+    /// request, and the request is pinned to the checked addresses.
+    async fn post(&self, url: &str, event: &str, body: &[u8]) -> Result<(), String> {
+        let secret = self.config.secret.as_deref().unwrap_or_default();
+        let parsed = Url::parse(url).map_err(|e| e.to_string())?;
+        let addrs = resolve_allowed(&parsed, &self.policy).await?;
+        let host = parsed.host_str().unwrap_or_default().to_owned();
+        let client = Client::builder()
+            .timeout(self.config.timeout)
+            .redirect(redirect::Policy::none())
+            .resolve_to_addrs(&host, &addrs)
+            .build()
+            .map_err(|e| format!("cannot build HTTP client: {}", e))?;
+
+        let timestamp = unix_now();
+        let res = client
+            .post(parsed)
+            .header("content-type", "application/json")
+            .header(EVENT_HEADER, event)
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(SIGNATURE_HEADER, sign(secret, timestamp, body))
+            .body(body.to_vec())
+            .send()
+            .await
+            .map_err(|e| format!("unreachable: {}", e))?;
+        if res.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("HTTP {}", res.status().as_u16()))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn signs_timestamp_and_body() {
+        assert_eq!(
+            sign("secret", 1700000000, br#"{"id":"abc"}"#),
+            "sha256=5ad265e6615b64b835cae994e1526056136c85c5a0d090d4f35b730288b456de"
+        );
+    }
+
+    #[test]
+    fn signature_covers_the_timestamp() {
+        let body = br#"{"id":"abc"}"#;
+        assert_ne!(
+            sign("secret", 1700000000, body),
+            sign("secret", 1700000001, body)
+        );
+    }
+}
@@ -0,0 +1,149 @@
+use serde::Deserialize;
+
+use crate::send::dto::Priority;
+
+fn default_encoding() -> String {
+    "plain".to_owned()
+}
+
+/// Email sender of an XML request
+///
+/// `<from name="Example Support">sender@example.com</from>`
+#[derive(Deserialize)]
+pub struct XmlSender {
+    /// Display name shown by mail clients
+    #[serde(rename = "@name")]
+    pub name: Option<String>,
+
+    /// Sender email address
+    #[serde(rename = "$text")]
+    pub address: String,
+}
+
+/// Attachment of an XML request
+///
+/// `<attachment filename="invoice.pdf" contentType="application/pdf">JVBERi0...</attachment>`
+#[derive(Deserialize)]
+pub struct XmlAttachment {
+    /// File name shown to the recipient
+    #[serde(rename = "@filename")]
+    pub filename: String,
+
+    /// MIME type of the file
+    #[serde(rename = "@contentType")]
+    pub content_type: String,
+
+    /// Optional converter applied before sending
+    #[serde(rename = "@convert")]
+    pub convert: Option<String>,
+
+    /// Optional SHA-256 of the decoded file, hexadecimal
+    #[serde(rename = "@sha256")]
+    pub sha256: Option<String>,
+
+    /// Base64 encoded file content
+    #[serde(rename = "$text")]
+    pub data: String,
+}
+
+/// Inline image of an XML request
+///
+/// `<inlineImage contentId="logo" contentType="image/png">iVBORw0...</inlineImage>`
+#[derive(Deserialize)]
+pub struct XmlInlineImage {
+    /// Content ID referenced by the HTML body, without angle brackets
+    #[serde(rename = "@contentId")]
+    pub content_id: String,
+
+    /// MIME type of the image
+    #[serde(rename = "@contentType")]
+    pub content_type: String,
+
+    /// Base64 encoded image content
+    #[serde(rename = "$text")]
+    pub data: String,
+}
+
+/// Custom header of an XML request
+///
+/// `<header name="X-Campaign-Id">spring</header>`
+#[derive(Deserialize)]
+pub struct XmlHeader {
+    /// Header name
+    #[serde(rename = "@name")]
+    pub name: String,
+
+    /// Header value
+    #[serde(rename = "$text", default)]
+    pub value: String,
+}
+
+/// Email request in XML
+///
+/// The `<mail>` root element carries the delivery options as attributes and
+/// the message as child elements; repeated `<to>` and `<attachment>` elements
+/// give several recipients and files.
+#[derive(Deserialize)]
+pub struct XmlMail {
+    /// Queue the email for background delivery instead of sending it now
+    #[serde(rename = "@queue", default)]
+    pub queue: bool,
+
+    /// SMTP profile to send through; the default profile when absent
+    #[serde(rename = "@profile")]
+    pub profile: Option<String>,
+
+    /// Delivery time (RFC3339); a future time schedules the email
+    #[serde(rename = "@sendAt")]
+    pub send_at: Option<String>,
+
+    /// URL notified of the delivery outcome; queued or scheduled emails only
+    #[serde(rename = "@callbackUrl")]
+    pub callback_url: Option<String>,
+
+    /// Validate and build the email without sending or queueing it
+    #[serde(rename = "@dryRun", default)]
+    pub dry_run: bool,
+
+    /// Encoding of the text and html elements ("plain" or "base64"). Defaults to "plain".
+    #[serde(rename = "@encoding", default = "default_encoding")]
+    pub encoding: String,
+
+    /// Email sender
+    pub from: XmlSender,
+
+    /// Optional address replies should be sent to instead of the sender
+    #[serde(rename = "replyTo")]
+    pub reply_to: Option<String>,
+
+    /// Recipient email addresses
+    #[serde(default)]
+    pub to: Vec<String>,
+
+    /// Email subject line
+    pub subject: String,
+
+    /// Plain text body
+    pub text: Option<String>,
+
+    /// HTML body
+    pub html: Option<String>,
+
+    /// Optional preview text shown by inbox clients next to the subject (HTML bodies only)
+    pub preheader: Option<String>,
+
+    /// Files attached to the email
+    #[serde(rename = "attachment", default)]
+    pub attachments: Vec<XmlAttachment>,
+
+    /// Images embedded in the HTML body
+    #[serde(rename = "inlineImage", default)]
+    pub inline_images: Vec<XmlInlineImage>,
+
+    /// Extra headers
+    #[serde(rename = "header", default)]
+    pub headers: Vec<XmlHeader>,
+
+    /// Optional importance ("high", "normal" or "low")
+    pub priority: Option<Priority>,
+}
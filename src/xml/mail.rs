@@ -0,0 +1,83 @@
+//! XML request mapping
+//!
+//! This module turns an XML request into a regular email request that can go
+//! through the send pipeline.
+
+use crate::send::dto::{Attachment, InlineImage, SendMailPayload, SendMailReq, Sender};
+use crate::xml::dto::XmlMail;
+
+/// Maps an XML request onto an email request
+///
+/// An email with only an `<html>` element is sent as a single HTML part.
+///
+/// # Arguments
+/// * `xml` - The decoded XML request
+///
+/// # Returns
+/// The equivalent email request
+pub fn to_mail_req(xml: XmlMail) -> SendMailReq {
+    let (text, html, content_type) = match (xml.text, xml.html) {
+        (Some(text), html) => (text, html, "plain"),
+        (None, Some(html)) => (html, None, "html"),
+        (None, None) => (String::new(), None, "plain"),
+    };
+
+    let from = match xml.from.name {
+        Some(name) => Sender::Named {
+            address: xml.from.address,
+            name: Some(name),
+        },
+        None => Sender::Address(xml.from.address),
+    };
+
+    let attachments = xml
+        .attachments
+        .into_iter()
+        .map(|a| Attachment {
+            filename: a.filename,
+            content_type: a.content_type,
+            // Base64 in XML is often wrapped over several lines
+            data: a.data.split_whitespace().collect::<String>().into(),
+            convert: a.convert,
+            sha256: a.sha256,
+        })
+        .collect();
+
+    let inline_images = xml
+        .inline_images
+        .into_iter()
+        .map(|i| InlineImage {
+            content_id: i.content_id,
+            content_type: i.content_type,
+            data: i.data.split_whitespace().collect::<String>().into(),
+        })
+        .collect();
+
+    SendMailReq {
+        mail: SendMailPayload {
+            from,
+            reply_to: xml.reply_to,
+            to: xml.to,
+            subject: xml.subject,
+            text,
+            html,
+            encoding: xml.encoding,
+            content_type: content_type.to_owned(),
+            preheader: xml.preheader,
+            attachments,
+            inline_images,
+            calendar: None,
+            headers: xml.headers.into_iter().map(|h| (h.name, h.value)).collect(),
+            priority: xml.priority,
+        },
+        queue: xml.queue,
+        profile: xml.profile,
+        send_at: xml.send_at,
+        callback_url: xml.callback_url,
+        dry_run: xml.dry_run,
+        smtp: None,
+        fault: None,
+        idempotency_key: None,
+        tenant: None,
+    }
+}
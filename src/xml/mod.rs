@@ -0,0 +1,14 @@
+//! XML compatibility module
+//!
+//! This module accepts emails described in XML, for clients of legacy mail
+//! gateways that cannot produce JSON, and maps them onto regular email
+//! requests handed to the send pipeline.
+
+/// Data transfer objects for XML requests
+pub mod dto;
+
+/// XML request mapping
+pub mod mail;
+
+/// HTTP controllers for the XML endpoint
+pub mod xml_controller;
@@ -0,0 +1,99 @@
+//! HTTP controllers for the XML endpoint
+//!
+//! This module maps an XML email onto a regular email request and sends it.
+
+use crate::codec::Xml;
+use crate::send::context::SendContext;
+use crate::send::dto::QueuedRes;
+use crate::send::send_controller::send_mail;
+use crate::settings::RustMailRes;
+use crate::xml::dto::XmlMail;
+use crate::xml::mail::to_mail_req;
+use actix_web::{HttpRequest, HttpResponse, Result, post, web};
+use log::info;
+use utoipa::OpenApi;
+
+/// Example request body shown in the OpenAPI specification
+const XML_EXAMPLE: &str = r#"<mail queue="false" encoding="plain">
+  <from name="Example Support">sender@example.com</from>
+  <to>alice@example.com</to>
+  <subject>Invoice</subject>
+  <text>Your invoice is attached.</text>
+  <attachment filename="invoice.pdf" contentType="application/pdf">JVBERi0xLjQK</attachment>
+</mail>"#;
+
+/// POST endpoint for sending an email described in XML
+///
+/// Registered only when `XML_ENDPOINT` is set. The body is decoded as XML
+/// whatever its `Content-Type`, mapped onto a `/send` request and sent exactly
+/// like one (limits, link validation, duplicate guard, queueing). Responses are
+/// the same JSON (or MessagePack) responses as `/send`.
+///
+/// # Schema
+/// The root `<mail>` element takes the optional attributes `queue`, `dryRun`
+/// (`true` or `false`), `profile`, `sendAt` (RFC3339) and `encoding` (`plain`
+/// or `base64`, for the text and html elements), and the child elements:
+/// * `<from name="...">` - Sender address, with an optional display name
+/// * `<replyTo>` - Optional reply address
+/// * `<to>` - Recipient address, repeated for each recipient
+/// * `<subject>` - Subject line
+/// * `<text>` / `<html>` - Plain text and HTML bodies (either or both)
+/// * `<preheader>` - Optional preview text (HTML bodies only)
+/// * `<attachment filename="..." contentType="..." convert="..." sha256="...">` -
+///   Base64 file content, repeated for each file; `convert` and `sha256` are optional
+///
+/// # Arguments
+/// * `req` - HTTP request, used to negotiate the response format
+/// * `body` - XML payload
+/// * `ctx` - Shared send state injected by Actix
+///
+/// # Returns
+/// * `Ok(HttpResponse)` - Same response as `/send`
+/// * `Err(actix_web::Error)` - JSON fail response when the XML is malformed or
+///   misses a required element; same errors as `/send` otherwise
+#[utoipa::path(
+    post,
+    path = "/send/xml",
+    tag = "send",
+    request_body(
+        content = String,
+        content_type = "application/xml",
+        description = "`<mail>` document, see the schema in the README",
+        example = json!(XML_EXAMPLE)
+    ),
+    responses(
+        (status = 200, description = "Same responses as `/send`", body = RustMailRes),
+        (status = 202, description = "Email queued or scheduled", body = QueuedRes),
+        (status = 400, description = "Malformed XML or invalid request", body = RustMailRes),
+        (status = 401, description = "Missing or invalid API key", body = RustMailRes),
+        (status = 404, description = "XML endpoint not enabled (`XML_ENDPOINT`)"),
+        (status = 429, description = "Rate limit exceeded", body = RustMailRes),
+        (status = 500, description = "SMTP or internal error", body = RustMailRes),
+        (status = 503, description = "Service overloaded, low priority mail shed", body = RustMailRes)
+    )
+)]
+#[post("send/xml")]
+async fn send_xml(
+    req: HttpRequest,
+    body: Xml<XmlMail>,
+    ctx: web::Data<SendContext>,
+) -> Result<HttpResponse> {
+    info!("send xml request");
+    let mail = to_mail_req(body.into_inner());
+    send_mail(&req, mail, &ctx).await
+}
+
+/// OpenAPI description of the endpoints of this module
+#[derive(OpenApi)]
+#[openapi(paths(send_xml))]
+pub struct ApiDoc;
+
+/// Configures the Actix-web service routes
+///
+/// Registers all HTTP endpoints for this module with the application.
+///
+/// # Arguments
+/// * `cfg` - Actix-web service configuration to register routes
+pub fn config(cfg: &mut web::ServiceConfig) {
+    cfg.service(send_xml);
+}